@@ -1,7 +1,8 @@
-use crate::{Checkpoint, Slot, Uint64};
+use crate::{block::hash_tree_root, crypto, Checkpoint, Slot, State, Uint64};
 use serde::{Deserialize, Serialize};
 use ssz::ByteVector;
 use ssz_derive::Ssz;
+use std::collections::BTreeMap;
 use typenum::{Prod, Sum, U100, U12, U31};
 
 pub type U3100 = Prod<U31, U100>;
@@ -31,6 +32,36 @@ pub type AggregationBits = ssz::BitList<U4096>;
 /// Limit is VALIDATOR_REGISTRY_LIMIT (4096).
 pub type AggregatedSignatures = ssz::PersistentList<Signature, U4096>;
 
+/// List of aggregated attestations included in a block (without signatures).
+/// Limit is VALIDATOR_REGISTRY_LIMIT (4096).
+pub type AggregatedAttestationList = ssz::PersistentList<AggregatedAttestations, U4096>;
+
+/// Signatures of the aggregated attestations in a block, one entry per aggregate.
+/// Limit is VALIDATOR_REGISTRY_LIMIT (4096).
+pub type AggregatedAttestationSignatures = ssz::PersistentList<AggregatedSignatures, U4096>;
+
+/// Number of gossip subnets that individual attestations are spread over.
+pub const ATTESTATION_SUBNET_COUNT: u64 = 64;
+
+/// Subnet a validator publishes its attestations on.
+pub fn compute_subnet_id(validator_id: u64) -> u64 {
+    validator_id % ATTESTATION_SUBNET_COUNT
+}
+
+/// Whether `validator_id` aggregates the attestations of its subnet at `slot`.
+///
+/// The members of a subnet take turns by slot, so every non-empty subnet has
+/// exactly one aggregator per slot.
+pub fn is_aggregator(validator_id: u64, slot: Slot, num_validators: u64) -> bool {
+    if validator_id >= num_validators {
+        return false;
+    }
+
+    let members =
+        (num_validators - compute_subnet_id(validator_id)).div_ceil(ATTESTATION_SUBNET_COUNT);
+    validator_id / ATTESTATION_SUBNET_COUNT == slot.0 % members
+}
+
 /// Attestation content describing the validator's observed chain view.
 #[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
 pub struct AttestationData {
@@ -54,6 +85,33 @@ pub struct Attestation {
     pub data: AttestationData,
 }
 
+impl Attestation {
+    pub fn subnet_id(&self) -> u64 {
+        compute_subnet_id(self.validator_id.0)
+    }
+
+    /// Verify `signature` over this attestation with the validator's key from `state`.
+    ///
+    /// The slot is the XMSS epoch. Without the `xmss-verify` feature only the
+    /// validator index is checked.
+    pub fn verify_signature(&self, signature: &Signature, state: &State) -> Result<(), String> {
+        let validator = state
+            .validators
+            .get(self.validator_id.0)
+            .map_err(|_| format!("Validator index {} out of range", self.validator_id.0))?;
+
+        let message: [u8; 32] = hash_tree_root(self).0.into();
+        let epoch = self.data.slot.0 as u32;
+
+        crypto::verify(&validator.pubkey, epoch, &message, signature).map_err(|e| {
+            format!(
+                "Invalid signature of validator {} at slot {}: {e}",
+                self.validator_id.0, self.data.slot.0
+            )
+        })
+    }
+}
+
 /// Validator attestation bundled with its signature.
 #[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
 pub struct SignedAttestation {
@@ -63,6 +121,13 @@ pub struct SignedAttestation {
     pub signature: Signature,
 }
 
+impl SignedAttestation {
+    /// Verify the signature with the validator's key from `state`.
+    pub fn verify_signature(&self, state: &State) -> Result<(), String> {
+        self.message.verify_signature(&self.signature, state)
+    }
+}
+
 /// Aggregated attestation consisting of participation bits and message.
 #[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
 pub struct AggregatedAttestations {
@@ -88,3 +153,187 @@ pub struct SignedAggregatedAttestations {
     /// TODO: this will be replaced by a SNARK in future devnets.
     pub signature: AggregatedSignatures,
 }
+
+impl AggregatedAttestations {
+    /// Indices of the participating validators, in ascending order.
+    pub fn validator_ids(&self) -> impl Iterator<Item = Uint64> + '_ {
+        (0..self.aggregation_bits.len())
+            .filter(|&index| {
+                self.aggregation_bits
+                    .get(index)
+                    .map(|bit| *bit)
+                    .unwrap_or(false)
+            })
+            .map(|index| Uint64(index as u64))
+    }
+
+    /// The individual attestation of every participant, in validator order.
+    pub fn to_attestations(&self) -> Vec<Attestation> {
+        self.validator_ids()
+            .map(|validator_id| Attestation {
+                validator_id,
+                data: self.data.clone(),
+            })
+            .collect()
+    }
+}
+
+impl SignedAggregatedAttestations {
+    /// Group attestations with identical data into one aggregate each.
+    ///
+    /// Aggregates are returned in the order their data first appears. Within an
+    /// aggregate signatures follow validator order; a validator's repeated
+    /// attestation for the same data is dropped.
+    pub fn aggregate<'a>(
+        attestations: impl IntoIterator<Item = &'a SignedAttestation>,
+    ) -> Result<Vec<Self>, String> {
+        let mut groups: Vec<(AttestationData, BTreeMap<u64, Signature>)> = Vec::new();
+        for signed_attestation in attestations {
+            let attestation = &signed_attestation.message;
+            let index = match groups
+                .iter()
+                .position(|(data, _)| *data == attestation.data)
+            {
+                Some(index) => index,
+                None => {
+                    groups.push((attestation.data.clone(), BTreeMap::new()));
+                    groups.len() - 1
+                }
+            };
+            groups[index]
+                .1
+                .entry(attestation.validator_id.0)
+                .or_insert_with(|| signed_attestation.signature.clone());
+        }
+
+        groups
+            .into_iter()
+            .map(|(data, signatures)| {
+                let length = signatures.keys().last().map_or(0, |&max| max as usize + 1);
+                let mut aggregation_bits = AggregationBits::with_length(length);
+                let mut signature = AggregatedSignatures::default();
+                for (validator_id, validator_signature) in signatures {
+                    aggregation_bits.set(validator_id as usize, true);
+                    signature
+                        .push(validator_signature)
+                        .map_err(|e| format!("Failed to push signature: {:?}", e))?;
+                }
+
+                Ok(Self {
+                    message: AggregatedAttestations {
+                        aggregation_bits,
+                        data,
+                    },
+                    signature,
+                })
+            })
+            .collect()
+    }
+
+    /// The individual signed attestation of every participant, in validator order.
+    pub fn to_signed_attestations(&self) -> Vec<SignedAttestation> {
+        self.message
+            .to_attestations()
+            .into_iter()
+            .zip(&self.signature)
+            .map(|(message, signature)| SignedAttestation {
+                message,
+                signature: signature.clone(),
+            })
+            .collect()
+    }
+
+    /// Verify every participant's signature with their key from `state`.
+    ///
+    /// The signatures must match the set bits one to one, in validator order.
+    pub fn verify_signatures(&self, state: &State) -> Result<(), String> {
+        verify_aggregate(&self.message, &self.signature, state)
+    }
+}
+
+/// Check `signatures` against the participants of `aggregate`, in validator order.
+pub(crate) fn verify_aggregate(
+    aggregate: &AggregatedAttestations,
+    signatures: &AggregatedSignatures,
+    state: &State,
+) -> Result<(), String> {
+    let attestations = aggregate.to_attestations();
+    if attestations.len() as u64 != signatures.len_u64() {
+        return Err(format!(
+            "Number of signatures ({}) does not match number of participants ({})",
+            signatures.len_u64(),
+            attestations.len()
+        ));
+    }
+
+    for (attestation, signature) in attestations.iter().zip(signatures) {
+        attestation.verify_signature(signature, state)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(validator_id: u64, slot: u64) -> SignedAttestation {
+        SignedAttestation {
+            message: Attestation {
+                validator_id: Uint64(validator_id),
+                data: AttestationData {
+                    slot: Slot(slot),
+                    ..AttestationData::default()
+                },
+            },
+            signature: Signature::default(),
+        }
+    }
+
+    #[test]
+    fn aggregate_groups_by_data() {
+        let attestations = [signed(3, 1), signed(0, 2), signed(1, 1), signed(3, 1)];
+
+        let aggregates = SignedAggregatedAttestations::aggregate(&attestations).unwrap();
+
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!(aggregates[0].message.data.slot, Slot(1));
+        assert_eq!(
+            aggregates[0].message.validator_ids().collect::<Vec<_>>(),
+            vec![Uint64(1), Uint64(3)]
+        );
+        assert_eq!(aggregates[0].signature.len_u64(), 2);
+        assert_eq!(aggregates[1].message.data.slot, Slot(2));
+        assert_eq!(
+            aggregates[1].message.validator_ids().collect::<Vec<_>>(),
+            vec![Uint64(0)]
+        );
+    }
+
+    #[test]
+    fn aggregate_round_trips_to_attestations() {
+        let attestations = [signed(2, 1), signed(0, 1)];
+
+        let aggregates = SignedAggregatedAttestations::aggregate(&attestations).unwrap();
+        let expanded = aggregates[0].to_signed_attestations();
+
+        assert_eq!(expanded, vec![signed(0, 1), signed(2, 1)]);
+    }
+
+    #[test]
+    fn one_aggregator_per_subnet_and_slot() {
+        let num_validators = ATTESTATION_SUBNET_COUNT * 2 + 3;
+
+        for slot in 0..4 {
+            let mut aggregators = vec![0; ATTESTATION_SUBNET_COUNT as usize];
+            for validator_id in 0..num_validators {
+                if is_aggregator(validator_id, Slot(slot), num_validators) {
+                    aggregators[compute_subnet_id(validator_id) as usize] += 1;
+                }
+            }
+            assert!(aggregators.iter().all(|&count| count == 1));
+        }
+
+        assert!(!is_aggregator(num_validators, Slot(0), num_validators));
+    }
+}
@@ -1,11 +1,40 @@
+use crate::attestation::verify_aggregate;
 use crate::{
-    Attestation, Attestations, BlockSignatures, Bytes32, Signature, Slot, State, ValidatorIndex,
+    AggregatedAttestationList, AggregatedAttestationSignatures, Attestation, Attestations,
+    BlockSignatures, Bytes32, Signature, SignedAggregatedAttestations, SignedAttestation, Slot,
+    State, StateTransitionError, ValidatorIndex,
 };
 use serde::{Deserialize, Serialize};
+use ssz::{SszReadDefault, SszWrite};
 use ssz_derive::Ssz;
 
-#[cfg(feature = "xmss-verify")]
-use leansig::signature::generalized_xmss::instantiations_poseidon::lifetime_2_to_the_20::target_sum::SIGTargetSumLifetime20W2NoOff;
+/// Block format. A devnet uses one version for every block it gossips, serves
+/// and stores; an upgrade to a new format starts a new fork.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockVersion {
+    /// One attestation and one signature per validator, see [`BlockBody`].
+    #[default]
+    V0,
+    /// Attestations with identical data are aggregated, see [`AggregatedBlockBody`].
+    V1,
+}
+
+impl BlockVersion {
+    /// Fork name in the gossip topics of the devnets using this version.
+    pub fn fork(self) -> &'static str {
+        match self {
+            Self::V0 => "devnet0",
+            Self::V1 => "devnet1",
+        }
+    }
+
+    pub fn from_fork(fork: &str) -> Option<Self> {
+        [Self::V0, Self::V1]
+            .into_iter()
+            .find(|version| version.fork() == fork)
+    }
+}
 
 /// The body of a block, containing payload data.
 ///
@@ -60,6 +89,60 @@ pub struct SignedBlockWithAttestation {
     pub signature: BlockSignatures,
 }
 
+/// Body of a [`BlockVersion::V1`] block.
+///
+/// Attestations with the same data are aggregated into one entry; their
+/// signatures are in [`AggregatedBlockSignatures`].
+#[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
+pub struct AggregatedBlockBody {
+    #[serde(with = "crate::serde_helpers")]
+    pub attestations: AggregatedAttestationList,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatedBlock {
+    pub slot: Slot,
+    pub proposer_index: ValidatorIndex,
+    pub parent_root: Bytes32,
+    pub state_root: Bytes32,
+    pub body: AggregatedBlockBody,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatedBlockWithAttestation {
+    pub block: AggregatedBlock,
+    pub proposer_attestation: Attestation,
+}
+
+/// Signatures of a [`BlockVersion::V1`] block.
+#[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatedBlockSignatures {
+    /// Signatures of each body aggregate, in body order.
+    #[serde(with = "crate::serde_helpers")]
+    pub attestation_signatures: AggregatedAttestationSignatures,
+    pub proposer_signature: Signature,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedAggregatedBlockWithAttestation {
+    pub message: AggregatedBlockWithAttestation,
+    pub signature: AggregatedBlockSignatures,
+}
+
+/// A signed block in any [`BlockVersion`].
+///
+/// Serializes to JSON as the inner block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VersionedSignedBlock {
+    V0(SignedBlockWithAttestation),
+    V1(SignedAggregatedBlockWithAttestation),
+}
+
 /// Legacy signed block structure (kept for backwards compatibility).
 #[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
 pub struct SignedBlock {
@@ -73,6 +156,30 @@ pub fn hash_tree_root<T: ssz::SszHash>(value: &T) -> Bytes32 {
     Bytes32(h)
 }
 
+impl Block {
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            slot: self.slot,
+            proposer_index: self.proposer_index,
+            parent_root: self.parent_root,
+            state_root: self.state_root,
+            body_root: hash_tree_root(&self.body),
+        }
+    }
+}
+
+impl AggregatedBlock {
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            slot: self.slot,
+            proposer_index: self.proposer_index,
+            parent_root: self.parent_root,
+            state_root: self.state_root,
+            body_root: hash_tree_root(&self.body),
+        }
+    }
+}
+
 impl SignedBlockWithAttestation {
     /// Verify all XMSS signatures in this signed block.
     ///
@@ -83,169 +190,231 @@ impl SignedBlockWithAttestation {
     ///
     /// # XMSS Verification
     ///
-    /// ## Without feature flag (default):
-    /// The function performs structural validation only:
+    /// The `xmss-verify` feature enables cryptographic XMSS signature verification
+    /// using the leanSig library. The node binary builds with it by default and
+    /// refuses to start with signature verification on if it was built without it.
+    ///
+    /// Without the feature, e.g. when `containers` is built on its own, only the
+    /// structure is checked:
     /// - Verifies signature count matches attestation count
     /// - Validates validator indices are within bounds
-    /// - Prepares all data for verification
-    ///
-    /// ## With `xmss-verify` feature flag:
-    /// Enables cryptographic XMSS signature verification using the leanSig library.
-    ///
-    /// To enable: `cargo build --features xmss-verify`
     ///
     /// # Arguments
     ///
     /// * `parent_state` - The state at the parent block, used to retrieve
     ///   validator public keys and verify signatures.
     ///
-    /// # Returns
-    ///
-    /// `true` if all signatures are cryptographically valid (or verification is disabled).
+    /// # Errors
     ///
-    /// # Panics
-    ///
-    /// Panics if validation fails:
+    /// Returns [`StateTransitionError::InvalidSignatures`] describing the first failure:
     /// - Signature count mismatch
     /// - Validator index out of range
-    /// - XMSS signature verification failure (when feature enabled)
+    /// - Malformed public key or signature, or a signature that does not verify
+    ///   (when feature enabled)
     ///
     /// # References
     ///
     /// - Spec: <https://github.com/leanEthereum/leanSpec/blob/main/src/lean_spec/subspecs/containers/block/block.py#L35>
     /// - XMSS Library: <https://github.com/leanEthereum/leanSig>
-    pub fn verify_signatures(&self, parent_state: State) -> bool {
-        // Unpack the signed block components
+    pub fn verify_signatures(&self, parent_state: &State) -> Result<(), StateTransitionError> {
         let block = &self.message.block;
         let signatures = &self.signature;
 
-        // Combine all attestations that need verification
+        // Each attestation must have exactly one corresponding signature.
         //
-        // This creates a single list containing both:
-        // 1. Block body attestations (from other validators)
-        // 2. Proposer attestation (from the block producer)
-        let mut all_attestations: Vec<Attestation> = Vec::new();
-
-        // Collect block body attestations
-        let mut i: u64 = 0;
-        loop {
-            match block.body.attestations.get(i) {
-                Ok(a) => all_attestations.push(a.clone()),
-                Err(_) => break,
-            }
-            i += 1;
+        // The ordering must be preserved:
+        // 1. Block body attestations,
+        // 2. The proposer attestation.
+        let num_attestations = block.body.attestations.len_u64() + 1;
+        if signatures.len_u64() != num_attestations {
+            return Err(StateTransitionError::InvalidSignatures(format!(
+                "Number of signatures ({}) does not match number of attestations ({})",
+                signatures.len_u64(),
+                num_attestations
+            )));
         }
 
-        // Append proposer attestation
-        all_attestations.push(self.message.proposer_attestation.clone());
+        let all_attestations = (&block.body.attestations)
+            .into_iter()
+            .chain(std::iter::once(&self.message.proposer_attestation));
 
-        // Collect signatures into a Vec
-        let mut signatures_vec: Vec<Signature> = Vec::new();
-        let mut j: u64 = 0;
-        loop {
-            match signatures.get(j) {
-                Ok(s) => signatures_vec.push(s.clone()),
-                Err(_) => break,
-            }
-            j += 1;
+        for (attestation, signature) in all_attestations.zip(signatures) {
+            attestation
+                .verify_signature(signature, parent_state)
+                .map_err(StateTransitionError::InvalidSignatures)?;
         }
 
-        // Verify signature count matches attestation count
-        //
-        // Each attestation must have exactly one corresponding signature.
-        //
-        // The ordering must be preserved:
-        // 1. Block body attestations,
-        // 2. The proposer attestation.
-        assert!(
-            signatures_vec.len() == all_attestations.len(),
-            "Number of signatures does not match number of attestations"
-        );
-
-        let validators = &parent_state.validators;
-        let num_validators: u64 = validators.len_u64();
-
-        // Verify each attestation signature
-        for (attestation, signature) in all_attestations.iter().zip(signatures_vec.iter()) {
-            // Ensure validator exists in the active set
-            assert!(
-                attestation.validator_id.0 < num_validators,
-                "Validator index out of range"
-            );
-
-            let validator = validators
-                .get(attestation.validator_id.0)
-                .expect("validator must exist");
-
-            // Verify the XMSS signature
-            //
-            // This cryptographically proves that:
-            // - The validator possesses the secret key for their public key
-            // - The attestation has not been tampered with
-            // - The signature was created at the correct epoch (slot)
-
-            #[cfg(feature = "xmss-verify")]
-            {
-                use leansig::serialization::Serializable;
-                use leansig::signature::SignatureScheme;
-
-                // Compute the message hash from the attestation
-                let message_bytes: [u8; 32] = hash_tree_root(attestation).0.into();
-                let epoch = attestation.data.slot.0 as u32;
-
-                // Get public key bytes - use as_bytes() method
-                let pubkey_bytes = validator.pubkey.0.as_bytes();
-
-                // Deserialize the public key using Serializable trait
-                type PubKey = <SIGTargetSumLifetime20W2NoOff as SignatureScheme>::PublicKey;
-                let pubkey = match PubKey::from_bytes(pubkey_bytes) {
-                    Ok(pk) => pk,
-                    Err(e) => {
-                        eprintln!(
-                            "Failed to deserialize public key at slot {:?}: {:?}",
-                            attestation.data.slot, e
-                        );
-                        return false;
-                    }
-                };
-
-                // Get signature bytes - use as_bytes() method
-                let sig_bytes = signature.as_bytes();
-
-                // Deserialize the signature using Serializable trait
-                type Sig = <SIGTargetSumLifetime20W2NoOff as SignatureScheme>::Signature;
-                let sig = match Sig::from_bytes(sig_bytes) {
-                    Ok(s) => s,
-                    Err(e) => {
-                        eprintln!(
-                            "Failed to deserialize signature at slot {:?}: {:?}",
-                            attestation.data.slot, e
-                        );
-                        return false;
-                    }
-                };
-
-                // Verify the signature
-                if !SIGTargetSumLifetime20W2NoOff::verify(&pubkey, epoch, &message_bytes, &sig) {
-                    eprintln!(
-                        "XMSS signature verification failed at slot {:?}",
-                        attestation.data.slot
-                    );
-                    return false;
-                }
-            }
+        Ok(())
+    }
+}
+
+impl SignedAggregatedBlockWithAttestation {
+    /// Verify all XMSS signatures in this signed block.
+    ///
+    /// Every aggregate must carry one signature per participant, in validator
+    /// order, followed by the proposer's signature over its attestation. See
+    /// [`SignedBlockWithAttestation::verify_signatures`] for the feature flags.
+    pub fn verify_signatures(&self, parent_state: &State) -> Result<(), StateTransitionError> {
+        let aggregates = &self.message.block.body.attestations;
+        let signatures = &self.signature.attestation_signatures;
+
+        if signatures.len_u64() != aggregates.len_u64() {
+            return Err(StateTransitionError::InvalidSignatures(format!(
+                "Number of signature lists ({}) does not match number of aggregated attestations ({})",
+                signatures.len_u64(),
+                aggregates.len_u64()
+            )));
+        }
+
+        for (aggregate, aggregate_signatures) in aggregates.into_iter().zip(signatures) {
+            verify_aggregate(aggregate, aggregate_signatures, parent_state)
+                .map_err(StateTransitionError::InvalidSignatures)?;
+        }
+
+        self.message
+            .proposer_attestation
+            .verify_signature(&self.signature.proposer_signature, parent_state)
+            .map_err(StateTransitionError::InvalidSignatures)
+    }
+
+    /// Body aggregates paired with their signatures.
+    pub fn signed_aggregates(&self) -> Vec<SignedAggregatedAttestations> {
+        (&self.message.block.body.attestations)
+            .into_iter()
+            .zip(&self.signature.attestation_signatures)
+            .map(|(message, signature)| SignedAggregatedAttestations {
+                message: message.clone(),
+                signature: signature.clone(),
+            })
+            .collect()
+    }
+}
+
+impl VersionedSignedBlock {
+    pub fn version(&self) -> BlockVersion {
+        match self {
+            Self::V0(_) => BlockVersion::V0,
+            Self::V1(_) => BlockVersion::V1,
+        }
+    }
+
+    pub fn slot(&self) -> Slot {
+        match self {
+            Self::V0(block) => block.message.block.slot,
+            Self::V1(block) => block.message.block.slot,
+        }
+    }
+
+    pub fn proposer_index(&self) -> ValidatorIndex {
+        match self {
+            Self::V0(block) => block.message.block.proposer_index,
+            Self::V1(block) => block.message.block.proposer_index,
+        }
+    }
+
+    pub fn parent_root(&self) -> Bytes32 {
+        match self {
+            Self::V0(block) => block.message.block.parent_root,
+            Self::V1(block) => block.message.block.parent_root,
+        }
+    }
 
-            #[cfg(not(feature = "xmss-verify"))]
-            {
-                // Placeholder: XMSS verification disabled
-                // To enable, compile with --features xmss-verify
-                let _pubkey = &validator.pubkey;
-                let _slot = attestation.data.slot;
-                let _message = hash_tree_root(attestation);
-                let _sig = signature;
+    pub fn state_root(&self) -> Bytes32 {
+        match self {
+            Self::V0(block) => block.message.block.state_root,
+            Self::V1(block) => block.message.block.state_root,
+        }
+    }
+
+    /// Hash tree root of the block, which identifies it.
+    pub fn block_root(&self) -> Bytes32 {
+        match self {
+            Self::V0(block) => hash_tree_root(&block.message.block),
+            Self::V1(block) => hash_tree_root(&block.message.block),
+        }
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        match self {
+            Self::V0(block) => block.message.block.header(),
+            Self::V1(block) => block.message.block.header(),
+        }
+    }
+
+    pub fn proposer_attestation(&self) -> &Attestation {
+        match self {
+            Self::V0(block) => &block.message.proposer_attestation,
+            Self::V1(block) => &block.message.proposer_attestation,
+        }
+    }
+
+    /// Body attestations with their signatures, one per validator. Aggregates
+    /// are expanded in validator order.
+    pub fn signed_attestations(&self) -> Vec<SignedAttestation> {
+        match self {
+            Self::V0(block) => (&block.message.block.body.attestations)
+                .into_iter()
+                .zip(&block.signature)
+                .map(|(message, signature)| SignedAttestation {
+                    message: message.clone(),
+                    signature: signature.clone(),
+                })
+                .collect(),
+            Self::V1(block) => block
+                .signed_aggregates()
+                .iter()
+                .flat_map(SignedAggregatedAttestations::to_signed_attestations)
+                .collect(),
+        }
+    }
+
+    /// The proposer's signature over its attestation, `None` if the block has none.
+    pub fn proposer_signature(&self) -> Option<Signature> {
+        match self {
+            Self::V0(block) => block
+                .signature
+                .get(block.message.block.body.attestations.len_u64())
+                .ok()
+                .cloned(),
+            Self::V1(block) => Some(block.signature.proposer_signature.clone()),
+        }
+    }
+
+    pub fn verify_signatures(&self, parent_state: &State) -> Result<(), StateTransitionError> {
+        match self {
+            Self::V0(block) => block.verify_signatures(parent_state),
+            Self::V1(block) => block.verify_signatures(parent_state),
+        }
+    }
+
+    pub fn to_ssz(&self) -> Result<Vec<u8>, String> {
+        match self {
+            Self::V0(block) => block.to_ssz(),
+            Self::V1(block) => block.to_ssz(),
+        }
+        .map_err(|e| format!("{:?}", e))
+    }
+
+    /// Decode a block of the given version.
+    pub fn from_ssz(version: BlockVersion, bytes: &[u8]) -> Result<Self, String> {
+        match version {
+            BlockVersion::V0 => SignedBlockWithAttestation::from_ssz_default(bytes).map(Self::V0),
+            BlockVersion::V1 => {
+                SignedAggregatedBlockWithAttestation::from_ssz_default(bytes).map(Self::V1)
             }
         }
+        .map_err(|e| format!("{:?}", e))
+    }
+}
+
+impl From<SignedBlockWithAttestation> for VersionedSignedBlock {
+    fn from(block: SignedBlockWithAttestation) -> Self {
+        Self::V0(block)
+    }
+}
 
-        true
+impl From<SignedAggregatedBlockWithAttestation> for VersionedSignedBlock {
+    fn from(block: SignedAggregatedBlockWithAttestation) -> Self {
+        Self::V1(block)
     }
 }
@@ -0,0 +1,10 @@
+use crate::Slot;
+use serde::{Deserialize, Serialize};
+use ssz_derive::Ssz;
+
+/// Canonical blocks in the slots `start_slot..start_slot + count`, oldest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
+pub struct BlocksByRangeRequest {
+    pub start_slot: Slot,
+    pub count: u64,
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use ssz_derive::Ssz;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter};
 use std::path::Path;
 
 #[derive(Clone, Debug, PartialEq, Eq, Ssz, Default, Serialize, Deserialize)]
@@ -26,4 +26,10 @@ impl GenesisConfig {
         let config = serde_yaml::from_reader(reader)?;
         Ok(config)
     }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let file = File::create(path)?;
+        serde_yaml::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,142 @@
+//! The XMSS signature scheme used for validator keys.
+//!
+//! Signing, verification and key (de)serialization all go through this module,
+//! so the validator and the import path always agree on the leansig
+//! instantiation. The scheme is only compiled in with the `xmss` feature;
+//! `xmss-verify` additionally turns on checking in [`verify`].
+
+use crate::{validator::BlsPublicKey, Signature};
+
+#[cfg(feature = "xmss")]
+use leansig::serialization::Serializable;
+#[cfg(feature = "xmss")]
+use leansig::signature::{SignatureScheme, SignatureSchemeSecretKey};
+
+/// The leansig instantiation backing lean consensus keys: 2^32 epochs,
+/// 52-byte public keys and 3112-byte signatures.
+#[cfg(feature = "xmss")]
+pub type Scheme = leansig::signature::generalized_xmss::instantiations_poseidon_top_level::lifetime_2_to_the_32::hashing_optimized::SIGTopLevelTargetSumLifetime32Dim64Base8;
+
+#[cfg(feature = "xmss")]
+pub type PublicKey = <Scheme as SignatureScheme>::PublicKey;
+#[cfg(feature = "xmss")]
+pub type SecretKey = <Scheme as SignatureScheme>::SecretKey;
+#[cfg(feature = "xmss")]
+pub type SchemeSignature = <Scheme as SignatureScheme>::Signature;
+
+#[cfg(feature = "xmss")]
+pub fn public_key_from_bytes(public_key: &BlsPublicKey) -> Result<PublicKey, String> {
+    PublicKey::from_bytes(public_key.0.as_bytes())
+        .map_err(|e| format!("Failed to deserialize public key: {:?}", e))
+}
+
+#[cfg(feature = "xmss")]
+pub fn public_key_to_bytes(public_key: &PublicKey) -> Result<BlsPublicKey, String> {
+    let bytes = public_key.to_bytes();
+    ssz::ByteVector::try_from(bytes.as_slice())
+        .map(BlsPublicKey)
+        .map_err(|_| format!("Invalid public key size: expected 52, got {}", bytes.len()))
+}
+
+#[cfg(feature = "xmss")]
+pub fn secret_key_from_bytes(bytes: &[u8]) -> Result<SecretKey, String> {
+    SecretKey::from_bytes(bytes).map_err(|e| format!("Failed to deserialize secret key: {:?}", e))
+}
+
+#[cfg(feature = "xmss")]
+pub fn signature_from_bytes(signature: &Signature) -> Result<SchemeSignature, String> {
+    SchemeSignature::from_bytes(signature.as_bytes())
+        .map_err(|e| format!("Failed to deserialize signature: {:?}", e))
+}
+
+#[cfg(feature = "xmss")]
+pub fn signature_to_bytes(signature: &SchemeSignature) -> Result<Signature, String> {
+    let bytes = signature.to_bytes();
+    Signature::try_from(bytes.as_slice())
+        .map_err(|_| format!("Invalid signature size: expected 3112, got {}", bytes.len()))
+}
+
+/// Generate a keypair that can sign at `num_active_epochs` epochs starting at
+/// `activation_epoch`. Returns the public key and the serialized secret key.
+#[cfg(feature = "xmss")]
+pub fn generate_keypair(
+    activation_epoch: u64,
+    num_active_epochs: u64,
+) -> Result<(BlsPublicKey, Vec<u8>), String> {
+    let activation_epoch = usize::try_from(activation_epoch)
+        .map_err(|_| format!("Activation epoch {activation_epoch} is too large"))?;
+    let num_active_epochs = usize::try_from(num_active_epochs)
+        .map_err(|_| format!("{num_active_epochs} active epochs is too many"))?;
+
+    let mut rng = rand::rng();
+    let (public_key, secret_key) = Scheme::key_gen(&mut rng, activation_epoch, num_active_epochs);
+
+    Ok((public_key_to_bytes(&public_key)?, secret_key.to_bytes()))
+}
+
+/// Epochs `secret_key` was generated for, as `(activation_epoch, num_active_epochs)`.
+#[cfg(feature = "xmss")]
+pub fn activation_interval(secret_key: &SecretKey) -> (u64, u64) {
+    let interval = secret_key.get_activation_interval();
+    (interval.start, interval.end - interval.start)
+}
+
+/// Sign a 32-byte message (a hash tree root) for `epoch`.
+#[cfg(feature = "xmss")]
+pub fn sign(secret_key: &SecretKey, epoch: u32, message: &[u8; 32]) -> Result<Signature, String> {
+    let signature = Scheme::sign(secret_key, epoch, message)
+        .map_err(|e| format!("Failed to sign message: {:?}", e))?;
+
+    signature_to_bytes(&signature)
+}
+
+/// Verify `signature` over `message` at `epoch` against `public_key`.
+///
+/// Without the `xmss-verify` feature nothing is checked and every signature passes.
+pub fn verify(
+    public_key: &BlsPublicKey,
+    epoch: u32,
+    message: &[u8; 32],
+    signature: &Signature,
+) -> Result<(), String> {
+    #[cfg(feature = "xmss-verify")]
+    {
+        let public_key = public_key_from_bytes(public_key)?;
+        let signature = signature_from_bytes(signature)?;
+
+        if !Scheme::verify(&public_key, epoch, message, &signature) {
+            return Err(format!(
+                "XMSS signature verification failed at epoch {epoch}"
+            ));
+        }
+    }
+
+    #[cfg(not(feature = "xmss-verify"))]
+    {
+        let _ = (public_key, epoch, message, signature);
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "xmss-verify"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_own_signature_verifies() {
+        let mut rng = rand::rng();
+        let (public_key, secret_key) = Scheme::key_gen(&mut rng, 0, 2);
+        let public_key = public_key_to_bytes(&public_key).unwrap();
+
+        // Keys read back from disk must still sign
+        let secret_key = secret_key_from_bytes(&secret_key.to_bytes()).unwrap();
+
+        let message = [7u8; 32];
+        let signature = sign(&secret_key, 1, &message).unwrap();
+
+        assert_eq!(verify(&public_key, 1, &message, &signature), Ok(()));
+        assert!(verify(&public_key, 0, &message, &signature).is_err());
+        assert!(verify(&public_key, 1, &[8u8; 32], &signature).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{Root, Slot};
+use std::fmt;
+
+/// Why a block could not be applied to a state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateTransitionError {
+    /// The block's signatures are missing, malformed or do not verify.
+    InvalidSignatures(String),
+    /// Slots can only be processed forwards.
+    SlotNotInFuture {
+        state_slot: Slot,
+        target_slot: Slot,
+    },
+    /// The block is not for the slot the state was advanced to.
+    SlotMismatch {
+        block_slot: Slot,
+        state_slot: Slot,
+    },
+    /// The block is not newer than the latest block header.
+    BlockNotNewer {
+        block_slot: Slot,
+        header_slot: Slot,
+    },
+    WrongProposer {
+        slot: Slot,
+        proposer_index: u64,
+    },
+    ParentRootMismatch {
+        expected: Root,
+        actual: Root,
+    },
+    StateRootMismatch {
+        expected: Root,
+        actual: Root,
+    },
+}
+
+impl fmt::Display for StateTransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSignatures(reason) => write!(f, "Invalid block signatures: {reason}"),
+            Self::SlotNotInFuture {
+                state_slot,
+                target_slot,
+            } => write!(
+                f,
+                "Target slot {} must be in the future of state slot {}",
+                target_slot.0, state_slot.0
+            ),
+            Self::SlotMismatch {
+                block_slot,
+                state_slot,
+            } => write!(
+                f,
+                "Block slot mismatch: block slot {}, state slot {}",
+                block_slot.0, state_slot.0
+            ),
+            Self::BlockNotNewer {
+                block_slot,
+                header_slot,
+            } => write!(
+                f,
+                "Block is older than latest header: block slot {}, header slot {}",
+                block_slot.0, header_slot.0
+            ),
+            Self::WrongProposer {
+                slot,
+                proposer_index,
+            } => write!(
+                f,
+                "Incorrect block proposer: validator {proposer_index} is not the proposer for slot {}",
+                slot.0
+            ),
+            Self::ParentRootMismatch { expected, actual } => write!(
+                f,
+                "Block parent root mismatch: expected 0x{expected}, got 0x{actual}"
+            ),
+            Self::StateRootMismatch { expected, actual } => write!(
+                f,
+                "Invalid block state root: expected 0x{expected}, got 0x{actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateTransitionError {}
@@ -1,7 +1,10 @@
 pub mod attestation;
 pub mod block;
+pub mod blocks_by_range;
 pub mod checkpoint;
 pub mod config;
+pub mod crypto;
+pub mod error;
 pub mod serde_helpers;
 pub mod slot;
 pub mod state;
@@ -10,14 +13,21 @@ pub mod types;
 pub mod validator;
 
 pub use attestation::{
-    AggregatedAttestations, AggregatedSignatures, AggregationBits, Attestation, AttestationData,
-    Attestations, BlockSignatures, Signature, SignedAggregatedAttestations, SignedAttestation,
+    AggregatedAttestationList, AggregatedAttestationSignatures, AggregatedAttestations,
+    AggregatedSignatures, AggregationBits, Attestation, AttestationData, Attestations,
+    BlockSignatures, Signature, SignedAggregatedAttestations, SignedAttestation,
+    ATTESTATION_SUBNET_COUNT,
 };
 pub use block::{
-    Block, BlockBody, BlockHeader, BlockWithAttestation, SignedBlock, SignedBlockWithAttestation,
+    AggregatedBlock, AggregatedBlockBody, AggregatedBlockSignatures,
+    AggregatedBlockWithAttestation, Block, BlockBody, BlockHeader, BlockVersion,
+    BlockWithAttestation, SignedAggregatedBlockWithAttestation, SignedBlock,
+    SignedBlockWithAttestation, VersionedSignedBlock,
 };
+pub use blocks_by_range::BlocksByRangeRequest;
 pub use checkpoint::Checkpoint;
 pub use config::{Config, GenesisConfig};
+pub use error::StateTransitionError;
 pub use slot::Slot;
 pub use state::State;
 pub use status::Status;
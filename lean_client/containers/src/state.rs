@@ -1,7 +1,12 @@
 use crate::validator::Validator;
 use crate::{
-    block::{hash_tree_root, Block, BlockBody, BlockHeader, SignedBlockWithAttestation},
-    Attestation, Attestations, BlockSignatures, Bytes32, Checkpoint, Config, Slot, Uint64,
+    block::{
+        hash_tree_root, AggregatedBlock, AggregatedBlockBody, Block, BlockBody, BlockHeader,
+        SignedBlockWithAttestation, VersionedSignedBlock,
+    },
+    AggregatedAttestationList, AggregatedAttestationSignatures, AggregatedAttestations,
+    Attestation, Attestations, BlockSignatures, Bytes32, Checkpoint, Config,
+    SignedAggregatedAttestations, SignedAttestation, Slot, StateTransitionError, Uint64,
     ValidatorIndex,
 };
 use crate::{
@@ -138,8 +143,14 @@ impl State {
 
     /// Simple RR proposer rule (round-robin).
     pub fn is_proposer(&self, index: ValidatorIndex) -> bool {
+        self.is_proposer_at(self.slot, index)
+    }
+
+    /// Round-robin proposer rule for an arbitrary `slot`, e.g. a child block's slot
+    /// checked against its parent state.
+    pub fn is_proposer_at(&self, slot: Slot, index: ValidatorIndex) -> bool {
         let num_validators: u64 = self.validators.len_u64();
-        (self.slot.0 % num_validators) == (index.0 % num_validators)
+        (slot.0 % num_validators) == (index.0 % num_validators)
     }
 
     pub fn get_justifications(&self) -> BTreeMap<Bytes32, Vec<bool>> {
@@ -214,41 +225,54 @@ impl State {
     // updated for fork choice tests
     pub fn state_transition(
         &self,
-        signed_block: SignedBlockWithAttestation,
+        signed_block: impl Into<VersionedSignedBlock>,
         valid_signatures: bool,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, StateTransitionError> {
         self.state_transition_with_validation(signed_block, valid_signatures, true)
     }
 
     // updated for fork choice tests
     pub fn state_transition_with_validation(
         &self,
-        signed_block: SignedBlockWithAttestation,
+        signed_block: impl Into<VersionedSignedBlock>,
         valid_signatures: bool,
         validate_state_root: bool,
-    ) -> Result<Self, String> {
+    ) -> Result<Self, StateTransitionError> {
         if !valid_signatures {
-            return Err("Block signatures must be valid".to_string());
+            return Err(StateTransitionError::InvalidSignatures(
+                "block signatures must be valid".to_string(),
+            ));
         }
 
-        let block = &signed_block.message.block;
-        let mut state = self.process_slots(block.slot)?;
-        state = state.process_block(block)?;
+        let signed_block = signed_block.into();
+        let mut state = self.process_slots(signed_block.slot())?;
+        state = match &signed_block {
+            VersionedSignedBlock::V0(block) => state.process_block(&block.message.block)?,
+            VersionedSignedBlock::V1(block) => {
+                state.process_aggregated_block(&block.message.block)?
+            }
+        };
 
         if validate_state_root {
             let state_for_hash = state.clone();
             let state_root = hash_tree_root(&state_for_hash);
-            if block.state_root != state_root {
-                return Err("Invalid block state root".to_string());
+            if signed_block.state_root() != state_root {
+                return Err(StateTransitionError::StateRootMismatch {
+                    expected: state_root,
+                    actual: signed_block.state_root(),
+                });
             }
         }
 
         Ok(state)
     }
 
-    pub fn process_slots(&self, target_slot: Slot) -> Result<Self, String> {
+    pub fn process_slots(&self, target_slot: Slot) -> Result<Self, StateTransitionError> {
         if self.slot >= target_slot {
-            return Err("Target slot must be in the future".to_string());
+            return Err(StateTransitionError::SlotNotInFuture {
+                state_slot: self.slot,
+                target_slot,
+            });
         }
 
         let mut state = self.clone();
@@ -279,7 +303,7 @@ impl State {
         self.clone()
     }
 
-    pub fn process_block(&self, block: &Block) -> Result<Self, String> {
+    pub fn process_block(&self, block: &Block) -> Result<Self, StateTransitionError> {
         let state = self.process_block_header(block)?;
         let state_after_ops = state.process_attestations(&block.body.attestations);
 
@@ -288,22 +312,48 @@ impl State {
         Ok(state_after_ops)
     }
 
-    pub fn process_block_header(&self, block: &Block) -> Result<Self, String> {
+    /// Apply a [`crate::BlockVersion::V1`] block: the header checks are those of
+    /// [`Self::process_block`], and every participant of an aggregate votes.
+    pub fn process_aggregated_block(
+        &self,
+        block: &AggregatedBlock,
+    ) -> Result<Self, StateTransitionError> {
+        let state = self.process_header(&block.header())?;
+        Ok(state.process_aggregated_attestations(&block.body.attestations))
+    }
+
+    pub fn process_block_header(&self, block: &Block) -> Result<Self, StateTransitionError> {
+        self.process_header(&block.header())
+    }
+
+    fn process_header(&self, block: &BlockHeader) -> Result<Self, StateTransitionError> {
         if !(block.slot == self.slot) {
-            return Err(String::from("Block slot mismatch"));
+            return Err(StateTransitionError::SlotMismatch {
+                block_slot: block.slot,
+                state_slot: self.slot,
+            });
         }
         if !(block.slot > self.latest_block_header.slot) {
-            return Err(String::from("Block is older than latest header"));
+            return Err(StateTransitionError::BlockNotNewer {
+                block_slot: block.slot,
+                header_slot: self.latest_block_header.slot,
+            });
         }
         if !self.is_proposer(block.proposer_index) {
-            return Err(String::from("Incorrect block proposer"));
+            return Err(StateTransitionError::WrongProposer {
+                slot: block.slot,
+                proposer_index: block.proposer_index.0,
+            });
         }
 
         // Create a mutable clone for hash computation
         let latest_header_for_hash = self.latest_block_header.clone();
         let parent_root = hash_tree_root(&latest_header_for_hash);
         if block.parent_root != parent_root {
-            return Err(String::from("Block parent root mismatch"));
+            return Err(StateTransitionError::ParentRootMismatch {
+                expected: parent_root,
+                actual: block.parent_root,
+            });
         }
 
         // Build new PersistentList for historical hashes
@@ -342,14 +392,11 @@ impl State {
                 .expect("within limit");
         }
 
-        let body_for_hash = block.body.clone();
-        let body_root = hash_tree_root(&body_for_hash);
-
         let new_latest_block_header = BlockHeader {
             slot: block.slot,
             proposer_index: block.proposer_index,
             parent_root: block.parent_root,
-            body_root,
+            body_root: block.body_root,
             state_root: Bytes32(ssz::H256::zero()),
         };
 
@@ -376,13 +423,6 @@ impl State {
     }
 
     pub fn process_attestations(&self, attestations: &Attestations) -> Self {
-        let mut justifications = self.get_justifications();
-        let mut latest_justified = self.latest_justified.clone();
-        let mut latest_finalized = self.latest_finalized.clone();
-        // Store initial finalized slot for justifiability checks (per leanSpec)
-        let initial_finalized_slot = self.latest_finalized.slot;
-        let justified_slots = self.justified_slots.clone();
-
         // PersistentList doesn't expose iter; convert to Vec for simple iteration for now
         // Build a temporary Vec by probing sequentially until index error
         let mut votes_vec: Vec<Attestation> = Vec::new();
@@ -395,6 +435,30 @@ impl State {
             i += 1;
         }
 
+        self.process_votes(&votes_vec)
+    }
+
+    /// Count the vote of every participant of each aggregate, in block order.
+    pub fn process_aggregated_attestations(
+        &self,
+        attestations: &AggregatedAttestationList,
+    ) -> Self {
+        let votes_vec: Vec<Attestation> = attestations
+            .into_iter()
+            .flat_map(AggregatedAttestations::to_attestations)
+            .collect();
+
+        self.process_votes(&votes_vec)
+    }
+
+    fn process_votes(&self, votes_vec: &[Attestation]) -> Self {
+        let mut justifications = self.get_justifications();
+        let mut latest_justified = self.latest_justified.clone();
+        let mut latest_finalized = self.latest_finalized.clone();
+        // Store initial finalized slot for justifiability checks (per leanSpec)
+        let initial_finalized_slot = self.latest_finalized.slot;
+        let justified_slots = self.justified_slots.clone();
+
         // Create mutable working BitList for justified_slots tracking
         let mut justified_slots_working = Vec::new();
         for i in 0..justified_slots.len() {
@@ -546,7 +610,7 @@ impl State {
         // Note: parent_root comes from fork choice and is already validated.
         // We cannot validate it against the header hash here because process_slots()
         // caches the state root in the header, changing its hash.
-        let pre_state = self.process_slots(slot)?;
+        let pre_state = self.process_slots(slot).map_err(|e| e.to_string())?;
 
         // Iteratively collect valid attestations using fixed-point algorithm
         //
@@ -572,7 +636,9 @@ impl State {
             };
 
             // Apply state transition to get the post-block state
-            let post_state = pre_state.process_block(&candidate_block)?;
+            let post_state = pre_state
+                .process_block(&candidate_block)
+                .map_err(|e| e.to_string())?;
 
             // No attestation source provided: done after computing post_state
             if available_signed_attestations.is_none() || known_block_roots.is_none() {
@@ -649,6 +715,50 @@ impl State {
             }
         }
     }
+
+    /// Build a [`crate::BlockVersion::V1`] block on top of this state.
+    ///
+    /// Attestations with identical data are aggregated, in the order their data
+    /// first appears in `attestations`. Unlike [`Self::build_block`] no further
+    /// attestations are collected, so the caller passes the ones to include.
+    ///
+    /// # Returns
+    ///
+    /// Tuple of (Block, post-State, signatures of each aggregate in body order)
+    pub fn build_aggregated_block<'a>(
+        &self,
+        slot: Slot,
+        proposer_index: ValidatorIndex,
+        parent_root: Bytes32,
+        attestations: impl IntoIterator<Item = &'a SignedAttestation>,
+    ) -> Result<(AggregatedBlock, Self, AggregatedAttestationSignatures), String> {
+        let pre_state = self.process_slots(slot).map_err(|e| e.to_string())?;
+
+        let mut body = AggregatedBlockBody::default();
+        let mut signatures = AggregatedAttestationSignatures::default();
+        for aggregate in SignedAggregatedAttestations::aggregate(attestations)? {
+            body.attestations
+                .push(aggregate.message)
+                .map_err(|e| format!("Failed to push attestation: {:?}", e))?;
+            signatures
+                .push(aggregate.signature)
+                .map_err(|e| format!("Failed to push signatures: {:?}", e))?;
+        }
+
+        let mut block = AggregatedBlock {
+            slot,
+            proposer_index,
+            parent_root,
+            state_root: Bytes32(ssz::H256::zero()),
+            body,
+        };
+        let post_state = pre_state
+            .process_aggregated_block(&block)
+            .map_err(|e| e.to_string())?;
+        block.state_root = hash_tree_root(&post_state);
+
+        Ok((block, post_state, signatures))
+    }
 }
 
 #[cfg(test)]
@@ -839,6 +949,67 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_build_aggregated_block() {
+        let genesis_state = State::generate_genesis(Uint64(0), Uint64(4));
+        let pre_state = genesis_state.process_slots(Slot(1)).unwrap();
+        let parent_root = hash_tree_root(&pre_state.latest_block_header);
+
+        let data = crate::AttestationData {
+            slot: Slot(1),
+            head: Checkpoint {
+                root: parent_root,
+                slot: Slot(0),
+            },
+            target: Checkpoint {
+                root: parent_root,
+                slot: Slot(1),
+            },
+            source: Checkpoint {
+                root: parent_root,
+                slot: Slot(0),
+            },
+        };
+        let attestations: Vec<SignedAttestation> = [2, 0]
+            .into_iter()
+            .map(|validator_id| SignedAttestation {
+                message: Attestation {
+                    validator_id: Uint64(validator_id),
+                    data: data.clone(),
+                },
+                signature: Default::default(),
+            })
+            .collect();
+
+        let (block, post_state, signatures) = genesis_state
+            .build_aggregated_block(Slot(1), ValidatorIndex(1), parent_root, &attestations)
+            .unwrap();
+
+        assert_eq!(block.body.attestations.len_u64(), 1);
+        let aggregate = block.body.attestations.get(0).unwrap();
+        assert_eq!(
+            aggregate.validator_ids().collect::<Vec<_>>(),
+            vec![Uint64(0), Uint64(2)]
+        );
+        assert_eq!(signatures.get(0).unwrap().len_u64(), 2);
+        assert_eq!(block.state_root, hash_tree_root(&post_state));
+
+        let signed_block = crate::SignedAggregatedBlockWithAttestation {
+            message: crate::AggregatedBlockWithAttestation {
+                block,
+                proposer_attestation: Attestation::default(),
+            },
+            signature: crate::AggregatedBlockSignatures {
+                attestation_signatures: signatures,
+                proposer_signature: Default::default(),
+            },
+        };
+        let transitioned = genesis_state
+            .state_transition_with_validation(signed_block, true, true)
+            .unwrap();
+        assert_eq!(transitioned, post_state);
+    }
+
     #[test]
     fn test_build_block_state_root_matches() {
         // Create genesis state
@@ -125,8 +125,8 @@ fn test_process_block_header_invalid(
     let result = state_at_slot_1.process_block_header(&block);
 
     assert!(result.is_err());
-    let err_msg = result.unwrap_err();
-    assert!(err_msg.contains(expected_error));
+    let err_msg = result.unwrap_err().to_string();
+    assert!(err_msg.contains(expected_error), "{err_msg}");
 }
 
 // This test verifies that attestations correctly justify and finalize slots
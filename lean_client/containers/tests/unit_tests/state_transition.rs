@@ -3,7 +3,7 @@ use containers::{
     block::{hash_tree_root, Block, BlockWithAttestation, SignedBlockWithAttestation},
     state::State,
     types::{Bytes32, Uint64},
-    Attestation, BlockSignatures, Slot,
+    Attestation, BlockSignatures, Slot, StateTransitionError,
 };
 use pretty_assertions::assert_eq;
 use rstest::fixture;
@@ -79,7 +79,10 @@ fn test_state_transition_invalid_signatures() {
 
     let result = state.state_transition(final_signed_block_with_attestation, false);
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err(), "Block signatures must be valid");
+    assert!(matches!(
+        result.unwrap_err(),
+        StateTransitionError::InvalidSignatures(_)
+    ));
 }
 
 #[test]
@@ -103,5 +106,8 @@ fn test_state_transition_bad_state_root() {
 
     let result = state.state_transition(final_signed_block_with_attestation, true);
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err(), "Invalid block state root");
+    assert!(matches!(
+        result.unwrap_err(),
+        StateTransitionError::StateRootMismatch { .. }
+    ));
 }
@@ -0,0 +1,160 @@
+use containers::{Root, Slot, StateTransitionError};
+use std::fmt;
+
+/// Why `on_block` did not import a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OnBlockError {
+    /// The parent is not imported yet. The block was queued and is imported
+    /// once the parent arrives; `pending` is the queue size afterwards.
+    UnknownParent { parent_root: Root, pending: usize },
+    /// The block is already queued for its parent. Importing a block that is
+    /// already in the store is not an error and returns `Ok`, as in the spec.
+    Duplicate(Root),
+    /// The block is at or before the finalized slot, so it cannot become canonical.
+    PreFinalized { slot: Slot, finalized_slot: Slot },
+    /// The parent block is known but its post-state is not.
+    MissingParentState(Root),
+    /// The block failed signature checks or the state transition.
+    Transition(StateTransitionError),
+    /// One of the block's attestations could not be applied.
+    Attestation(OnAttestationError),
+    /// The block could not be written to the attached storage.
+    Storage(String),
+}
+
+impl OnBlockError {
+    /// Metric label for a rejected block.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::UnknownParent { .. } => "unknown_parent",
+            Self::Duplicate(_) => "duplicate",
+            Self::PreFinalized { .. } => "pre_finalized",
+            Self::MissingParentState(_) => "missing_parent_state",
+            Self::Transition(StateTransitionError::InvalidSignatures(_)) => "invalid_signature",
+            Self::Transition(_) => "state_transition",
+            Self::Attestation(_) => "attestation",
+            Self::Storage(_) => "storage",
+        }
+    }
+}
+
+impl fmt::Display for OnBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownParent {
+                parent_root,
+                pending,
+            } => write!(
+                f,
+                "Block queued: parent 0x{parent_root} not yet available (pending: {pending} blocks)"
+            ),
+            Self::Duplicate(root) => write!(f, "Block 0x{root} is already queued"),
+            Self::PreFinalized {
+                slot,
+                finalized_slot,
+            } => write!(
+                f,
+                "Block slot {} is not after finalized slot {}",
+                slot.0, finalized_slot.0
+            ),
+            Self::MissingParentState(root) => write!(f, "No state for parent block 0x{root}"),
+            Self::Transition(err) => write!(f, "{err}"),
+            Self::Attestation(err) => write!(f, "Block attestation: {err}"),
+            Self::Storage(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for OnBlockError {}
+
+impl From<StateTransitionError> for OnBlockError {
+    fn from(err: StateTransitionError) -> Self {
+        Self::Transition(err)
+    }
+}
+
+impl From<OnAttestationError> for OnBlockError {
+    fn from(err: OnAttestationError) -> Self {
+        Self::Attestation(err)
+    }
+}
+
+/// Why `on_attestation` did not apply an attestation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OnAttestationError {
+    /// The attestation is for a slot our clock has not reached.
+    FutureSlot {
+        slot: Slot,
+        current_slot: Slot,
+    },
+    SourceAfterTarget {
+        source_slot: Slot,
+        target_slot: Slot,
+    },
+    UnknownHead(Root),
+    UnknownTarget(Root),
+    /// There is no head state to look up the validator's key in.
+    NoHeadState,
+    InvalidSignature(String),
+}
+
+impl fmt::Display for OnAttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FutureSlot { slot, current_slot } => write!(
+                f,
+                "Attestation for slot {} has not yet occurred, out of sync (current slot: {})",
+                slot.0, current_slot.0
+            ),
+            Self::SourceAfterTarget {
+                source_slot,
+                target_slot,
+            } => write!(
+                f,
+                "Source slot {} exceeds target slot {}",
+                source_slot.0, target_slot.0
+            ),
+            Self::UnknownHead(root) => write!(f, "Unknown head block 0x{root}"),
+            Self::UnknownTarget(root) => write!(f, "Unknown target block 0x{root}"),
+            Self::NoHeadState => write!(f, "No head state"),
+            Self::InvalidSignature(err) => write!(f, "Invalid signature: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for OnAttestationError {}
+
+/// Why `prune_finalized` did not finish pruning the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PruneError {
+    /// The finalized block is not in the store, which means the store is
+    /// inconsistent. Nothing was pruned.
+    MissingFinalizedBlock { root: Root, slot: Slot },
+    /// A pruned block or state could not be deleted from the attached storage.
+    Storage(String),
+}
+
+impl PruneError {
+    /// Metric label for a failed prune.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::MissingFinalizedBlock { .. } => "missing_finalized_block",
+            Self::Storage(_) => "storage",
+        }
+    }
+}
+
+impl fmt::Display for PruneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFinalizedBlock { root, slot } => write!(
+                f,
+                "Finalized block 0x{root} at slot {} is not in the store",
+                slot.0
+            ),
+            Self::Storage(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for PruneError {}
@@ -0,0 +1,40 @@
+use containers::{attestation::Attestation, checkpoint::Checkpoint, Root, Slot};
+use serde::Serialize;
+
+/// Something observable that happened to the store. Sent on `Store::events`
+/// when a sender is attached; nobody listening is not an error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum ChainEvent {
+    /// `update_head` moved the head. `depth_of_reorg` is the number of slots
+    /// between the previous head and the common ancestor, 0 when the new head
+    /// descends from the previous one.
+    Head {
+        slot: Slot,
+        block: Root,
+        previous_head: Root,
+        depth_of_reorg: u64,
+    },
+    /// A block was imported.
+    Block {
+        slot: Slot,
+        block: Root,
+    },
+    /// A gossip attestation was accepted.
+    Attestation(Attestation),
+    JustifiedCheckpoint(Checkpoint),
+    FinalizedCheckpoint(Checkpoint),
+}
+
+impl ChainEvent {
+    /// Event name used by subscribers to pick the events they want.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            Self::Head { .. } => "head",
+            Self::Block { .. } => "block",
+            Self::Attestation(_) => "attestation",
+            Self::JustifiedCheckpoint(_) => "justified_checkpoint",
+            Self::FinalizedCheckpoint(_) => "finalized_checkpoint",
+        }
+    }
+}
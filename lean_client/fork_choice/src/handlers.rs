@@ -1,8 +1,11 @@
+use crate::error::{OnAttestationError, OnBlockError};
+use crate::events::ChainEvent;
 use crate::store::*;
 use containers::{
-    attestation::SignedAttestation, block::SignedBlockWithAttestation, Bytes32, ValidatorIndex,
+    attestation::{AttestationData, SignedAggregatedAttestations, SignedAttestation},
+    block::VersionedSignedBlock,
+    Bytes32, Slot, ValidatorIndex,
 };
-use ssz::SszHash;
 
 #[inline]
 pub fn on_tick(store: &mut Store, time: u64, has_proposal: bool) {
@@ -19,33 +22,121 @@ pub fn on_tick(store: &mut Store, time: u64, has_proposal: bool) {
     }
 }
 
+/// Gossip attestations (`is_from_block = false`) have their signature checked
+/// against the head state, unless gossip validation already did
+/// (`signature_verified = true`); block attestations were checked with their
+/// block. The head and target blocks must be known.
 #[inline]
 pub fn on_attestation(
     store: &mut Store,
     signed_attestation: SignedAttestation,
     is_from_block: bool,
-) -> Result<(), String> {
-    let validator_id = ValidatorIndex(signed_attestation.message.validator_id.0);
-    let attestation_slot = signed_attestation.message.data.slot;
-    let source_slot = signed_attestation.message.data.source.slot;
-    let target_slot = signed_attestation.message.data.target.slot;
+    signature_verified: bool,
+) -> Result<(), OnAttestationError> {
+    check_known_blocks(store, &signed_attestation.message.data)?;
+
+    if store.verify_signatures && !is_from_block && !signature_verified {
+        let state = store
+            .states
+            .get(&store.head)
+            .ok_or(OnAttestationError::NoHeadState)?;
+        signed_attestation
+            .verify_signature(state)
+            .map_err(OnAttestationError::InvalidSignature)?;
+    }
+
+    let attestation = (!is_from_block).then(|| signed_attestation.message.clone());
+    process_attestation(store, signed_attestation, is_from_block)?;
+
+    if let Some(attestation) = attestation {
+        store.emit(ChainEvent::Attestation(attestation));
+    }
+    Ok(())
+}
+
+/// A gossip aggregate counts as one gossip attestation per participant. All
+/// signatures are checked against the head state before any vote is applied,
+/// unless gossip validation already did (`signatures_verified = true`).
+pub fn on_aggregated_attestation(
+    store: &mut Store,
+    signed_aggregate: SignedAggregatedAttestations,
+    signatures_verified: bool,
+) -> Result<(), OnAttestationError> {
+    check_known_blocks(store, &signed_aggregate.message.data)?;
+
+    if store.verify_signatures && !signatures_verified {
+        let state = store
+            .states
+            .get(&store.head)
+            .ok_or(OnAttestationError::NoHeadState)?;
+        signed_aggregate
+            .verify_signatures(state)
+            .map_err(OnAttestationError::InvalidSignature)?;
+    }
+
+    // Every participant shares the aggregate's data, so one check covers them all and
+    // the aggregate is applied either completely or not at all
+    check_attestation_slots(store, &signed_aggregate.message.data)?;
+    for signed_attestation in signed_aggregate.to_signed_attestations() {
+        let attestation = signed_attestation.message.clone();
+        apply_attestation(store, signed_attestation, false);
+        store.emit(ChainEvent::Attestation(attestation));
+    }
+    Ok(())
+}
+
+fn check_known_blocks(store: &Store, data: &AttestationData) -> Result<(), OnAttestationError> {
+    if !store.blocks.contains_key(&data.head.root) {
+        return Err(OnAttestationError::UnknownHead(data.head.root));
+    }
+    if !store.blocks.contains_key(&data.target.root) {
+        return Err(OnAttestationError::UnknownTarget(data.target.root));
+    }
+    Ok(())
+}
+
+/// Apply an attestation whose signature is already known to be valid.
+fn process_attestation(
+    store: &mut Store,
+    signed_attestation: SignedAttestation,
+    is_from_block: bool,
+) -> Result<(), OnAttestationError> {
+    check_attestation_slots(store, &signed_attestation.message.data)?;
+    apply_attestation(store, signed_attestation, is_from_block);
+    Ok(())
+}
 
+fn check_attestation_slots(
+    store: &Store,
+    data: &AttestationData,
+) -> Result<(), OnAttestationError> {
     // Validate attestation is not from future
     let curr_slot = store.time / INTERVALS_PER_SLOT;
-    if attestation_slot.0 > curr_slot {
-        return Err(format!(
-            "Err: (Fork-choice::Handlers::OnAttestation) Attestation for slot {} has not yet occurred, out of sync. (CURRENT SLOT NUMBER: {})",
-            attestation_slot.0, curr_slot
-        ));
+    if data.slot.0 > curr_slot {
+        return Err(OnAttestationError::FutureSlot {
+            slot: data.slot,
+            current_slot: Slot(curr_slot),
+        });
     }
 
     // Validate source slot does not exceed target slot (per leanSpec validate_attestation)
-    if source_slot > target_slot {
-        return Err(format!(
-            "Err: (Fork-choice::Handlers::OnAttestation) Source slot {} exceeds target slot {}",
-            source_slot.0, target_slot.0
-        ));
+    if data.source.slot > data.target.slot {
+        return Err(OnAttestationError::SourceAfterTarget {
+            source_slot: data.source.slot,
+            target_slot: data.target.slot,
+        });
     }
+    Ok(())
+}
+
+/// Record the vote of an attestation that passed `check_attestation_slots`.
+fn apply_attestation(
+    store: &mut Store,
+    signed_attestation: SignedAttestation,
+    is_from_block: bool,
+) {
+    let validator_id = ValidatorIndex(signed_attestation.message.validator_id.0);
+    let attestation_slot = signed_attestation.message.data.slot;
 
     if is_from_block {
         // On-chain attestation processing - immediately becomes "known"
@@ -81,32 +172,49 @@ pub fn on_attestation(
                 .insert(validator_id, signed_attestation);
         }
     }
-    Ok(())
 }
 
-pub fn on_block(store: &mut Store, signed_block: SignedBlockWithAttestation) -> Result<(), String> {
-    let block_root = Bytes32(signed_block.message.block.hash_tree_root());
+/// `signatures_verified` skips the signature check of a block that gossip
+/// validation already checked against its parent state. Queued descendants it
+/// unblocks are always checked.
+pub fn on_block(
+    store: &mut Store,
+    signed_block: impl Into<VersionedSignedBlock>,
+    signatures_verified: bool,
+) -> Result<(), OnBlockError> {
+    let signed_block = signed_block.into();
+    let block_root = signed_block.block_root();
 
     if store.blocks.contains_key(&block_root) {
         return Ok(());
     }
 
-    let parent_root = signed_block.message.block.parent_root;
+    let slot = signed_block.slot();
+    if slot <= store.latest_finalized.slot {
+        return Err(rejected(OnBlockError::PreFinalized {
+            slot,
+            finalized_slot: store.latest_finalized.slot,
+        }));
+    }
+
+    let parent_root = signed_block.parent_root();
 
     if !store.states.contains_key(&parent_root) && !parent_root.0.is_zero() {
-        store
-            .blocks_queue
-            .entry(parent_root)
-            .or_insert_with(Vec::new)
-            .push(signed_block);
-        return Err(format!(
-            "Err: (Fork-choice::Handlers::OnBlock) Block queued: parent {:?} not yet available (pending: {} blocks)",
-            &parent_root.0.as_bytes()[..4],
-            store.blocks_queue.values().map(|v| v.len()).sum::<usize>()
-        ));
+        let queued = store.blocks_queue.entry(parent_root).or_default();
+        if queued.contains(&signed_block) {
+            return Err(OnBlockError::Duplicate(block_root));
+        }
+        queued.push(signed_block);
+        metrics::BLOCKS_QUEUED.inc();
+        return Err(OnBlockError::UnknownParent {
+            parent_root,
+            pending: store.blocks_queue.values().map(|v| v.len()).sum::<usize>(),
+        });
     }
 
-    process_block_internal(store, signed_block, block_root)?;
+    let _timer = metrics::ON_BLOCK_SECONDS.start_timer();
+
+    process_block_internal(store, signed_block, block_root, signatures_verified)?;
     process_pending_blocks(store, vec![block_root]);
 
     Ok(())
@@ -114,84 +222,125 @@ pub fn on_block(store: &mut Store, signed_block: SignedBlockWithAttestation) ->
 
 fn process_block_internal(
     store: &mut Store,
-    signed_block: SignedBlockWithAttestation,
+    signed_block: VersionedSignedBlock,
     block_root: Bytes32,
-) -> Result<(), String> {
-    let block = &signed_block.message.block;
+    signatures_verified: bool,
+) -> Result<(), OnBlockError> {
+    import_block(store, signed_block, block_root, signatures_verified).map_err(rejected)
+}
+
+fn import_block(
+    store: &mut Store,
+    signed_block: VersionedSignedBlock,
+    block_root: Bytes32,
+    signatures_verified: bool,
+) -> Result<(), OnBlockError> {
+    let parent_root = signed_block.parent_root();
+    let slot = signed_block.slot();
 
     // Get parent state for validation
-    let state = match store.states.get(&block.parent_root) {
-        Some(state) => state,
-        None => {
-            return Err(
-                "Err: (Fork-choice::Handlers::ProcessBlockInternal) No parent state.".to_string(),
-            );
-        }
-    };
+    let state = store
+        .states
+        .get(&parent_root)
+        .ok_or(OnBlockError::MissingParentState(parent_root))?;
+
+    if store.verify_signatures && !signatures_verified {
+        signed_block.verify_signatures(state)?;
+    }
 
     // Execute state transition to get post-state
+    let timer = metrics::STATE_TRANSITION_SECONDS.start_timer();
     let new_state = state.state_transition_with_validation(signed_block.clone(), true, true)?;
+    timer.observe_duration();
+
+    // Body attestations are applied as on-chain (is_from_block=true), and aggregates
+    // count as one attestation per participant. The proposer attestation is applied
+    // as gossip (is_from_block=false) so it goes to "new" attestations and doesn't
+    // immediately affect fork choice. Its signature was already checked together
+    // with the block; the default is only used by tests.
+    let body_attestations = signed_block.signed_attestations();
+    let proposer_signed_attestation = SignedAttestation {
+        message: signed_block.proposer_attestation().clone(),
+        signature: signed_block.proposer_signature().unwrap_or_default(),
+    };
+
+    // Check every vote before anything is stored or emitted, so a rejected block
+    // never reaches storage or event subscribers
+    for signed_attestation in body_attestations
+        .iter()
+        .chain([&proposer_signed_attestation])
+    {
+        check_attestation_slots(store, &signed_attestation.message.data)?;
+    }
+
+    // Persist before inserting so a restart never sees a block without its state
+    store
+        .persist_block(block_root, &signed_block, &new_state)
+        .map_err(OnBlockError::Storage)?;
 
     // Store block and state
     store.blocks.insert(block_root, signed_block.clone());
     store.states.insert(block_root, new_state.clone());
+    store.proto_array.on_block(block_root, parent_root, slot);
+
+    store.emit(ChainEvent::Block {
+        slot,
+        block: block_root,
+    });
 
     if new_state.latest_justified.slot > store.latest_justified.slot {
         store.latest_justified = new_state.latest_justified.clone();
+        store.emit(ChainEvent::JustifiedCheckpoint(
+            store.latest_justified.clone(),
+        ));
     }
-    if new_state.latest_finalized.slot > store.latest_finalized.slot {
+    let finalized_advanced = new_state.latest_finalized.slot > store.latest_finalized.slot;
+    if finalized_advanced {
         store.latest_finalized = new_state.latest_finalized.clone();
+        store.emit(ChainEvent::FinalizedCheckpoint(
+            store.latest_finalized.clone(),
+        ));
     }
 
-    // Process block body attestations as on-chain (is_from_block=true)
-    let attestations = &signed_block.message.block.body.attestations;
-    let signatures = &signed_block.signature;
-
-    for i in 0.. {
-        match (attestations.get(i), signatures.get(i)) {
-            (Ok(attestation), Ok(signature)) => {
-                let signed_attestation = SignedAttestation {
-                    message: attestation.clone(),
-                    signature: signature.clone(),
-                };
-                on_attestation(store, signed_attestation, true)?;
-            }
-            _ => break,
-        }
+    for signed_attestation in body_attestations {
+        apply_attestation(store, signed_attestation, true);
     }
 
     // Update head BEFORE processing proposer attestation
     update_head(store);
+    store.persist_checkpoints().map_err(OnBlockError::Storage)?;
 
-    // Process proposer attestation as gossip (is_from_block=false)
-    // This ensures it goes to "new" attestations and doesn't immediately affect fork choice
-    let num_body_attestations = attestations.len_u64();
-
-    // Get proposer signature or use default if not present (for tests)
-    use containers::attestation::Signature;
-    let proposer_signature = signatures
-        .get(num_body_attestations)
-        .map(|sig| sig.clone())
-        .unwrap_or_else(|_| Signature::default());
+    apply_attestation(store, proposer_signed_attestation, false);
 
-    let proposer_signed_attestation = SignedAttestation {
-        message: signed_block.message.proposer_attestation.clone(),
-        signature: proposer_signature,
-    };
+    metrics::BLOCKS_IMPORTED.inc();
 
-    // Process proposer attestation as if received via gossip (is_from_block=false)
-    // This ensures it goes to "new" attestations and doesn't immediately affect fork choice
-    on_attestation(store, proposer_signed_attestation, false)?;
+    // The block is imported whether or not pruning works out; whatever is left
+    // behind is pruned again at the next finalization
+    if finalized_advanced {
+        if let Err(error) = prune_finalized(store) {
+            metrics::PRUNE_FAILURES
+                .with_label_values(&[error.reason()])
+                .inc();
+        }
+    }
 
     Ok(())
 }
 
+/// Count a block import failure by its reason and pass the error on.
+fn rejected(error: OnBlockError) -> OnBlockError {
+    metrics::BLOCKS_REJECTED
+        .with_label_values(&[error.reason()])
+        .inc();
+    error
+}
+
 fn process_pending_blocks(store: &mut Store, mut roots: Vec<Bytes32>) {
     while let Some(parent_root) = roots.pop() {
         if let Some(purgatory) = store.blocks_queue.remove(&parent_root) {
             for block in purgatory {
-                let block_origins = Bytes32(block.message.block.hash_tree_root());
-                if let Ok(()) = process_block_internal(store, block, block_origins) {
+                let block_origins = block.block_root();
+                if let Ok(()) = process_block_internal(store, block, block_origins, false) {
                     roots.push(block_origins);
                 }
             }
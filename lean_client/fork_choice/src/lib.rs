@@ -1,2 +1,6 @@
+pub mod error;
+pub mod events;
 pub mod handlers;
+pub mod proto_array;
 pub mod store;
+pub mod validation;
@@ -0,0 +1,231 @@
+//! Incremental block DAG for LMD-GHOST.
+//!
+//! Blocks are kept in insertion order, so a parent always sits before its
+//! children. Every node carries the number of votes for it or any of its
+//! descendants, one count per [`VoteSet`]. When the attestations change only the
+//! difference to the previously applied votes is pushed up the array, in a single
+//! backwards pass, instead of walking every vote up to the root.
+
+use containers::{attestation::SignedAttestation, Root, Slot, ValidatorIndex};
+use std::collections::{HashMap, HashSet};
+
+/// Attestation sets fork choice runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteSet {
+    /// `latest_known_attestations`, used for the head.
+    Known = 0,
+    /// `latest_new_attestations`, used for the safe target.
+    New = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtoNode {
+    pub root: Root,
+    pub slot: Slot,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    /// Votes for this block or a descendant, indexed by [`VoteSet`].
+    pub weights: [usize; 2],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProtoArray {
+    nodes: Vec<ProtoNode>,
+    indices: HashMap<Root, usize>,
+    /// Block each validator's vote currently counts for, per [`VoteSet`].
+    applied: [HashMap<ValidatorIndex, Root>; 2],
+}
+
+impl ProtoArray {
+    /// Build from unordered blocks given as `(root, parent_root, slot)`.
+    pub fn from_blocks(blocks: impl IntoIterator<Item = (Root, Root, Slot)>) -> Self {
+        let mut blocks = blocks.into_iter().collect::<Vec<_>>();
+        // A block's slot is always above its parent's
+        blocks.sort_by_key(|(root, _, slot)| (*slot, *root));
+
+        let mut proto_array = Self::default();
+        for (root, parent_root, slot) in blocks {
+            proto_array.on_block(root, parent_root, slot);
+        }
+        proto_array
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, root: &Root) -> bool {
+        self.indices.contains_key(root)
+    }
+
+    pub fn get(&self, root: &Root) -> Option<&ProtoNode> {
+        self.indices.get(root).map(|&index| &self.nodes[index])
+    }
+
+    pub fn nodes(&self) -> &[ProtoNode] {
+        &self.nodes
+    }
+
+    /// Add a block. Its parent must be added first for votes to reach it; a block
+    /// with an unknown parent becomes a new root.
+    pub fn on_block(&mut self, root: Root, parent_root: Root, slot: Slot) {
+        if self.indices.contains_key(&root) {
+            return;
+        }
+
+        let index = self.nodes.len();
+        let parent = self.indices.get(&parent_root).copied();
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+
+        self.nodes.push(ProtoNode {
+            root,
+            slot,
+            parent,
+            children: Vec::new(),
+            weights: [0; 2],
+        });
+        self.indices.insert(root, index);
+    }
+
+    /// Bring the weights of `set` in line with `attestations`.
+    ///
+    /// Votes for blocks that are not in the array yet are left out and picked up
+    /// by a later call once the block has been added.
+    pub fn apply_votes(
+        &mut self,
+        set: VoteSet,
+        attestations: &HashMap<ValidatorIndex, SignedAttestation>,
+    ) {
+        let mut deltas = vec![0i64; self.nodes.len()];
+        let indices = &self.indices;
+        let applied = &mut self.applied[set as usize];
+
+        applied.retain(|validator, root| {
+            let keep = attestations.contains_key(validator);
+            if !keep {
+                deltas[indices[root]] -= 1;
+            }
+            keep
+        });
+
+        for (validator, attestation) in attestations {
+            let head = attestation.message.data.head.root;
+            let target = indices.contains_key(&head).then_some(head);
+            let current = applied.get(validator).copied();
+            if current == target {
+                continue;
+            }
+
+            if let Some(current) = current {
+                deltas[indices[&current]] -= 1;
+            }
+            match target {
+                Some(target) => {
+                    deltas[indices[&target]] += 1;
+                    applied.insert(*validator, target);
+                }
+                None => {
+                    applied.remove(validator);
+                }
+            }
+        }
+
+        self.apply_deltas(set, deltas);
+    }
+
+    /// Add `deltas` to each node and push them on to its ancestors.
+    fn apply_deltas(&mut self, set: VoteSet, mut deltas: Vec<i64>) {
+        for index in (0..self.nodes.len()).rev() {
+            let delta = deltas[index];
+            if delta == 0 {
+                continue;
+            }
+
+            let node = &mut self.nodes[index];
+            node.weights[set as usize] = (node.weights[set as usize] as i64 + delta) as usize;
+            if let Some(parent) = node.parent {
+                deltas[parent] += delta;
+            }
+        }
+    }
+
+    /// Votes of `set` for `root` or one of its descendants.
+    pub fn weight(&self, set: VoteSet, root: &Root) -> Option<usize> {
+        self.get(root).map(|node| node.weights[set as usize])
+    }
+
+    /// LMD-GHOST from `root`: repeatedly step to the child with the most votes,
+    /// ties going to the highest root, ignoring children with fewer than
+    /// `min_votes`. Returns `None` if `root` is not in the array.
+    pub fn find_head(&self, set: VoteSet, root: Root, min_votes: usize) -> Option<Root> {
+        let mut curr = *self.indices.get(&root)?;
+
+        loop {
+            let weight = |index: usize| self.nodes[index].weights[set as usize];
+            let best_child = self.nodes[curr]
+                .children
+                .iter()
+                .copied()
+                .filter(|&child| weight(child) >= min_votes)
+                .max_by_key(|&child| (weight(child), self.nodes[child].root));
+
+            match best_child {
+                Some(child) => curr = child,
+                None => return Some(self.nodes[curr].root),
+            }
+        }
+    }
+
+    /// Drop every node not in `keep`, withdrawing the votes that counted for them.
+    pub fn prune(&mut self, keep: &HashSet<Root>) {
+        if self.nodes.iter().all(|node| keep.contains(&node.root)) {
+            return;
+        }
+
+        for set in [VoteSet::Known, VoteSet::New] {
+            let mut deltas = vec![0i64; self.nodes.len()];
+            self.applied[set as usize].retain(|_, root| {
+                let kept = keep.contains(root);
+                if !kept {
+                    deltas[self.indices[root]] -= 1;
+                }
+                kept
+            });
+            self.apply_deltas(set, deltas);
+        }
+
+        let nodes = std::mem::take(&mut self.nodes);
+        let mut new_indices = vec![None; nodes.len()];
+        for (index, node) in nodes.into_iter().enumerate() {
+            if !keep.contains(&node.root) {
+                continue;
+            }
+
+            new_indices[index] = Some(self.nodes.len());
+            self.nodes.push(ProtoNode {
+                parent: node.parent.and_then(|parent| new_indices[parent]),
+                ..node
+            });
+        }
+
+        for node in &mut self.nodes {
+            node.children = node
+                .children
+                .iter()
+                .filter_map(|&child| new_indices[child])
+                .collect();
+        }
+        self.indices = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.root, index))
+            .collect();
+    }
+}
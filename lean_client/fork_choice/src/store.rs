@@ -1,9 +1,17 @@
+use crate::error::PruneError;
+use crate::events::ChainEvent;
+use crate::proto_array::{ProtoArray, VoteSet};
 use containers::{
-    attestation::SignedAttestation, block::SignedBlockWithAttestation, checkpoint::Checkpoint,
+    attestation::SignedAttestation, block::VersionedSignedBlock, checkpoint::Checkpoint,
     config::Config, state::State, Bytes32, Root, Slot, ValidatorIndex,
 };
 use ssz::SszHash;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::ops::AddAssign;
+use std::sync::Arc;
+use storage::{ForkChoiceCheckpoints, Storage};
+use tokio::sync::broadcast;
 pub type Interval = u64;
 pub const INTERVALS_PER_SLOT: Interval = 4;
 pub const SECONDS_PER_SLOT: u64 = 4;
@@ -17,20 +25,144 @@ pub struct Store {
     pub safe_target: Root,
     pub latest_justified: Checkpoint,
     pub latest_finalized: Checkpoint,
-    pub blocks: HashMap<Root, SignedBlockWithAttestation>,
+    pub blocks: HashMap<Root, VersionedSignedBlock>,
     pub states: HashMap<Root, State>,
     pub latest_known_attestations: HashMap<ValidatorIndex, SignedAttestation>,
     pub latest_new_attestations: HashMap<ValidatorIndex, SignedAttestation>,
-    pub blocks_queue: HashMap<Root, Vec<SignedBlockWithAttestation>>,
+    pub blocks_queue: HashMap<Root, Vec<VersionedSignedBlock>>,
+    /// Block DAG with vote weights, kept in step with `blocks`.
+    pub proto_array: ProtoArray,
+    /// Optional persistent backend. Imported blocks, their post-states and the
+    /// fork-choice checkpoints are written through to it.
+    pub storage: Option<Arc<dyn Storage>>,
+    /// Totals of everything removed by `prune_finalized` since startup.
+    pub prune_stats: PruneStats,
+    /// Check XMSS signatures of imported blocks and gossip attestations.
+    pub verify_signatures: bool,
+    /// Optional subscriber channel for head, block, attestation and checkpoint events.
+    pub events: Option<broadcast::Sender<ChainEvent>>,
+}
+
+/// Number of entries removed from the store by pruning.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    /// Blocks on forks that conflict with the finalized block.
+    pub blocks: usize,
+    /// Post-states of blocks older than the finalized block or on conflicting forks.
+    pub states: usize,
+    /// Queued orphan blocks at or below the finalized slot.
+    pub queued_blocks: usize,
+    /// Known and new attestations whose head is pruned or older than finalized.
+    pub attestations: usize,
+}
+
+impl AddAssign for PruneStats {
+    fn add_assign(&mut self, other: Self) {
+        self.blocks += other.blocks;
+        self.states += other.states;
+        self.queued_blocks += other.queued_blocks;
+        self.attestations += other.attestations;
+    }
+}
+
+fn storage_err(action: &str, err: impl Debug) -> String {
+    format!("Err: (Fork-choice::Store) Failed to {action}: {err:?}")
+}
+
+impl Store {
+    /// Turn signature checks on import on or off. Tests that build blocks
+    /// without real signatures switch them off.
+    pub fn with_signature_verification(mut self, enabled: bool) -> Self {
+        self.verify_signatures = enabled;
+        self
+    }
+
+    /// Publish chain events on `sender`.
+    pub fn with_events(mut self, sender: broadcast::Sender<ChainEvent>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+
+    /// Send `event` to subscribers, if any are attached.
+    pub fn emit(&self, event: ChainEvent) {
+        if let Some(events) = &self.events {
+            // Fails only when nobody is subscribed
+            let _ = events.send(event);
+        }
+    }
+
+    /// Attach a persistent backend and write the current contents into it.
+    pub fn with_storage(mut self, storage: Arc<dyn Storage>) -> Result<Self, String> {
+        for (root, block) in &self.blocks {
+            storage
+                .put_block(*root, block)
+                .map_err(|e| storage_err("persist block", e))?;
+        }
+        for (root, state) in &self.states {
+            storage
+                .put_state(*root, state)
+                .map_err(|e| storage_err("persist state", e))?;
+        }
+
+        self.storage = Some(storage);
+        self.persist_checkpoints()?;
+        Ok(self)
+    }
+
+    /// Write an imported block and its post-state to the attached backend, if any.
+    pub fn persist_block(
+        &self,
+        root: Root,
+        block: &VersionedSignedBlock,
+        state: &State,
+    ) -> Result<(), String> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        storage
+            .put_block(root, block)
+            .map_err(|e| storage_err("persist block", e))?;
+        storage
+            .put_state(root, state)
+            .map_err(|e| storage_err("persist state", e))
+    }
+
+    /// Write head, justified and finalized checkpoints to the attached backend, if any.
+    pub fn persist_checkpoints(&self) -> Result<(), String> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        let head_slot = self
+            .blocks
+            .get(&self.head)
+            .map(|block| block.slot())
+            .unwrap_or_default();
+
+        let checkpoints = ForkChoiceCheckpoints {
+            head: Checkpoint {
+                root: self.head,
+                slot: head_slot,
+            },
+            latest_justified: self.latest_justified.clone(),
+            latest_finalized: self.latest_finalized.clone(),
+        };
+
+        storage
+            .put_checkpoints(&checkpoints)
+            .map_err(|e| storage_err("persist checkpoints", e))
+    }
 }
 
 pub fn get_forkchoice_store(
     anchor_state: State,
-    anchor_block: SignedBlockWithAttestation,
+    anchor_block: impl Into<VersionedSignedBlock>,
     config: Config,
 ) -> Store {
-    let block_root = Bytes32(anchor_block.message.block.hash_tree_root());
-    let block_slot = anchor_block.message.block.slot;
+    let anchor_block = anchor_block.into();
+    let block_root = anchor_block.block_root();
+    let block_slot = anchor_block.slot();
 
     let latest_justified = if anchor_state.latest_justified.root.0.is_zero() {
         Checkpoint {
@@ -57,14 +189,153 @@ pub fn get_forkchoice_store(
         safe_target: block_root,
         latest_justified,
         latest_finalized,
+        proto_array: ProtoArray::from_blocks([(
+            block_root,
+            anchor_block.parent_root(),
+            block_slot,
+        )]),
         blocks: [(block_root, anchor_block)].into(),
         states: [(block_root, anchor_state)].into(),
         latest_known_attestations: HashMap::new(),
         latest_new_attestations: HashMap::new(),
         blocks_queue: HashMap::new(),
+        storage: None,
+        prune_stats: PruneStats::default(),
+        verify_signatures: true,
+        events: None,
     }
 }
 
+/// Start fork choice from a trusted finalized block instead of genesis.
+///
+/// `anchor_state` must be the post-state of `anchor_block`. The ancestors of the
+/// anchor are unknown, so it becomes both the justified and finalized checkpoint.
+pub fn get_forkchoice_store_from_checkpoint(
+    anchor_state: State,
+    anchor_block: impl Into<VersionedSignedBlock>,
+    config: Config,
+) -> Result<Store, String> {
+    let anchor_block = anchor_block.into();
+
+    let state_root = Bytes32(anchor_state.hash_tree_root());
+    if state_root != anchor_block.state_root() {
+        return Err(format!(
+            "Err: (Fork-choice::Store) Checkpoint state root 0x{:x} does not match block state root 0x{:x}",
+            state_root.0,
+            anchor_block.state_root().0
+        ));
+    }
+
+    if anchor_state.slot != anchor_block.slot() {
+        return Err(format!(
+            "Err: (Fork-choice::Store) Checkpoint state slot {} does not match block slot {}",
+            anchor_state.slot.0,
+            anchor_block.slot().0
+        ));
+    }
+
+    let anchor_slot = anchor_block.slot();
+    let mut store = get_forkchoice_store(anchor_state, anchor_block, config);
+    let anchor = Checkpoint {
+        root: store.head,
+        slot: anchor_slot,
+    };
+    store.latest_justified = anchor.clone();
+    store.latest_finalized = anchor;
+
+    Ok(store)
+}
+
+/// Rebuild a store from a persistent backend.
+///
+/// Returns `Ok(None)` when the backend is empty, in which case the caller
+/// should fall back to `get_forkchoice_store` with an anchor state and block.
+pub fn get_forkchoice_store_from_storage(
+    storage: Arc<dyn Storage>,
+    config: Config,
+) -> Result<Option<Store>, String> {
+    let Some(checkpoints) = storage
+        .get_checkpoints()
+        .map_err(|e| storage_err("load checkpoints", e))?
+    else {
+        return Ok(None);
+    };
+
+    let mut blocks = HashMap::new();
+    for root in storage
+        .block_roots()
+        .map_err(|e| storage_err("list blocks", e))?
+    {
+        if let Some(block) = storage
+            .get_block(root)
+            .map_err(|e| storage_err("load block", e))?
+        {
+            blocks.insert(root, block);
+        }
+    }
+
+    let mut states = HashMap::new();
+    for root in storage
+        .state_roots()
+        .map_err(|e| storage_err("list states", e))?
+    {
+        if let Some(state) = storage
+            .get_state(root)
+            .map_err(|e| storage_err("load state", e))?
+        {
+            states.insert(root, state);
+        }
+    }
+
+    for checkpoint in [
+        &checkpoints.head,
+        &checkpoints.latest_justified,
+        &checkpoints.latest_finalized,
+    ] {
+        if !blocks.contains_key(&checkpoint.root) {
+            return Err(format!(
+                "Err: (Fork-choice::Store) Stored checkpoint block 0x{:x} at slot {} is missing",
+                checkpoint.root.0, checkpoint.slot.0
+            ));
+        }
+    }
+
+    if !states.contains_key(&checkpoints.head.root) {
+        return Err(format!(
+            "Err: (Fork-choice::Store) Stored head state 0x{:x} is missing",
+            checkpoints.head.root.0
+        ));
+    }
+
+    let proto_array = ProtoArray::from_blocks(
+        blocks
+            .iter()
+            .map(|(root, block)| (*root, block.parent_root(), block.slot())),
+    );
+
+    Ok(Some(Store {
+        time: checkpoints.head.slot.0 * INTERVALS_PER_SLOT,
+        config,
+        head: checkpoints.head.root,
+        safe_target: checkpoints.latest_justified.root,
+        latest_justified: checkpoints.latest_justified,
+        latest_finalized: checkpoints.latest_finalized,
+        blocks,
+        states,
+        proto_array,
+        latest_known_attestations: HashMap::new(),
+        latest_new_attestations: HashMap::new(),
+        blocks_queue: HashMap::new(),
+        storage: Some(storage),
+        prune_stats: PruneStats::default(),
+        verify_signatures: true,
+        events: None,
+    }))
+}
+
+/// LMD-GHOST by a full scan of `store.blocks` and every vote in
+/// `latest_attestations`. Fork choice itself runs on `Store::proto_array`; this is
+/// the reference it must agree with and the fallback for roots it does not hold.
 pub fn get_fork_choice_head(
     store: &Store,
     mut root: Root,
@@ -75,47 +346,20 @@ pub fn get_fork_choice_head(
         root = store
             .blocks
             .iter()
-            .min_by_key(|(_, block)| block.message.block.slot)
+            .min_by_key(|(_, block)| block.slot())
             .map(|(r, _)| *r)
             .expect("Error: Empty block.");
     }
-    let mut vote_weights: HashMap<Root, usize> = HashMap::new();
-    let root_slot = store.blocks[&root].message.block.slot;
-
     // stage 1: accumulate weights by walking up from each attestation's head
-    for attestation in latest_attestations.values() {
-        let mut curr = attestation.message.data.head.root;
-
-        if let Some(block) = store.blocks.get(&curr) {
-            let mut curr_slot = block.message.block.slot;
-
-            while curr_slot > root_slot {
-                *vote_weights.entry(curr).or_insert(0) += 1;
-
-                if let Some(parent_block) = store.blocks.get(&curr) {
-                    curr = parent_block.message.block.parent_root;
-                    if curr.0.is_zero() {
-                        break;
-                    }
-                    if let Some(next_block) = store.blocks.get(&curr) {
-                        curr_slot = next_block.message.block.slot;
-                    } else {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            }
-        }
-    }
+    let vote_weights = get_vote_weights(store, root, latest_attestations);
 
     // stage 2
     let mut child_map: HashMap<Root, Vec<Root>> = HashMap::new();
     for (block_hash, block) in &store.blocks {
-        if !block.message.block.parent_root.0.is_zero() {
+        if !block.parent_root().0.is_zero() {
             if vote_weights.get(block_hash).copied().unwrap_or(0) >= min_votes {
                 child_map
-                    .entry(block.message.block.parent_root)
+                    .entry(block.parent_root())
                     .or_default()
                     .push(*block_hash);
             }
@@ -143,6 +387,48 @@ pub fn get_fork_choice_head(
     }
 }
 
+/// Number of `latest_attestations` voting for each block above `root`, counting a
+/// vote for a block towards all of its ancestors. Blocks without votes are absent.
+pub fn get_vote_weights(
+    store: &Store,
+    root: Root,
+    latest_attestations: &HashMap<ValidatorIndex, SignedAttestation>,
+) -> HashMap<Root, usize> {
+    let mut vote_weights: HashMap<Root, usize> = HashMap::new();
+    let Some(root_block) = store.blocks.get(&root) else {
+        return vote_weights;
+    };
+    let root_slot = root_block.slot();
+
+    for attestation in latest_attestations.values() {
+        let mut curr = attestation.message.data.head.root;
+
+        if let Some(block) = store.blocks.get(&curr) {
+            let mut curr_slot = block.slot();
+
+            while curr_slot > root_slot {
+                *vote_weights.entry(curr).or_insert(0) += 1;
+
+                if let Some(parent_block) = store.blocks.get(&curr) {
+                    curr = parent_block.parent_root();
+                    if curr.0.is_zero() {
+                        break;
+                    }
+                    if let Some(next_block) = store.blocks.get(&curr) {
+                        curr_slot = next_block.slot();
+                    } else {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    vote_weights
+}
+
 pub fn get_latest_justified(states: &HashMap<Root, State>) -> Option<&Checkpoint> {
     states
         .values()
@@ -150,15 +436,225 @@ pub fn get_latest_justified(states: &HashMap<Root, State>) -> Option<&Checkpoint
         .max_by_key(|checkpoint| checkpoint.slot)
 }
 
+/// Root of the block at `slot` on the chain ending in `root`.
+///
+/// Empty slots resolve to the closest earlier block. Returns `None` if the chain
+/// in the store does not reach back to `slot`.
+pub fn get_ancestor_at_slot(store: &Store, root: Root, slot: Slot) -> Option<Root> {
+    let mut curr = root;
+    loop {
+        let block = store.blocks.get(&curr)?;
+        if block.slot() <= slot {
+            return Some(curr);
+        }
+        curr = block.parent_root();
+    }
+}
+
+/// Canonical blocks with slots in `start_slot..start_slot + count`, oldest first.
+pub fn get_canonical_blocks_by_range(
+    store: &Store,
+    start_slot: Slot,
+    count: u64,
+) -> Vec<VersionedSignedBlock> {
+    let end_slot = start_slot.0.saturating_add(count);
+    let mut blocks = Vec::new();
+    let mut curr = store.head;
+
+    while let Some(block) = store.blocks.get(&curr) {
+        let slot = block.slot();
+        if slot < start_slot {
+            break;
+        }
+        if slot.0 < end_slot {
+            blocks.push(block.clone());
+        }
+        curr = block.parent_root();
+    }
+
+    blocks.reverse();
+    blocks
+}
+
+/// Drop everything that can no longer become canonical once `latest_finalized` is set.
+///
+/// Keeps the finalized block with all its ancestors and descendants, and drops
+/// blocks on conflicting forks. States are only kept for the finalized block and
+/// its descendants. Queued orphans at or below the finalized slot and attestations
+/// whose head is gone or older than finalized are evicted as well. Removed blocks
+/// and states are also deleted from the attached storage. Fails without pruning
+/// anything if the finalized block is not in the store.
+pub fn prune_finalized(store: &mut Store) -> Result<PruneStats, PruneError> {
+    let finalized_root = store.latest_finalized.root;
+    let finalized_slot = store.latest_finalized.slot;
+
+    // Finalized checkpoints always come from imported blocks, so a missing one
+    // means the store is inconsistent and pruning would drop the wrong forks
+    if !store.blocks.contains_key(&finalized_root) {
+        return Err(PruneError::MissingFinalizedBlock {
+            root: finalized_root,
+            slot: finalized_slot,
+        });
+    }
+
+    // Finalized block and its descendants
+    let mut children: HashMap<Root, Vec<Root>> = HashMap::new();
+    for (root, block) in &store.blocks {
+        children.entry(block.parent_root()).or_default().push(*root);
+    }
+
+    let mut descendants = HashSet::new();
+    let mut pending = vec![finalized_root];
+    while let Some(root) = pending.pop() {
+        if descendants.insert(root) {
+            if let Some(list) = children.get(&root) {
+                pending.extend(list.iter().copied());
+            }
+        }
+    }
+
+    // Canonical history below the finalized block
+    let mut keep_blocks = descendants.clone();
+    let mut curr = finalized_root;
+    while let Some(block) = store.blocks.get(&curr) {
+        keep_blocks.insert(curr);
+        curr = block.parent_root();
+    }
+
+    // Fork choice must still be able to start from justified
+    if !descendants.contains(&store.latest_justified.root) {
+        return Ok(PruneStats::default());
+    }
+
+    let mut stats = PruneStats::default();
+
+    let stale_blocks: Vec<Root> = store
+        .blocks
+        .keys()
+        .filter(|root| !keep_blocks.contains(root))
+        .copied()
+        .collect();
+    let stale_states: Vec<Root> = store
+        .states
+        .keys()
+        .filter(|root| !descendants.contains(root))
+        .copied()
+        .collect();
+
+    store.proto_array.prune(&keep_blocks);
+
+    for root in &stale_blocks {
+        if let Some(storage) = &store.storage {
+            storage
+                .delete_block(*root)
+                .map_err(|e| PruneError::Storage(storage_err("delete block", e)))?;
+        }
+        store.blocks.remove(root);
+        stats.blocks += 1;
+    }
+
+    for root in &stale_states {
+        if let Some(storage) = &store.storage {
+            storage
+                .delete_state(*root)
+                .map_err(|e| PruneError::Storage(storage_err("delete state", e)))?;
+        }
+        store.states.remove(root);
+        stats.states += 1;
+    }
+
+    store.blocks_queue.retain(|_, queued| {
+        let before = queued.len();
+        queued.retain(|block| block.slot() > finalized_slot);
+        stats.queued_blocks += before - queued.len();
+        !queued.is_empty()
+    });
+
+    let blocks = &store.blocks;
+    let is_live = |attestation: &SignedAttestation| {
+        let head = &attestation.message.data.head;
+        head.slot >= finalized_slot && blocks.contains_key(&head.root)
+    };
+    for attestations in [
+        &mut store.latest_known_attestations,
+        &mut store.latest_new_attestations,
+    ] {
+        let before = attestations.len();
+        attestations.retain(|_, attestation| is_live(attestation));
+        stats.attestations += before - attestations.len();
+    }
+
+    if !store.blocks.contains_key(&store.safe_target) {
+        store.safe_target = store.latest_justified.root;
+    }
+
+    store.prune_stats += stats;
+    for (kind, count) in [
+        ("blocks", stats.blocks),
+        ("states", stats.states),
+        ("queued_blocks", stats.queued_blocks),
+        ("attestations", stats.attestations),
+    ] {
+        metrics::PRUNED
+            .with_label_values(&[kind])
+            .inc_by(count as u64);
+    }
+    Ok(stats)
+}
+
+/// LMD-GHOST head from the latest justified root over one set of attestations,
+/// using the proto-array. Falls back to the full scan in `get_fork_choice_head`
+/// when the justified block is not in the proto-array.
+fn find_head(store: &mut Store, set: VoteSet, min_votes: usize) -> Root {
+    let attestations = match set {
+        VoteSet::Known => &store.latest_known_attestations,
+        VoteSet::New => &store.latest_new_attestations,
+    };
+    let root = store.latest_justified.root;
+
+    store.proto_array.apply_votes(set, attestations);
+    store
+        .proto_array
+        .find_head(set, root, min_votes)
+        .unwrap_or_else(|| get_fork_choice_head(store, root, attestations, min_votes))
+}
+
 pub fn update_head(store: &mut Store) {
     // Compute new head using LMD-GHOST from latest justified root
-    let new_head = get_fork_choice_head(
-        store,
-        store.latest_justified.root,
-        &store.latest_known_attestations,
-        0,
-    );
-    store.head = new_head;
+    let new_head = find_head(store, VoteSet::Known, 0);
+    if new_head == store.head {
+        return;
+    }
+
+    let previous_head = std::mem::replace(&mut store.head, new_head);
+    if let Some(block) = store.blocks.get(&new_head) {
+        store.emit(ChainEvent::Head {
+            slot: block.slot(),
+            block: new_head,
+            previous_head,
+            depth_of_reorg: get_reorg_depth(store, previous_head, new_head),
+        });
+    }
+}
+
+/// Slots between `old_head` and its latest common ancestor with `new_head`.
+pub fn get_reorg_depth(store: &Store, old_head: Root, new_head: Root) -> u64 {
+    let Some(old_block) = store.blocks.get(&old_head) else {
+        return 0;
+    };
+    let old_slot = old_block.slot();
+
+    let mut curr = old_head;
+    while let Some(block) = store.blocks.get(&curr) {
+        let slot = block.slot();
+        if get_ancestor_at_slot(store, new_head, slot) == Some(curr) {
+            return old_slot.0 - slot.0;
+        }
+        curr = block.parent_root();
+    }
+
+    // No common ancestor in the store; everything we know of was reorged out
+    old_slot.0
 }
 
 pub fn update_safe_target(store: &mut Store) {
@@ -169,9 +665,7 @@ pub fn update_safe_target(store: &mut Store) {
     };
 
     let min_score = (n_validators * 2 + 2) / 3;
-    let root = store.latest_justified.root;
-    store.safe_target =
-        get_fork_choice_head(store, root, &store.latest_new_attestations, min_score);
+    store.safe_target = find_head(store, VoteSet::New, min_score);
 }
 
 pub fn accept_new_attestations(store: &mut Store) {
@@ -196,16 +690,16 @@ pub fn tick_interval(store: &mut Store, has_proposal: bool) {
 
 pub fn get_vote_target(store: &Store) -> Checkpoint {
     let mut target = store.head;
-    let safe_slot = store.blocks[&store.safe_target].message.block.slot;
+    let safe_slot = store.blocks[&store.safe_target].slot();
     let source_slot = store.latest_justified.slot;
 
     // Walk back toward safe target (up to 3 steps per leanSpec JUSTIFICATION_LOOKBACK_SLOTS)
     for _ in 0..3 {
-        if store.blocks[&target].message.block.slot > safe_slot {
-            let parent = store.blocks[&target].message.block.parent_root;
+        if store.blocks[&target].slot() > safe_slot {
+            let parent = store.blocks[&target].parent_root();
             // Don't walk back if it would make target <= source (invalid attestation)
             if let Some(parent_block) = store.blocks.get(&parent) {
-                if parent_block.message.block.slot <= source_slot {
+                if parent_block.slot() <= source_slot {
                     break;
                 }
             }
@@ -217,25 +711,22 @@ pub fn get_vote_target(store: &Store) -> Checkpoint {
 
     let final_slot = store.latest_finalized.slot;
     while !store.blocks[&target]
-        .message
-        .block
-        .slot
+        .slot()
         .is_justifiable_after(final_slot)
     {
-        let parent = store.blocks[&target].message.block.parent_root;
+        let parent = store.blocks[&target].parent_root();
         // Don't walk back if it would make target <= source (invalid attestation)
         if let Some(parent_block) = store.blocks.get(&parent) {
-            if parent_block.message.block.slot <= source_slot {
+            if parent_block.slot() <= source_slot {
                 break;
             }
         }
         target = parent;
     }
 
-    let block_target = &store.blocks[&target].message.block;
     Checkpoint {
         root: target,
-        slot: block_target.slot,
+        slot: store.blocks[&target].slot(),
     }
 }
 
@@ -0,0 +1,247 @@
+//! Cheap checks on gossip messages before they are imported or forwarded.
+//!
+//! These decide whether gossipsub should propagate a message and whether its
+//! sender should be penalised. Passing them does not guarantee a successful
+//! import: the full state transition still runs in `on_block`.
+
+use crate::error::{OnAttestationError, OnBlockError};
+use crate::store::{Interval, Store, INTERVALS_PER_SLOT};
+use containers::{
+    attestation::{AttestationData, SignedAggregatedAttestations, SignedAttestation},
+    block::VersionedSignedBlock,
+    Root, Slot, State, ValidatorIndex,
+};
+
+/// How far ahead of our clock a message may be, to absorb tick lag and clock skew.
+pub const GOSSIP_CLOCK_DISPARITY: Interval = 1;
+
+/// Why a gossip message failed validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipError {
+    /// The parent block is not imported yet, so the block cannot be checked further.
+    /// It is still worth queueing until the parent arrives.
+    UnknownParent(Root),
+    /// Not provably invalid (duplicate, outside the slot window, ...).
+    /// Dropped without penalising the sender.
+    Ignore(String),
+    /// Invalid. Dropped and the sender is penalised.
+    Reject(String),
+}
+
+impl From<OnBlockError> for GossipError {
+    /// Verdict for a gossip block that passed validation but failed to import.
+    fn from(err: OnBlockError) -> Self {
+        match err {
+            OnBlockError::UnknownParent { parent_root, .. } => Self::UnknownParent(parent_root),
+            OnBlockError::Transition(_) => Self::Reject(err.to_string()),
+            OnBlockError::Attestation(err) => err.into(),
+            OnBlockError::Duplicate(_)
+            | OnBlockError::PreFinalized { .. }
+            | OnBlockError::MissingParentState(_)
+            | OnBlockError::Storage(_) => Self::Ignore(err.to_string()),
+        }
+    }
+}
+
+impl From<OnAttestationError> for GossipError {
+    fn from(err: OnAttestationError) -> Self {
+        match err {
+            OnAttestationError::SourceAfterTarget { .. }
+            | OnAttestationError::InvalidSignature(_) => Self::Reject(err.to_string()),
+            OnAttestationError::FutureSlot { .. }
+            | OnAttestationError::UnknownHead(_)
+            | OnAttestationError::UnknownTarget(_)
+            | OnAttestationError::NoHeadState => Self::Ignore(err.to_string()),
+        }
+    }
+}
+
+fn is_from_future(store: &Store, slot: Slot) -> bool {
+    slot.0 * INTERVALS_PER_SLOT > store.time + GOSSIP_CLOCK_DISPARITY
+}
+
+pub fn validate_gossip_block(
+    store: &Store,
+    signed_block: &VersionedSignedBlock,
+) -> Result<(), GossipError> {
+    let block = signed_block.header();
+    let block_root = signed_block.block_root();
+
+    if is_from_future(store, block.slot) {
+        return Err(GossipError::Ignore(format!(
+            "block slot {} is in the future",
+            block.slot.0
+        )));
+    }
+
+    if block.slot <= store.latest_finalized.slot {
+        return Err(GossipError::Ignore(format!(
+            "block slot {} is not after finalized slot {}",
+            block.slot.0, store.latest_finalized.slot.0
+        )));
+    }
+
+    let queued = store
+        .blocks_queue
+        .get(&block.parent_root)
+        .is_some_and(|blocks| {
+            blocks
+                .iter()
+                .any(|queued| queued.block_root() == block_root)
+        });
+    if queued || store.blocks.contains_key(&block_root) {
+        return Err(GossipError::Ignore("block already known".to_string()));
+    }
+
+    let (Some(parent), Some(parent_state)) = (
+        store.blocks.get(&block.parent_root),
+        store.states.get(&block.parent_root),
+    ) else {
+        return Err(GossipError::UnknownParent(block.parent_root));
+    };
+
+    if block.slot <= parent.slot() {
+        return Err(GossipError::Reject(format!(
+            "block slot {} is not after parent slot {}",
+            block.slot.0,
+            parent.slot().0
+        )));
+    }
+
+    if block.proposer_index.0 >= parent_state.validators.len_u64()
+        || !parent_state.is_proposer_at(block.slot, block.proposer_index)
+    {
+        return Err(GossipError::Reject(format!(
+            "validator {} is not the proposer for slot {}",
+            block.proposer_index.0, block.slot.0
+        )));
+    }
+
+    let proposer_attestation = signed_block.proposer_attestation();
+    if proposer_attestation.validator_id.0 != block.proposer_index.0 {
+        return Err(GossipError::Reject(format!(
+            "proposer attestation is from validator {}, not proposer {}",
+            proposer_attestation.validator_id.0, block.proposer_index.0
+        )));
+    }
+
+    if store.verify_signatures {
+        signed_block
+            .verify_signatures(parent_state)
+            .map_err(|err| GossipError::Reject(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+pub fn validate_gossip_attestation(
+    store: &Store,
+    signed_attestation: &SignedAttestation,
+) -> Result<(), GossipError> {
+    let attestation = &signed_attestation.message;
+    let data = &attestation.data;
+
+    validate_attestation_data(store, data)?;
+
+    let validator_id = ValidatorIndex(attestation.validator_id.0);
+    if has_attestation(store, validator_id, data.slot) {
+        return Err(GossipError::Ignore(format!(
+            "already have an attestation from validator {} for slot {}",
+            validator_id.0, data.slot.0
+        )));
+    }
+
+    if store.verify_signatures {
+        signed_attestation
+            .verify_signature(head_state(store)?)
+            .map_err(GossipError::Reject)?;
+    }
+
+    Ok(())
+}
+
+/// An aggregate is useful if at least one participant's attestation is new to us.
+pub fn validate_gossip_aggregate(
+    store: &Store,
+    signed_aggregate: &SignedAggregatedAttestations,
+) -> Result<(), GossipError> {
+    let aggregate = &signed_aggregate.message;
+    let data = &aggregate.data;
+
+    validate_attestation_data(store, data)?;
+
+    let mut participants = aggregate.validator_ids().peekable();
+    if participants.peek().is_none() {
+        return Err(GossipError::Reject(
+            "aggregate has no participants".to_string(),
+        ));
+    }
+
+    if participants
+        .all(|validator_id| has_attestation(store, ValidatorIndex(validator_id.0), data.slot))
+    {
+        return Err(GossipError::Ignore(format!(
+            "already have the attestations of every participant for slot {}",
+            data.slot.0
+        )));
+    }
+
+    if store.verify_signatures {
+        signed_aggregate
+            .verify_signatures(head_state(store)?)
+            .map_err(GossipError::Reject)?;
+    }
+
+    Ok(())
+}
+
+/// Checks shared by individual and aggregated attestations.
+fn validate_attestation_data(store: &Store, data: &AttestationData) -> Result<(), GossipError> {
+    if is_from_future(store, data.slot) {
+        return Err(GossipError::Ignore(format!(
+            "attestation slot {} is in the future",
+            data.slot.0
+        )));
+    }
+
+    if data.target.slot < store.latest_finalized.slot {
+        return Err(GossipError::Ignore(format!(
+            "attestation target slot {} is before finalized slot {}",
+            data.target.slot.0, store.latest_finalized.slot.0
+        )));
+    }
+
+    if data.source.slot > data.target.slot {
+        return Err(GossipError::Reject(format!(
+            "source slot {} exceeds target slot {}",
+            data.source.slot.0, data.target.slot.0
+        )));
+    }
+
+    if !store.blocks.contains_key(&data.head.root) {
+        return Err(GossipError::Ignore(format!(
+            "unknown head block 0x{:x}",
+            data.head.root.0
+        )));
+    }
+
+    Ok(())
+}
+
+/// Whether we already have an attestation from `validator_id` at `slot` or later.
+fn has_attestation(store: &Store, validator_id: ValidatorIndex, slot: Slot) -> bool {
+    [
+        &store.latest_new_attestations,
+        &store.latest_known_attestations,
+    ]
+    .iter()
+    .filter_map(|attestations| attestations.get(&validator_id))
+    .any(|existing| existing.message.data.slot >= slot)
+}
+
+fn head_state(store: &Store) -> Result<&State, GossipError> {
+    store
+        .states
+        .get(&store.head)
+        .ok_or_else(|| GossipError::Ignore("no head state".to_string()))
+}
@@ -423,7 +423,7 @@ fn verify_checks(
     };
 
     if let Some(expected_slot) = checks.head_slot {
-        let actual_slot = store.blocks[&store.head].message.block.slot.0;
+        let actual_slot = store.blocks[&store.head].slot().0;
         if actual_slot != expected_slot {
             return Err(format!(
                 "Step {}: Head slot mismatch - expected {}, got {}",
@@ -440,12 +440,12 @@ fn verify_checks(
             let actual_slot = store
                 .blocks
                 .get(&store.head)
-                .map(|b| b.message.block.slot.0)
+                .map(|b| b.slot().0)
                 .unwrap_or(0);
             let expected_slot = store
                 .blocks
                 .get(expected_root)
-                .map(|b| b.message.block.slot.0)
+                .map(|b| b.slot().0)
                 .unwrap_or(0);
             return Err(format!(
                 "Step {}: Head root mismatch for label '{}' - expected slot {}, got slot {} (known_attestations: {}, new_attestations: {})",
@@ -517,7 +517,8 @@ fn run_single_test(_test_name: &str, test: TestVector) -> Result<(), String> {
         genesis_time: test.anchor_state.config.genesis_time,
     };
 
-    let mut store = get_forkchoice_store(anchor_state, anchor_block, config);
+    let mut store =
+        get_forkchoice_store(anchor_state, anchor_block, config).with_signature_verification(false);
     let mut block_labels: HashMap<String, Bytes32> = HashMap::new();
 
     for (step_idx, step) in test.steps.iter().enumerate() {
@@ -538,7 +539,7 @@ fn run_single_test(_test_name: &str, test: TestVector) -> Result<(), String> {
                         store.config.genesis_time + (signed_block.message.block.slot.0 * 4);
                     on_tick(&mut store, block_time, false);
 
-                    on_block(&mut store, signed_block)?;
+                    on_block(&mut store, signed_block, false).map_err(|e| e.to_string())?;
                     Ok(block_root)
                 }));
 
@@ -593,7 +594,8 @@ fn run_single_test(_test_name: &str, test: TestVector) -> Result<(), String> {
                         message: attestation,
                         signature: Signature::default(),
                     };
-                    on_attestation(&mut store, signed_attestation, false)
+                    on_attestation(&mut store, signed_attestation, false, false)
+                        .map_err(|e| e.to_string())
                 }));
 
                 let result = match result {
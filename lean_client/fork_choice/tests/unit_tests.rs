@@ -1,6 +1,13 @@
 mod unit_tests {
     pub mod common;
+    pub mod events;
     pub mod fork_choice;
+    pub mod on_block;
+    pub mod proto_array;
+    pub mod pruning;
+    pub mod signatures;
+    pub mod storage;
     pub mod time;
+    pub mod validation;
     pub mod votes;
 }
@@ -0,0 +1,149 @@
+use super::common::create_test_store;
+use containers::{
+    attestation::{Attestation, AttestationData, Signature, SignedAttestation},
+    block::{Block, BlockBody, BlockWithAttestation, SignedBlockWithAttestation},
+    checkpoint::Checkpoint,
+    Bytes32, Slot, Uint64, ValidatorIndex,
+};
+use fork_choice::{
+    events::ChainEvent,
+    handlers::on_attestation,
+    store::{get_reorg_depth, update_head, Store},
+};
+use ssz::SszHash;
+use tokio::sync::broadcast;
+
+fn add_block(store: &mut Store, parent_root: Bytes32, slot: u64) -> Bytes32 {
+    let block = Block {
+        slot: Slot(slot),
+        proposer_index: ValidatorIndex(slot),
+        parent_root,
+        state_root: Bytes32::default(),
+        body: BlockBody::default(),
+    };
+    let root = Bytes32(block.hash_tree_root());
+
+    let signed_block = SignedBlockWithAttestation {
+        message: BlockWithAttestation {
+            block,
+            proposer_attestation: Default::default(),
+        },
+        signature: Default::default(),
+    };
+
+    let state = store.states[&parent_root].clone();
+    store.proto_array.on_block(root, parent_root, Slot(slot));
+    store.blocks.insert(root, signed_block.into());
+    store.states.insert(root, state);
+    root
+}
+
+fn vote(store: &mut Store, validator_id: u64, root: Bytes32, slot: u64) {
+    let head = Checkpoint {
+        root,
+        slot: Slot(slot),
+    };
+    store.latest_known_attestations.insert(
+        ValidatorIndex(validator_id),
+        SignedAttestation {
+            message: Attestation {
+                validator_id: Uint64(validator_id),
+                data: AttestationData {
+                    slot: head.slot,
+                    head: head.clone(),
+                    target: head,
+                    source: Checkpoint::default(),
+                },
+            },
+            signature: Signature::default(),
+        },
+    );
+}
+
+#[test]
+fn test_reorg_depth() {
+    let mut store = create_test_store();
+    let genesis_root = store.head;
+
+    // genesis <- a1 <- a2 <- a3, genesis <- b2
+    let a1 = add_block(&mut store, genesis_root, 1);
+    let a2 = add_block(&mut store, a1, 2);
+    let a3 = add_block(&mut store, a2, 3);
+    let b2 = add_block(&mut store, genesis_root, 2);
+
+    assert_eq!(get_reorg_depth(&store, a1, a3), 0);
+    assert_eq!(get_reorg_depth(&store, a3, b2), 3);
+    assert_eq!(get_reorg_depth(&store, b2, a1), 2);
+}
+
+#[test]
+fn test_update_head_emits_head_event_only_on_change() {
+    let (sender, mut events) = broadcast::channel(16);
+    let mut store = create_test_store().with_events(sender);
+    let genesis_root = store.head;
+
+    let a1 = add_block(&mut store, genesis_root, 1);
+    let a2 = add_block(&mut store, a1, 2);
+    let b1 = add_block(&mut store, genesis_root, 1);
+
+    vote(&mut store, 0, a2, 2);
+    update_head(&mut store);
+    assert_eq!(
+        events.try_recv().unwrap(),
+        ChainEvent::Head {
+            slot: Slot(2),
+            block: a2,
+            previous_head: genesis_root,
+            depth_of_reorg: 0,
+        }
+    );
+
+    update_head(&mut store);
+    assert!(events.try_recv().is_err());
+
+    vote(&mut store, 1, b1, 1);
+    vote(&mut store, 2, b1, 1);
+    update_head(&mut store);
+    assert_eq!(
+        events.try_recv().unwrap(),
+        ChainEvent::Head {
+            slot: Slot(1),
+            block: b1,
+            previous_head: a2,
+            depth_of_reorg: 2,
+        }
+    );
+}
+
+#[test]
+fn test_gossip_attestation_emits_event() {
+    let (sender, mut events) = broadcast::channel(16);
+    let mut store = create_test_store().with_events(sender);
+    let genesis = Checkpoint {
+        root: store.head,
+        slot: Slot(0),
+    };
+
+    let attestation = Attestation {
+        validator_id: Uint64(3),
+        data: AttestationData {
+            slot: Slot(0),
+            head: genesis.clone(),
+            target: genesis.clone(),
+            source: genesis,
+        },
+    };
+    let signed_attestation = SignedAttestation {
+        message: attestation.clone(),
+        signature: Signature::default(),
+    };
+
+    on_attestation(&mut store, signed_attestation.clone(), true, false).unwrap();
+    assert!(events.try_recv().is_err());
+
+    on_attestation(&mut store, signed_attestation, false, false).unwrap();
+    assert_eq!(
+        events.try_recv().unwrap(),
+        ChainEvent::Attestation(attestation)
+    );
+}
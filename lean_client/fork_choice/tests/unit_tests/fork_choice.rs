@@ -51,7 +51,7 @@ fn test_get_vote_target_chain() {
             signature: Default::default(),
         };
 
-        store.blocks.insert(block_root, signed_block);
+        store.blocks.insert(block_root, signed_block.into());
         parent_root = block_root;
     }
 
@@ -64,3 +64,159 @@ fn test_get_vote_target_chain() {
 
     assert_eq!(target.slot, Slot(6));
 }
+
+#[test]
+fn test_get_ancestor_at_slot() {
+    use containers::{
+        block::{Block, BlockBody, BlockWithAttestation, SignedBlockWithAttestation},
+        Bytes32, ValidatorIndex,
+    };
+    use fork_choice::store::get_ancestor_at_slot;
+    use ssz::SszHash;
+
+    let mut store = create_test_store();
+    let genesis_root = store.head;
+    let mut parent_root = genesis_root;
+    let mut roots = vec![];
+
+    // Blocks at slots 2, 4 and 6, leaving the odd slots empty
+    for slot in [2, 4, 6] {
+        let block = Block {
+            slot: Slot(slot),
+            proposer_index: ValidatorIndex(0),
+            parent_root,
+            state_root: Bytes32::default(),
+            body: BlockBody::default(),
+        };
+
+        let block_root = Bytes32(block.hash_tree_root());
+
+        store.blocks.insert(
+            block_root,
+            SignedBlockWithAttestation {
+                message: BlockWithAttestation {
+                    block,
+                    proposer_attestation: Default::default(),
+                },
+                signature: Default::default(),
+            }
+            .into(),
+        );
+        roots.push(block_root);
+        parent_root = block_root;
+    }
+
+    let head = parent_root;
+
+    assert_eq!(get_ancestor_at_slot(&store, head, Slot(6)), Some(roots[2]));
+    assert_eq!(get_ancestor_at_slot(&store, head, Slot(4)), Some(roots[1]));
+    assert_eq!(get_ancestor_at_slot(&store, head, Slot(3)), Some(roots[0]));
+    assert_eq!(
+        get_ancestor_at_slot(&store, head, Slot(1)),
+        Some(genesis_root)
+    );
+    assert_eq!(get_ancestor_at_slot(&store, head, Slot(10)), Some(head));
+    assert_eq!(
+        get_ancestor_at_slot(&store, Bytes32(ssz::H256::repeat_byte(1)), Slot(0)),
+        None
+    );
+}
+
+#[test]
+fn test_get_canonical_blocks_by_range() {
+    use containers::{
+        block::{Block, BlockBody, BlockWithAttestation, SignedBlockWithAttestation},
+        Bytes32, ValidatorIndex,
+    };
+    use fork_choice::store::get_canonical_blocks_by_range;
+    use ssz::SszHash;
+
+    let mut store = create_test_store();
+    let mut parent_root = store.head;
+
+    for slot in [1, 2, 4, 5] {
+        let block = Block {
+            slot: Slot(slot),
+            proposer_index: ValidatorIndex(0),
+            parent_root,
+            state_root: Bytes32::default(),
+            body: BlockBody::default(),
+        };
+
+        let block_root = Bytes32(block.hash_tree_root());
+
+        store.blocks.insert(
+            block_root,
+            SignedBlockWithAttestation {
+                message: BlockWithAttestation {
+                    block,
+                    proposer_attestation: Default::default(),
+                },
+                signature: Default::default(),
+            }
+            .into(),
+        );
+        parent_root = block_root;
+    }
+
+    store.head = parent_root;
+
+    let slots = |start, count| {
+        get_canonical_blocks_by_range(&store, Slot(start), count)
+            .iter()
+            .map(|block| block.slot().0)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(slots(0, 10), vec![0, 1, 2, 4, 5]);
+    assert_eq!(slots(2, 3), vec![2, 4]);
+    assert_eq!(slots(3, 1), Vec::<u64>::new());
+    assert_eq!(slots(6, 4), Vec::<u64>::new());
+}
+
+#[test]
+fn test_get_forkchoice_store_from_checkpoint() {
+    use containers::{
+        block::{Block, BlockWithAttestation, SignedBlockWithAttestation},
+        config::Config,
+        state::State,
+        validator::Validator,
+        Bytes32, Uint64, ValidatorIndex,
+    };
+    use fork_choice::store::get_forkchoice_store_from_checkpoint;
+    use ssz::SszHash;
+
+    let mut state =
+        State::generate_genesis_with_validators(Uint64(1000), vec![Validator::default(); 4]);
+    state.slot = Slot(40);
+
+    let block = |state_root| SignedBlockWithAttestation {
+        message: BlockWithAttestation {
+            block: Block {
+                slot: Slot(40),
+                proposer_index: ValidatorIndex(0),
+                parent_root: Bytes32(ssz::H256::repeat_byte(7)),
+                state_root,
+                body: Default::default(),
+            },
+            proposer_attestation: Default::default(),
+        },
+        signature: Default::default(),
+    };
+    let config = Config { genesis_time: 1000 };
+
+    let mismatched = block(Bytes32::default());
+    assert!(
+        get_forkchoice_store_from_checkpoint(state.clone(), mismatched, config.clone()).is_err()
+    );
+
+    let anchor = block(Bytes32(state.hash_tree_root()));
+    let anchor_root = Bytes32(anchor.message.block.hash_tree_root());
+    let store = get_forkchoice_store_from_checkpoint(state, anchor, config).unwrap();
+
+    assert_eq!(store.head, anchor_root);
+    assert_eq!(store.latest_finalized.root, anchor_root);
+    assert_eq!(store.latest_finalized.slot, Slot(40));
+    assert_eq!(store.latest_justified, store.latest_finalized);
+    assert_eq!(store.time, 40 * fork_choice::store::INTERVALS_PER_SLOT);
+}
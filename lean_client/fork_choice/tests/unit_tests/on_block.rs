@@ -0,0 +1,201 @@
+use super::common::create_test_store;
+use containers::{
+    attestation::{Attestation, AttestationData, Signature, SignedAttestation},
+    block::{
+        AggregatedBlockSignatures, AggregatedBlockWithAttestation, Block, BlockBody,
+        BlockWithAttestation, SignedAggregatedBlockWithAttestation, SignedBlockWithAttestation,
+        VersionedSignedBlock,
+    },
+    checkpoint::Checkpoint,
+    Bytes32, Slot, Uint64, ValidatorIndex,
+};
+use fork_choice::{
+    error::{OnAttestationError, OnBlockError},
+    handlers::on_block,
+    store::INTERVALS_PER_SLOT,
+    validation::GossipError,
+};
+use tokio::sync::broadcast;
+
+fn block(slot: u64, parent_root: Bytes32) -> SignedBlockWithAttestation {
+    SignedBlockWithAttestation {
+        message: BlockWithAttestation {
+            block: Block {
+                slot: Slot(slot),
+                proposer_index: ValidatorIndex(slot),
+                parent_root,
+                state_root: Bytes32::default(),
+                body: BlockBody::default(),
+            },
+            proposer_attestation: Default::default(),
+        },
+        signature: Default::default(),
+    }
+}
+
+#[test]
+fn test_block_with_unknown_parent_is_queued_once() {
+    let mut store = create_test_store();
+    let parent_root = Bytes32(ssz::H256::repeat_byte(9));
+    let orphan = block(2, parent_root);
+
+    assert_eq!(
+        on_block(&mut store, orphan.clone(), false),
+        Err(OnBlockError::UnknownParent {
+            parent_root,
+            pending: 1
+        })
+    );
+    assert!(matches!(
+        on_block(&mut store, orphan, false),
+        Err(OnBlockError::Duplicate(_))
+    ));
+    assert_eq!(store.blocks_queue[&parent_root].len(), 1);
+}
+
+#[test]
+fn test_block_at_finalized_slot_is_rejected() {
+    let mut store = create_test_store();
+    let genesis_root = store.head;
+    store.latest_finalized.slot = Slot(5);
+
+    assert_eq!(
+        on_block(&mut store, block(3, genesis_root), false),
+        Err(OnBlockError::PreFinalized {
+            slot: Slot(3),
+            finalized_slot: Slot(5)
+        })
+    );
+    assert!(store.blocks_queue.is_empty());
+}
+
+#[test]
+fn test_errors_map_to_gossip_verdicts() {
+    let root = Bytes32(ssz::H256::repeat_byte(1));
+
+    assert_eq!(
+        GossipError::from(OnBlockError::UnknownParent {
+            parent_root: root,
+            pending: 3
+        }),
+        GossipError::UnknownParent(root)
+    );
+    assert!(matches!(
+        GossipError::from(OnBlockError::Duplicate(root)),
+        GossipError::Ignore(_)
+    ));
+    assert!(matches!(
+        GossipError::from(OnBlockError::Attestation(
+            OnAttestationError::SourceAfterTarget {
+                source_slot: Slot(2),
+                target_slot: Slot(1)
+            }
+        )),
+        GossipError::Reject(_)
+    ));
+    assert!(matches!(
+        GossipError::from(OnAttestationError::UnknownHead(root)),
+        GossipError::Ignore(_)
+    ));
+}
+
+#[test]
+fn test_aggregated_block_counts_every_participant() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+    let genesis_root = store.head;
+    let genesis = Checkpoint {
+        root: genesis_root,
+        slot: Slot(0),
+    };
+    let attestation = |validator_id, slot| Attestation {
+        validator_id: Uint64(validator_id),
+        data: AttestationData {
+            slot: Slot(slot),
+            head: genesis.clone(),
+            target: genesis.clone(),
+            source: genesis.clone(),
+        },
+    };
+
+    let attestations: Vec<SignedAttestation> = [5, 3]
+        .into_iter()
+        .map(|validator_id| SignedAttestation {
+            message: attestation(validator_id, 0),
+            signature: Signature::default(),
+        })
+        .collect();
+    let (block, _, signatures) = store.states[&genesis_root]
+        .build_aggregated_block(Slot(1), ValidatorIndex(1), genesis_root, &attestations)
+        .unwrap();
+    assert_eq!(block.body.attestations.len_u64(), 1);
+
+    let signed_block: VersionedSignedBlock = SignedAggregatedBlockWithAttestation {
+        message: AggregatedBlockWithAttestation {
+            block,
+            proposer_attestation: attestation(1, 1),
+        },
+        signature: AggregatedBlockSignatures {
+            attestation_signatures: signatures,
+            proposer_signature: Signature::default(),
+        },
+    }
+    .into();
+    let root = signed_block.block_root();
+
+    on_block(&mut store, signed_block, false).unwrap();
+
+    assert!(store.blocks.contains_key(&root));
+    for validator_id in [3, 5] {
+        assert!(store
+            .latest_known_attestations
+            .contains_key(&ValidatorIndex(validator_id)));
+    }
+}
+
+#[test]
+fn test_block_with_future_proposer_attestation_is_not_stored() {
+    let (sender, mut events) = broadcast::channel(16);
+    let mut store = create_test_store().with_events(sender);
+    store.time = INTERVALS_PER_SLOT;
+    let genesis_root = store.head;
+    let genesis = Checkpoint {
+        root: genesis_root,
+        slot: Slot(0),
+    };
+
+    let (block, _, signatures) = store.states[&genesis_root]
+        .build_aggregated_block(Slot(1), ValidatorIndex(1), genesis_root, &[])
+        .unwrap();
+    let signed_block: VersionedSignedBlock = SignedAggregatedBlockWithAttestation {
+        message: AggregatedBlockWithAttestation {
+            block,
+            proposer_attestation: Attestation {
+                validator_id: Uint64(1),
+                data: AttestationData {
+                    slot: Slot(3),
+                    head: genesis.clone(),
+                    target: genesis.clone(),
+                    source: genesis,
+                },
+            },
+        },
+        signature: AggregatedBlockSignatures {
+            attestation_signatures: signatures,
+            proposer_signature: Signature::default(),
+        },
+    }
+    .into();
+    let root = signed_block.block_root();
+
+    assert_eq!(
+        on_block(&mut store, signed_block, false),
+        Err(OnBlockError::Attestation(OnAttestationError::FutureSlot {
+            slot: Slot(3),
+            current_slot: Slot(1)
+        }))
+    );
+    assert!(!store.blocks.contains_key(&root));
+    assert!(!store.states.contains_key(&root));
+    assert!(events.try_recv().is_err());
+}
@@ -0,0 +1,134 @@
+use containers::{
+    attestation::{Attestation, AttestationData, Signature, SignedAttestation},
+    checkpoint::Checkpoint,
+    Bytes32, Slot, Uint64, ValidatorIndex,
+};
+use fork_choice::proto_array::{ProtoArray, VoteSet};
+use std::collections::{HashMap, HashSet};
+
+fn root(byte: u8) -> Bytes32 {
+    Bytes32(ssz::H256::repeat_byte(byte))
+}
+
+fn vote(validator_id: u64, head: Bytes32) -> (ValidatorIndex, SignedAttestation) {
+    let head = Checkpoint {
+        root: head,
+        slot: Slot(0),
+    };
+    let attestation = SignedAttestation {
+        message: Attestation {
+            validator_id: Uint64(validator_id),
+            data: AttestationData {
+                slot: Slot(0),
+                head: head.clone(),
+                target: head,
+                source: Checkpoint::default(),
+            },
+        },
+        signature: Signature::default(),
+    };
+    (ValidatorIndex(validator_id), attestation)
+}
+
+/// genesis(1) <- a1(2) <- a2(3), genesis <- b1(4) <- b2(5)
+fn two_forks() -> ProtoArray {
+    ProtoArray::from_blocks([
+        (root(5), root(4), Slot(2)),
+        (root(3), root(2), Slot(2)),
+        (root(1), Bytes32::default(), Slot(0)),
+        (root(4), root(1), Slot(1)),
+        (root(2), root(1), Slot(1)),
+    ])
+}
+
+#[test]
+fn test_weights_follow_vote_changes() {
+    let mut proto_array = two_forks();
+    let mut votes = HashMap::from([vote(0, root(3)), vote(1, root(3)), vote(2, root(5))]);
+
+    proto_array.apply_votes(VoteSet::Known, &votes);
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(1)), Some(3));
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(2)), Some(2));
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(4)), Some(1));
+    assert_eq!(
+        proto_array.find_head(VoteSet::Known, root(1), 0),
+        Some(root(3))
+    );
+
+    // Two validators move to the other fork and one vote is dropped
+    votes.extend([vote(0, root(5)), vote(1, root(4))]);
+    votes.remove(&ValidatorIndex(2));
+    proto_array.apply_votes(VoteSet::Known, &votes);
+
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(2)), Some(0));
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(4)), Some(2));
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(5)), Some(1));
+    assert_eq!(
+        proto_array.find_head(VoteSet::Known, root(1), 0),
+        Some(root(5))
+    );
+
+    // Vote sets are independent
+    assert_eq!(proto_array.weight(VoteSet::New, &root(1)), Some(0));
+}
+
+#[test]
+fn test_ties_go_to_highest_root_and_min_votes_filters() {
+    let mut proto_array = two_forks();
+    let votes = HashMap::from([vote(0, root(3)), vote(1, root(5))]);
+    proto_array.apply_votes(VoteSet::New, &votes);
+
+    assert_eq!(
+        proto_array.find_head(VoteSet::New, root(1), 0),
+        Some(root(5))
+    );
+    assert_eq!(
+        proto_array.find_head(VoteSet::New, root(1), 2),
+        Some(root(1))
+    );
+    assert_eq!(proto_array.find_head(VoteSet::New, root(9), 0), None);
+}
+
+#[test]
+fn test_vote_for_unknown_block_counts_once_it_arrives() {
+    let mut proto_array = two_forks();
+    let votes = HashMap::from([vote(0, root(6))]);
+
+    proto_array.apply_votes(VoteSet::Known, &votes);
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(1)), Some(0));
+
+    proto_array.on_block(root(6), root(3), Slot(3));
+    proto_array.apply_votes(VoteSet::Known, &votes);
+
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(2)), Some(1));
+    assert_eq!(
+        proto_array.find_head(VoteSet::Known, root(1), 0),
+        Some(root(6))
+    );
+}
+
+#[test]
+fn test_prune_keeps_weights_consistent() {
+    let mut proto_array = two_forks();
+    let mut votes = HashMap::from([vote(0, root(3)), vote(1, root(5))]);
+    proto_array.apply_votes(VoteSet::Known, &votes);
+
+    // Finalize a1: the b fork goes
+    proto_array.prune(&HashSet::from([root(1), root(2), root(3)]));
+
+    assert_eq!(proto_array.len(), 3);
+    assert!(!proto_array.contains(&root(5)));
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(1)), Some(1));
+
+    // The pruned vote moving onto the kept chain is counted once
+    votes.extend([vote(1, root(3))]);
+    proto_array.apply_votes(VoteSet::Known, &votes);
+    assert_eq!(proto_array.weight(VoteSet::Known, &root(3)), Some(2));
+
+    proto_array.on_block(root(7), root(3), Slot(3));
+    assert_eq!(proto_array.get(&root(7)).unwrap().parent, Some(2));
+    assert_eq!(
+        proto_array.find_head(VoteSet::Known, root(2), 0),
+        Some(root(7))
+    );
+}
@@ -0,0 +1,148 @@
+use super::common::create_test_store;
+use containers::{
+    attestation::{Attestation, AttestationData, Signature, SignedAttestation},
+    block::{Block, BlockBody, BlockWithAttestation, SignedBlockWithAttestation},
+    checkpoint::Checkpoint,
+    Bytes32, Slot, Uint64, ValidatorIndex,
+};
+use fork_choice::error::PruneError;
+use fork_choice::store::{prune_finalized, PruneStats, Store};
+use ssz::SszHash;
+
+fn add_block(store: &mut Store, parent_root: Bytes32, slot: u64, proposer: u64) -> Bytes32 {
+    let block = Block {
+        slot: Slot(slot),
+        proposer_index: ValidatorIndex(proposer),
+        parent_root,
+        state_root: Bytes32::default(),
+        body: BlockBody::default(),
+    };
+    let root = Bytes32(block.hash_tree_root());
+
+    let signed_block = SignedBlockWithAttestation {
+        message: BlockWithAttestation {
+            block,
+            proposer_attestation: Default::default(),
+        },
+        signature: Default::default(),
+    };
+
+    let state = store.states[&parent_root].clone();
+    store.blocks.insert(root, signed_block.into());
+    store.states.insert(root, state);
+    root
+}
+
+fn attestation_for(validator_id: u64, head: Checkpoint) -> SignedAttestation {
+    SignedAttestation {
+        message: Attestation {
+            validator_id: Uint64(validator_id),
+            data: AttestationData {
+                slot: head.slot,
+                head: head.clone(),
+                target: head,
+                source: Checkpoint::default(),
+            },
+        },
+        signature: Signature::default(),
+    }
+}
+
+#[test]
+fn test_prune_drops_conflicting_forks_and_old_states() {
+    let mut store = create_test_store();
+    let genesis_root = store.head;
+
+    // Canonical chain: genesis <- a1 <- a2 <- a3
+    let a1 = add_block(&mut store, genesis_root, 1, 1);
+    let a2 = add_block(&mut store, a1, 2, 2);
+    let a3 = add_block(&mut store, a2, 3, 3);
+
+    // Conflicting fork: genesis <- b1 <- b2
+    let b1 = add_block(&mut store, genesis_root, 1, 5);
+    let b2 = add_block(&mut store, b1, 2, 6);
+
+    let finalized = Checkpoint {
+        root: a2,
+        slot: Slot(2),
+    };
+    store.latest_finalized = finalized.clone();
+    store.latest_justified = finalized;
+    store.head = a3;
+
+    store.latest_known_attestations.insert(
+        ValidatorIndex(1),
+        attestation_for(
+            1,
+            Checkpoint {
+                root: b2,
+                slot: Slot(2),
+            },
+        ),
+    );
+    store.latest_known_attestations.insert(
+        ValidatorIndex(2),
+        attestation_for(
+            2,
+            Checkpoint {
+                root: a3,
+                slot: Slot(3),
+            },
+        ),
+    );
+
+    let orphan = store.blocks[&b2].clone();
+    store.blocks_queue.insert(Bytes32::default(), vec![orphan]);
+
+    let stats = prune_finalized(&mut store).unwrap();
+
+    assert_eq!(
+        stats,
+        PruneStats {
+            blocks: 2,
+            states: 4,
+            queued_blocks: 1,
+            attestations: 1,
+        }
+    );
+    assert_eq!(store.prune_stats, stats);
+
+    for root in [genesis_root, a1, a2, a3] {
+        assert!(store.blocks.contains_key(&root));
+    }
+    for root in [b1, b2] {
+        assert!(!store.blocks.contains_key(&root));
+    }
+
+    assert_eq!(store.states.len(), 2);
+    assert!(store.states.contains_key(&a2));
+    assert!(store.states.contains_key(&a3));
+
+    assert!(store.blocks_queue.is_empty());
+    assert!(store
+        .latest_known_attestations
+        .contains_key(&ValidatorIndex(2)));
+    assert!(!store
+        .latest_known_attestations
+        .contains_key(&ValidatorIndex(1)));
+}
+
+#[test]
+fn test_prune_fails_without_finalized_block() {
+    let mut store = create_test_store();
+    store.latest_finalized = Checkpoint {
+        root: Bytes32(ssz::H256::repeat_byte(7)),
+        slot: Slot(5),
+    };
+
+    assert_eq!(
+        prune_finalized(&mut store),
+        Err(PruneError::MissingFinalizedBlock {
+            root: Bytes32(ssz::H256::repeat_byte(7)),
+            slot: Slot(5),
+        })
+    );
+    assert_eq!(store.prune_stats, PruneStats::default());
+    assert_eq!(store.blocks.len(), 1);
+    assert_eq!(store.states.len(), 1);
+}
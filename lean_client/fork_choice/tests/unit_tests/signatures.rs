@@ -0,0 +1,119 @@
+use super::common::create_test_store;
+use containers::{
+    attestation::{
+        Attestation, AttestationData, Signature, SignedAggregatedAttestations, SignedAttestation,
+    },
+    block::{Block, BlockBody, BlockWithAttestation, SignedBlockWithAttestation},
+    checkpoint::Checkpoint,
+    Bytes32, Slot, StateTransitionError, Uint64, ValidatorIndex,
+};
+use fork_choice::error::{OnAttestationError, OnBlockError};
+use fork_choice::handlers::{on_aggregated_attestation, on_attestation, on_block};
+
+#[test]
+fn test_block_without_signatures_is_rejected() {
+    let mut store = create_test_store().with_signature_verification(true);
+    let genesis_root = store.head;
+
+    let signed_block = SignedBlockWithAttestation {
+        message: BlockWithAttestation {
+            block: Block {
+                slot: Slot(1),
+                proposer_index: ValidatorIndex(1),
+                parent_root: genesis_root,
+                state_root: Bytes32::default(),
+                body: BlockBody::default(),
+            },
+            proposer_attestation: Attestation::default(),
+        },
+        signature: Default::default(),
+    };
+
+    let err = on_block(&mut store, signed_block, false).unwrap_err();
+
+    assert!(
+        matches!(
+            err,
+            OnBlockError::Transition(StateTransitionError::InvalidSignatures(_))
+        ),
+        "{err}"
+    );
+    assert_eq!(store.blocks.len(), 1);
+}
+
+#[test]
+fn test_gossip_attestation_from_unknown_validator_is_rejected() {
+    let mut store = create_test_store().with_signature_verification(true);
+    let head = Checkpoint {
+        root: store.head,
+        slot: Slot(0),
+    };
+
+    let signed_attestation = SignedAttestation {
+        message: Attestation {
+            validator_id: Uint64(1000),
+            data: AttestationData {
+                slot: Slot(0),
+                head: head.clone(),
+                target: head.clone(),
+                source: head,
+            },
+        },
+        signature: Signature::default(),
+    };
+
+    let err = on_attestation(&mut store, signed_attestation.clone(), false, false).unwrap_err();
+    assert!(
+        matches!(err, OnAttestationError::InvalidSignature(_)),
+        "{err}"
+    );
+    assert!(store.latest_new_attestations.is_empty());
+
+    // Gossip validation checked it already
+    on_attestation(&mut store, signed_attestation.clone(), false, true).unwrap();
+    assert!(store
+        .latest_new_attestations
+        .contains_key(&ValidatorIndex(1000)));
+
+    // Block attestations are checked together with their block
+    on_attestation(&mut store, signed_attestation, true, false).unwrap();
+    assert!(store
+        .latest_known_attestations
+        .contains_key(&ValidatorIndex(1000)));
+}
+
+#[test]
+fn test_gossip_verified_aggregate_skips_signature_check() {
+    let mut store = create_test_store().with_signature_verification(true);
+    let head = Checkpoint {
+        root: store.head,
+        slot: Slot(0),
+    };
+
+    let attestations = [1000, 1001].map(|validator_id| SignedAttestation {
+        message: Attestation {
+            validator_id: Uint64(validator_id),
+            data: AttestationData {
+                slot: Slot(0),
+                head: head.clone(),
+                target: head.clone(),
+                source: head.clone(),
+            },
+        },
+        signature: Signature::default(),
+    });
+    let aggregate = SignedAggregatedAttestations::aggregate(&attestations)
+        .unwrap()
+        .remove(0);
+
+    let err = on_aggregated_attestation(&mut store, aggregate.clone(), false).unwrap_err();
+    assert!(
+        matches!(err, OnAttestationError::InvalidSignature(_)),
+        "{err}"
+    );
+    assert!(store.latest_new_attestations.is_empty());
+
+    // Gossip validation checked them already
+    on_aggregated_attestation(&mut store, aggregate, true).unwrap();
+    assert_eq!(store.latest_new_attestations.len(), 2);
+}
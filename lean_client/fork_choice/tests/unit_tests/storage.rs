@@ -0,0 +1,34 @@
+use super::common::create_test_store;
+use containers::config::Config;
+use fork_choice::store::get_forkchoice_store_from_storage;
+use std::sync::Arc;
+use storage::{MemoryStorage, Storage};
+
+#[test]
+fn test_empty_storage_yields_no_store() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+
+    let store = get_forkchoice_store_from_storage(storage, Config { genesis_time: 1000 }).unwrap();
+
+    assert!(store.is_none());
+}
+
+#[test]
+fn test_store_round_trips_through_storage() {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+    let store = create_test_store().with_storage(storage.clone()).unwrap();
+
+    let checkpoints = storage.get_checkpoints().unwrap().unwrap();
+    assert_eq!(checkpoints.head.root, store.head);
+    assert_eq!(checkpoints.latest_finalized, store.latest_finalized);
+
+    let restored = get_forkchoice_store_from_storage(storage, store.config.clone())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(restored.head, store.head);
+    assert_eq!(restored.latest_justified, store.latest_justified);
+    assert_eq!(restored.latest_finalized, store.latest_finalized);
+    assert_eq!(restored.blocks, store.blocks);
+    assert_eq!(restored.states, store.states);
+}
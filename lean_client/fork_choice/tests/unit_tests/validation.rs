@@ -0,0 +1,246 @@
+use super::common::create_test_store;
+use containers::{
+    attestation::{
+        Attestation, AttestationData, BlockSignatures, Signature, SignedAggregatedAttestations,
+        SignedAttestation,
+    },
+    block::{
+        Block, BlockBody, BlockWithAttestation, SignedBlockWithAttestation, VersionedSignedBlock,
+    },
+    checkpoint::Checkpoint,
+    Bytes32, Slot, Uint64, ValidatorIndex,
+};
+use fork_choice::store::{Store, INTERVALS_PER_SLOT};
+use fork_choice::validation::{
+    validate_gossip_aggregate, validate_gossip_attestation, validate_gossip_block, GossipError,
+};
+
+fn child_block(parent_root: Bytes32, slot: u64, proposer: u64) -> SignedBlockWithAttestation {
+    let mut signature = BlockSignatures::default();
+    signature.push(Signature::default()).unwrap();
+
+    SignedBlockWithAttestation {
+        message: BlockWithAttestation {
+            block: Block {
+                slot: Slot(slot),
+                proposer_index: ValidatorIndex(proposer),
+                parent_root,
+                state_root: Bytes32::default(),
+                body: BlockBody::default(),
+            },
+            proposer_attestation: Attestation {
+                validator_id: Uint64(proposer),
+                data: AttestationData::default(),
+            },
+        },
+        signature,
+    }
+}
+
+fn head_attestation(store: &Store, validator_id: u64, slot: u64) -> SignedAttestation {
+    let head = Checkpoint {
+        root: store.head,
+        slot: Slot(0),
+    };
+
+    SignedAttestation {
+        message: Attestation {
+            validator_id: Uint64(validator_id),
+            data: AttestationData {
+                slot: Slot(slot),
+                head: head.clone(),
+                target: head.clone(),
+                source: head,
+            },
+        },
+        signature: Signature::default(),
+    }
+}
+
+#[test]
+fn test_valid_block_is_accepted() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let block = child_block(store.head, 1, 1);
+
+    assert_eq!(validate_gossip_block(&store, &block.into()), Ok(()));
+}
+
+#[test]
+fn test_block_from_the_future_is_ignored() {
+    let store = create_test_store();
+
+    let block = child_block(store.head, 2, 2);
+
+    assert!(matches!(
+        validate_gossip_block(&store, &block.into()),
+        Err(GossipError::Ignore(_))
+    ));
+}
+
+#[test]
+fn test_known_block_is_ignored() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let block: VersionedSignedBlock = child_block(store.head, 1, 1).into();
+    store.blocks.insert(block.block_root(), block.clone());
+
+    assert!(matches!(
+        validate_gossip_block(&store, &block),
+        Err(GossipError::Ignore(_))
+    ));
+}
+
+#[test]
+fn test_block_with_unknown_parent() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let parent_root = Bytes32(ssz::H256::repeat_byte(7));
+    let block = child_block(parent_root, 1, 1);
+
+    assert_eq!(
+        validate_gossip_block(&store, &block.into()),
+        Err(GossipError::UnknownParent(parent_root))
+    );
+}
+
+#[test]
+fn test_block_from_wrong_proposer_is_rejected() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let block = child_block(store.head, 1, 2);
+
+    assert!(matches!(
+        validate_gossip_block(&store, &block.into()),
+        Err(GossipError::Reject(_))
+    ));
+}
+
+#[test]
+fn test_block_with_bad_signatures_is_rejected() {
+    let mut store = create_test_store().with_signature_verification(true);
+    store.time = INTERVALS_PER_SLOT;
+
+    let mut block = child_block(store.head, 1, 1);
+    block.signature = BlockSignatures::default();
+
+    assert!(matches!(
+        validate_gossip_block(&store, &block.into()),
+        Err(GossipError::Reject(_))
+    ));
+}
+
+#[test]
+fn test_valid_attestation_is_accepted() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let attestation = head_attestation(&store, 3, 1);
+
+    assert_eq!(validate_gossip_attestation(&store, &attestation), Ok(()));
+}
+
+#[test]
+fn test_duplicate_attestation_is_ignored() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let attestation = head_attestation(&store, 3, 1);
+    store
+        .latest_new_attestations
+        .insert(ValidatorIndex(3), attestation.clone());
+
+    assert!(matches!(
+        validate_gossip_attestation(&store, &attestation),
+        Err(GossipError::Ignore(_))
+    ));
+}
+
+#[test]
+fn test_attestation_with_source_after_target_is_rejected() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let mut attestation = head_attestation(&store, 3, 1);
+    attestation.message.data.source.slot = Slot(1);
+
+    assert!(matches!(
+        validate_gossip_attestation(&store, &attestation),
+        Err(GossipError::Reject(_))
+    ));
+}
+
+#[test]
+fn test_attestation_from_unknown_validator_is_rejected() {
+    let mut store = create_test_store().with_signature_verification(true);
+    store.time = INTERVALS_PER_SLOT;
+
+    let attestation = head_attestation(&store, 1000, 1);
+
+    assert!(matches!(
+        validate_gossip_attestation(&store, &attestation),
+        Err(GossipError::Reject(_))
+    ));
+}
+
+fn head_aggregate(store: &Store, validator_ids: &[u64], slot: u64) -> SignedAggregatedAttestations {
+    let attestations = validator_ids
+        .iter()
+        .map(|&validator_id| head_attestation(store, validator_id, slot))
+        .collect::<Vec<_>>();
+
+    SignedAggregatedAttestations::aggregate(&attestations)
+        .unwrap()
+        .remove(0)
+}
+
+#[test]
+fn test_valid_aggregate_is_accepted() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let aggregate = head_aggregate(&store, &[1, 4], 1);
+
+    assert_eq!(validate_gossip_aggregate(&store, &aggregate), Ok(()));
+}
+
+#[test]
+fn test_aggregate_without_participants_is_rejected() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let mut aggregate = head_aggregate(&store, &[1], 1);
+    aggregate.message.aggregation_bits = Default::default();
+    aggregate.signature = Default::default();
+
+    assert!(matches!(
+        validate_gossip_aggregate(&store, &aggregate),
+        Err(GossipError::Reject(_))
+    ));
+}
+
+#[test]
+fn test_aggregate_of_known_attestations_is_ignored() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    for validator_id in [1, 4] {
+        let attestation = head_attestation(&store, validator_id, 1);
+        store
+            .latest_new_attestations
+            .insert(ValidatorIndex(validator_id), attestation);
+    }
+
+    assert!(matches!(
+        validate_gossip_aggregate(&store, &head_aggregate(&store, &[1, 4], 1)),
+        Err(GossipError::Ignore(_))
+    ));
+    assert_eq!(
+        validate_gossip_aggregate(&store, &head_aggregate(&store, &[1, 4, 5], 1)),
+        Ok(())
+    );
+}
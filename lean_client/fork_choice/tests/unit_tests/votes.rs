@@ -1,10 +1,13 @@
 use super::common::create_test_store;
 use containers::{
-    attestation::{Attestation, AttestationData, Signature, SignedAttestation},
+    attestation::{
+        Attestation, AttestationData, Signature, SignedAggregatedAttestations, SignedAttestation,
+    },
     checkpoint::Checkpoint,
     Bytes32, Slot, Uint64, ValidatorIndex,
 };
-use fork_choice::handlers::on_attestation;
+use fork_choice::error::OnAttestationError;
+use fork_choice::handlers::{on_aggregated_attestation, on_attestation};
 use fork_choice::store::{accept_new_attestations, INTERVALS_PER_SLOT};
 
 fn create_signed_attestation(
@@ -121,7 +124,7 @@ fn test_on_attestation_lifecycle() {
     // 1. Attestation from network (gossip)
     let signed_attestation_gossip = create_signed_attestation(1, slot_0, store.head);
 
-    on_attestation(&mut store, signed_attestation_gossip.clone(), false)
+    on_attestation(&mut store, signed_attestation_gossip.clone(), false, false)
         .expect("Gossip attestation valid");
 
     // Should be in new_attestations, not known_attestations
@@ -136,7 +139,8 @@ fn test_on_attestation_lifecycle() {
     );
 
     // 2. Same attestation included in a block
-    on_attestation(&mut store, signed_attestation_gossip, true).expect("Block attestation valid");
+    on_attestation(&mut store, signed_attestation_gossip, true, false)
+        .expect("Block attestation valid");
 
     assert!(store.latest_known_attestations.contains_key(&validator_idx));
     assert_eq!(
@@ -151,7 +155,7 @@ fn test_on_attestation_lifecycle() {
     store.time = 1 * INTERVALS_PER_SLOT; // Advance time
     let signed_attestation_next = create_signed_attestation(1, slot_1, store.head);
 
-    on_attestation(&mut store, signed_attestation_next, false)
+    on_attestation(&mut store, signed_attestation_next, false, false)
         .expect("Next gossip attestation valid");
 
     // Should update new_attestations
@@ -179,8 +183,31 @@ fn test_on_attestation_future_slot() {
 
     let signed_attestation = create_signed_attestation(1, future_slot, store.head);
 
-    let result = on_attestation(&mut store, signed_attestation, false);
+    let result = on_attestation(&mut store, signed_attestation, false, false);
     assert!(result.is_err());
+    assert!(matches!(result, Err(OnAttestationError::FutureSlot { .. })));
+}
+
+#[test]
+fn test_on_attestation_unknown_head_or_target() {
+    let mut store = create_test_store();
+    let unknown = Bytes32(ssz::H256::repeat_byte(7));
+
+    let unknown_head = create_signed_attestation(1, Slot(0), unknown);
+    assert_eq!(
+        on_attestation(&mut store, unknown_head, false, false),
+        Err(OnAttestationError::UnknownHead(unknown))
+    );
+
+    let mut unknown_target = create_signed_attestation(1, Slot(0), store.head);
+    unknown_target.message.data.target.root = unknown;
+    assert_eq!(
+        on_attestation(&mut store, unknown_target, true, false),
+        Err(OnAttestationError::UnknownTarget(unknown))
+    );
+
+    assert!(store.latest_new_attestations.is_empty());
+    assert!(store.latest_known_attestations.is_empty());
 }
 
 #[test]
@@ -191,7 +218,7 @@ fn test_on_attestation_update_vote() {
     // First attestation at slot 0
     let signed_attestation1 = create_signed_attestation(1, Slot(0), store.head);
 
-    on_attestation(&mut store, signed_attestation1, false).expect("First attestation valid");
+    on_attestation(&mut store, signed_attestation1, false, false).expect("First attestation valid");
     assert_eq!(
         store.latest_new_attestations[&validator_idx]
             .message
@@ -206,7 +233,8 @@ fn test_on_attestation_update_vote() {
     // Second attestation at slot 1
     let signed_attestation2 = create_signed_attestation(1, Slot(1), store.head);
 
-    on_attestation(&mut store, signed_attestation2, false).expect("Second attestation valid");
+    on_attestation(&mut store, signed_attestation2, false, false)
+        .expect("Second attestation valid");
     assert_eq!(
         store.latest_new_attestations[&validator_idx]
             .message
@@ -227,7 +255,8 @@ fn test_on_attestation_ignore_old_vote() {
     // Newer attestation first
     let signed_attestation_new = create_signed_attestation(1, Slot(2), store.head);
 
-    on_attestation(&mut store, signed_attestation_new, false).expect("New attestation valid");
+    on_attestation(&mut store, signed_attestation_new, false, false)
+        .expect("New attestation valid");
     assert_eq!(
         store.latest_new_attestations[&validator_idx]
             .message
@@ -239,7 +268,7 @@ fn test_on_attestation_ignore_old_vote() {
     // Older attestation second
     let signed_attestation_old = create_signed_attestation(1, Slot(1), store.head);
 
-    on_attestation(&mut store, signed_attestation_old, false)
+    on_attestation(&mut store, signed_attestation_old, false, false)
         .expect("Old attestation processed but ignored");
     // Should still be slot 2
     assert_eq!(
@@ -258,14 +287,15 @@ fn test_on_attestation_from_block_supersedes_new() {
 
     // First, add attestation via gossip
     let signed_attestation1 = create_signed_attestation(1, Slot(0), store.head);
-    on_attestation(&mut store, signed_attestation1, false).expect("Gossip attestation valid");
+    on_attestation(&mut store, signed_attestation1, false, false)
+        .expect("Gossip attestation valid");
 
     assert!(store.latest_new_attestations.contains_key(&validator_idx));
     assert!(!store.latest_known_attestations.contains_key(&validator_idx));
 
     // Then, add same attestation via block (on-chain)
     let signed_attestation2 = create_signed_attestation(1, Slot(0), store.head);
-    on_attestation(&mut store, signed_attestation2, true).expect("Block attestation valid");
+    on_attestation(&mut store, signed_attestation2, true, false).expect("Block attestation valid");
 
     // Should move from new to known
     assert!(!store.latest_new_attestations.contains_key(&validator_idx));
@@ -279,7 +309,8 @@ fn test_on_attestation_newer_from_block_removes_older_new() {
 
     // Add older attestation via gossip
     let signed_attestation_gossip = create_signed_attestation(1, Slot(0), store.head);
-    on_attestation(&mut store, signed_attestation_gossip, false).expect("Gossip attestation valid");
+    on_attestation(&mut store, signed_attestation_gossip, false, false)
+        .expect("Gossip attestation valid");
 
     assert_eq!(
         store.latest_new_attestations[&validator_idx]
@@ -292,7 +323,8 @@ fn test_on_attestation_newer_from_block_removes_older_new() {
     // Add newer attestation via block (on-chain)
     store.time = 1 * INTERVALS_PER_SLOT;
     let signed_attestation_block = create_signed_attestation(1, Slot(1), store.head);
-    on_attestation(&mut store, signed_attestation_block, true).expect("Block attestation valid");
+    on_attestation(&mut store, signed_attestation_block, true, false)
+        .expect("Block attestation valid");
 
     // New attestation should be removed (superseded by newer on-chain one)
     assert!(!store.latest_new_attestations.contains_key(&validator_idx));
@@ -304,3 +336,49 @@ fn test_on_attestation_newer_from_block_removes_older_new() {
         Slot(1)
     );
 }
+
+#[test]
+fn test_on_aggregated_attestation_counts_every_participant() {
+    let mut store = create_test_store();
+    store.time = INTERVALS_PER_SLOT;
+
+    let attestations = [
+        create_signed_attestation(2, Slot(1), store.head),
+        create_signed_attestation(7, Slot(1), store.head),
+    ];
+    let aggregate = SignedAggregatedAttestations::aggregate(&attestations)
+        .unwrap()
+        .remove(0);
+
+    on_aggregated_attestation(&mut store, aggregate, false).unwrap();
+
+    assert_eq!(store.latest_new_attestations.len(), 2);
+    for attestation in attestations {
+        let validator_id = ValidatorIndex(attestation.message.validator_id.0);
+        assert_eq!(store.latest_new_attestations[&validator_id], attestation);
+    }
+    assert!(store.latest_known_attestations.is_empty());
+}
+
+#[test]
+fn test_on_aggregated_attestation_is_applied_completely_or_not_at_all() {
+    let mut store = create_test_store();
+
+    let attestations = [
+        create_signed_attestation(2, Slot(1), store.head),
+        create_signed_attestation(7, Slot(1), store.head),
+    ];
+    let aggregate = SignedAggregatedAttestations::aggregate(&attestations)
+        .unwrap()
+        .remove(0);
+
+    assert!(matches!(
+        on_aggregated_attestation(&mut store, aggregate.clone(), false),
+        Err(OnAttestationError::FutureSlot { .. })
+    ));
+    assert!(store.latest_new_attestations.is_empty());
+
+    store.time = INTERVALS_PER_SLOT;
+    on_aggregated_attestation(&mut store, aggregate, false).unwrap();
+    assert_eq!(store.latest_new_attestations.len(), 2);
+}
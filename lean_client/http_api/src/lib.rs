@@ -0,0 +1,421 @@
+//! Beacon-style JSON API for inspecting a running node.
+//!
+//! Node endpoints are answered from the network side directly. Chain and fork
+//! choice endpoints need the `Store`, which belongs to the chain task, so they
+//! send an [`ApiQuery`] and wait for the answer, like the network service does
+//! with `ChainQuery`. Responses are wrapped in `{"data": ...}`, except for
+//! `/eth/v1/events`, which streams [`ChainEvent`]s as server-sent events.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Json, Router,
+};
+use containers::{ssz::SszWrite, Root, Slot};
+use fork_choice::events::ChainEvent;
+use futures::{Stream, StreamExt};
+use networking::{
+    peer_manager::PeerManager,
+    types::{ConnectionState, Direction, PeerCount},
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::{debug, info};
+
+mod query;
+
+pub use query::{
+    answer_query, ApiQuery, BlockId, FinalityCheckpoints, ForkChoiceDump, ForkChoiceNode,
+    SyncStatus,
+};
+
+const SSZ_CONTENT_TYPE: &str = "application/octet-stream";
+const CONSENSUS_VERSION_HEADER: &str = "eth-consensus-version";
+
+const EVENT_TOPICS: [&str; 5] = [
+    "head",
+    "block",
+    "attestation",
+    "justified_checkpoint",
+    "finalized_checkpoint",
+];
+
+/// How the node identifies itself on the network.
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeIdentity {
+    pub peer_id: String,
+    pub enr: Option<String>,
+    pub p2p_addresses: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct ApiContext {
+    queries: mpsc::UnboundedSender<ApiQuery>,
+    identity: Arc<NodeIdentity>,
+    peers: Arc<Mutex<PeerManager>>,
+    events: broadcast::Sender<ChainEvent>,
+}
+
+impl ApiContext {
+    pub fn new(
+        queries: mpsc::UnboundedSender<ApiQuery>,
+        identity: NodeIdentity,
+        peers: Arc<Mutex<PeerManager>>,
+        events: broadcast::Sender<ChainEvent>,
+    ) -> Self {
+        Self {
+            queries,
+            identity: Arc::new(identity),
+            peers,
+            events,
+        }
+    }
+
+    /// Send a query to the chain task and wait for the answer.
+    async fn query<T>(
+        &self,
+        query: impl FnOnce(oneshot::Sender<T>) -> ApiQuery,
+    ) -> Result<T, ApiError> {
+        let (respond_to, answer) = oneshot::channel();
+        self.queries
+            .send(query(respond_to))
+            .map_err(|_| ApiError::unavailable())?;
+        answer.await.map_err(|_| ApiError::unavailable())
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+
+    fn unavailable() -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "chain is not available".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: u16,
+    message: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.status.as_u16(),
+            message: self.message,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+#[derive(Serialize)]
+struct Data<T> {
+    data: T,
+}
+
+fn data<T: Serialize>(data: T) -> Response {
+    Json(Data { data }).into_response()
+}
+
+fn wants_ssz(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains(SSZ_CONTENT_TYPE))
+}
+
+fn ssz<E: std::fmt::Debug>(bytes: Result<Vec<u8>, E>) -> Response {
+    match bytes {
+        Ok(bytes) => ([(header::CONTENT_TYPE, SSZ_CONTENT_TYPE)], bytes).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}")).into_response(),
+    }
+}
+
+/// SSZ if the client asked for it in `Accept`, JSON otherwise.
+fn data_or_ssz<T: Serialize + SszWrite>(headers: &HeaderMap, value: T) -> Response {
+    if !wants_ssz(headers) {
+        return data(value);
+    }
+
+    ssz(value.to_ssz())
+}
+
+fn parse_id(id: &str) -> Result<BlockId, ApiError> {
+    id.parse().map_err(ApiError::bad_request)
+}
+
+pub fn router(context: ApiContext) -> Router {
+    Router::new()
+        .route("/eth/v1/node/identity", get(node_identity))
+        .route("/eth/v1/node/peers", get(node_peers))
+        .route("/eth/v1/node/peer_count", get(node_peer_count))
+        .route("/eth/v1/node/syncing", get(node_syncing))
+        .route("/eth/v1/node/version", get(node_version))
+        .route("/eth/v1/beacon/headers/{block_id}", get(block_header))
+        .route("/eth/v2/beacon/blocks/{block_id}", get(block))
+        .route(
+            "/eth/v1/beacon/states/{state_id}/finality_checkpoints",
+            get(finality_checkpoints),
+        )
+        .route("/eth/v2/debug/beacon/states/{state_id}", get(state))
+        .route("/eth/v1/debug/fork_choice", get(fork_choice))
+        .route("/eth/v1/events", get(events))
+        .with_state(context)
+}
+
+/// Serve the API on `address` until the listener fails.
+pub async fn serve(address: SocketAddr, context: ApiContext) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(address).await?;
+
+    info!(%address, "HTTP API listening");
+    axum::serve(listener, router(context)).await?;
+
+    Ok(())
+}
+
+async fn node_identity(State(context): State<ApiContext>) -> Response {
+    data(&*context.identity)
+}
+
+#[derive(Serialize)]
+struct PeerData {
+    peer_id: String,
+    state: ConnectionState,
+    direction: Direction,
+    head_slot: Option<Slot>,
+    finalized_slot: Option<Slot>,
+    reputation: i32,
+}
+
+async fn node_peers(State(context): State<ApiContext>) -> Response {
+    let peers = context
+        .peers
+        .lock()
+        .peers()
+        .map(|(peer_id, info)| PeerData {
+            peer_id: peer_id.to_string(),
+            state: info.state,
+            direction: info.direction,
+            head_slot: info.status.as_ref().map(|status| status.head.slot),
+            finalized_slot: info.status.as_ref().map(|status| status.finalized.slot),
+            reputation: info.reputation,
+        })
+        .collect::<Vec<_>>();
+
+    data(peers)
+}
+
+async fn node_peer_count(State(context): State<ApiContext>) -> Response {
+    let count: PeerCount = context.peers.lock().peer_count();
+    data(count)
+}
+
+async fn node_syncing(State(context): State<ApiContext>) -> Result<Response, ApiError> {
+    let status = context
+        .query(|respond_to| ApiQuery::SyncStatus { respond_to })
+        .await?;
+    Ok(data(status))
+}
+
+#[derive(Serialize)]
+struct Version {
+    version: String,
+}
+
+async fn node_version() -> Response {
+    data(Version {
+        version: format!("lean_client/v{}", env!("CARGO_PKG_VERSION")),
+    })
+}
+
+#[derive(Serialize)]
+struct BlockHeader {
+    root: Root,
+    slot: Slot,
+    proposer_index: u64,
+    parent_root: Root,
+    state_root: Root,
+}
+
+async fn block_header(
+    State(context): State<ApiContext>,
+    Path(block_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let block_id = parse_id(&block_id)?;
+    let (root, signed_block) = context
+        .query(|respond_to| ApiQuery::Block {
+            block_id,
+            respond_to,
+        })
+        .await?
+        .ok_or_else(|| ApiError::not_found("block not found"))?;
+
+    Ok(data(BlockHeader {
+        root,
+        slot: signed_block.slot(),
+        proposer_index: signed_block.proposer_index().0,
+        parent_root: signed_block.parent_root(),
+        state_root: signed_block.state_root(),
+    }))
+}
+
+async fn block(
+    State(context): State<ApiContext>,
+    Path(block_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let block_id = parse_id(&block_id)?;
+    let (_, signed_block) = context
+        .query(|respond_to| ApiQuery::Block {
+            block_id,
+            respond_to,
+        })
+        .await?
+        .ok_or_else(|| ApiError::not_found("block not found"))?;
+
+    // The SSZ encoding differs between block versions, so tell the client which one it got
+    let version = signed_block.version().fork();
+    let response = if wants_ssz(&headers) {
+        ssz(signed_block.to_ssz())
+    } else {
+        data(signed_block)
+    };
+
+    Ok(([(CONSENSUS_VERSION_HEADER, version)], response).into_response())
+}
+
+async fn state(
+    State(context): State<ApiContext>,
+    Path(state_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let state_id = parse_id(&state_id)?;
+    let state = context
+        .query(|respond_to| ApiQuery::State {
+            state_id,
+            respond_to,
+        })
+        .await?
+        .ok_or_else(|| ApiError::not_found("state not found"))?;
+
+    Ok(data_or_ssz(&headers, state))
+}
+
+async fn finality_checkpoints(
+    State(context): State<ApiContext>,
+    Path(state_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let state_id = parse_id(&state_id)?;
+    let checkpoints = context
+        .query(|respond_to| ApiQuery::FinalityCheckpoints {
+            state_id,
+            respond_to,
+        })
+        .await?
+        .ok_or_else(|| ApiError::not_found("state not found"))?;
+
+    Ok(data(checkpoints))
+}
+
+async fn fork_choice(State(context): State<ApiContext>) -> Result<Response, ApiError> {
+    let dump = context
+        .query(|respond_to| ApiQuery::ForkChoice { respond_to })
+        .await?;
+    Ok(data(dump))
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Comma separated list of event topics.
+    topics: String,
+}
+
+fn parse_topics(topics: &str) -> Result<Vec<&'static str>, ApiError> {
+    topics
+        .split(',')
+        .map(|topic| {
+            EVENT_TOPICS
+                .iter()
+                .find(|known| **known == topic.trim())
+                .copied()
+                .ok_or_else(|| ApiError::bad_request(format!("unknown event topic: {topic}")))
+        })
+        .collect()
+}
+
+/// Stream the requested topics until the client disconnects. A client that
+/// falls too far behind misses events rather than holding up the chain task.
+async fn events(
+    State(context): State<ApiContext>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let topics = parse_topics(&query.topics)?;
+
+    let stream = BroadcastStream::new(context.events.subscribe()).filter_map(move |event| {
+        let event = match event {
+            Ok(event) if topics.contains(&event.topic()) => Some(event),
+            Ok(_) => None,
+            Err(err) => {
+                debug!(%err, "Event subscriber lagged");
+                None
+            }
+        };
+
+        futures::future::ready(event.and_then(|event| {
+            Event::default()
+                .event(event.topic())
+                .json_data(&event)
+                .ok()
+                .map(Ok)
+        }))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_topics() {
+        assert_eq!(
+            parse_topics("head, finalized_checkpoint").unwrap(),
+            vec!["head", "finalized_checkpoint"]
+        );
+        assert!(parse_topics("head,chain_reorg").is_err());
+    }
+}
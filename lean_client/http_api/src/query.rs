@@ -0,0 +1,331 @@
+//! Store lookups answered by the chain task, which owns the `Store`.
+
+use std::str::FromStr;
+
+use containers::{block::VersionedSignedBlock, state::State, Bytes32, Checkpoint, Root, Slot};
+use fork_choice::{
+    proto_array::VoteSet,
+    store::{get_ancestor_at_slot, Store, INTERVALS_PER_SLOT},
+};
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+/// Block (or post-state) selector used in API paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockId {
+    Head,
+    Justified,
+    Finalized,
+    Slot(Slot),
+    Root(Root),
+}
+
+impl FromStr for BlockId {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        match id {
+            "head" => Ok(Self::Head),
+            "justified" => Ok(Self::Justified),
+            "finalized" => Ok(Self::Finalized),
+            _ => match id.strip_prefix("0x") {
+                Some(hex) => Bytes32::from_str(hex)
+                    .map(Self::Root)
+                    .map_err(|_| format!("invalid root: {id}")),
+                None => id
+                    .parse::<u64>()
+                    .map(|slot| Self::Slot(Slot(slot)))
+                    .map_err(|_| format!("invalid block id: {id}")),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FinalityCheckpoints {
+    pub justified: Checkpoint,
+    pub finalized: Checkpoint,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyncStatus {
+    pub head_slot: Slot,
+    pub current_slot: Slot,
+    pub sync_distance: u64,
+    pub is_syncing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ForkChoiceNode {
+    pub root: Root,
+    pub parent_root: Root,
+    pub slot: Slot,
+    /// Known attestations voting for this block or a descendant, as of the last
+    /// head update.
+    pub weight: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ForkChoiceDump {
+    pub head: Root,
+    pub justified: Checkpoint,
+    pub finalized: Checkpoint,
+    pub nodes: Vec<ForkChoiceNode>,
+}
+
+/// A lookup for the chain task. The answer goes back on `respond_to`; `None`
+/// means the block or state is not in the store.
+#[derive(Debug)]
+pub enum ApiQuery {
+    Block {
+        block_id: BlockId,
+        respond_to: oneshot::Sender<Option<(Root, VersionedSignedBlock)>>,
+    },
+    State {
+        state_id: BlockId,
+        respond_to: oneshot::Sender<Option<State>>,
+    },
+    FinalityCheckpoints {
+        state_id: BlockId,
+        respond_to: oneshot::Sender<Option<FinalityCheckpoints>>,
+    },
+    SyncStatus {
+        respond_to: oneshot::Sender<SyncStatus>,
+    },
+    ForkChoice {
+        respond_to: oneshot::Sender<ForkChoiceDump>,
+    },
+}
+
+/// Answer `query` from `store`. A dropped receiver is not an error; the HTTP
+/// client simply went away.
+pub fn answer_query(store: &Store, query: ApiQuery) {
+    match query {
+        ApiQuery::Block {
+            block_id,
+            respond_to,
+        } => {
+            let block = resolve_block_root(store, block_id)
+                .and_then(|root| Some((root, store.blocks.get(&root)?.clone())));
+            let _ = respond_to.send(block);
+        }
+        ApiQuery::State {
+            state_id,
+            respond_to,
+        } => {
+            let state = resolve_state_root(store, state_id)
+                .and_then(|root| store.states.get(&root))
+                .cloned();
+            let _ = respond_to.send(state);
+        }
+        ApiQuery::FinalityCheckpoints {
+            state_id,
+            respond_to,
+        } => {
+            let checkpoints = match state_id {
+                // Fork choice may be ahead of any single state
+                BlockId::Head => Some(FinalityCheckpoints {
+                    justified: store.latest_justified.clone(),
+                    finalized: store.latest_finalized.clone(),
+                }),
+                _ => resolve_state_root(store, state_id)
+                    .and_then(|root| store.states.get(&root))
+                    .map(|state| FinalityCheckpoints {
+                        justified: state.latest_justified.clone(),
+                        finalized: state.latest_finalized.clone(),
+                    }),
+            };
+            let _ = respond_to.send(checkpoints);
+        }
+        ApiQuery::SyncStatus { respond_to } => {
+            let _ = respond_to.send(sync_status(store));
+        }
+        ApiQuery::ForkChoice { respond_to } => {
+            let _ = respond_to.send(fork_choice_dump(store));
+        }
+    }
+}
+
+fn resolve_block_root(store: &Store, block_id: BlockId) -> Option<Root> {
+    let root = match block_id {
+        BlockId::Head => store.head,
+        BlockId::Justified => store.latest_justified.root,
+        BlockId::Finalized => store.latest_finalized.root,
+        BlockId::Slot(slot) => {
+            let root = get_ancestor_at_slot(store, store.head, slot)?;
+            // Empty slots resolve to an earlier block, which is not what was asked for
+            if store.blocks.get(&root)?.slot() != slot {
+                return None;
+            }
+            root
+        }
+        BlockId::Root(root) => root,
+    };
+
+    store.blocks.contains_key(&root).then_some(root)
+}
+
+/// States are keyed by block root; a state root is accepted too.
+fn resolve_state_root(store: &Store, state_id: BlockId) -> Option<Root> {
+    if let BlockId::Root(root) = state_id {
+        if store.states.contains_key(&root) {
+            return Some(root);
+        }
+
+        return store
+            .blocks
+            .iter()
+            .find(|(_, block)| block.state_root() == root)
+            .map(|(block_root, _)| *block_root);
+    }
+
+    resolve_block_root(store, state_id)
+}
+
+fn sync_status(store: &Store) -> SyncStatus {
+    let head_slot = store
+        .blocks
+        .get(&store.head)
+        .map(|block| block.slot())
+        .unwrap_or_default();
+    let current_slot = Slot(store.time / INTERVALS_PER_SLOT);
+    let sync_distance = current_slot.0.saturating_sub(head_slot.0);
+
+    SyncStatus {
+        head_slot,
+        current_slot,
+        sync_distance,
+        // A missed proposal leaves the head one slot behind without us being out of sync
+        is_syncing: sync_distance > 1,
+    }
+}
+
+fn fork_choice_dump(store: &Store) -> ForkChoiceDump {
+    let mut nodes = store
+        .blocks
+        .iter()
+        .map(|(root, block)| ForkChoiceNode {
+            root: *root,
+            parent_root: block.parent_root(),
+            slot: block.slot(),
+            weight: store
+                .proto_array
+                .weight(VoteSet::Known, root)
+                .unwrap_or_default() as u64,
+        })
+        .collect::<Vec<_>>();
+    nodes.sort_by_key(|node| (node.slot.0, node.root));
+
+    ForkChoiceDump {
+        head: store.head,
+        justified: store.latest_justified.clone(),
+        finalized: store.latest_finalized.clone(),
+        nodes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use containers::{
+        block::{Block, BlockWithAttestation, SignedBlockWithAttestation},
+        config::Config,
+        ssz::SszHash,
+        validator::Validator,
+        Uint64, ValidatorIndex,
+    };
+    use fork_choice::store::get_forkchoice_store;
+
+    use super::*;
+
+    fn genesis_store() -> (Store, Root) {
+        let state =
+            State::generate_genesis_with_validators(Uint64(1000), vec![Validator::default(); 4]);
+        let block = Block {
+            slot: Slot(0),
+            proposer_index: ValidatorIndex(0),
+            parent_root: Bytes32::default(),
+            state_root: Bytes32(state.hash_tree_root()),
+            body: Default::default(),
+        };
+        let root = Bytes32(block.hash_tree_root());
+        let signed_block = SignedBlockWithAttestation {
+            message: BlockWithAttestation {
+                block,
+                proposer_attestation: Default::default(),
+            },
+            signature: Default::default(),
+        };
+
+        let store = get_forkchoice_store(state, signed_block, Config { genesis_time: 1000 });
+        (store, root)
+    }
+
+    fn ask<T>(store: &Store, query: impl FnOnce(oneshot::Sender<T>) -> ApiQuery) -> T {
+        let (respond_to, mut answer) = oneshot::channel();
+        answer_query(store, query(respond_to));
+        answer.try_recv().unwrap()
+    }
+
+    #[test]
+    fn test_block_id_parsing() {
+        assert_eq!("head".parse(), Ok(BlockId::Head));
+        assert_eq!("finalized".parse(), Ok(BlockId::Finalized));
+        assert_eq!("12".parse(), Ok(BlockId::Slot(Slot(12))));
+
+        let root = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            root.parse(),
+            Ok(BlockId::Root(Bytes32::from_str(&"ab".repeat(32)).unwrap()))
+        );
+
+        assert!("latest".parse::<BlockId>().is_err());
+        assert!("0x1234".parse::<BlockId>().is_err());
+    }
+
+    #[test]
+    fn test_block_lookups() {
+        let (store, root) = genesis_store();
+
+        for block_id in [BlockId::Head, BlockId::Slot(Slot(0)), BlockId::Root(root)] {
+            let block = ask(&store, |respond_to| ApiQuery::Block {
+                block_id,
+                respond_to,
+            });
+            assert_eq!(block.map(|(block_root, _)| block_root), Some(root));
+        }
+
+        let missing = ask(&store, |respond_to| ApiQuery::Block {
+            block_id: BlockId::Slot(Slot(5)),
+            respond_to,
+        });
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn test_state_lookup_by_state_root() {
+        let (store, root) = genesis_store();
+        let state_root = store.blocks[&root].state_root();
+
+        let state = ask(&store, |respond_to| ApiQuery::State {
+            state_id: BlockId::Root(state_root),
+            respond_to,
+        });
+
+        assert_eq!(state.as_ref(), store.states.get(&root));
+    }
+
+    #[test]
+    fn test_sync_status_and_fork_choice_dump() {
+        let (mut store, root) = genesis_store();
+        store.time = 3 * INTERVALS_PER_SLOT;
+
+        let status = ask(&store, |respond_to| ApiQuery::SyncStatus { respond_to });
+        assert_eq!(status.sync_distance, 3);
+        assert!(status.is_syncing);
+
+        let dump = ask(&store, |respond_to| ApiQuery::ForkChoice { respond_to });
+        assert_eq!(dump.head, root);
+        assert_eq!(dump.nodes.len(), 1);
+        assert_eq!(dump.nodes[0].weight, 0);
+    }
+}
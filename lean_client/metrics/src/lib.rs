@@ -0,0 +1,247 @@
+//! Prometheus metrics for the lean client.
+//!
+//! All metrics live in the default Prometheus registry and are registered on first
+//! use, so any crate can record them without a handle being passed around.
+//! [`serve`] exposes the registry on `/metrics`.
+
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use axum::{http::header::CONTENT_TYPE, http::StatusCode, response::IntoResponse, routing::get};
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, Histogram, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use tracing::info;
+
+// Chain
+
+pub static HEAD_SLOT: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("lean_head_slot", "Slot of the fork choice head block").unwrap()
+});
+
+pub static JUSTIFIED_SLOT: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "lean_justified_slot",
+        "Slot of the latest justified checkpoint"
+    )
+    .unwrap()
+});
+
+pub static FINALIZED_SLOT: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "lean_finalized_slot",
+        "Slot of the latest finalized checkpoint"
+    )
+    .unwrap()
+});
+
+/// Store contents by `kind`: blocks, states, queued_blocks, new_attestations,
+/// known_attestations.
+pub static STORE_SIZE: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "lean_store_size",
+        "Number of items held by the store",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+// Fork choice
+
+pub static BLOCKS_IMPORTED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("lean_blocks_imported_total", "Blocks added to the store").unwrap()
+});
+
+pub static BLOCKS_QUEUED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "lean_blocks_queued_total",
+        "Blocks queued because their parent is not known yet"
+    )
+    .unwrap()
+});
+
+pub static BLOCKS_REJECTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_blocks_rejected_total",
+        "Blocks that failed to import, by reason",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+pub static ON_BLOCK_SECONDS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "lean_fork_choice_on_block_seconds",
+        "Time to import a block whose parent is known"
+    )
+    .unwrap()
+});
+
+/// Entries removed from the store after finalization, by `kind`: blocks, states,
+/// queued_blocks, attestations.
+pub static PRUNED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_pruned_total",
+        "Entries pruned from the store after finalization",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+pub static PRUNE_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_prune_failures_total",
+        "Finalizations after which the store could not be pruned, by reason",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+pub static STATE_TRANSITION_SECONDS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "lean_state_transition_seconds",
+        "Time spent in the block state transition"
+    )
+    .unwrap()
+});
+
+// Network
+
+/// Received gossip messages by `topic` kind and validation `verdict`.
+pub static GOSSIP_MESSAGES_RECEIVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_gossip_messages_received_total",
+        "Gossip messages received, by topic and validation verdict",
+        &["topic", "verdict"]
+    )
+    .unwrap()
+});
+
+pub static GOSSIP_MESSAGES_PUBLISHED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_gossip_messages_published_total",
+        "Gossip messages published by this node, by topic",
+        &["topic"]
+    )
+    .unwrap()
+});
+
+/// Req/resp requests by `protocol` and `direction` (inbound or outbound).
+pub static REQ_RESP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_req_resp_requests_total",
+        "Req/resp requests sent and received",
+        &["protocol", "direction"]
+    )
+    .unwrap()
+});
+
+pub static REQ_RESP_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_req_resp_failures_total",
+        "Req/resp requests that failed",
+        &["protocol", "direction"]
+    )
+    .unwrap()
+});
+
+pub static CONNECTED_PEERS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "lean_connected_peers",
+        "Connected peers by connection direction",
+        &["direction"]
+    )
+    .unwrap()
+});
+
+// Validator
+
+pub static BLOCKS_PRODUCED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_validator_blocks_produced_total",
+        "Blocks produced, by validator index",
+        &["validator_index"]
+    )
+    .unwrap()
+});
+
+pub static ATTESTATIONS_PRODUCED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_validator_attestations_produced_total",
+        "Attestations produced, by validator index",
+        &["validator_index"]
+    )
+    .unwrap()
+});
+
+pub static AGGREGATES_PRODUCED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "lean_validator_aggregates_produced_total",
+        "Subnet aggregates produced, by aggregating validator index",
+        &["validator_index"]
+    )
+    .unwrap()
+});
+
+pub static KEY_REMAINING_SIGNATURES: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "lean_validator_key_remaining_signatures",
+        "Epochs an XMSS key can still sign at, by validator index",
+        &["validator_index"]
+    )
+    .unwrap()
+});
+
+/// Everything in the default registry in the Prometheus text format.
+pub fn gather() -> Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+/// Serve `/metrics` on `address` until the listener fails.
+pub async fn serve(address: SocketAddr) -> Result<()> {
+    let app = axum::Router::new().route("/metrics", get(metrics));
+    let listener = tokio::net::TcpListener::bind(address).await?;
+
+    info!(%address, "Metrics server listening");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn metrics() -> impl IntoResponse {
+    match gather() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            body,
+        ),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            [(CONTENT_TYPE, "text/plain")],
+            err.to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather_includes_recorded_metrics() {
+        HEAD_SLOT.set(42);
+        BLOCKS_REJECTED
+            .with_label_values(&["state_transition"])
+            .inc();
+
+        let text = gather().unwrap();
+
+        assert!(text.contains("lean_head_slot 42"));
+        assert!(text.contains(r#"lean_blocks_rejected_total{reason="state_transition"} 1"#));
+    }
+}
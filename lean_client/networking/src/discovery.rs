@@ -0,0 +1,224 @@
+//! discv5 peer discovery.
+//!
+//! Publishes a signed ENR for the node and runs random node lookups to find more
+//! peers. Discovered QUIC addresses are collected in [`DiscoveredPeers`] and dialed
+//! by the network service like any other [`BootnodeSource`].
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Result, anyhow};
+use discv5::{ConfigBuilder, Discv5, Enr, ListenConfig, enr::CombinedKey, enr::NodeId};
+use libp2p::{Multiaddr, multiaddr::Protocol};
+use libp2p_identity::Keypair;
+use parking_lot::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::bootnodes::BootnodeSource;
+use crate::enr_ext::{CombinedKeyExt, EnrExt, QUIC_ENR_KEY, QUIC6_ENR_KEY};
+
+/// ENR key holding the fork name the node follows, e.g. `devnet0`.
+pub const FORK_ENR_KEY: &str = "fork";
+
+/// QUIC addresses found by discovery that have not been dialed yet.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveredPeers(Arc<Mutex<Vec<Multiaddr>>>);
+
+impl DiscoveredPeers {
+    fn extend(&self, addrs: impl IntoIterator<Item = Multiaddr>) {
+        self.0.lock().extend(addrs);
+    }
+}
+
+impl BootnodeSource for DiscoveredPeers {
+    /// Takes the addresses found since the last call.
+    fn to_multiaddrs(&self) -> Vec<Multiaddr> {
+        std::mem::take(&mut *self.0.lock())
+    }
+}
+
+pub struct Discovery {
+    discv5: Arc<Discv5>,
+    fork: String,
+    found: DiscoveredPeers,
+    query_running: Arc<AtomicBool>,
+}
+
+impl Discovery {
+    /// Start discv5 on `listen_address:discovery_port`, advertising `quic_port` for
+    /// libp2p, and seed the routing table with `bootnodes`.
+    pub async fn new(
+        local_key: &Keypair,
+        listen_address: IpAddr,
+        discovery_port: u16,
+        quic_port: u16,
+        fork: String,
+        bootnodes: &[Enr],
+    ) -> Result<Self> {
+        let enr_key = CombinedKey::from_libp2p(local_key.clone())?;
+        let local_enr = build_enr(&enr_key, listen_address, discovery_port, quic_port, &fork)?;
+        info!(enr = %local_enr, peer_id = %local_enr.peer_id(), "Local ENR");
+
+        let config =
+            ConfigBuilder::new(ListenConfig::from_ip(listen_address, discovery_port)).build();
+        let mut discv5: Discv5 = Discv5::new(local_enr, enr_key, config)
+            .map_err(|err| anyhow!("Failed to create discv5: {err}"))?;
+
+        for enr in bootnodes {
+            if let Err(err) = discv5.add_enr(enr.clone()) {
+                warn!(%enr, err, "Failed to add bootnode ENR");
+            }
+        }
+
+        discv5
+            .start()
+            .await
+            .map_err(|err| anyhow!("Failed to start discv5: {err:?}"))?;
+
+        Ok(Self {
+            discv5: Arc::new(discv5),
+            fork,
+            found: DiscoveredPeers::default(),
+            query_running: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn local_enr(&self) -> Enr {
+        self.discv5.local_enr()
+    }
+
+    pub fn found(&self) -> &DiscoveredPeers {
+        &self.found
+    }
+
+    /// Look up a random node id unless a lookup is already running. `on_done` runs
+    /// once the results have been added to [`Self::found`].
+    pub fn start_query(&self, on_done: impl FnOnce() + Send + 'static) {
+        if self.query_running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let discv5 = self.discv5.clone();
+        let fork = self.fork.clone();
+        let found = self.found.clone();
+        let query_running = self.query_running.clone();
+
+        tokio::spawn(async move {
+            match discv5.find_node(NodeId::random()).await {
+                Ok(enrs) => {
+                    let addrs = enrs
+                        .iter()
+                        .filter(|enr| is_on_fork(enr, &fork))
+                        .flat_map(quic_multiaddrs)
+                        .collect::<Vec<_>>();
+                    debug!(
+                        found = enrs.len(),
+                        dialable = addrs.len(),
+                        "Discovery query finished"
+                    );
+                    found.extend(addrs);
+                }
+                Err(err) => debug!(?err, "Discovery query failed"),
+            }
+
+            query_running.store(false, Ordering::Release);
+            on_done();
+        });
+    }
+}
+
+/// Build and sign the local ENR. An unspecified `ip` is left out; discv5 fills in
+/// the externally observed address later.
+pub fn build_enr(
+    enr_key: &CombinedKey,
+    ip: IpAddr,
+    discovery_port: u16,
+    quic_port: u16,
+    fork: &str,
+) -> Result<Enr> {
+    let mut builder = Enr::builder();
+
+    if !ip.is_unspecified() {
+        builder.ip(ip);
+    }
+
+    match ip {
+        IpAddr::V4(_) => builder
+            .udp4(discovery_port)
+            .add_value(QUIC_ENR_KEY, &quic_port),
+        IpAddr::V6(_) => builder
+            .udp6(discovery_port)
+            .add_value(QUIC6_ENR_KEY, &quic_port),
+    };
+    builder.add_value(FORK_ENR_KEY, &fork);
+
+    builder
+        .build(enr_key)
+        .map_err(|err| anyhow!("Failed to build ENR: {err:?}"))
+}
+
+/// Records without a fork field are kept; they may belong to plain bootnodes.
+fn is_on_fork(enr: &Enr, fork: &str) -> bool {
+    enr.get_decodable::<String>(FORK_ENR_KEY)
+        .and_then(Result::ok)
+        .is_none_or(|enr_fork| enr_fork == fork)
+}
+
+/// Dialable QUIC addresses of `enr`, each ending in the peer id.
+pub fn quic_multiaddrs(enr: &Enr) -> Vec<Multiaddr> {
+    let peer_id = enr.peer_id();
+    enr.multiaddr_quic()
+        .into_iter()
+        .map(|addr| addr.with(Protocol::P2p(peer_id)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_local_enr_advertises_quic_and_fork() {
+        let keypair = Keypair::generate_secp256k1();
+        let enr_key = CombinedKey::from_libp2p(keypair.clone()).unwrap();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        let enr = build_enr(&enr_key, ip, 9000, 9001, "devnet0").unwrap();
+
+        assert_eq!(enr.peer_id(), keypair.public().to_peer_id());
+        assert_eq!(enr.udp4(), Some(9000));
+        assert_eq!(enr.quic4(), Some(9001));
+        assert!(is_on_fork(&enr, "devnet0"));
+        assert!(!is_on_fork(&enr, "devnet1"));
+
+        let expected: Multiaddr = format!("/ip4/10.0.0.1/udp/9001/quic-v1/p2p/{}", enr.peer_id())
+            .parse()
+            .unwrap();
+        assert_eq!(quic_multiaddrs(&enr), vec![expected]);
+    }
+
+    #[test]
+    fn test_unspecified_ip_is_left_out() {
+        let enr_key = CombinedKey::from_libp2p(Keypair::generate_secp256k1()).unwrap();
+        let ip = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+        let enr = build_enr(&enr_key, ip, 9000, 9001, "devnet0").unwrap();
+
+        assert_eq!(enr.ip4(), None);
+        assert!(quic_multiaddrs(&enr).is_empty());
+    }
+
+    #[test]
+    fn test_discovered_peers_are_taken_once() {
+        let found = DiscoveredPeers::default();
+        let addr: Multiaddr = "/ip4/10.0.0.1/udp/9001/quic-v1".parse().unwrap();
+
+        found.extend([addr.clone()]);
+
+        assert_eq!(found.to_multiaddrs(), vec![addr]);
+        assert!(found.to_multiaddrs().is_empty());
+    }
+}
@@ -1,6 +1,9 @@
+use crate::gossipsub::scoring;
 use crate::gossipsub::topic::GossipsubTopic;
 use crate::types::MESSAGE_DOMAIN_VALID_SNAPPY;
-use libp2p::gossipsub::{Config, ConfigBuilder, Message, MessageId, ValidationMode};
+use libp2p::gossipsub::{
+    Config, ConfigBuilder, Message, MessageId, PeerScoreParams, PeerScoreThresholds, ValidationMode,
+};
 use sha2::Digest;
 use sha2::Sha256;
 use std::time::Duration;
@@ -9,6 +12,9 @@ use std::time::Duration;
 pub struct GossipsubConfig {
     pub config: Config,
     pub topics: Vec<GossipsubTopic>,
+    /// Size of the validator set, used to derive the expected attestation rate
+    /// for peer scoring.
+    pub active_validators: u64,
 }
 
 impl GossipsubConfig {
@@ -46,12 +52,26 @@ impl GossipsubConfig {
         GossipsubConfig {
             config,
             topics: Vec::new(),
+            active_validators: 0,
         }
     }
 
     pub fn set_topics(&mut self, topics: Vec<GossipsubTopic>) {
         self.topics = topics;
     }
+
+    pub fn set_active_validators(&mut self, active_validators: u64) {
+        self.active_validators = active_validators;
+    }
+
+    /// Peer scoring parameters for the configured topics.
+    pub fn score_params(&self) -> PeerScoreParams {
+        scoring::peer_score_params(&self.topics, self.config.mesh_n(), self.active_validators)
+    }
+
+    pub fn score_thresholds(&self) -> PeerScoreThresholds {
+        scoring::peer_score_thresholds()
+    }
 }
 
 /// Computes the message ID according to leanSpec:
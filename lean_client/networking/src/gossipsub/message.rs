@@ -1,25 +1,59 @@
 use crate::gossipsub::topic::GossipsubKind;
 use crate::gossipsub::topic::GossipsubTopic;
+use crate::gossipsub::topic::uses_attestation_subnets;
 use containers::SignedAttestation;
-use containers::SignedBlockWithAttestation;
 use containers::ssz::SszReadDefault;
+use containers::{BlockVersion, SignedAggregatedAttestations, VersionedSignedBlock};
 use libp2p::gossipsub::TopicHash;
 
 pub enum GossipsubMessage {
-    Block(SignedBlockWithAttestation),
+    Block(VersionedSignedBlock),
     Attestation(SignedAttestation),
+    Aggregate(SignedAggregatedAttestations),
 }
 
 impl GossipsubMessage {
+    /// Topic kind the message is gossiped on in `fork`.
+    pub fn kind(&self, fork: &str) -> GossipsubKind {
+        match self {
+            Self::Block(_) => GossipsubKind::Block,
+            Self::Attestation(_) if !uses_attestation_subnets(fork) => GossipsubKind::Attestation,
+            Self::Attestation(attestation) => {
+                GossipsubKind::AttestationSubnet(attestation.message.subnet_id())
+            }
+            Self::Aggregate(_) => GossipsubKind::Aggregation,
+        }
+    }
+
+    /// Blocks are decoded in the format of the topic's fork. Attestations on a
+    /// subnet topic must be on their validator's subnet.
     pub fn decode(topic: &TopicHash, data: &[u8]) -> Result<Self, String> {
-        match GossipsubTopic::decode(topic)?.kind {
-            GossipsubKind::Block => Ok(Self::Block(
-                SignedBlockWithAttestation::from_ssz_default(data)
-                    .map_err(|e| format!("{:?}", e))?,
-            )),
+        let topic = GossipsubTopic::decode(topic)?;
+        match topic.kind {
+            GossipsubKind::Block => {
+                let version = BlockVersion::from_fork(&topic.fork)
+                    .ok_or_else(|| format!("No block format for fork {}", topic.fork))?;
+                Ok(Self::Block(VersionedSignedBlock::from_ssz(version, data)?))
+            }
             GossipsubKind::Attestation => Ok(Self::Attestation(
                 SignedAttestation::from_ssz_default(data).map_err(|e| format!("{:?}", e))?,
             )),
+            GossipsubKind::AttestationSubnet(subnet_id) => {
+                let attestation =
+                    SignedAttestation::from_ssz_default(data).map_err(|e| format!("{:?}", e))?;
+                if attestation.message.subnet_id() != subnet_id {
+                    return Err(format!(
+                        "Attestation of validator {} belongs to subnet {}, not {subnet_id}",
+                        attestation.message.validator_id.0,
+                        attestation.message.subnet_id()
+                    ));
+                }
+                Ok(Self::Attestation(attestation))
+            }
+            GossipsubKind::Aggregation => Ok(Self::Aggregate(
+                SignedAggregatedAttestations::from_ssz_default(data)
+                    .map_err(|e| format!("{:?}", e))?,
+            )),
         }
     }
 }
@@ -1,5 +1,6 @@
 pub mod config;
 pub mod message;
+pub mod scoring;
 pub mod topic;
 
 #[cfg(test)]
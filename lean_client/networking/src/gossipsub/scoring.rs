@@ -0,0 +1,168 @@
+//! Gossipsub peer scoring for the lean topics.
+//!
+//! The layout follows the Ethereum consensus scoring parameters, scaled to the
+//! slot timing in `chain::config`. Counters decay once per slot. Invalid messages
+//! dominate the score, so a peer that keeps sending them falls below the graylist
+//! threshold quickly. Slow or idle mesh peers are penalised but can never reach it
+//! on delivery penalties alone.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use chain::config::SLOT_DURATION_MS;
+use containers::ATTESTATION_SUBNET_COUNT;
+use libp2p::gossipsub::{
+    PeerScoreParams, PeerScoreThresholds, TopicHash, TopicScoreParams,
+    score_parameter_decay_with_base,
+};
+
+use crate::gossipsub::topic::{GossipsubKind, GossipsubTopic};
+
+/// Below this score we stop gossiping to a peer.
+pub const GOSSIP_THRESHOLD: f64 = -4000.0;
+/// Below this score we stop publishing to a peer.
+pub const PUBLISH_THRESHOLD: f64 = -8000.0;
+/// Below this score all messages from a peer are ignored. The network service
+/// also disconnects and bans peers that reach it.
+pub const GRAYLIST_THRESHOLD: f64 = -16000.0;
+/// Only peers above this score may send us peer exchange records.
+pub const ACCEPT_PX_THRESHOLD: f64 = 100.0;
+/// Median mesh score below which opportunistic grafting kicks in.
+pub const OPPORTUNISTIC_GRAFT_THRESHOLD: f64 = 5.0;
+
+/// Cap on the positive contribution of all topics together.
+const MAX_POSITIVE_SCORE: f64 = 100.0;
+const MAX_IN_MESH_SCORE: f64 = 10.0;
+const MAX_FIRST_MESSAGE_DELIVERIES_SCORE: f64 = 40.0;
+
+const BLOCK_TOPIC_WEIGHT: f64 = 0.5;
+const ATTESTATION_TOPIC_WEIGHT: f64 = 0.5;
+const AGGREGATION_TOPIC_WEIGHT: f64 = 0.5;
+
+const DECAY_TO_ZERO: f64 = 0.01;
+
+/// Peers beyond this many on one IP address are penalised.
+const IP_COLOCATION_THRESHOLD: f64 = 3.0;
+
+fn slot_duration() -> Duration {
+    Duration::from_millis(SLOT_DURATION_MS)
+}
+
+/// Per-slot decay factor that brings a counter to zero after `slots` slots.
+fn decay(slots: u32) -> f64 {
+    score_parameter_decay_with_base(slot_duration() * slots, slot_duration(), DECAY_TO_ZERO)
+}
+
+/// Value a counter settles at when it grows by `rate` per slot and decays by `decay`.
+fn decay_convergence(decay: f64, rate: f64) -> f64 {
+    rate / (1.0 - decay)
+}
+
+pub fn peer_score_thresholds() -> PeerScoreThresholds {
+    PeerScoreThresholds {
+        gossip_threshold: GOSSIP_THRESHOLD,
+        publish_threshold: PUBLISH_THRESHOLD,
+        graylist_threshold: GRAYLIST_THRESHOLD,
+        accept_px_threshold: ACCEPT_PX_THRESHOLD,
+        opportunistic_graft_threshold: OPPORTUNISTIC_GRAFT_THRESHOLD,
+    }
+}
+
+/// Score parameters for `topics`. Every active validator attests once per slot on
+/// its subnet, and every non-empty subnet yields one aggregate per slot, which
+/// sets the expected message rates.
+pub fn peer_score_params(
+    topics: &[GossipsubTopic],
+    mesh_n: usize,
+    active_validators: u64,
+) -> PeerScoreParams {
+    let topics = topics
+        .iter()
+        .map(|topic| {
+            let params = match topic.kind {
+                GossipsubKind::Block => topic_score_params(BLOCK_TOPIC_WEIGHT, 1.0, mesh_n),
+                GossipsubKind::Attestation => topic_score_params(
+                    ATTESTATION_TOPIC_WEIGHT,
+                    active_validators.max(1) as f64,
+                    mesh_n,
+                ),
+                GossipsubKind::AttestationSubnet(_) => topic_score_params(
+                    ATTESTATION_TOPIC_WEIGHT,
+                    active_validators.div_ceil(ATTESTATION_SUBNET_COUNT).max(1) as f64,
+                    mesh_n,
+                ),
+                GossipsubKind::Aggregation => topic_score_params(
+                    AGGREGATION_TOPIC_WEIGHT,
+                    active_validators.clamp(1, ATTESTATION_SUBNET_COUNT) as f64,
+                    mesh_n,
+                ),
+            };
+            (TopicHash::from(topic.clone()), params)
+        })
+        .collect::<HashMap<_, _>>();
+
+    // Roughly one broken promise or early re-graft per slot is tolerated
+    let behaviour_penalty_threshold = 6.0;
+    let behaviour_penalty_decay = decay(100);
+    let behaviour_penalty_target =
+        decay_convergence(behaviour_penalty_decay, 1.0) - behaviour_penalty_threshold;
+
+    PeerScoreParams {
+        topics,
+        topic_score_cap: MAX_POSITIVE_SCORE,
+        app_specific_weight: 0.0,
+        ip_colocation_factor_weight: -MAX_POSITIVE_SCORE,
+        ip_colocation_factor_threshold: IP_COLOCATION_THRESHOLD,
+        ip_colocation_factor_whitelist: HashSet::new(),
+        behaviour_penalty_weight: GOSSIP_THRESHOLD / behaviour_penalty_target.powi(2),
+        behaviour_penalty_threshold,
+        behaviour_penalty_decay,
+        decay_interval: slot_duration(),
+        decay_to_zero: DECAY_TO_ZERO,
+        retain_score: slot_duration() * 100,
+    }
+}
+
+fn topic_score_params(
+    topic_weight: f64,
+    messages_per_slot: f64,
+    mesh_n: usize,
+) -> TopicScoreParams {
+    let time_in_mesh_cap = (3600 * 1000 / SLOT_DURATION_MS) as f64;
+
+    let first_message_deliveries_decay = decay(20);
+    let first_message_deliveries_cap = decay_convergence(
+        first_message_deliveries_decay,
+        2.0 * messages_per_slot / mesh_n as f64,
+    );
+
+    // Expect each mesh peer to deliver at least a fraction of the messages first
+    let mesh_message_deliveries_decay = decay(16);
+    let mesh_message_deliveries_threshold = decay_convergence(
+        mesh_message_deliveries_decay,
+        messages_per_slot / (2.0 * mesh_n as f64),
+    );
+    let mesh_message_deliveries_weight =
+        -MAX_POSITIVE_SCORE / (topic_weight * mesh_message_deliveries_threshold.powi(2));
+
+    TopicScoreParams {
+        topic_weight,
+        time_in_mesh_weight: MAX_IN_MESH_SCORE / time_in_mesh_cap,
+        time_in_mesh_quantum: slot_duration(),
+        time_in_mesh_cap,
+        first_message_deliveries_weight: MAX_FIRST_MESSAGE_DELIVERIES_SCORE
+            / first_message_deliveries_cap,
+        first_message_deliveries_decay,
+        first_message_deliveries_cap,
+        mesh_message_deliveries_weight,
+        mesh_message_deliveries_decay,
+        mesh_message_deliveries_cap: 4.0 * mesh_message_deliveries_threshold,
+        mesh_message_deliveries_threshold,
+        mesh_message_deliveries_window: slot_duration() / 2,
+        mesh_message_deliveries_activation: slot_duration() * 8,
+        mesh_failure_penalty_weight: mesh_message_deliveries_weight,
+        mesh_failure_penalty_decay: mesh_message_deliveries_decay,
+        invalid_message_deliveries_weight: -MAX_POSITIVE_SCORE / topic_weight,
+        invalid_message_deliveries_decay: decay(50),
+    }
+}
@@ -1,6 +1,12 @@
 use crate::gossipsub::message::GossipsubMessage;
 use crate::gossipsub::topic::{
-    ATTESTATION_TOPIC, BLOCK_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX, TOPIC_PREFIX,
+    AGGREGATION_TOPIC, ATTESTATION_TOPIC, BLOCK_TOPIC, GossipsubKind, SSZ_SNAPPY_ENCODING_POSTFIX,
+    TOPIC_PREFIX,
+};
+use containers::ssz::SszWrite;
+use containers::{
+    SignedAggregatedAttestations, SignedAggregatedBlockWithAttestation, SignedAttestation, Uint64,
+    VersionedSignedBlock,
 };
 use libp2p::gossipsub::TopicHash;
 
@@ -29,8 +35,8 @@ fn test_message_decode_invalid_ssz_for_block() {
 #[test]
 fn test_message_decode_invalid_ssz_for_attestation() {
     let topic_str = format!(
-        "/{}/{}/{}/{}",
-        TOPIC_PREFIX, "genesis", ATTESTATION_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX
+        "/{}/{}/{}_{}/{}",
+        TOPIC_PREFIX, "genesis", ATTESTATION_TOPIC, 0, SSZ_SNAPPY_ENCODING_POSTFIX
     );
     let topic = TopicHash::from_raw(topic_str);
     let invalid_ssz = b"not_valid_ssz";
@@ -82,3 +88,71 @@ fn test_message_decode_unsupported_kind() {
     let result = GossipsubMessage::decode(&topic, data);
     assert!(result.is_err());
 }
+
+#[test]
+fn test_message_decode_block_in_topic_fork_format() {
+    let block: VersionedSignedBlock = SignedAggregatedBlockWithAttestation::default().into();
+    let data = block.to_ssz().unwrap();
+
+    for (fork, expected) in [("devnet1", true), ("devnet0", false)] {
+        let topic_str = format!(
+            "/{}/{}/{}/{}",
+            TOPIC_PREFIX, fork, BLOCK_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX
+        );
+        let topic = TopicHash::from_raw(topic_str);
+
+        let decoded = match GossipsubMessage::decode(&topic, &data) {
+            Ok(GossipsubMessage::Block(decoded)) => decoded == block,
+            _ => false,
+        };
+        assert_eq!(decoded, expected, "fork {fork}");
+    }
+}
+
+fn subnet_topic(subnet_id: u64) -> TopicHash {
+    TopicHash::from_raw(format!(
+        "/{}/{}/{}_{}/{}",
+        TOPIC_PREFIX, "genesis", ATTESTATION_TOPIC, subnet_id, SSZ_SNAPPY_ENCODING_POSTFIX
+    ))
+}
+
+#[test]
+fn test_message_decode_attestation_on_its_subnet() {
+    let mut attestation = SignedAttestation::default();
+    attestation.message.validator_id = Uint64(2);
+    let data = attestation.to_ssz().unwrap();
+
+    assert!(matches!(
+        GossipsubMessage::decode(&subnet_topic(2), &data),
+        Ok(GossipsubMessage::Attestation(decoded)) if decoded == attestation
+    ));
+    assert!(GossipsubMessage::decode(&subnet_topic(3), &data).is_err());
+}
+
+#[test]
+fn test_message_decode_attestation_on_global_topic() {
+    let mut attestation = SignedAttestation::default();
+    attestation.message.validator_id = Uint64(2);
+    let data = attestation.to_ssz().unwrap();
+    let topic = TopicHash::from_raw(format!(
+        "/{}/{}/{}/{}",
+        TOPIC_PREFIX, "devnet0", ATTESTATION_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX
+    ));
+
+    let decoded = GossipsubMessage::decode(&topic, &data).unwrap();
+    assert!(matches!(&decoded, GossipsubMessage::Attestation(decoded) if *decoded == attestation));
+    assert_eq!(decoded.kind("devnet0"), GossipsubKind::Attestation);
+    assert_eq!(decoded.kind("devnet1"), GossipsubKind::AttestationSubnet(2));
+}
+
+#[test]
+fn test_message_decode_aggregate() {
+    let aggregate = SignedAggregatedAttestations::default();
+    let topic = TopicHash::from_raw(format!(
+        "/{}/{}/{}/{}",
+        TOPIC_PREFIX, "genesis", AGGREGATION_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX
+    ));
+
+    let result = GossipsubMessage::decode(&topic, &aggregate.to_ssz().unwrap());
+    assert!(matches!(result, Ok(GossipsubMessage::Aggregate(decoded)) if decoded == aggregate));
+}
@@ -1,4 +1,5 @@
 mod config;
 mod message;
 mod message_id;
+mod scoring;
 mod topic;
@@ -0,0 +1,64 @@
+use crate::gossipsub::config::GossipsubConfig;
+use crate::gossipsub::scoring::{GRAYLIST_THRESHOLD, peer_score_params, peer_score_thresholds};
+use crate::gossipsub::topic::{get_subnet_topics, get_topics};
+use libp2p::gossipsub::TopicHash;
+
+#[test]
+fn test_score_params_are_valid() {
+    for (fork, topic_count) in [("devnet0", 2), ("devnet1", 4)] {
+        let mut config = GossipsubConfig::new();
+        let mut topics = get_topics(fork.to_string());
+        topics.extend(get_subnet_topics(fork.to_string(), [0, 1]));
+        config.set_topics(topics);
+
+        for active_validators in [0, 4, 4096] {
+            config.set_active_validators(active_validators);
+            let params = config.score_params();
+
+            assert_eq!(params.topics.len(), topic_count, "{fork}");
+            assert_eq!(params.validate(), Ok(()), "{fork}");
+        }
+    }
+
+    let config = GossipsubConfig::new();
+
+    assert_eq!(config.score_thresholds().validate(), Ok(()));
+}
+
+#[test]
+fn test_thresholds_are_ordered() {
+    let thresholds = peer_score_thresholds();
+
+    assert!(thresholds.graylist_threshold < thresholds.publish_threshold);
+    assert!(thresholds.publish_threshold < thresholds.gossip_threshold);
+    assert!(thresholds.gossip_threshold < 0.0);
+    assert_eq!(thresholds.graylist_threshold, GRAYLIST_THRESHOLD);
+}
+
+#[test]
+fn test_invalid_messages_lead_to_graylist() {
+    let mut topics = get_topics("devnet0".to_string());
+    topics.extend(get_topics("devnet1".to_string()));
+    topics.extend(get_subnet_topics("devnet1".to_string(), [0]));
+    let params = peer_score_params(&topics, 8, 16);
+
+    for topic in topics {
+        let topic_params = &params.topics[&TopicHash::from(topic)];
+        let penalty = |invalid: f64| {
+            topic_params.topic_weight
+                * topic_params.invalid_message_deliveries_weight
+                * invalid.powi(2)
+        };
+
+        // A single invalid message is forgiven by the best possible positive score...
+        assert!(penalty(1.0) + params.topic_score_cap > GRAYLIST_THRESHOLD);
+        // ...but a steady stream of them is not
+        assert!(penalty(20.0) + params.topic_score_cap < GRAYLIST_THRESHOLD);
+
+        // Missing mesh deliveries alone never graylist an honest but slow peer
+        let max_delivery_penalty = topic_params.topic_weight
+            * topic_params.mesh_message_deliveries_weight
+            * topic_params.mesh_message_deliveries_threshold.powi(2);
+        assert!(max_delivery_penalty > GRAYLIST_THRESHOLD);
+    }
+}
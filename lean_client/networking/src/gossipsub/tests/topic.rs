@@ -1,7 +1,8 @@
 use crate::gossipsub::topic::{
-    ATTESTATION_TOPIC, BLOCK_TOPIC, GossipsubKind, GossipsubTopic, SSZ_SNAPPY_ENCODING_POSTFIX,
-    TOPIC_PREFIX, get_topics,
+    AGGREGATION_TOPIC, ATTESTATION_TOPIC, BLOCK_TOPIC, GossipsubKind, GossipsubTopic,
+    SSZ_SNAPPY_ENCODING_POSTFIX, TOPIC_PREFIX, get_subnet_topics, get_topics,
 };
+use containers::ATTESTATION_SUBNET_COUNT;
 use libp2p::gossipsub::TopicHash;
 
 #[test]
@@ -19,19 +20,62 @@ fn test_topic_decode_valid_block() {
 }
 
 #[test]
-fn test_topic_decode_valid_attestation() {
+fn test_topic_decode_valid_attestation_subnet() {
     let topic_str = format!(
-        "/{}/{}/{}/{}",
-        TOPIC_PREFIX, "genesis", ATTESTATION_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX
+        "/{}/{}/{}_{}/{}",
+        TOPIC_PREFIX, "genesis", ATTESTATION_TOPIC, 3, SSZ_SNAPPY_ENCODING_POSTFIX
     );
     let topic_hash = TopicHash::from_raw(topic_str);
 
     let decoded = GossipsubTopic::decode(&topic_hash).unwrap();
 
     assert_eq!(decoded.fork, "genesis");
+    assert_eq!(decoded.kind, GossipsubKind::AttestationSubnet(3));
+}
+
+#[test]
+fn test_topic_decode_valid_global_attestation() {
+    let topic_str = format!(
+        "/{}/{}/{}/{}",
+        TOPIC_PREFIX, "devnet0", ATTESTATION_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX
+    );
+    let topic_hash = TopicHash::from_raw(topic_str);
+
+    let decoded = GossipsubTopic::decode(&topic_hash).unwrap();
+
     assert_eq!(decoded.kind, GossipsubKind::Attestation);
 }
 
+#[test]
+fn test_topic_decode_valid_aggregation() {
+    let topic_str = format!(
+        "/{}/{}/{}/{}",
+        TOPIC_PREFIX, "genesis", AGGREGATION_TOPIC, SSZ_SNAPPY_ENCODING_POSTFIX
+    );
+    let topic_hash = TopicHash::from_raw(topic_str);
+
+    let decoded = GossipsubTopic::decode(&topic_hash).unwrap();
+
+    assert_eq!(decoded.kind, GossipsubKind::Aggregation);
+}
+
+#[test]
+fn test_topic_decode_invalid_subnet() {
+    for kind in [
+        format!("{ATTESTATION_TOPIC}_"),
+        format!("{ATTESTATION_TOPIC}_{ATTESTATION_SUBNET_COUNT}"),
+        format!("{ATTESTATION_TOPIC}_x"),
+    ] {
+        let topic_str = format!(
+            "/{}/{}/{}/{}",
+            TOPIC_PREFIX, "genesis", kind, SSZ_SNAPPY_ENCODING_POSTFIX
+        );
+        let topic_hash = TopicHash::from_raw(topic_str);
+
+        assert!(GossipsubTopic::decode(&topic_hash).is_err(), "{kind}");
+    }
+}
+
 #[test]
 fn test_topic_decode_invalid_prefix() {
     let topic_str = format!(
@@ -97,7 +141,7 @@ fn test_topic_to_string() {
 fn test_topic_encoding_decoding_roundtrip() {
     let original = GossipsubTopic {
         fork: "testfork".to_string(),
-        kind: GossipsubKind::Attestation,
+        kind: GossipsubKind::AttestationSubnet(5),
     };
 
     let topic_hash: TopicHash = original.clone().into();
@@ -109,24 +153,62 @@ fn test_topic_encoding_decoding_roundtrip() {
 
 #[test]
 fn test_get_topics_all_same_fork() {
-    let topics = get_topics("myfork".to_string());
+    let topics = get_topics("devnet1".to_string());
 
     assert_eq!(topics.len(), 2);
 
     let kinds: Vec<_> = topics.iter().map(|t| t.kind).collect();
     assert!(kinds.contains(&GossipsubKind::Block));
-    assert!(kinds.contains(&GossipsubKind::Attestation));
+    assert!(kinds.contains(&GossipsubKind::Aggregation));
 
     // All should have the same fork
     for topic in &topics {
-        assert_eq!(topic.fork, "myfork");
+        assert_eq!(topic.fork, "devnet1");
+    }
+}
+
+#[test]
+fn test_get_topics_devnet0_keeps_global_attestation_topic() {
+    for fork in ["devnet0", "genesis"] {
+        let kinds: Vec<_> = get_topics(fork.to_string())
+            .iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![GossipsubKind::Block, GossipsubKind::Attestation],
+            "{fork}"
+        );
+        assert!(
+            get_subnet_topics(fork.to_string(), [1, 4]).is_empty(),
+            "{fork}"
+        );
     }
 }
 
+#[test]
+fn test_get_subnet_topics() {
+    let topics = get_subnet_topics("devnet1".to_string(), [1, 4]);
+
+    let kinds: Vec<_> = topics.iter().map(|t| t.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            GossipsubKind::AttestationSubnet(1),
+            GossipsubKind::AttestationSubnet(4)
+        ]
+    );
+}
+
 #[test]
 fn test_gossipsub_kind_display() {
     assert_eq!(GossipsubKind::Block.to_string(), BLOCK_TOPIC);
     assert_eq!(GossipsubKind::Attestation.to_string(), ATTESTATION_TOPIC);
+    assert_eq!(
+        GossipsubKind::AttestationSubnet(7).to_string(),
+        format!("{ATTESTATION_TOPIC}_7")
+    );
+    assert_eq!(GossipsubKind::Aggregation.to_string(), AGGREGATION_TOPIC);
 }
 
 #[test]
@@ -141,11 +223,11 @@ fn test_topic_equality() {
     };
     let topic3 = GossipsubTopic {
         fork: "genesis".to_string(),
-        kind: GossipsubKind::Attestation,
+        kind: GossipsubKind::AttestationSubnet(0),
     };
     let topic4 = GossipsubTopic {
         fork: "genesis2".to_string(),
-        kind: GossipsubKind::Attestation,
+        kind: GossipsubKind::AttestationSubnet(0),
     };
 
     assert_eq!(topic1, topic2);
@@ -1,10 +1,14 @@
+use containers::{ATTESTATION_SUBNET_COUNT, BlockVersion};
 use libp2p::gossipsub::{IdentTopic, TopicHash};
 
 pub const TOPIC_PREFIX: &str = "leanconsensus";
 pub const SSZ_SNAPPY_ENCODING_POSTFIX: &str = "ssz_snappy";
 
 pub const BLOCK_TOPIC: &str = "block";
+/// Global attestation topic of devnet0, and prefix of the devnet1 subnet topics,
+/// `attestation_{subnet_id}`.
 pub const ATTESTATION_TOPIC: &str = "attestation";
+pub const AGGREGATION_TOPIC: &str = "aggregation";
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct GossipsubTopic {
@@ -15,10 +19,28 @@ pub struct GossipsubTopic {
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq)]
 pub enum GossipsubKind {
     Block,
+    /// All individual attestations, on forks without subnets.
     Attestation,
+    /// Individual attestations of the validators mapped to this subnet.
+    AttestationSubnet(u64),
+    /// Aggregates published by the subnet aggregators.
+    Aggregation,
 }
 
+/// Whether `fork` splits attestations into subnets and aggregates. devnet0 gossips
+/// them all on one topic; unknown forks are treated like devnet0, as for blocks.
+pub fn uses_attestation_subnets(fork: &str) -> bool {
+    BlockVersion::from_fork(fork).unwrap_or_default() != BlockVersion::V0
+}
+
+/// Topics every node subscribes to.
 pub fn get_topics(fork: String) -> Vec<GossipsubTopic> {
+    let attestation_kind = if uses_attestation_subnets(&fork) {
+        GossipsubKind::Aggregation
+    } else {
+        GossipsubKind::Attestation
+    };
+
     vec![
         GossipsubTopic {
             fork: fork.clone(),
@@ -26,11 +48,30 @@ pub fn get_topics(fork: String) -> Vec<GossipsubTopic> {
         },
         GossipsubTopic {
             fork: fork.clone(),
-            kind: GossipsubKind::Attestation,
+            kind: attestation_kind,
         },
     ]
 }
 
+/// Attestation topics of the given subnets, for nodes that aggregate them. Empty
+/// on forks without subnets.
+pub fn get_subnet_topics(
+    fork: String,
+    subnet_ids: impl IntoIterator<Item = u64>,
+) -> Vec<GossipsubTopic> {
+    if !uses_attestation_subnets(&fork) {
+        return Vec::new();
+    }
+
+    subnet_ids
+        .into_iter()
+        .map(|subnet_id| GossipsubTopic {
+            fork: fork.clone(),
+            kind: GossipsubKind::AttestationSubnet(subnet_id),
+        })
+        .collect()
+}
+
 impl GossipsubTopic {
     pub fn decode(topic: &TopicHash) -> Result<Self, String> {
         let topic_parts = Self::split_topic(topic)?;
@@ -66,9 +107,21 @@ impl GossipsubTopic {
         match parts[2] {
             BLOCK_TOPIC => Ok(GossipsubKind::Block),
             ATTESTATION_TOPIC => Ok(GossipsubKind::Attestation),
-            other => Err(format!("Invalid topic kind: {other:?}")),
+            AGGREGATION_TOPIC => Ok(GossipsubKind::Aggregation),
+            other => Self::extract_subnet_id(other)
+                .map(GossipsubKind::AttestationSubnet)
+                .ok_or_else(|| format!("Invalid topic kind: {other:?}")),
         }
     }
+
+    fn extract_subnet_id(kind: &str) -> Option<u64> {
+        let subnet_id = kind
+            .strip_prefix(ATTESTATION_TOPIC)?
+            .strip_prefix('_')?
+            .parse::<u64>()
+            .ok()?;
+        (subnet_id < ATTESTATION_SUBNET_COUNT).then_some(subnet_id)
+    }
 }
 
 impl std::fmt::Display for GossipsubTopic {
@@ -95,14 +148,7 @@ impl From<GossipsubTopic> for String {
 
 impl From<GossipsubTopic> for TopicHash {
     fn from(val: GossipsubTopic) -> Self {
-        let kind_str = match &val.kind {
-            GossipsubKind::Block => BLOCK_TOPIC,
-            GossipsubKind::Attestation => ATTESTATION_TOPIC,
-        };
-        TopicHash::from_raw(format!(
-            "/{}/{}/{}/{}",
-            TOPIC_PREFIX, val.fork, kind_str, SSZ_SNAPPY_ENCODING_POSTFIX
-        ))
+        TopicHash::from_raw(val.to_string())
     }
 }
 
@@ -111,6 +157,10 @@ impl std::fmt::Display for GossipsubKind {
         match self {
             GossipsubKind::Block => write!(f, "{BLOCK_TOPIC}"),
             GossipsubKind::Attestation => write!(f, "{ATTESTATION_TOPIC}"),
+            GossipsubKind::AttestationSubnet(subnet_id) => {
+                write!(f, "{ATTESTATION_TOPIC}_{subnet_id}")
+            }
+            GossipsubKind::Aggregation => write!(f, "{AGGREGATION_TOPIC}"),
         }
     }
 }
@@ -1,8 +1,11 @@
 pub mod bootnodes;
 pub mod compressor;
+pub mod discovery;
 mod enr_ext;
 pub mod gossipsub;
 pub mod network;
+pub mod peer_manager;
 pub mod req_resp;
 pub mod serde_utils;
+pub mod sync;
 pub mod types;
@@ -3,10 +3,15 @@ use libp2p::{connection_limits, identify, swarm::NetworkBehaviour};
 use crate::gossipsub::GossipsubBehaviour;
 use crate::req_resp::ReqResp;
 
+/// Each req/resp protocol gets its own behaviour: a behaviour offers all of its
+/// protocols on every outbound request, so sharing one would negotiate the first
+/// protocol for every request type.
 #[derive(NetworkBehaviour)]
 pub struct LeanNetworkBehaviour {
     pub identify: identify::Behaviour,
-    pub req_resp: ReqResp,
+    pub status: ReqResp,
+    pub blocks_by_root: ReqResp,
+    pub blocks_by_range: ReqResp,
     pub gossipsub: GossipsubBehaviour,
     pub connection_limits: connection_limits::Behaviour,
 }
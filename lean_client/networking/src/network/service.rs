@@ -5,37 +5,54 @@ use std::{
     num::{NonZeroU8, NonZeroUsize},
     sync::Arc,
     sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
 };
 
 use anyhow::{Result, anyhow};
-use containers::ssz::SszWrite;
+use chain::config::SLOT_DURATION_MS;
+use containers::{BlockVersion, Checkpoint, Status, VersionedSignedBlock, ssz::SszWrite};
 use derive_more::Display;
 use discv5::Enr;
 use futures::StreamExt;
 use libp2p::{
     Multiaddr, SwarmBuilder,
     connection_limits::{self, ConnectionLimits},
-    gossipsub::{Event, IdentTopic, MessageAuthenticity},
+    gossipsub::{Event, IdentTopic, MessageAcceptance, MessageAuthenticity, MessageId},
     identify,
     multiaddr::Protocol,
+    request_response::{OutboundRequestId, ResponseChannel},
     swarm::{Config, Swarm, SwarmEvent},
 };
 use libp2p_identity::{Keypair, PeerId};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use tokio::select;
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::time::{Duration, MissedTickBehavior, interval};
 use tracing::{debug, info, trace, warn};
 
 use crate::{
     bootnodes::{BootnodeSource, StaticBootnodes},
     compressor::Compressor,
+    discovery::Discovery,
     enr_ext::EnrExt,
-    gossipsub::{self, config::GossipsubConfig, message::GossipsubMessage, topic::GossipsubKind},
+    gossipsub::{
+        self,
+        config::GossipsubConfig,
+        message::GossipsubMessage,
+        scoring::GRAYLIST_THRESHOLD,
+        topic::{GossipsubKind, GossipsubTopic, uses_attestation_subnets},
+    },
     network::behaviour::{LeanNetworkBehaviour, LeanNetworkBehaviourEvent},
-    req_resp::{self, BLOCKS_BY_ROOT_PROTOCOL_V1, LeanRequest, ReqRespMessage, STATUS_PROTOCOL_V1},
+    peer_manager::{BAN_DURATION, DEFAULT_MAX_PEERS, DEFAULT_MIN_PEERS, PeerAction, PeerManager},
+    req_resp::{
+        self, BLOCKS_BY_RANGE_PROTOCOL_V1, BLOCKS_BY_ROOT_PROTOCOL_V1, BlocksByRangeRequest,
+        LeanRequest, LeanResponse, ReqResp, ReqRespMessage, STATUS_PROTOCOL_V1,
+    },
+    sync::{BatchId, SyncAction, SyncManager},
     types::{
-        ChainMessage, ChainMessageSink, ConnectionState, OutboundP2pRequest, P2pRequestSource,
+        ChainMessage, ChainMessageSink, ChainQuery, Direction, GossipValidation,
+        OutboundP2pRequest, P2pRequestSource,
     },
 };
 
@@ -44,7 +61,14 @@ pub struct NetworkServiceConfig {
     pub gossipsub_config: GossipsubConfig,
     pub socket_address: IpAddr,
     pub socket_port: u16,
+    /// UDP port for discv5. Discovery is off unless this is set.
+    pub discovery_port: Option<u16>,
+    /// Discovery looks for more peers below this many connections.
+    pub min_peers: usize,
+    /// Connections above this many are pruned, worst peers first.
+    pub max_peers: usize,
     bootnodes: StaticBootnodes,
+    enr_bootnodes: Vec<Enr>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Display)]
@@ -58,7 +82,11 @@ impl Bootnode {
     fn addrs(&self) -> Vec<Multiaddr> {
         match self {
             Self::Multiaddr(addr) => vec![addr.clone()],
-            Self::Enr(enr) => enr.multiaddr_quic(),
+            Self::Enr(enr) => enr
+                .multiaddr_quic()
+                .into_iter()
+                .map(|addr| addr.with(Protocol::P2p(enr.peer_id())))
+                .collect(),
         }
     }
 }
@@ -103,10 +131,22 @@ impl NetworkServiceConfig {
         socket_port: u16,
         bootnodes: Vec<String>,
     ) -> Self {
+        let bootnodes = bootnodes
+            .iter()
+            .flat_map(|addr_str| parse_bootnode_argument(&addr_str))
+            .collect::<Vec<_>>();
+
+        let enr_bootnodes = bootnodes
+            .iter()
+            .filter_map(|bootnode| match bootnode {
+                Bootnode::Enr(enr) => Some(enr.clone()),
+                Bootnode::Multiaddr(_) => None,
+            })
+            .collect();
+
         let bootnodes = StaticBootnodes::new(
             bootnodes
                 .iter()
-                .flat_map(|addr_str| parse_bootnode_argument(&addr_str))
                 .flat_map(|bootnode| {
                     let addrs = bootnode.addrs();
                     if addrs.is_empty() {
@@ -122,9 +162,37 @@ impl NetworkServiceConfig {
             gossipsub_config,
             socket_address,
             socket_port,
+            discovery_port: None,
+            min_peers: DEFAULT_MIN_PEERS,
+            max_peers: DEFAULT_MAX_PEERS,
             bootnodes,
+            enr_bootnodes,
         }
     }
+
+    pub fn with_discovery_port(mut self, discovery_port: u16) -> Self {
+        self.discovery_port = Some(discovery_port);
+        self
+    }
+
+    pub fn with_peer_limits(mut self, min_peers: usize, max_peers: usize) -> Self {
+        self.min_peers = min_peers;
+        self.max_peers = max_peers;
+        self
+    }
+
+    fn fork(&self) -> String {
+        self.gossipsub_config
+            .topics
+            .first()
+            .map(|topic| topic.fork.clone())
+            .unwrap_or_default()
+    }
+
+    /// Block format of the fork we gossip on, also used for req/resp block responses.
+    fn block_version(&self) -> BlockVersion {
+        BlockVersion::from_fork(&self.fork()).unwrap_or_default()
+    }
 }
 
 #[derive(Debug)]
@@ -138,6 +206,27 @@ pub enum NetworkEvent {
     DisconnectPeer(PeerId),
 }
 
+/// Work that depends on an asynchronous chain query and is finished by the event loop.
+enum DeferredAction {
+    SendResponse {
+        peer: PeerId,
+        channel: ResponseChannel<LeanResponse>,
+        response: LeanResponse,
+    },
+    Disconnect {
+        peer: PeerId,
+        reason: &'static str,
+    },
+    GossipVerdict {
+        source: PeerId,
+        message_id: MessageId,
+        message: GossipsubMessage,
+        verdict: GossipValidation,
+    },
+    /// A discovery query finished; dial whatever it found.
+    DialDiscovered,
+}
+
 pub struct NetworkService<R, S>
 where
     R: P2pRequestSource<OutboundP2pRequest> + Send + 'static,
@@ -145,10 +234,17 @@ where
 {
     network_config: Arc<NetworkServiceConfig>,
     swarm: Swarm<LeanNetworkBehaviour>,
-    peer_table: Arc<Mutex<HashMap<PeerId, ConnectionState>>>,
+    peer_manager: Arc<Mutex<PeerManager>>,
     peer_count: Arc<AtomicU64>,
     outbound_p2p_requests: R,
     chain_message_sink: S,
+    chain_queries: Option<mpsc::UnboundedSender<ChainQuery>>,
+    local_status: Option<watch::Receiver<Status>>,
+    deferred_sender: mpsc::UnboundedSender<DeferredAction>,
+    deferred: mpsc::UnboundedReceiver<DeferredAction>,
+    sync: SyncManager,
+    sync_requests: HashMap<OutboundRequestId, BatchId>,
+    discovery: Option<Discovery>,
 }
 
 impl<R, S> NetworkService<R, S>
@@ -209,13 +305,43 @@ where
             .with_swarm_config(|_| config)
             .build();
 
+        let (deferred_sender, deferred) = mpsc::unbounded_channel();
+
+        let peer_manager = PeerManager::new(network_config.min_peers, network_config.max_peers);
+
+        let discovery = match network_config.discovery_port {
+            Some(discovery_port) => {
+                let fork = network_config.fork();
+
+                Some(
+                    Discovery::new(
+                        &local_key,
+                        network_config.socket_address,
+                        discovery_port,
+                        network_config.socket_port,
+                        fork,
+                        &network_config.enr_bootnodes,
+                    )
+                    .await?,
+                )
+            }
+            None => None,
+        };
+
         let mut service = Self {
             network_config,
             swarm,
-            peer_table: Arc::new(Mutex::new(HashMap::new())),
+            peer_manager: Arc::new(Mutex::new(peer_manager)),
             peer_count,
             outbound_p2p_requests,
             chain_message_sink,
+            chain_queries: None,
+            local_status: None,
+            deferred_sender,
+            deferred,
+            sync: SyncManager::new(),
+            sync_requests: HashMap::new(),
+            discovery,
         };
 
         service.listen(&multiaddr)?;
@@ -224,20 +350,55 @@ where
         Ok(service)
     }
 
+    /// Answer inbound requests that need chain data by querying the chain task
+    /// through `chain_queries`. Without it such requests get empty responses.
+    pub fn with_chain_queries(mut self, chain_queries: mpsc::UnboundedSender<ChainQuery>) -> Self {
+        self.chain_queries = Some(chain_queries);
+        self
+    }
+
+    /// Advertise the chain's current Status in handshakes. Without it the node
+    /// sends zeroed checkpoints and skips conflict checks against peers.
+    pub fn with_status(mut self, local_status: watch::Receiver<Status>) -> Self {
+        self.local_status = Some(local_status);
+        self
+    }
+
     pub async fn start(&mut self) -> Result<()> {
         // Periodic reconnect attempts to bootnodes
         let mut reconnect_interval = interval(Duration::from_secs(30));
         reconnect_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Range sync progress checks, in addition to the ones triggered by responses
+        let mut sync_interval = interval(Duration::from_secs(2));
+        sync_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Peer upkeep. Scores decay once per slot, so there is no point checking them more often
+        let mut heartbeat_interval = interval(Duration::from_millis(SLOT_DURATION_MS));
+        heartbeat_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Look for more peers while below target
+        let mut discovery_interval = interval(Duration::from_secs(10));
+        discovery_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
         loop {
             select! {
                 _ = reconnect_interval.tick() => {
                     self.connect_to_peers(self.network_config.bootnodes.to_multiaddrs()).await;
                 }
+                _ = sync_interval.tick() => {
+                    self.drive_sync().await;
+                }
+                _ = heartbeat_interval.tick() => {
+                    self.peer_heartbeat();
+                }
+                _ = discovery_interval.tick() => {
+                    self.discover_peers();
+                }
                 request = self.outbound_p2p_requests.recv() => {
                     if let Some(request) = request {
                         self.dispatch_outbound_request(request).await;
                     }
                 }
+                Some(action) = self.deferred.recv() => {
+                    self.handle_deferred_action(action).await;
+                }
                 event = self.swarm.select_next_some() => {
                     if let Some(event) = self.parse_swarm_event(event).await {
                         info!(?event, "Swarm event");
@@ -255,8 +416,17 @@ where
             SwarmEvent::Behaviour(LeanNetworkBehaviourEvent::Gossipsub(event)) => {
                 self.handle_gossipsub_event(event).await
             }
-            SwarmEvent::Behaviour(LeanNetworkBehaviourEvent::ReqResp(event)) => {
-                self.handle_request_response_event(event)
+            SwarmEvent::Behaviour(LeanNetworkBehaviourEvent::Status(event)) => {
+                self.handle_request_response_event(event, STATUS_PROTOCOL_V1)
+                    .await
+            }
+            SwarmEvent::Behaviour(LeanNetworkBehaviourEvent::BlocksByRoot(event)) => {
+                self.handle_request_response_event(event, BLOCKS_BY_ROOT_PROTOCOL_V1)
+                    .await
+            }
+            SwarmEvent::Behaviour(LeanNetworkBehaviourEvent::BlocksByRange(event)) => {
+                self.handle_request_response_event(event, BLOCKS_BY_RANGE_PROTOCOL_V1)
+                    .await
             }
             SwarmEvent::Behaviour(LeanNetworkBehaviourEvent::Identify(event)) => {
                 self.handle_identify_event(event)
@@ -268,17 +438,20 @@ where
             SwarmEvent::ConnectionEstablished {
                 peer_id, endpoint, ..
             } => {
-                self.peer_table
-                    .lock()
-                    .insert(peer_id, ConnectionState::Connected);
-
-                let connected = self
-                    .peer_table
-                    .lock()
-                    .values()
-                    .filter(|s| **s == ConnectionState::Connected)
-                    .count() as u64;
-                self.peer_count.store(connected, Ordering::Relaxed);
+                if self.peer_manager.lock().is_banned(&peer_id) {
+                    self.disconnect_peer(peer_id, "peer is banned");
+                    return None;
+                }
+
+                let direction = if endpoint.is_dialer() {
+                    Direction::Outbound
+                } else {
+                    Direction::Inbound
+                };
+                let connected = self.update_peers(|peers| {
+                    peers.on_connected(peer_id, direction);
+                    peers.connected_count()
+                });
 
                 info!(peer = %peer_id, "Connected to peer (total: {})", connected);
 
@@ -288,18 +461,21 @@ where
 
                 None
             }
-            SwarmEvent::ConnectionClosed { peer_id, .. } => {
-                self.peer_table
-                    .lock()
-                    .insert(peer_id, ConnectionState::Disconnected);
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established,
+                ..
+            } => {
+                // Another connection to the same peer is still open
+                if num_established > 0 {
+                    return None;
+                }
 
-                let connected = self
-                    .peer_table
-                    .lock()
-                    .values()
-                    .filter(|s| **s == ConnectionState::Connected)
-                    .count() as u64;
-                self.peer_count.store(connected, Ordering::Relaxed);
+                let connected = self.update_peers(|peers| {
+                    peers.on_disconnected(peer_id);
+                    peers.connected_count()
+                });
+                self.sync.remove_peer(&peer_id);
 
                 info!(peer = %peer_id, "Disconnected from peer (total: {})", connected);
                 Some(NetworkEvent::PeerDisconnected(peer_id))
@@ -314,6 +490,9 @@ where
             }
             SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
                 warn!(?peer_id, ?error, "Failed to connect to peer");
+                if let Some(peer_id) = peer_id {
+                    self.update_peers(|peers| peers.on_dial_failed(&peer_id));
+                }
                 None
             }
             SwarmEvent::NewListenAddr {
@@ -353,42 +532,36 @@ where
                 info!(peer = %peer_id, topic = %topic, "A peer unsubscribed from topic");
             }
 
-            Event::Message { message, .. } => {
-                match GossipsubMessage::decode(&message.topic, &message.data) {
-                    Ok(GossipsubMessage::Block(signed_block_with_attestation)) => {
-                        let slot = signed_block_with_attestation.message.block.slot.0;
-
-                        if let Err(err) = self
-                            .chain_message_sink
-                            .send(ChainMessage::ProcessBlock {
-                                signed_block_with_attestation,
-                                is_trusted: false,
-                                should_gossip: true,
-                            })
-                            .await
-                        {
-                            warn!(
-                                "failed to send block with attestation for slot {slot} to chain: {err:?}"
-                            );
-                        }
-                    }
-                    Ok(GossipsubMessage::Attestation(signed_attestation)) => {
-                        let slot = signed_attestation.message.data.slot.0;
+            Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            } => {
+                self.peer_manager.lock().on_message(&propagation_source);
 
-                        if let Err(err) = self
-                            .chain_message_sink
-                            .send(ChainMessage::ProcessAttestation {
-                                signed_attestation: signed_attestation,
-                                is_trusted: false,
-                                should_gossip: true,
-                            })
-                            .await
-                        {
-                            warn!("failed to send vote for slot {slot} to chain: {err:?}");
-                        }
+                match GossipsubMessage::decode(&message.topic, &message.data) {
+                    Ok(decoded) => {
+                        self.validate_gossip(propagation_source, message_id, decoded)
+                            .await;
                     }
                     Err(err) => {
                         warn!(%err, topic = %message.topic, "gossip decode failed");
+                        let topic = GossipsubTopic::decode(&message.topic)
+                            .map(|topic| topic.kind.to_string())
+                            .unwrap_or_else(|_| "unknown".to_string());
+                        metrics::GOSSIP_MESSAGES_RECEIVED
+                            .with_label_values(&[&topic, "undecodable"])
+                            .inc();
+                        self.report_validation(
+                            &message_id,
+                            &propagation_source,
+                            MessageAcceptance::Reject,
+                        );
+                        self.report_peer(
+                            propagation_source,
+                            PeerAction::LowTolerance,
+                            "undecodable gossip message",
+                        );
                     }
                 }
             }
@@ -399,13 +572,34 @@ where
         None
     }
 
-    fn handle_request_response_event(&mut self, event: ReqRespMessage) -> Option<NetworkEvent> {
-        use crate::req_resp::LeanResponse;
+    async fn handle_request_response_event(
+        &mut self,
+        event: ReqRespMessage,
+        protocol: &'static str,
+    ) -> Option<NetworkEvent> {
         use libp2p::request_response::{Event, Message};
 
+        if let Event::Message { peer, .. } = &event {
+            self.peer_manager.lock().on_message(peer);
+        }
+
         match event {
             Event::Message { peer, message, .. } => match message {
-                Message::Response { response, .. } => {
+                Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(batch) = self.sync_requests.remove(&request_id) {
+                        match response {
+                            LeanResponse::BlocksByRange(blocks) => {
+                                self.sync.on_batch_downloaded(batch, peer, blocks)
+                            }
+                            _ => self.sync.on_batch_failed(batch, peer),
+                        }
+                        self.drive_sync().await;
+                        return None;
+                    }
+
                     match response {
                         LeanResponse::BlocksByRoot(blocks) => {
                             info!(
@@ -418,12 +612,13 @@ where
                             let chain_sink = self.chain_message_sink.clone();
                             tokio::spawn(async move {
                                 for block in blocks {
-                                    let slot = block.message.block.slot.0;
+                                    let slot = block.slot().0;
                                     if let Err(e) = chain_sink
                                         .send(ChainMessage::ProcessBlock {
                                             signed_block_with_attestation: block,
                                             is_trusted: false,
                                             should_gossip: false, // Don't re-gossip requested blocks
+                                            signatures_verified: false,
                                         })
                                         .await
                                     {
@@ -441,8 +636,16 @@ where
                                 }
                             });
                         }
-                        LeanResponse::Status(_) => {
+                        LeanResponse::BlocksByRange(blocks) => {
+                            debug!(
+                                peer = %peer,
+                                num_blocks = blocks.len(),
+                                "Ignoring BlocksByRange response without a sync batch"
+                            );
+                        }
+                        LeanResponse::Status(status) => {
                             info!(peer = %peer, "Received Status response");
+                            self.handle_peer_status(peer, status).await;
                         }
                         LeanResponse::Empty => {
                             warn!(peer = %peer, "Received empty response");
@@ -452,36 +655,70 @@ where
                 Message::Request {
                     request, channel, ..
                 } => {
-                    use crate::req_resp::{LeanRequest, LeanResponse};
+                    metrics::REQ_RESP_REQUESTS
+                        .with_label_values(&[protocol, "inbound"])
+                        .inc();
 
-                    let response = match request {
-                        LeanRequest::Status(_) => {
+                    match request {
+                        LeanRequest::Status(status) => {
                             info!(peer = %peer, "Received Status request");
-                            LeanResponse::Status(containers::Status::default())
+                            let response = LeanResponse::Status(self.local_status());
+                            self.send_response(peer, channel, response);
+                            self.handle_peer_status(peer, status).await;
                         }
-                        LeanRequest::BlocksByRoot(roots) => {
+                        LeanRequest::BlocksByRoot(mut roots) => {
                             info!(peer = %peer, num_roots = roots.len(), "Received BlocksByRoot request");
-                            // TODO: Lookup blocks from our store and return them
-                            // For now, return empty to prevent timeout
-                            LeanResponse::BlocksByRoot(vec![])
+                            roots.truncate(req_resp::MAX_REQUEST_BLOCKS);
+                            self.serve_blocks(
+                                peer,
+                                channel,
+                                |respond_to| ChainQuery::BlocksByRoot { roots, respond_to },
+                                LeanResponse::BlocksByRoot,
+                            );
+                        }
+                        LeanRequest::BlocksByRange(BlocksByRangeRequest { start_slot, count }) => {
+                            info!(
+                                peer = %peer,
+                                start_slot = start_slot.0,
+                                count,
+                                "Received BlocksByRange request"
+                            );
+                            self.serve_blocks(
+                                peer,
+                                channel,
+                                |respond_to| ChainQuery::BlocksByRange {
+                                    start_slot,
+                                    count,
+                                    respond_to,
+                                },
+                                LeanResponse::BlocksByRange,
+                            );
                         }
-                    };
-
-                    if let Err(e) = self
-                        .swarm
-                        .behaviour_mut()
-                        .req_resp
-                        .send_response(channel, response)
-                    {
-                        warn!(peer = %peer, ?e, "Failed to send response");
                     }
                 }
             },
-            Event::OutboundFailure { peer, error, .. } => {
+            Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+                ..
+            } => {
                 warn!(peer = %peer, ?error, "Request failed");
+                metrics::REQ_RESP_FAILURES
+                    .with_label_values(&[protocol, "outbound"])
+                    .inc();
+                self.report_peer(peer, PeerAction::HighTolerance, "outbound request failed");
+
+                if let Some(batch) = self.sync_requests.remove(&request_id) {
+                    self.sync.on_batch_failed(batch, peer);
+                    self.drive_sync().await;
+                }
             }
             Event::InboundFailure { peer, error, .. } => {
                 warn!(peer = %peer, ?error, "Inbound request failed");
+                metrics::REQ_RESP_FAILURES
+                    .with_label_values(&[protocol, "inbound"])
+                    .inc();
             }
             Event::ResponseSent { peer, .. } => {
                 trace!(peer = %peer, "Response sent");
@@ -490,6 +727,464 @@ where
         None
     }
 
+    /// Answer a block request from the chain task's store.
+    fn serve_blocks(
+        &mut self,
+        peer: PeerId,
+        channel: ResponseChannel<LeanResponse>,
+        query: impl FnOnce(oneshot::Sender<Vec<VersionedSignedBlock>>) -> ChainQuery,
+        into_response: fn(Vec<VersionedSignedBlock>) -> LeanResponse,
+    ) {
+        let Some(chain_queries) = &self.chain_queries else {
+            self.send_response(peer, channel, into_response(vec![]));
+            return;
+        };
+
+        let (respond_to, blocks) = oneshot::channel();
+        if let Err(err) = chain_queries.send(query(respond_to)) {
+            warn!(peer = %peer, ?err, "Chain query channel closed");
+            self.send_response(peer, channel, into_response(vec![]));
+            return;
+        }
+
+        // The chain task may be busy importing a block; wait for its answer without
+        // blocking the swarm and hand the response back to the event loop.
+        let deferred = self.deferred_sender.clone();
+        tokio::spawn(async move {
+            let blocks = blocks.await.unwrap_or_default();
+            debug!(peer = %peer, num_blocks = blocks.len(), "Serving blocks");
+
+            let _ = deferred.send(DeferredAction::SendResponse {
+                peer,
+                channel,
+                response: into_response(blocks),
+            });
+        });
+    }
+
+    /// Send the requests and imports the range sync asks for.
+    async fn drive_sync(&mut self) {
+        let local_head = self.local_status().head.slot;
+
+        for action in self.sync.poll(local_head) {
+            match action {
+                SyncAction::Request {
+                    peer,
+                    batch,
+                    request,
+                } => {
+                    debug!(
+                        peer = %peer,
+                        start_slot = request.start_slot.0,
+                        count = request.count,
+                        "Requesting block range"
+                    );
+                    metrics::REQ_RESP_REQUESTS
+                        .with_label_values(&[BLOCKS_BY_RANGE_PROTOCOL_V1, "outbound"])
+                        .inc();
+                    let request_id = self
+                        .swarm
+                        .behaviour_mut()
+                        .blocks_by_range
+                        .send_request(&peer, LeanRequest::BlocksByRange(request));
+                    self.sync_requests.insert(request_id, batch);
+                }
+                SyncAction::Import(blocks) => {
+                    // Sent in order on the chain channel, so parents are imported first
+                    for block in blocks {
+                        let slot = block.slot().0;
+                        if let Err(err) = self
+                            .chain_message_sink
+                            .send(ChainMessage::ProcessBlock {
+                                signed_block_with_attestation: block,
+                                is_trusted: false,
+                                should_gossip: false,
+                                signatures_verified: false,
+                            })
+                            .await
+                        {
+                            warn!(slot, ?err, "Failed to send synced block to chain");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_deferred_action(&mut self, action: DeferredAction) {
+        match action {
+            DeferredAction::SendResponse {
+                peer,
+                channel,
+                response,
+            } => self.send_response(peer, channel, response),
+            DeferredAction::Disconnect { peer, reason } => self.disconnect_peer(peer, reason),
+            DeferredAction::GossipVerdict {
+                source,
+                message_id,
+                message,
+                verdict,
+            } => {
+                self.apply_gossip_verdict(source, message_id, message, verdict)
+                    .await
+            }
+            DeferredAction::DialDiscovered => {
+                let peers = self
+                    .discovery
+                    .as_ref()
+                    .map(|discovery| discovery.found().to_multiaddrs())
+                    .unwrap_or_default();
+                if !peers.is_empty() {
+                    self.connect_to_peers(peers).await;
+                }
+            }
+        }
+    }
+
+    /// Start a discovery query if discv5 is running and the peer manager wants more peers.
+    fn discover_peers(&self) {
+        let Some(discovery) = &self.discovery else {
+            return;
+        };
+
+        if !self.peer_manager.lock().needs_peers() {
+            return;
+        }
+
+        let deferred = self.deferred_sender.clone();
+        discovery.start_query(move || {
+            let _ = deferred.send(DeferredAction::DialDiscovered);
+        });
+    }
+
+    /// Ask the chain to validate a gossip message. The verdict comes back through
+    /// the deferred queue and is applied by `apply_gossip_verdict`.
+    async fn validate_gossip(
+        &mut self,
+        source: PeerId,
+        message_id: MessageId,
+        message: GossipsubMessage,
+    ) {
+        let Some(chain_queries) = &self.chain_queries else {
+            // Nothing to validate against; leave all checks to import
+            self.apply_gossip_verdict(source, message_id, message, GossipValidation::Accept)
+                .await;
+            return;
+        };
+
+        let (respond_to, verdict) = oneshot::channel();
+        let query = match &message {
+            GossipsubMessage::Block(block) => ChainQuery::ValidateBlock {
+                block: block.clone(),
+                respond_to,
+            },
+            GossipsubMessage::Attestation(attestation) => ChainQuery::ValidateAttestation {
+                attestation: attestation.clone(),
+                respond_to,
+            },
+            GossipsubMessage::Aggregate(aggregate) => ChainQuery::ValidateAggregate {
+                aggregate: aggregate.clone(),
+                respond_to,
+            },
+        };
+        if let Err(err) = chain_queries.send(query) {
+            warn!(?err, "Chain query channel closed");
+            self.report_validation(&message_id, &source, MessageAcceptance::Ignore);
+            return;
+        }
+
+        let deferred = self.deferred_sender.clone();
+        tokio::spawn(async move {
+            let verdict = verdict
+                .await
+                .unwrap_or_else(|_| GossipValidation::Ignore("chain did not answer".to_string()));
+
+            let _ = deferred.send(DeferredAction::GossipVerdict {
+                source,
+                message_id,
+                message,
+                verdict,
+            });
+        });
+    }
+
+    /// Report a validation verdict to gossipsub and import the message if it is usable.
+    async fn apply_gossip_verdict(
+        &mut self,
+        source: PeerId,
+        message_id: MessageId,
+        message: GossipsubMessage,
+        verdict: GossipValidation,
+    ) {
+        let verdict_label = match &verdict {
+            GossipValidation::Accept => "accept",
+            GossipValidation::UnknownParent => "unknown_parent",
+            GossipValidation::Ignore(_) => "ignore",
+            GossipValidation::Reject(_) => "reject",
+        };
+        metrics::GOSSIP_MESSAGES_RECEIVED
+            .with_label_values(&[
+                &message.kind(&self.network_config.fork()).to_string(),
+                verdict_label,
+            ])
+            .inc();
+
+        let (acceptance, import) = match &verdict {
+            GossipValidation::Accept => (MessageAcceptance::Accept, true),
+            GossipValidation::UnknownParent => (MessageAcceptance::Ignore, true),
+            GossipValidation::Ignore(reason) => {
+                debug!(peer = %source, reason, "Ignoring gossip message");
+                (MessageAcceptance::Ignore, false)
+            }
+            GossipValidation::Reject(reason) => {
+                warn!(peer = %source, reason, "Rejecting gossip message");
+                self.report_peer(source, PeerAction::MidTolerance, "invalid gossip message");
+                (MessageAcceptance::Reject, false)
+            }
+        };
+
+        self.report_validation(&message_id, &source, acceptance);
+
+        if !import {
+            return;
+        }
+
+        // Gossipsub forwards accepted messages itself, so the chain must not republish them.
+        // Accepted messages had their signatures checked during validation.
+        let signatures_verified = matches!(verdict, GossipValidation::Accept);
+        let (chain_message, slot) = match message {
+            GossipsubMessage::Block(signed_block_with_attestation) => {
+                let slot = signed_block_with_attestation.slot().0;
+                let message = ChainMessage::ProcessBlock {
+                    signed_block_with_attestation,
+                    is_trusted: false,
+                    should_gossip: false,
+                    signatures_verified,
+                };
+                (message, slot)
+            }
+            GossipsubMessage::Attestation(signed_attestation) => {
+                let slot = signed_attestation.message.data.slot.0;
+                let message = ChainMessage::ProcessAttestation {
+                    signed_attestation,
+                    is_trusted: false,
+                    should_gossip: false,
+                    signatures_verified,
+                };
+                (message, slot)
+            }
+            GossipsubMessage::Aggregate(signed_aggregate) => {
+                let slot = signed_aggregate.message.data.slot.0;
+                let message = ChainMessage::ProcessAggregate {
+                    signed_aggregate,
+                    is_trusted: false,
+                    should_gossip: false,
+                    signatures_verified,
+                };
+                (message, slot)
+            }
+        };
+
+        if let Err(err) = self.chain_message_sink.send(chain_message).await {
+            warn!(slot, ?err, "Failed to send gossip message to chain");
+        }
+    }
+
+    fn report_validation(
+        &mut self,
+        message_id: &MessageId,
+        source: &PeerId,
+        acceptance: MessageAcceptance,
+    ) {
+        // Fails only if the message already left the cache, which leaves nothing to report
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .gossipsub
+            .report_message_validation_result(message_id, source, acceptance);
+    }
+
+    /// Record a peer's Status and act on it.
+    ///
+    /// Peers whose finalized checkpoint is not on our chain are disconnected. The
+    /// others become range sync candidates, which starts a sync if they are ahead.
+    async fn handle_peer_status(&mut self, peer: PeerId, status: Status) {
+        info!(
+            peer = %peer,
+            finalized_slot = status.finalized.slot.0,
+            head_slot = status.head.slot.0,
+            "Peer status"
+        );
+
+        self.peer_manager.lock().set_status(peer, status.clone());
+
+        let local = self.local_status();
+
+        // Zeroed checkpoints carry no information about either chain
+        let comparable = !status.finalized.root.0.is_zero() && !local.finalized.root.0.is_zero();
+
+        if comparable {
+            if status.finalized.slot == local.finalized.slot
+                && status.finalized.root != local.finalized.root
+            {
+                self.disconnect_peer(peer, "finalized checkpoint conflicts with ours");
+                return;
+            }
+
+            if status.finalized.slot < local.finalized.slot {
+                self.check_finalized_ancestor(peer, status.finalized.clone());
+            }
+        }
+
+        if status.head.slot > local.head.slot {
+            info!(
+                peer = %peer,
+                peer_head_slot = status.head.slot.0,
+                local_head_slot = local.head.slot.0,
+                "Peer is ahead of us"
+            );
+        }
+
+        self.sync.add_peer(peer, status.head.slot);
+        self.drive_sync().await;
+    }
+
+    /// Disconnect `peer` later if its (older) finalized checkpoint is not on our chain.
+    fn check_finalized_ancestor(&self, peer: PeerId, finalized: Checkpoint) {
+        let Some(chain_queries) = &self.chain_queries else {
+            return;
+        };
+
+        let (respond_to, root) = oneshot::channel();
+        let query = ChainQuery::BlockRootAtSlot {
+            slot: finalized.slot,
+            respond_to,
+        };
+        if chain_queries.send(query).is_err() {
+            return;
+        }
+
+        let deferred = self.deferred_sender.clone();
+        tokio::spawn(async move {
+            if let Ok(Some(root)) = root.await
+                && root != finalized.root
+            {
+                let _ = deferred.send(DeferredAction::Disconnect {
+                    peer,
+                    reason: "finalized checkpoint is not on our canonical chain",
+                });
+            }
+        });
+    }
+
+    /// Periodic peer upkeep: lift expired bans, prune peers above the limit and
+    /// ban peers whose gossipsub score fell below the graylist threshold.
+    fn peer_heartbeat(&mut self) {
+        let heartbeat = self.update_peers(|peers| peers.heartbeat(Instant::now()));
+
+        for peer in heartbeat.unbanned {
+            self.swarm
+                .behaviour_mut()
+                .gossipsub
+                .remove_blacklisted_peer(&peer);
+            debug!(peer = %peer, "Peer ban expired");
+        }
+
+        for peer in heartbeat.prune {
+            self.disconnect_peer(peer, "too many peers");
+        }
+
+        let gossipsub = &self.swarm.behaviour().gossipsub;
+        let graylisted = self
+            .swarm
+            .connected_peers()
+            .filter_map(|peer| {
+                let score = gossipsub.peer_score(peer)?;
+                (score < GRAYLIST_THRESHOLD).then_some((*peer, score))
+            })
+            .collect::<Vec<_>>();
+
+        for (peer, score) in graylisted {
+            warn!(peer = %peer, score, "Peer score below graylist threshold");
+            self.ban_peer(peer, "gossipsub score below graylist threshold");
+        }
+    }
+
+    /// Lower the reputation of `peer` and ban it once it is too low.
+    fn report_peer(&mut self, peer: PeerId, action: PeerAction, reason: &str) {
+        debug!(peer = %peer, ?action, reason, "Reporting peer");
+
+        if self.peer_manager.lock().report(&peer, action) {
+            self.ban_peer(peer, reason);
+        }
+    }
+
+    fn ban_peer(&mut self, peer: PeerId, reason: &str) {
+        self.peer_manager.lock().ban(peer, BAN_DURATION);
+        self.swarm.behaviour_mut().gossipsub.blacklist_peer(&peer);
+        self.sync.remove_peer(&peer);
+        self.disconnect_peer(peer, reason);
+    }
+
+    fn disconnect_peer(&mut self, peer: PeerId, reason: &str) {
+        warn!(peer = %peer, reason, "Disconnecting peer");
+
+        self.peer_manager.lock().on_disconnecting(&peer);
+
+        if self.swarm.disconnect_peer_id(peer).is_err() {
+            debug!(peer = %peer, "Peer was not connected");
+        }
+    }
+
+    fn local_status(&self) -> Status {
+        self.local_status
+            .as_ref()
+            .map(|status| status.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    /// Apply `update` to the peer manager and refresh the shared connected peer count.
+    fn update_peers<T>(&self, update: impl FnOnce(&mut PeerManager) -> T) -> T {
+        let mut peers = self.peer_manager.lock();
+        let result = update(&mut peers);
+        self.peer_count
+            .store(peers.connected_count() as u64, Ordering::Relaxed);
+
+        for (direction, label) in [
+            (Direction::Inbound, "inbound"),
+            (Direction::Outbound, "outbound"),
+        ] {
+            metrics::CONNECTED_PEERS
+                .with_label_values(&[label])
+                .set(peers.connected_count_by(direction) as i64);
+        }
+
+        result
+    }
+
+    fn req_resp_for(&mut self, response: &LeanResponse) -> &mut ReqResp {
+        let behaviour = self.swarm.behaviour_mut();
+        match response {
+            LeanResponse::Status(_) | LeanResponse::Empty => &mut behaviour.status,
+            LeanResponse::BlocksByRoot(_) => &mut behaviour.blocks_by_root,
+            LeanResponse::BlocksByRange(_) => &mut behaviour.blocks_by_range,
+        }
+    }
+
+    fn send_response(
+        &mut self,
+        peer: PeerId,
+        channel: ResponseChannel<LeanResponse>,
+        response: LeanResponse,
+    ) {
+        if let Err(e) = self
+            .req_resp_for(&response)
+            .send_response(channel, response)
+        {
+            warn!(peer = %peer, ?e, "Failed to send response");
+        }
+    }
+
     fn handle_identify_event(&mut self, event: identify::Event) -> Option<NetworkEvent> {
         match event {
             identify::Event::Received {
@@ -538,12 +1233,8 @@ where
                 .find(|protocol| matches!(protocol, Protocol::P2p(_)))
                 && peer_id != self.local_peer_id()
             {
-                let current_state = self.peer_table.lock().get(&peer_id).cloned();
-                if !matches!(
-                    current_state,
-                    Some(ConnectionState::Disconnected | ConnectionState::Connecting) | None
-                ) {
-                    trace!(?peer_id, "Already connected");
+                if !self.peer_manager.lock().can_dial(&peer_id) {
+                    trace!(?peer_id, "Not dialing peer");
                     continue;
                 }
 
@@ -553,34 +1244,15 @@ where
                 }
 
                 info!(peer = %peer_id, "Dialing peer");
-                self.peer_table
-                    .lock()
-                    .insert(peer_id, ConnectionState::Connecting);
+                self.update_peers(|peers| peers.on_dialing(peer_id));
             }
         }
     }
 
-    fn get_random_connected_peer(&self) -> Option<PeerId> {
-        let peers: Vec<PeerId> = self
-            .peer_table
-            .lock()
-            .iter()
-            .filter(|(_, state)| **state == ConnectionState::Connected)
-            .map(|(peer_id, _)| *peer_id)
-            .collect();
-
-        if peers.is_empty() {
-            None
-        } else {
-            use rand::seq::SliceRandom;
-            peers.choose(&mut rand::thread_rng()).copied()
-        }
-    }
-
     async fn dispatch_outbound_request(&mut self, request: OutboundP2pRequest) {
         match request {
             OutboundP2pRequest::GossipBlockWithAttestation(signed_block_with_attestation) => {
-                let slot = signed_block_with_attestation.message.block.slot.0;
+                let slot = signed_block_with_attestation.slot().0;
                 match signed_block_with_attestation.to_ssz() {
                     Ok(bytes) => {
                         if let Err(err) = self.publish_to_topic(GossipsubKind::Block, bytes) {
@@ -596,12 +1268,18 @@ where
             }
             OutboundP2pRequest::GossipAttestation(signed_attestation) => {
                 let slot = signed_attestation.message.data.slot.0;
+                let subnet_id = signed_attestation.message.subnet_id();
+                let kind = if uses_attestation_subnets(&self.network_config.fork()) {
+                    GossipsubKind::AttestationSubnet(subnet_id)
+                } else {
+                    GossipsubKind::Attestation
+                };
                 match signed_attestation.to_ssz() {
                     Ok(bytes) => {
-                        if let Err(err) = self.publish_to_topic(GossipsubKind::Attestation, bytes) {
-                            warn!(slot = slot, ?err, "Publish attestation failed");
+                        if let Err(err) = self.publish_to_topic(kind, bytes) {
+                            warn!(slot = slot, subnet_id, ?err, "Publish attestation failed");
                         } else {
-                            info!(slot = slot, "Broadcasted attestation");
+                            info!(slot = slot, subnet_id, "Broadcasted attestation");
                         }
                     }
                     Err(err) => {
@@ -609,8 +1287,24 @@ where
                     }
                 }
             }
+            OutboundP2pRequest::GossipAggregate(signed_aggregate) => {
+                let slot = signed_aggregate.message.data.slot.0;
+                match signed_aggregate.to_ssz() {
+                    Ok(bytes) => {
+                        if let Err(err) = self.publish_to_topic(GossipsubKind::Aggregation, bytes) {
+                            warn!(slot = slot, ?err, "Publish aggregate failed");
+                        } else {
+                            info!(slot = slot, "Broadcasted aggregate");
+                        }
+                    }
+                    Err(err) => {
+                        warn!(slot = slot, ?err, "Serialize aggregate failed");
+                    }
+                }
+            }
             OutboundP2pRequest::RequestBlocksByRoot(roots) => {
-                if let Some(peer_id) = self.get_random_connected_peer() {
+                let best_peer = self.peer_manager.lock().best_peer();
+                if let Some(peer_id) = best_peer {
                     info!(
                         peer = %peer_id,
                         num_blocks = roots.len(),
@@ -624,45 +1318,59 @@ where
         }
     }
 
+    /// Publish on the topic of `kind`. Attestation subnets we are not subscribed
+    /// to are reached through gossipsub fanout.
     fn publish_to_topic(&mut self, kind: GossipsubKind, data: Vec<u8>) -> Result<()> {
-        let topic = self
-            .network_config
-            .gossipsub_config
-            .topics
-            .iter()
-            .find(|topic| topic.kind == kind)
-            .cloned()
-            .ok_or_else(|| anyhow!("Missing gossipsub topic for kind {kind:?}"))?;
+        let topic = GossipsubTopic {
+            fork: self.network_config.fork(),
+            kind,
+        };
 
         self.swarm
             .behaviour_mut()
             .gossipsub
             .publish(IdentTopic::from(topic), data)
-            .map(|_| ())
-            .map_err(|err| anyhow!("publish failed: {err:?}"))
+            .map_err(|err| anyhow!("publish failed: {err:?}"))?;
+
+        metrics::GOSSIP_MESSAGES_PUBLISHED
+            .with_label_values(&[&kind.to_string()])
+            .inc();
+
+        Ok(())
     }
 
-    pub fn peer_table(&self) -> Arc<Mutex<HashMap<PeerId, ConnectionState>>> {
-        self.peer_table.clone()
+    pub fn peer_manager(&self) -> Arc<Mutex<PeerManager>> {
+        self.peer_manager.clone()
     }
 
     pub fn local_peer_id(&self) -> PeerId {
         *self.swarm.local_peer_id()
     }
 
+    /// Our signed ENR, if discovery is running.
+    pub fn local_enr(&self) -> Option<Enr> {
+        self.discovery.as_ref().map(Discovery::local_enr)
+    }
+
+    pub fn listen_multiaddr(&self) -> Result<Multiaddr> {
+        Self::multiaddr(&self.network_config)
+    }
+
     pub fn swarm_mut(&mut self) -> &mut Swarm<LeanNetworkBehaviour> {
         &mut self.swarm
     }
 
     fn send_status_request(&mut self, peer_id: PeerId) {
-        let status = containers::Status::default();
-        let request = LeanRequest::Status(status);
+        let request = LeanRequest::Status(self.local_status());
 
         info!(peer = %peer_id, "Sending Status request for handshake");
+        metrics::REQ_RESP_REQUESTS
+            .with_label_values(&[STATUS_PROTOCOL_V1, "outbound"])
+            .inc();
         let _request_id = self
             .swarm
             .behaviour_mut()
-            .req_resp
+            .status
             .send_request(&peer_id, request);
     }
 
@@ -687,10 +1395,13 @@ where
 
         let request = LeanRequest::BlocksByRoot(roots.clone());
         info!(peer = %peer_id, num_roots = roots.len(), "Sending BlocksByRoot request");
+        metrics::REQ_RESP_REQUESTS
+            .with_label_values(&[BLOCKS_BY_ROOT_PROTOCOL_V1, "outbound"])
+            .inc();
         let _request_id = self
             .swarm
             .behaviour_mut()
-            .req_resp
+            .blocks_by_root
             .send_request(&peer_id, request);
     }
 
@@ -699,17 +1410,25 @@ where
         cfg: &NetworkServiceConfig,
     ) -> Result<LeanNetworkBehaviour> {
         let identify = Self::build_identify(local_key);
-        let gossipsub = gossipsub::GossipsubBehaviour::new_with_transform(
+        let mut gossipsub = gossipsub::GossipsubBehaviour::new_with_transform(
             MessageAuthenticity::Anonymous,
             cfg.gossipsub_config.config.clone(),
             Compressor::default(),
         )
         .map_err(|err| anyhow!("Failed to create gossipsub behaviour: {err:?}"))?;
-
-        let req_resp = req_resp::build(vec![
-            STATUS_PROTOCOL_V1.to_string(),
-            BLOCKS_BY_ROOT_PROTOCOL_V1.to_string(),
-        ]);
+        gossipsub
+            .with_peer_score(
+                cfg.gossipsub_config.score_params(),
+                cfg.gossipsub_config.score_thresholds(),
+            )
+            .map_err(|err| anyhow!("Invalid gossipsub peer score parameters: {err}"))?;
+
+        let block_version = cfg.block_version();
+        let status = req_resp::build([STATUS_PROTOCOL_V1.to_string()], block_version);
+        let blocks_by_root =
+            req_resp::build([BLOCKS_BY_ROOT_PROTOCOL_V1.to_string()], block_version);
+        let blocks_by_range =
+            req_resp::build([BLOCKS_BY_RANGE_PROTOCOL_V1.to_string()], block_version);
 
         let connection_limits = connection_limits::Behaviour::new(
             ConnectionLimits::default()
@@ -720,7 +1439,9 @@ where
 
         Ok(LeanNetworkBehaviour {
             identify,
-            req_resp,
+            status,
+            blocks_by_root,
+            blocks_by_range,
             gossipsub,
             connection_limits,
         })
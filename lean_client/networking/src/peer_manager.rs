@@ -0,0 +1,413 @@
+//! Peer bookkeeping: connection state and direction, Status, reputation and bans.
+//!
+//! The peer manager only decides. Dialing, disconnecting and gossipsub blacklisting
+//! are left to the network service, which acts on the peers returned by
+//! [`PeerManager::report`] and [`PeerManager::heartbeat`].
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use containers::Status;
+use libp2p_identity::PeerId;
+
+use crate::types::{ConnectionState, Direction, PeerCount, PeerInfo};
+
+/// Below this many connected peers discovery looks for more.
+pub const DEFAULT_MIN_PEERS: usize = 16;
+/// Above this many connected peers the worst ones are disconnected.
+pub const DEFAULT_MAX_PEERS: usize = 32;
+
+pub const BAN_DURATION: Duration = Duration::from_secs(3600);
+/// Disconnected peers are forgotten after this long without contact.
+pub const DISCONNECTED_PEER_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// A peer is banned once its reputation drops to this value.
+pub const BAN_REPUTATION: i32 = -100;
+/// Reputation recovered per heartbeat, so penalties wear off over time.
+const REPUTATION_RECOVERY: i32 = 1;
+
+/// How badly a peer misbehaved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerAction {
+    /// Provably malicious. The peer is banned straight away.
+    Fatal,
+    /// Clearly at fault. A few of these get the peer banned.
+    LowTolerance,
+    /// Likely at fault, e.g. an invalid gossip message.
+    MidTolerance,
+    /// Possibly not the peer's fault, e.g. a timed out request.
+    HighTolerance,
+}
+
+impl PeerAction {
+    fn penalty(self) -> i32 {
+        match self {
+            Self::Fatal => -BAN_REPUTATION,
+            Self::LowTolerance => 34,
+            Self::MidTolerance => 10,
+            Self::HighTolerance => 2,
+        }
+    }
+}
+
+/// What the network service has to do after a [`PeerManager::heartbeat`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// Peers whose ban expired.
+    pub unbanned: Vec<PeerId>,
+    /// Connected peers to disconnect because we are above the peer limit.
+    pub prune: Vec<PeerId>,
+}
+
+#[derive(Debug)]
+pub struct PeerManager {
+    peers: HashMap<PeerId, PeerInfo>,
+    min_peers: usize,
+    max_peers: usize,
+}
+
+impl Default for PeerManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_PEERS, DEFAULT_MAX_PEERS)
+    }
+}
+
+impl PeerManager {
+    pub fn new(min_peers: usize, max_peers: usize) -> Self {
+        Self {
+            peers: HashMap::new(),
+            min_peers,
+            max_peers: max_peers.max(min_peers),
+        }
+    }
+
+    pub fn get(&self, peer: &PeerId) -> Option<&PeerInfo> {
+        self.peers.get(peer)
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = (&PeerId, &PeerInfo)> {
+        self.peers.iter()
+    }
+
+    pub fn connected_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, info)| info.state == ConnectionState::Connected)
+            .map(|(peer, _)| peer)
+    }
+
+    pub fn connected_count(&self) -> usize {
+        self.connected_peers().count()
+    }
+
+    pub fn connected_count_by(&self, direction: Direction) -> usize {
+        self.peers
+            .values()
+            .filter(|info| info.state == ConnectionState::Connected && info.direction == direction)
+            .count()
+    }
+
+    pub fn peer_count(&self) -> PeerCount {
+        PeerCount::new(self.peers.values())
+    }
+
+    /// Whether discovery should look for more peers.
+    pub fn needs_peers(&self) -> bool {
+        self.connected_count() < self.min_peers
+    }
+
+    /// Whether dialing `peer` is worthwhile: it is not banned, not already
+    /// connected and we have room for another peer.
+    pub fn can_dial(&self, peer: &PeerId) -> bool {
+        let idle = self.peers.get(peer).is_none_or(|info| {
+            !info.is_banned() && matches!(info.state, ConnectionState::Disconnected)
+        });
+
+        idle && self.connected_count() < self.max_peers
+    }
+
+    pub fn is_banned(&self, peer: &PeerId) -> bool {
+        self.peers.get(peer).is_some_and(PeerInfo::is_banned)
+    }
+
+    pub fn on_dialing(&mut self, peer: PeerId) {
+        let info = self.entry(peer);
+        info.state = ConnectionState::Connecting;
+        info.direction = Direction::Outbound;
+    }
+
+    pub fn on_connected(&mut self, peer: PeerId, direction: Direction) {
+        let info = self.entry(peer);
+        info.state = ConnectionState::Connected;
+        info.direction = direction;
+    }
+
+    /// A dial failed. Peers with another connection open are left alone.
+    pub fn on_dial_failed(&mut self, peer: &PeerId) {
+        if let Some(info) = self.peers.get_mut(peer)
+            && info.state == ConnectionState::Connecting
+        {
+            info.state = ConnectionState::Disconnected;
+        }
+    }
+
+    pub fn on_disconnecting(&mut self, peer: &PeerId) {
+        if let Some(info) = self.peers.get_mut(peer) {
+            info.state = ConnectionState::Disconnecting;
+        }
+    }
+
+    pub fn on_disconnected(&mut self, peer: PeerId) {
+        let info = self.entry(peer);
+        info.state = ConnectionState::Disconnected;
+        info.status = None;
+    }
+
+    pub fn set_status(&mut self, peer: PeerId, status: Status) {
+        self.entry(peer).status = Some(status);
+    }
+
+    /// Note that `peer` sent us something.
+    pub fn on_message(&mut self, peer: &PeerId) {
+        if let Some(info) = self.peers.get_mut(peer) {
+            info.last_seen = Instant::now();
+        }
+    }
+
+    /// Lower the reputation of `peer`. Returns true if it should now be banned.
+    pub fn report(&mut self, peer: &PeerId, action: PeerAction) -> bool {
+        let Some(info) = self.peers.get_mut(peer) else {
+            return false;
+        };
+
+        info.reputation = (info.reputation - action.penalty()).max(BAN_REPUTATION);
+        info.reputation <= BAN_REPUTATION && !info.is_banned()
+    }
+
+    pub fn ban(&mut self, peer: PeerId, duration: Duration) {
+        let info = self.entry(peer);
+        info.banned_until = Some(Instant::now() + duration);
+        info.reputation = BAN_REPUTATION;
+    }
+
+    /// Connected peer best suited for a request: highest reputation, then most
+    /// advanced head.
+    pub fn best_peer(&self) -> Option<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, info)| info.state == ConnectionState::Connected)
+            .max_by_key(|(_, info)| {
+                let head_slot = info.status.as_ref().map(|status| status.head.slot.0);
+                (info.reputation, head_slot)
+            })
+            .map(|(peer, _)| *peer)
+    }
+
+    /// Periodic upkeep: recover reputation, lift expired bans, forget stale
+    /// disconnected peers and pick peers to prune.
+    pub fn heartbeat(&mut self, now: Instant) -> Heartbeat {
+        let mut heartbeat = Heartbeat::default();
+
+        for (peer, info) in &mut self.peers {
+            if info.banned_until.is_some_and(|until| until <= now) {
+                // The ban was the punishment; start over with a clean record
+                info.banned_until = None;
+                info.reputation = 0;
+                heartbeat.unbanned.push(*peer);
+            }
+
+            if info.banned_until.is_none() {
+                info.reputation = (info.reputation + REPUTATION_RECOVERY).min(0);
+            }
+        }
+
+        self.peers.retain(|_, info| {
+            info.state != ConnectionState::Disconnected
+                || info.banned_until.is_some()
+                || now.saturating_duration_since(info.last_seen) < DISCONNECTED_PEER_TTL
+        });
+
+        let excess = self.connected_count().saturating_sub(self.max_peers);
+        if excess > 0 {
+            // Worst first: lowest reputation, then inbound before the peers we chose
+            // to dial, then the ones we heard from least recently
+            let mut connected = self
+                .peers
+                .iter()
+                .filter(|(_, info)| info.state == ConnectionState::Connected)
+                .collect::<Vec<_>>();
+            connected.sort_by_key(|(_, info)| {
+                (
+                    info.reputation,
+                    info.direction == Direction::Outbound,
+                    info.last_seen,
+                )
+            });
+            heartbeat.prune = connected
+                .into_iter()
+                .take(excess)
+                .map(|(peer, _)| *peer)
+                .collect();
+        }
+
+        heartbeat
+    }
+
+    fn entry(&mut self, peer: PeerId) -> &mut PeerInfo {
+        let info = self
+            .peers
+            .entry(peer)
+            .or_insert_with(|| PeerInfo::new(ConnectionState::Disconnected));
+        info.last_seen = Instant::now();
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use containers::{Checkpoint, Slot};
+
+    use super::*;
+
+    fn connected(manager: &mut PeerManager, direction: Direction) -> PeerId {
+        let peer = PeerId::random();
+        manager.on_connected(peer, direction);
+        peer
+    }
+
+    #[test]
+    fn test_tracks_direction_and_count() {
+        let mut manager = PeerManager::new(2, 4);
+        let inbound = connected(&mut manager, Direction::Inbound);
+        let outbound = PeerId::random();
+        manager.on_dialing(outbound);
+
+        assert_eq!(manager.get(&inbound).unwrap().direction, Direction::Inbound);
+        assert_eq!(
+            manager.get(&outbound).unwrap().direction,
+            Direction::Outbound
+        );
+        assert_eq!(manager.peer_count().connected, 1);
+        assert_eq!(manager.peer_count().connecting, 1);
+        assert!(manager.needs_peers());
+
+        manager.on_connected(outbound, Direction::Outbound);
+        assert!(!manager.needs_peers());
+        assert_eq!(manager.connected_count(), 2);
+    }
+
+    #[test]
+    fn test_repeated_faults_lead_to_ban() {
+        let mut manager = PeerManager::default();
+        let peer = connected(&mut manager, Direction::Outbound);
+
+        assert!(!manager.report(&peer, PeerAction::LowTolerance));
+        assert!(!manager.report(&peer, PeerAction::LowTolerance));
+        assert!(manager.report(&peer, PeerAction::LowTolerance));
+
+        manager.ban(peer, BAN_DURATION);
+        assert!(manager.is_banned(&peer));
+        assert!(!manager.can_dial(&peer));
+
+        // Already banned, nothing more to do
+        assert!(!manager.report(&peer, PeerAction::Fatal));
+    }
+
+    #[test]
+    fn test_ban_expires() {
+        let mut manager = PeerManager::default();
+        let peer = connected(&mut manager, Direction::Inbound);
+        manager.report(&peer, PeerAction::Fatal);
+        manager.ban(peer, BAN_DURATION);
+        manager.on_disconnected(peer);
+        let banned_at = Instant::now();
+
+        // Kept while banned, even past the TTL of disconnected peers
+        let heartbeat = manager.heartbeat(banned_at + BAN_DURATION - Duration::from_secs(1));
+        assert!(heartbeat.unbanned.is_empty());
+        assert!(manager.is_banned(&peer));
+
+        let heartbeat = manager.heartbeat(banned_at + BAN_DURATION);
+        assert_eq!(heartbeat.unbanned, vec![peer]);
+        assert!(!manager.is_banned(&peer));
+
+        // A ban shorter than the TTL leaves the peer known, with a clean record
+        let peer = connected(&mut manager, Direction::Inbound);
+        manager.report(&peer, PeerAction::Fatal);
+        manager.ban(peer, Duration::from_secs(60));
+        manager.on_disconnected(peer);
+
+        let heartbeat = manager.heartbeat(Instant::now() + Duration::from_secs(60));
+        assert_eq!(heartbeat.unbanned, vec![peer]);
+        let info = &manager.peers[&peer];
+        assert_eq!(info.banned_until, None);
+        assert_eq!(info.reputation, 0);
+        assert!(manager.can_dial(&peer));
+    }
+
+    #[test]
+    fn test_reputation_recovers() {
+        let mut manager = PeerManager::default();
+        let peer = connected(&mut manager, Direction::Inbound);
+        manager.report(&peer, PeerAction::HighTolerance);
+
+        manager.heartbeat(Instant::now());
+        manager.heartbeat(Instant::now());
+        manager.heartbeat(Instant::now());
+
+        assert_eq!(manager.get(&peer).unwrap().reputation, 0);
+    }
+
+    #[test]
+    fn test_disconnected_peers_expire() {
+        let mut manager = PeerManager::default();
+        let peer = connected(&mut manager, Direction::Inbound);
+        manager.on_disconnected(peer);
+
+        manager.heartbeat(Instant::now());
+        assert!(manager.get(&peer).is_some());
+
+        manager.heartbeat(Instant::now() + DISCONNECTED_PEER_TTL);
+        assert!(manager.get(&peer).is_none());
+    }
+
+    #[test]
+    fn test_prunes_worst_peers_above_max() {
+        let mut manager = PeerManager::new(1, 2);
+        let bad = connected(&mut manager, Direction::Outbound);
+        let inbound = connected(&mut manager, Direction::Inbound);
+        connected(&mut manager, Direction::Outbound);
+        connected(&mut manager, Direction::Outbound);
+        manager.report(&bad, PeerAction::MidTolerance);
+
+        // With equal reputation, inbound peers go before the ones we dialed
+        let mut prune = manager.heartbeat(Instant::now()).prune;
+        prune.sort();
+        let mut expected = vec![bad, inbound];
+        expected.sort();
+
+        assert_eq!(prune, expected);
+    }
+
+    #[test]
+    fn test_best_peer_prefers_reputation_then_head() {
+        let mut manager = PeerManager::default();
+        let behind = connected(&mut manager, Direction::Outbound);
+        let ahead = connected(&mut manager, Direction::Outbound);
+        let faulty = connected(&mut manager, Direction::Outbound);
+
+        let status = |slot| Status {
+            finalized: Checkpoint::default(),
+            head: Checkpoint {
+                slot: Slot(slot),
+                ..Checkpoint::default()
+            },
+        };
+        manager.set_status(behind, status(5));
+        manager.set_status(ahead, status(10));
+        manager.set_status(faulty, status(20));
+        manager.report(&faulty, PeerAction::HighTolerance);
+
+        assert_eq!(manager.best_peer(), Some(ahead));
+    }
+}
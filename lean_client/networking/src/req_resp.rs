@@ -2,8 +2,9 @@ use std::io;
 use std::io::{Read, Write};
 
 use async_trait::async_trait;
+pub use containers::BlocksByRangeRequest;
 use containers::ssz::{SszReadDefault, SszWrite};
-use containers::{Bytes32, SignedBlockWithAttestation, Status};
+use containers::{BlockVersion, Bytes32, Status, VersionedSignedBlock};
 use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use libp2p::request_response::{
     Behaviour as RequestResponse, Codec, Config, Event, ProtocolSupport,
@@ -13,8 +14,16 @@ use snap::write::FrameEncoder;
 
 pub const MAX_REQUEST_BLOCKS: usize = 1024;
 
+/// Upper bound on the uncompressed size of a single response chunk.
+pub const MAX_PAYLOAD_SIZE: usize = 10 * 1024 * 1024;
+
+/// Result code prefixed to every successful response chunk, see `LeanCodec::encode_chunk`.
+const RESPONSE_CODE_SUCCESS: u8 = 0;
+
 pub const STATUS_PROTOCOL_V1: &str = "/leanconsensus/req/status/1/ssz_snappy";
 pub const BLOCKS_BY_ROOT_PROTOCOL_V1: &str = "/leanconsensus/req/lean_blocks_by_root/1/ssz_snappy";
+pub const BLOCKS_BY_RANGE_PROTOCOL_V1: &str =
+    "/leanconsensus/req/lean_blocks_by_range/1/ssz_snappy";
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct LeanProtocol(pub String);
@@ -29,19 +38,28 @@ impl AsRef<str> for LeanProtocol {
 pub enum LeanRequest {
     Status(Status),
     BlocksByRoot(Vec<Bytes32>),
+    BlocksByRange(BlocksByRangeRequest),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LeanResponse {
     Status(Status),
-    BlocksByRoot(Vec<SignedBlockWithAttestation>),
+    BlocksByRoot(Vec<VersionedSignedBlock>),
+    BlocksByRange(Vec<VersionedSignedBlock>),
     Empty,
 }
 
+/// Block responses are decoded in the node's block format.
 #[derive(Clone, Default)]
-pub struct LeanCodec;
+pub struct LeanCodec {
+    block_version: BlockVersion,
+}
 
 impl LeanCodec {
+    pub fn new(block_version: BlockVersion) -> Self {
+        Self { block_version }
+    }
+
     /// Compress data using Snappy framing format (required for req/resp protocol)
     fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
         let mut encoder = FrameEncoder::new(Vec::new());
@@ -71,6 +89,9 @@ impl LeanCodec {
                 }
                 bytes
             }
+            LeanRequest::BlocksByRange(request) => request.to_ssz().map_err(|e| {
+                io::Error::new(io::ErrorKind::Other, format!("SSZ encode failed: {e}"))
+            })?,
         };
         Self::compress(&ssz_bytes)
     }
@@ -110,6 +131,23 @@ impl LeanCodec {
                 ));
             }
             Ok(LeanRequest::BlocksByRoot(roots))
+        } else if protocol.contains("blocks_by_range") {
+            let request = BlocksByRangeRequest::from_ssz_default(&ssz_bytes).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("SSZ decode BlocksByRange request failed: {e:?}"),
+                )
+            })?;
+            if request.count == 0 || request.count > MAX_REQUEST_BLOCKS as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Invalid BlocksByRange count: {} (max {MAX_REQUEST_BLOCKS})",
+                        request.count
+                    ),
+                ));
+            }
+            Ok(LeanRequest::BlocksByRange(request))
         } else {
             Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -118,39 +156,148 @@ impl LeanCodec {
         }
     }
 
-    fn encode_response(response: &LeanResponse) -> io::Result<Vec<u8>> {
-        let ssz_bytes = match response {
-            LeanResponse::Status(status) => status.to_ssz().map_err(|e| {
+    fn write_varint(mut value: usize, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn read_varint(reader: &mut impl Read) -> io::Result<usize> {
+        let mut value = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let mut byte = [0u8; 1];
+            reader.read_exact(&mut byte)?;
+            value |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Varint length prefix too long",
+        ))
+    }
+
+    /// Append one response chunk to `out`.
+    ///
+    /// Every response, Status included, is a sequence of chunks in the leanSpec
+    /// req/resp format: `result code | uvarint(ssz length) | snappy frames(ssz)`.
+    fn encode_chunk(ssz_bytes: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        out.push(RESPONSE_CODE_SUCCESS);
+        Self::write_varint(ssz_bytes.len(), out);
+        out.extend(Self::compress(ssz_bytes)?);
+        Ok(())
+    }
+
+    /// Read one response chunk and return its uncompressed SSZ bytes.
+    fn read_chunk(reader: &mut io::Cursor<&[u8]>) -> io::Result<Vec<u8>> {
+        let mut code = [0u8; 1];
+        reader.read_exact(&mut code)?;
+        if code[0] != RESPONSE_CODE_SUCCESS {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("Peer responded with error code {}", code[0]),
+            ));
+        }
+
+        let length = Self::read_varint(reader)?;
+        if length > MAX_PAYLOAD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Response chunk length {length} exceeds {MAX_PAYLOAD_SIZE}"),
+            ));
+        }
+
+        let mut ssz_bytes = vec![0u8; length];
+        FrameDecoder::new(reader).read_exact(&mut ssz_bytes)?;
+        Ok(ssz_bytes)
+    }
+
+    /// Encode blocks as one response chunk each, so a response may carry any
+    /// number of blocks.
+    fn encode_block_chunks(blocks: &[VersionedSignedBlock]) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for block in blocks {
+            let block_bytes = block.to_ssz().map_err(|e| {
                 io::Error::new(io::ErrorKind::Other, format!("SSZ encode failed: {e}"))
-            })?,
-            LeanResponse::BlocksByRoot(blocks) => {
-                let mut bytes = Vec::new();
-                for block in blocks {
-                    let block_bytes = block.to_ssz().map_err(|e| {
-                        io::Error::new(io::ErrorKind::Other, format!("SSZ encode failed: {e}"))
-                    })?;
-                    bytes.extend_from_slice(&block_bytes);
-                }
-                bytes
+            })?;
+            Self::encode_chunk(&block_bytes, &mut bytes)?;
+        }
+        Ok(bytes)
+    }
+
+    fn decode_block_chunks(&self, data: &[u8]) -> io::Result<Vec<VersionedSignedBlock>> {
+        let mut reader = io::Cursor::new(data);
+        let mut blocks = Vec::new();
+
+        while (reader.position() as usize) < data.len() {
+            if blocks.len() >= MAX_REQUEST_BLOCKS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Too many blocks in response: > {MAX_REQUEST_BLOCKS}"),
+                ));
             }
-            LeanResponse::Empty => Vec::new(),
-        };
 
-        if ssz_bytes.is_empty() {
-            return Ok(Vec::new());
+            let block_bytes = Self::read_chunk(&mut reader)?;
+            let block =
+                VersionedSignedBlock::from_ssz(self.block_version, &block_bytes).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("SSZ decode Block failed: {e}"),
+                    )
+                })?;
+            blocks.push(block);
         }
 
-        Self::compress(&ssz_bytes)
+        Ok(blocks)
+    }
+
+    fn encode_response(response: &LeanResponse) -> io::Result<Vec<u8>> {
+        match response {
+            LeanResponse::Status(status) => {
+                let ssz_bytes = status.to_ssz().map_err(|e| {
+                    io::Error::new(io::ErrorKind::Other, format!("SSZ encode failed: {e}"))
+                })?;
+                let mut bytes = Vec::new();
+                Self::encode_chunk(&ssz_bytes, &mut bytes)?;
+                Ok(bytes)
+            }
+            LeanResponse::BlocksByRoot(blocks) | LeanResponse::BlocksByRange(blocks) => {
+                Self::encode_block_chunks(blocks)
+            }
+            LeanResponse::Empty => Ok(Vec::new()),
+        }
     }
 
-    fn decode_response(protocol: &str, data: &[u8]) -> io::Result<LeanResponse> {
+    fn decode_response(&self, protocol: &str, data: &[u8]) -> io::Result<LeanResponse> {
+        if protocol.contains("blocks_by_root") {
+            return self
+                .decode_block_chunks(data)
+                .map(LeanResponse::BlocksByRoot);
+        }
+
+        if protocol.contains("blocks_by_range") {
+            return self
+                .decode_block_chunks(data)
+                .map(LeanResponse::BlocksByRange);
+        }
+
         if data.is_empty() {
             return Ok(LeanResponse::Empty);
         }
 
-        let ssz_bytes = Self::decompress(data)?;
-
         if protocol.contains("status") {
+            let mut reader = io::Cursor::new(data);
+            let ssz_bytes = Self::read_chunk(&mut reader)?;
+            if (reader.position() as usize) < data.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Status response has more than one chunk",
+                ));
+            }
+
             let status = Status::from_ssz_default(&ssz_bytes).map_err(|e| {
                 io::Error::new(
                     io::ErrorKind::Other,
@@ -158,17 +305,6 @@ impl LeanCodec {
                 )
             })?;
             Ok(LeanResponse::Status(status))
-        } else if protocol.contains("blocks_by_root") {
-            if ssz_bytes.is_empty() {
-                return Ok(LeanResponse::BlocksByRoot(Vec::new()));
-            }
-            let block = SignedBlockWithAttestation::from_ssz_default(&ssz_bytes).map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("SSZ decode Block failed: {e:?}"),
-                )
-            })?;
-            Ok(LeanResponse::BlocksByRoot(vec![block]))
         } else {
             Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -207,7 +343,7 @@ impl Codec for LeanCodec {
     {
         let mut data = Vec::new();
         io.read_to_end(&mut data).await?;
-        Self::decode_response(&protocol.0, &data)
+        self.decode_response(&protocol.0, &data)
     }
 
     async fn write_request<T>(
@@ -320,18 +456,171 @@ pub type ReqResp = RequestResponse<LeanCodec>;
 
 pub type ReqRespMessage = Event<LeanRequest, LeanResponse>;
 
-pub fn build(protocols: impl IntoIterator<Item = String>) -> ReqResp {
+pub fn build(protocols: impl IntoIterator<Item = String>, block_version: BlockVersion) -> ReqResp {
     let protocols = protocols
         .into_iter()
         .map(|name| (LeanProtocol(name), ProtocolSupport::Full))
         .collect::<Vec<_>>();
 
-    RequestResponse::with_codec(LeanCodec::default(), protocols, Config::default())
+    RequestResponse::with_codec(LeanCodec::new(block_version), protocols, Config::default())
 }
 
 pub fn build_default() -> ReqResp {
-    build(vec![
-        STATUS_PROTOCOL_V1.to_string(),
-        BLOCKS_BY_ROOT_PROTOCOL_V1.to_string(),
-    ])
+    build(
+        vec![
+            STATUS_PROTOCOL_V1.to_string(),
+            BLOCKS_BY_ROOT_PROTOCOL_V1.to_string(),
+            BLOCKS_BY_RANGE_PROTOCOL_V1.to_string(),
+        ],
+        BlockVersion::default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use containers::{
+        SignedAggregatedBlockWithAttestation, SignedBlockWithAttestation, Slot, ValidatorIndex,
+        block::{AggregatedBlock, AggregatedBlockWithAttestation, Block, BlockWithAttestation},
+    };
+
+    fn block(slot: u64) -> VersionedSignedBlock {
+        SignedBlockWithAttestation {
+            message: BlockWithAttestation {
+                block: Block {
+                    slot: Slot(slot),
+                    proposer_index: ValidatorIndex(slot),
+                    ..Block::default()
+                },
+                proposer_attestation: Default::default(),
+            },
+            signature: Default::default(),
+        }
+        .into()
+    }
+
+    fn aggregated_block(slot: u64) -> VersionedSignedBlock {
+        SignedAggregatedBlockWithAttestation {
+            message: AggregatedBlockWithAttestation {
+                block: AggregatedBlock {
+                    slot: Slot(slot),
+                    proposer_index: ValidatorIndex(slot),
+                    ..AggregatedBlock::default()
+                },
+                proposer_attestation: Default::default(),
+            },
+            signature: Default::default(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn blocks_by_root_response_round_trip() {
+        let response = LeanResponse::BlocksByRoot(vec![block(1), block(2), block(3)]);
+
+        let encoded = LeanCodec::encode_response(&response).unwrap();
+        let decoded = LeanCodec::default()
+            .decode_response(BLOCKS_BY_ROOT_PROTOCOL_V1, &encoded)
+            .unwrap();
+
+        assert_eq!(decoded, response);
+    }
+
+    #[test]
+    fn empty_blocks_by_root_response_round_trip() {
+        let response = LeanResponse::BlocksByRoot(vec![]);
+
+        let encoded = LeanCodec::encode_response(&response).unwrap();
+        let decoded = LeanCodec::default()
+            .decode_response(BLOCKS_BY_ROOT_PROTOCOL_V1, &encoded)
+            .unwrap();
+
+        assert_eq!(decoded, response);
+    }
+
+    #[test]
+    fn blocks_by_range_round_trip() {
+        let request = LeanRequest::BlocksByRange(BlocksByRangeRequest {
+            start_slot: Slot(64),
+            count: 32,
+        });
+
+        let encoded = LeanCodec::encode_request(&request).unwrap();
+        let decoded = LeanCodec::decode_request(BLOCKS_BY_RANGE_PROTOCOL_V1, &encoded).unwrap();
+        assert_eq!(decoded, request);
+
+        let response = LeanResponse::BlocksByRange(vec![block(64), block(65)]);
+
+        let encoded = LeanCodec::encode_response(&response).unwrap();
+        let decoded = LeanCodec::default()
+            .decode_response(BLOCKS_BY_RANGE_PROTOCOL_V1, &encoded)
+            .unwrap();
+        assert_eq!(decoded, response);
+    }
+
+    #[test]
+    fn blocks_by_range_request_is_two_uint64_fields() {
+        let request = BlocksByRangeRequest {
+            start_slot: Slot(64),
+            count: 32,
+        };
+
+        let expected = [64u64.to_le_bytes(), 32u64.to_le_bytes()].concat();
+        assert_eq!(request.to_ssz().unwrap(), expected);
+    }
+
+    #[test]
+    fn blocks_by_range_request_rejects_oversized_count() {
+        let request = LeanRequest::BlocksByRange(BlocksByRangeRequest {
+            start_slot: Slot(0),
+            count: MAX_REQUEST_BLOCKS as u64 + 1,
+        });
+
+        let encoded = LeanCodec::encode_request(&request).unwrap();
+        assert!(LeanCodec::decode_request(BLOCKS_BY_RANGE_PROTOCOL_V1, &encoded).is_err());
+    }
+
+    #[test]
+    fn status_response_is_one_chunk() {
+        let response = LeanResponse::Status(Status::default());
+
+        let encoded = LeanCodec::encode_response(&response).unwrap();
+        assert_eq!(encoded[0], RESPONSE_CODE_SUCCESS);
+
+        let decoded = LeanCodec::default()
+            .decode_response(STATUS_PROTOCOL_V1, &encoded)
+            .unwrap();
+        assert_eq!(decoded, response);
+
+        let twice = [encoded.clone(), encoded].concat();
+        assert!(
+            LeanCodec::default()
+                .decode_response(STATUS_PROTOCOL_V1, &twice)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn blocks_by_root_response_rejects_error_code() {
+        let mut encoded =
+            LeanCodec::encode_response(&LeanResponse::BlocksByRoot(vec![block(1)])).unwrap();
+        encoded[0] = 1;
+
+        assert!(
+            LeanCodec::default()
+                .decode_response(BLOCKS_BY_ROOT_PROTOCOL_V1, &encoded)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn aggregated_blocks_decode_with_matching_codec() {
+        let response = LeanResponse::BlocksByRange(vec![aggregated_block(1), aggregated_block(2)]);
+        let encoded = LeanCodec::encode_response(&response).unwrap();
+
+        let decoded = LeanCodec::new(BlockVersion::V1)
+            .decode_response(BLOCKS_BY_RANGE_PROTOCOL_V1, &encoded)
+            .unwrap();
+        assert_eq!(decoded, response);
+    }
 }
@@ -0,0 +1,573 @@
+//! Range sync: catch up with peers that are ahead by downloading blocks in batches.
+//!
+//! The gap between our head and the best head advertised in peer Status messages
+//! is split into batches of `BATCH_SIZE` slots. Batches are downloaded in parallel
+//! from different peers with BlocksByRange and handed to the chain strictly in
+//! slot order. A failed or invalid download is retried with another peer, and
+//! peers that keep failing a batch are no longer synced from.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use containers::{Bytes32, Slot, VersionedSignedBlock};
+use libp2p_identity::PeerId;
+use tracing::{debug, info, warn};
+
+use crate::req_resp::{BlocksByRangeRequest, MAX_REQUEST_BLOCKS};
+
+/// Slots covered by one BlocksByRange request.
+pub const BATCH_SIZE: u64 = 64;
+
+/// Batches being downloaded or waiting for import at the same time.
+pub const MAX_PENDING_BATCHES: usize = 8;
+
+/// Download attempts per batch before the peers that failed it are dropped and
+/// sync restarts from that batch.
+pub const MAX_BATCH_ATTEMPTS: u8 = 5;
+
+/// Polls without head progress after every batch was handed to the chain before
+/// the remaining range is downloaded again.
+const STALLED_POLLS: u32 = 10;
+
+const _: () = assert!(BATCH_SIZE as usize <= MAX_REQUEST_BLOCKS);
+
+/// Batches are identified by their first slot.
+pub type BatchId = u64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BatchState {
+    AwaitingDownload,
+    Downloading(PeerId),
+    AwaitingImport {
+        peer: PeerId,
+        blocks: Vec<VersionedSignedBlock>,
+    },
+}
+
+#[derive(Debug)]
+struct Batch {
+    count: u64,
+    state: BatchState,
+    attempts: u8,
+    failed_peers: HashSet<PeerId>,
+}
+
+impl Batch {
+    fn new(count: u64) -> Self {
+        Self {
+            count,
+            state: BatchState::AwaitingDownload,
+            attempts: 0,
+            failed_peers: HashSet::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Send `request` to `peer` and report the outcome for `batch` with
+    /// `on_batch_downloaded` or `on_batch_failed`.
+    Request {
+        peer: PeerId,
+        batch: BatchId,
+        request: BlocksByRangeRequest,
+    },
+    /// Pass the blocks to the chain, in order.
+    Import(Vec<VersionedSignedBlock>),
+}
+
+#[derive(Debug, Default)]
+pub struct SyncManager {
+    /// Head slot advertised by each usable peer.
+    peers: HashMap<PeerId, Slot>,
+    batches: BTreeMap<BatchId, Batch>,
+    /// First slot not covered by a batch yet.
+    next_start: u64,
+    /// End of the last batch handed to the chain and the root its blocks end at,
+    /// which the first block of the next batch must link to.
+    imported_tip: Option<(u64, Bytes32)>,
+    last_local_head: Slot,
+    stalled_polls: u32,
+}
+
+impl SyncManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_peer(&mut self, peer: PeerId, head_slot: Slot) {
+        self.peers.insert(peer, head_slot);
+    }
+
+    /// Forget `peer` and reschedule its downloads on other peers.
+    pub fn remove_peer(&mut self, peer: &PeerId) {
+        if self.peers.remove(peer).is_none() {
+            return;
+        }
+
+        for batch in self.batches.values_mut() {
+            if batch.state == BatchState::Downloading(*peer) {
+                batch.state = BatchState::AwaitingDownload;
+            }
+        }
+    }
+
+    /// Highest head slot advertised by any peer.
+    pub fn target_slot(&self) -> Option<Slot> {
+        self.peers.values().max().copied()
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        !self.batches.is_empty()
+    }
+
+    pub fn on_batch_downloaded(
+        &mut self,
+        batch_id: BatchId,
+        peer: PeerId,
+        blocks: Vec<VersionedSignedBlock>,
+    ) {
+        // The link to the previous batch is checked here if that batch was already
+        // handed to the chain, otherwise when this one is
+        let parent_root = self.parent_root(batch_id);
+
+        let Some(batch) = self.batches.get_mut(&batch_id) else {
+            return;
+        };
+
+        if batch.state != BatchState::Downloading(peer) {
+            debug!(batch = batch_id, peer = %peer, "Ignoring stale batch response");
+            return;
+        }
+
+        if let Err(reason) = validate_batch(batch_id, batch.count, &blocks, parent_root) {
+            warn!(batch = batch_id, peer = %peer, reason, "Invalid batch response");
+            self.on_batch_failed(batch_id, peer);
+            return;
+        }
+
+        debug!(batch = batch_id, peer = %peer, blocks = blocks.len(), "Batch downloaded");
+        batch.state = BatchState::AwaitingImport { peer, blocks };
+    }
+
+    pub fn on_batch_failed(&mut self, batch_id: BatchId, peer: PeerId) {
+        if self
+            .batches
+            .get(&batch_id)
+            .is_some_and(|batch| batch.state == BatchState::Downloading(peer))
+        {
+            self.fail_batch(batch_id, peer);
+        }
+    }
+
+    fn fail_batch(&mut self, batch_id: BatchId, peer: PeerId) {
+        let Some(batch) = self.batches.get_mut(&batch_id) else {
+            return;
+        };
+
+        batch.attempts += 1;
+        batch.failed_peers.insert(peer);
+        batch.state = BatchState::AwaitingDownload;
+
+        if batch.attempts >= MAX_BATCH_ATTEMPTS {
+            // Recreating the batch alone would retry the same range forever
+            warn!(
+                batch = batch_id,
+                attempts = batch.attempts,
+                peers = batch.failed_peers.len(),
+                "Batch failed too often, no longer syncing from the peers that failed it"
+            );
+            let failed_peers = std::mem::take(&mut batch.failed_peers);
+            self.batches.retain(|start, _| *start < batch_id);
+            self.next_start = batch_id;
+            for peer in &failed_peers {
+                self.remove_peer(peer);
+            }
+        }
+    }
+
+    /// Root the first block of `batch_id` must have as parent, if already known.
+    fn parent_root(&self, batch_id: BatchId) -> Option<Bytes32> {
+        self.imported_tip
+            .filter(|(end, _)| *end == batch_id)
+            .map(|(_, root)| root)
+    }
+
+    /// Advance the state machine and return the work it needs done.
+    pub fn poll(&mut self, local_head: Slot) -> Vec<SyncAction> {
+        let mut actions = Vec::new();
+
+        // Hand over downloaded batches in slot order, stopping at the first gap
+        while let Some((&start, batch)) = self.batches.first_key_value() {
+            let BatchState::AwaitingImport { peer, blocks } = &batch.state else {
+                break;
+            };
+
+            let parent_root = self.parent_root(start);
+            if let Err(reason) = validate_batch(start, batch.count, blocks, parent_root) {
+                warn!(batch = start, peer = %peer, reason, "Invalid batch response");
+                let peer = *peer;
+                self.fail_batch(start, peer);
+                break;
+            }
+
+            let (start, batch) = self.batches.pop_first().expect("checked above");
+            let BatchState::AwaitingImport { blocks, .. } = batch.state else {
+                unreachable!("checked above");
+            };
+            let end = start + batch.count;
+            match blocks.last() {
+                Some(last) => {
+                    self.imported_tip = Some((end, last.block_root()));
+                    actions.push(SyncAction::Import(blocks));
+                }
+                None => self.imported_tip = parent_root.map(|root| (end, root)),
+            }
+        }
+
+        let Some(target) = self.target_slot() else {
+            return actions;
+        };
+
+        self.track_progress(local_head, target);
+        self.next_start = self.next_start.max(local_head.0 + 1);
+
+        while self.batches.len() < MAX_PENDING_BATCHES && self.next_start <= target.0 {
+            let count = BATCH_SIZE.min(target.0 - self.next_start + 1);
+            if self.batches.is_empty() {
+                info!(
+                    from = self.next_start,
+                    target = target.0,
+                    "Range sync started"
+                );
+            }
+            self.batches.insert(self.next_start, Batch::new(count));
+            self.next_start += count;
+        }
+
+        self.assign_downloads(&mut actions);
+        actions
+    }
+
+    /// Download the remaining range again if the head stops moving after all
+    /// batches were imported, e.g. because the chain rejected some blocks.
+    fn track_progress(&mut self, local_head: Slot, target: Slot) {
+        let waiting_for_chain =
+            self.batches.is_empty() && self.next_start > target.0 && local_head < target;
+
+        if local_head != self.last_local_head || !waiting_for_chain {
+            self.last_local_head = local_head;
+            self.stalled_polls = 0;
+            return;
+        }
+
+        self.stalled_polls += 1;
+        if self.stalled_polls >= STALLED_POLLS {
+            info!(
+                head = local_head.0,
+                target = target.0,
+                "Range sync stalled, retrying"
+            );
+            self.next_start = local_head.0 + 1;
+            // The chain did not take the blocks this tip came from
+            self.imported_tip = None;
+            self.stalled_polls = 0;
+        }
+    }
+
+    fn assign_downloads(&mut self, actions: &mut Vec<SyncAction>) {
+        let mut active: HashMap<PeerId, usize> = HashMap::new();
+        for batch in self.batches.values() {
+            if let BatchState::Downloading(peer) = batch.state {
+                *active.entry(peer).or_default() += 1;
+            }
+        }
+
+        for (&start, batch) in self.batches.iter_mut() {
+            if batch.state != BatchState::AwaitingDownload {
+                continue;
+            }
+
+            let candidates = self
+                .peers
+                .iter()
+                .filter(|(_, head)| head.0 >= start)
+                .map(|(peer, _)| *peer)
+                .collect::<Vec<_>>();
+
+            // Rotate away from peers that failed this batch while others remain
+            let fresh = candidates
+                .iter()
+                .filter(|peer| !batch.failed_peers.contains(*peer))
+                .copied()
+                .collect::<Vec<_>>();
+            let pool = if fresh.is_empty() { candidates } else { fresh };
+
+            let Some(peer) = pool
+                .into_iter()
+                .min_by_key(|peer| active.get(peer).copied().unwrap_or_default())
+            else {
+                continue;
+            };
+
+            *active.entry(peer).or_default() += 1;
+            batch.state = BatchState::Downloading(peer);
+            actions.push(SyncAction::Request {
+                peer,
+                batch: start,
+                request: BlocksByRangeRequest {
+                    start_slot: Slot(start),
+                    count: batch.count,
+                },
+            });
+        }
+    }
+}
+
+/// Blocks must lie in the requested range, be ordered by slot and form a chain,
+/// starting at `parent_root` if the previous batch's last root is known.
+fn validate_batch(
+    start: u64,
+    count: u64,
+    blocks: &[VersionedSignedBlock],
+    parent_root: Option<Bytes32>,
+) -> Result<(), &'static str> {
+    if blocks.len() as u64 > count {
+        return Err("more blocks than requested");
+    }
+
+    if let (Some(first), Some(parent_root)) = (blocks.first(), parent_root)
+        && first.parent_root() != parent_root
+    {
+        return Err("first block does not link to the previous batch");
+    }
+
+    let end = start + count;
+    if blocks.iter().any(|block| {
+        let slot = block.slot().0;
+        slot < start || slot >= end
+    }) {
+        return Err("block outside of requested range");
+    }
+
+    for pair in blocks.windows(2) {
+        let (parent, child) = (&pair[0], &pair[1]);
+
+        if child.slot() <= parent.slot() {
+            return Err("blocks not ordered by slot");
+        }
+
+        if child.parent_root() != parent.block_root() {
+            return Err("blocks do not form a chain");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use containers::{
+        Bytes32, SignedBlockWithAttestation, ValidatorIndex,
+        block::{Block, BlockWithAttestation},
+        ssz::SszHash,
+    };
+
+    use super::*;
+
+    fn chain(start: u64, end: u64) -> Vec<VersionedSignedBlock> {
+        let mut parent_root = Bytes32::default();
+        (start..end)
+            .map(|slot| {
+                let block = Block {
+                    slot: Slot(slot),
+                    proposer_index: ValidatorIndex(0),
+                    parent_root,
+                    ..Block::default()
+                };
+                parent_root = Bytes32(block.hash_tree_root());
+
+                SignedBlockWithAttestation {
+                    message: BlockWithAttestation {
+                        block,
+                        proposer_attestation: Default::default(),
+                    },
+                    signature: Default::default(),
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    fn requests(actions: &[SyncAction]) -> Vec<(PeerId, BatchId, BlocksByRangeRequest)> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                SyncAction::Request {
+                    peer,
+                    batch,
+                    request,
+                } => Some((*peer, *batch, *request)),
+                SyncAction::Import(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn idle_without_peers_ahead() {
+        let mut sync = SyncManager::new();
+        sync.add_peer(PeerId::random(), Slot(10));
+
+        assert!(sync.poll(Slot(10)).is_empty());
+        assert!(!sync.is_syncing());
+    }
+
+    #[test]
+    fn splits_range_into_batches_across_peers() {
+        let mut sync = SyncManager::new();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        sync.add_peer(a, Slot(200));
+        sync.add_peer(b, Slot(200));
+
+        let requests = requests(&sync.poll(Slot(0)));
+
+        let ranges = requests
+            .iter()
+            .map(|(_, _, request)| (request.start_slot.0, request.count))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![(1, 64), (65, 64), (129, 64), (193, 8)]);
+
+        let from_a = requests.iter().filter(|(peer, ..)| *peer == a).count();
+        assert_eq!(from_a, 2);
+    }
+
+    #[test]
+    fn imports_batches_in_order() {
+        let mut sync = SyncManager::new();
+        let peer = PeerId::random();
+        sync.add_peer(peer, Slot(100));
+
+        let requests = requests(&sync.poll(Slot(0)));
+        let blocks = chain(1, 101);
+
+        // Second batch arrives first and has to wait for the first one
+        sync.on_batch_downloaded(65, peer, blocks[64..].to_vec());
+        assert!(sync.poll(Slot(0)).is_empty());
+
+        sync.on_batch_downloaded(1, peer, blocks[..64].to_vec());
+        let actions = sync.poll(Slot(0));
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            actions,
+            vec![
+                SyncAction::Import(blocks[..64].to_vec()),
+                SyncAction::Import(blocks[64..].to_vec()),
+            ]
+        );
+        assert!(!sync.is_syncing());
+    }
+
+    #[test]
+    fn failed_batch_is_retried_with_another_peer() {
+        let mut sync = SyncManager::new();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        sync.add_peer(a, Slot(10));
+        sync.add_peer(b, Slot(10));
+
+        let (first_peer, batch, _) = requests(&sync.poll(Slot(0)))[0];
+        sync.on_batch_failed(batch, first_peer);
+
+        let (retry_peer, retry_batch, _) = requests(&sync.poll(Slot(0)))[0];
+        assert_eq!(retry_batch, batch);
+        assert_ne!(retry_peer, first_peer);
+    }
+
+    #[test]
+    fn invalid_response_fails_batch() {
+        let mut sync = SyncManager::new();
+        let peer = PeerId::random();
+        sync.add_peer(peer, Slot(10));
+        requests(&sync.poll(Slot(0)));
+
+        // Blocks outside the requested range
+        sync.on_batch_downloaded(1, peer, chain(20, 22));
+
+        let retried = requests(&sync.poll(Slot(0)));
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].1, 1);
+    }
+
+    #[test]
+    fn removed_peer_downloads_are_rescheduled() {
+        let mut sync = SyncManager::new();
+        let (a, b) = (PeerId::random(), PeerId::random());
+        sync.add_peer(a, Slot(10));
+        requests(&sync.poll(Slot(0)));
+
+        sync.add_peer(b, Slot(10));
+        sync.remove_peer(&a);
+
+        let retried = requests(&sync.poll(Slot(0)));
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].0, b);
+    }
+
+    #[test]
+    fn peers_failing_a_batch_too_often_are_dropped() {
+        let mut sync = SyncManager::new();
+        let peer = PeerId::random();
+        sync.add_peer(peer, Slot(10));
+
+        for _ in 0..MAX_BATCH_ATTEMPTS {
+            let (requested_peer, batch, _) = requests(&sync.poll(Slot(0)))[0];
+            assert_eq!(requested_peer, peer);
+            sync.on_batch_failed(batch, peer);
+        }
+
+        assert!(sync.poll(Slot(0)).is_empty());
+        assert!(!sync.is_syncing());
+        assert_eq!(sync.target_slot(), None);
+
+        // A peer that announces itself again is synced from with fresh attempts
+        sync.add_peer(peer, Slot(10));
+        assert_eq!(requests(&sync.poll(Slot(0))).len(), 1);
+    }
+
+    #[test]
+    fn batch_not_linking_to_previous_batch_is_retried() {
+        let mut sync = SyncManager::new();
+        let peer = PeerId::random();
+        sync.add_peer(peer, Slot(100));
+        requests(&sync.poll(Slot(0)));
+
+        let blocks = chain(1, 101);
+        // A valid chain on its own, but not a continuation of the first batch
+        let forked = chain(65, 101);
+
+        // Checked on hand-over when it arrives before the previous batch...
+        sync.on_batch_downloaded(65, peer, forked.clone());
+        sync.on_batch_downloaded(1, peer, blocks[..64].to_vec());
+        let actions = sync.poll(Slot(0));
+        assert_eq!(actions[0], SyncAction::Import(blocks[..64].to_vec()));
+        assert_eq!(
+            requests(&actions)
+                .iter()
+                .map(|(_, batch, _)| *batch)
+                .collect::<Vec<_>>(),
+            vec![65]
+        );
+
+        // ...and on download after it
+        sync.on_batch_downloaded(65, peer, forked);
+        let retried = requests(&sync.poll(Slot(0)));
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].1, 65);
+
+        sync.on_batch_downloaded(65, peer, blocks[64..].to_vec());
+        assert_eq!(
+            sync.poll(Slot(64)),
+            vec![SyncAction::Import(blocks[64..].to_vec())]
+        );
+        assert!(!sync.is_syncing());
+    }
+}
@@ -1,10 +1,12 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{fmt::Display, time::Instant};
 
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
-use containers::{Bytes32, SignedAttestation, SignedBlockWithAttestation};
+use containers::{
+    Bytes32, SignedAggregatedAttestations, SignedAttestation, Slot, Status, VersionedSignedBlock,
+};
 use serde::Serialize;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
 use crate::serde_utils::quoted_u64;
 
@@ -28,6 +30,38 @@ pub enum Direction {
     Unknown,
 }
 
+/// Everything the peer manager knows about a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub state: ConnectionState,
+    /// Who opened the connection. `Unknown` until the first one is established.
+    pub direction: Direction,
+    /// Latest Status received from the peer, `None` until the handshake completes.
+    pub status: Option<Status>,
+    /// Zero for a well-behaved peer, negative after faults. See `PeerAction`.
+    pub reputation: i32,
+    pub last_seen: Instant,
+    pub banned_until: Option<Instant>,
+}
+
+impl PeerInfo {
+    pub fn new(state: ConnectionState) -> Self {
+        Self {
+            state,
+            direction: Direction::Unknown,
+            status: None,
+            reputation: 0,
+            last_seen: Instant::now(),
+            banned_until: None,
+        }
+    }
+
+    pub fn is_banned(&self) -> bool {
+        self.banned_until
+            .is_some_and(|until| until > Instant::now())
+    }
+}
+
 #[derive(Default, Debug, Clone, Serialize)]
 pub struct PeerCount {
     #[serde(with = "quoted_u64")]
@@ -41,10 +75,10 @@ pub struct PeerCount {
 }
 
 impl PeerCount {
-    pub fn new(states: &HashMap<libp2p_identity::PeerId, ConnectionState>) -> Self {
+    pub fn new<'a>(peers: impl IntoIterator<Item = &'a PeerInfo>) -> Self {
         let mut count = PeerCount::default();
-        for state in states.values() {
-            match state {
+        for peer in peers {
+            match peer.state {
                 ConnectionState::Connected => count.connected += 1,
                 ConnectionState::Connecting => count.connecting += 1,
                 ConnectionState::Disconnected => count.disconnected += 1,
@@ -58,25 +92,35 @@ impl PeerCount {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ChainMessage {
     ProcessBlock {
-        signed_block_with_attestation: SignedBlockWithAttestation,
+        signed_block_with_attestation: VersionedSignedBlock,
         is_trusted: bool,
         should_gossip: bool,
+        /// Gossip validation already checked the signatures
+        signatures_verified: bool,
     },
     ProcessAttestation {
         signed_attestation: SignedAttestation,
         is_trusted: bool,
         should_gossip: bool,
+        /// Gossip validation already checked the signatures
+        signatures_verified: bool,
+    },
+    ProcessAggregate {
+        signed_aggregate: SignedAggregatedAttestations,
+        is_trusted: bool,
+        should_gossip: bool,
+        /// Gossip validation already checked the signatures
+        signatures_verified: bool,
     },
 }
 
 impl ChainMessage {
-    pub fn block_with_attestation(
-        signed_block_with_attestation: SignedBlockWithAttestation,
-    ) -> Self {
+    pub fn block_with_attestation(signed_block_with_attestation: VersionedSignedBlock) -> Self {
         ChainMessage::ProcessBlock {
             signed_block_with_attestation,
             is_trusted: false,
             should_gossip: true,
+            signatures_verified: false,
         }
     }
 
@@ -85,6 +129,16 @@ impl ChainMessage {
             signed_attestation,
             is_trusted: false,
             should_gossip: true,
+            signatures_verified: false,
+        }
+    }
+
+    pub fn aggregate(signed_aggregate: SignedAggregatedAttestations) -> Self {
+        ChainMessage::ProcessAggregate {
+            signed_aggregate,
+            is_trusted: false,
+            should_gossip: true,
+            signatures_verified: false,
         }
     }
 }
@@ -99,7 +153,7 @@ impl Display for ChainMessage {
                 write!(
                     f,
                     "ProcessBlockWithAttestation(slot={})",
-                    signed_block_with_attestation.message.block.slot.0
+                    signed_block_with_attestation.slot().0
                 )
             }
             ChainMessage::ProcessAttestation {
@@ -111,14 +165,79 @@ impl Display for ChainMessage {
                     signed_attestation.message.data.slot.0
                 )
             }
+            ChainMessage::ProcessAggregate {
+                signed_aggregate, ..
+            } => {
+                write!(
+                    f,
+                    "ProcessAggregate(slot={})",
+                    signed_aggregate.message.data.slot.0
+                )
+            }
         }
     }
 }
 
+/// Read-only queries from the network service to the chain task that owns the store.
+///
+/// Answers are sent back on `respond_to`. A dropped sender means the chain could
+/// not answer and the query should be treated as having no result.
+#[derive(Debug)]
+pub enum ChainQuery {
+    /// Look up blocks by root. Unknown roots are skipped.
+    BlocksByRoot {
+        roots: Vec<Bytes32>,
+        respond_to: oneshot::Sender<Vec<VersionedSignedBlock>>,
+    },
+    /// Canonical blocks in `start_slot..start_slot + count`, oldest first.
+    BlocksByRange {
+        start_slot: Slot,
+        count: u64,
+        respond_to: oneshot::Sender<Vec<VersionedSignedBlock>>,
+    },
+    /// Root of the canonical block at `slot`, or of the closest earlier block if the
+    /// slot is empty. `None` if our history does not reach back that far.
+    BlockRootAtSlot {
+        slot: Slot,
+        respond_to: oneshot::Sender<Option<Bytes32>>,
+    },
+    /// Validate a gossip block before it is imported and forwarded.
+    ValidateBlock {
+        block: VersionedSignedBlock,
+        respond_to: oneshot::Sender<GossipValidation>,
+    },
+    /// Validate a gossip attestation before it is imported and forwarded.
+    ValidateAttestation {
+        attestation: SignedAttestation,
+        respond_to: oneshot::Sender<GossipValidation>,
+    },
+    /// Validate a gossip aggregate before it is imported and forwarded.
+    ValidateAggregate {
+        aggregate: SignedAggregatedAttestations,
+        respond_to: oneshot::Sender<GossipValidation>,
+    },
+}
+
+/// The chain's verdict on a gossip message, reported back to gossipsub.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipValidation {
+    /// Valid: imported and forwarded to our mesh peers.
+    Accept,
+    /// Block whose parent we do not have yet: not forwarded, but still handed to
+    /// the chain so it is queued and the parent fetched.
+    UnknownParent,
+    /// Not provably invalid: dropped without penalising the sender.
+    Ignore(String),
+    /// Invalid: dropped and the sender is penalised.
+    Reject(String),
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OutboundP2pRequest {
-    GossipBlockWithAttestation(SignedBlockWithAttestation),
+    GossipBlockWithAttestation(VersionedSignedBlock),
+    /// Published on the attesting validator's subnet.
     GossipAttestation(SignedAttestation),
+    GossipAggregate(SignedAggregatedAttestations),
     RequestBlocksByRoot(Vec<Bytes32>),
 }
 
@@ -0,0 +1,111 @@
+//! `db` subcommand, for looking into a node's `--data-dir` while it is stopped.
+
+use std::path::{Path, PathBuf};
+
+use clap::Subcommand;
+use storage::{FileStorage, Storage};
+use validator::slashing_protection::Interchange;
+
+use crate::SLASHING_PROTECTION_FILE;
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Print the stored fork choice checkpoints and the number of blocks, states and
+    /// slashing protection records
+    Info {
+        /// Path: the node's --data-dir
+        #[arg(long)]
+        data_dir: PathBuf,
+    },
+    /// Write the slashing protection database as interchange JSON, for
+    /// `node --slashing-protection-import` on another client
+    ExportSlashingProtection {
+        /// Path: the node's --data-dir
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Path: interchange JSON to write
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+pub fn run(command: &DbCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        DbCommand::Info { data_dir } => run_info(data_dir),
+        DbCommand::ExportSlashingProtection { data_dir, output } => {
+            read_slashing_protection(data_dir)?.write_json_file(output)?;
+            println!("Wrote {}", output.display());
+            Ok(())
+        }
+    }
+}
+
+fn run_info(data_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    require_data_dir(data_dir)?;
+    let storage = FileStorage::open(data_dir)?;
+
+    match storage.get_checkpoints()? {
+        Some(checkpoints) => {
+            for (name, checkpoint) in [
+                ("Head", checkpoints.head),
+                ("Latest justified", checkpoints.latest_justified),
+                ("Latest finalized", checkpoints.latest_finalized),
+            ] {
+                println!(
+                    "{:<20}Slot {} | Root: 0x{:x}",
+                    format!("{name}:"),
+                    checkpoint.slot.0,
+                    checkpoint.root.0
+                );
+            }
+        }
+        None => println!("No fork choice checkpoints stored yet"),
+    }
+    println!("{:<20}{}", "Blocks:", storage.block_roots()?.len());
+    println!("{:<20}{}", "States:", storage.state_roots()?.len());
+
+    if data_dir.join(SLASHING_PROTECTION_FILE).exists() {
+        let interchange = read_slashing_protection(data_dir)?;
+        println!(
+            "{:<20}{} validators, genesis time {}",
+            "Slashing protection:",
+            interchange.data.len(),
+            interchange.metadata.genesis_time
+        );
+        for data in &interchange.data {
+            println!(
+                "  Validator {}: {} blocks, {} attestations",
+                data.validator_index,
+                data.signed_blocks.len(),
+                data.signed_attestations.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The slashing protection database in `data_dir`, read without writing to it:
+/// `SlashingProtection::open` rewrites the file, which could drop records a
+/// running node just added.
+fn read_slashing_protection(data_dir: &Path) -> Result<Interchange, Box<dyn std::error::Error>> {
+    require_data_dir(data_dir)?;
+    let path = data_dir.join(SLASHING_PROTECTION_FILE);
+    if !path.is_file() {
+        return Err(format!("No slashing protection database at {}", path.display()).into());
+    }
+    Ok(Interchange::from_json_file(&path)?)
+}
+
+/// `FileStorage::open` creates missing directories, which would hide a mistyped path.
+fn require_data_dir(data_dir: &Path) -> Result<(), String> {
+    if data_dir.is_dir() {
+        Ok(())
+    } else {
+        Err(format!(
+            "--data-dir {}: directory not found",
+            data_dir.display()
+        ))
+    }
+}
@@ -0,0 +1,115 @@
+//! `inspect` subcommand, which prints what is in the files a node is run with.
+
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Subcommand;
+use containers::block::{BlockVersion, VersionedSignedBlock};
+use containers::checkpoint::Checkpoint;
+use containers::ssz::{SszHash, SszReadDefault};
+use containers::state::State;
+use validator::keystore::{KdfParams, Keystore};
+
+use crate::node_config::{load_genesis, load_node_key};
+
+#[derive(Subcommand, Debug)]
+pub enum InspectCommand {
+    /// Genesis config.yaml, with the root of the genesis state it produces
+    Genesis { path: PathBuf },
+    /// SSZ-encoded State, as taken by --checkpoint-state
+    State { path: PathBuf },
+    /// SSZ-encoded signed block, as taken by --checkpoint-block
+    Block {
+        path: PathBuf,
+
+        /// Fork whose block format the file is in
+        #[arg(long, default_value = "devnet0")]
+        fork: String,
+    },
+    /// Validator keystore. It is not decrypted
+    Keystore { path: PathBuf },
+    /// p2p private key, as taken by --node-key
+    NodeKey { path: PathBuf },
+}
+
+pub fn run(command: &InspectCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        InspectCommand::Genesis { path } => {
+            let genesis = load_genesis(path)?;
+            let state = crate::genesis_state(&genesis);
+
+            println!("Genesis time:       {}", genesis.genesis_time);
+            println!("Active epoch:       {}", genesis.active_epoch);
+            println!("Validators:         {}", genesis.validator_count);
+            println!("Genesis state root: 0x{:x}", state.hash_tree_root());
+        }
+        InspectCommand::State { path } => {
+            let state = State::from_ssz_default(&fs::read(path)?)
+                .map_err(|e| format!("Invalid state {}: {e:?}", path.display()))?;
+
+            println!("Slot:               {}", state.slot.0);
+            println!("Genesis time:       {}", state.config.genesis_time);
+            println!("Validators:         {}", state.validators.len_u64());
+            println!("State root:         0x{:x}", state.hash_tree_root());
+            println!(
+                "Latest block:       Slot {} | Parent root: 0x{:x}",
+                state.latest_block_header.slot.0, state.latest_block_header.parent_root.0
+            );
+            println!(
+                "Latest justified:   {}",
+                format_checkpoint(&state.latest_justified)
+            );
+            println!(
+                "Latest finalized:   {}",
+                format_checkpoint(&state.latest_finalized)
+            );
+        }
+        InspectCommand::Block { path, fork } => {
+            let version = BlockVersion::from_fork(fork)
+                .ok_or_else(|| format!("Unknown fork {fork}, expected devnet0 or devnet1"))?;
+            let block = VersionedSignedBlock::from_ssz(version, &fs::read(path)?)
+                .map_err(|e| format!("Invalid {fork} block {}: {e}", path.display()))?;
+
+            println!("Slot:               {}", block.slot().0);
+            println!("Proposer:           {}", block.proposer_index().0);
+            println!("Block root:         0x{:x}", block.block_root().0);
+            println!("Parent root:        0x{:x}", block.parent_root().0);
+            println!("State root:         0x{:x}", block.state_root().0);
+            println!("Attestations:       {}", block.signed_attestations().len());
+        }
+        InspectCommand::Keystore { path } => {
+            let keystore = Keystore::from_json_file(path)?;
+            let kdf = match &keystore.crypto.kdf.params {
+                KdfParams::Scrypt { n, r, p, .. } => format!("scrypt (n={n}, r={r}, p={p})"),
+                KdfParams::Pbkdf2 { c, prf, .. } => format!("pbkdf2 ({prf}, c={c})"),
+            };
+
+            println!("UUID:               {}", keystore.uuid);
+            println!("Version:            {}", keystore.version);
+            match keystore.validator_index {
+                Some(index) => println!("Validator index:    {index}"),
+                None => println!("Validator index:    unknown"),
+            }
+            if !keystore.pubkey.is_empty() {
+                println!("Public key:         0x{}", keystore.pubkey);
+            }
+            println!("KDF:                {kdf}");
+            println!("Cipher:             {}", keystore.crypto.cipher.function);
+        }
+        InspectCommand::NodeKey { path } => {
+            let keypair = load_node_key(path)
+                .map_err(|e| format!("Invalid node key {}: {e}", path.display()))?;
+
+            println!("Peer ID:            {}", keypair.public().to_peer_id());
+        }
+    }
+
+    Ok(())
+}
+
+fn format_checkpoint(checkpoint: &Checkpoint) -> String {
+    format!(
+        "Slot {} | Root: 0x{:x}",
+        checkpoint.slot.0, checkpoint.root.0
+    )
+}
@@ -0,0 +1,395 @@
+//! `keygen` and `genesis` subcommands, which produce everything a devnet needs:
+//! XMSS validator keys, node keys, `validators.yaml` and the genesis `config.yaml`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Args;
+use containers::{validator::BlsPublicKey, GenesisConfig};
+use libp2p_identity::{secp256k1, Keypair, PeerId};
+use validator::keys::{write_secret_file, KeyManager, KeyMetadata};
+use validator::keystore::read_password_file;
+#[cfg(feature = "xmss-signing")]
+use validator::keystore::{Kdf, Keystore};
+
+/// Directory under `--output-dir` that `--hash-sig-key-dir` should point to.
+const KEYS_DIR: &str = "hash-sig-keys";
+const VALIDATORS_FILE: &str = "validators.yaml";
+/// Default time between running `genesis` and the chain starting.
+const GENESIS_DELAY_SECONDS: u64 = 30;
+
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    /// Number of validators to generate XMSS keys for
+    #[arg(long)]
+    num_validators: u64,
+
+    /// Number of nodes to generate node keys for. Validators are assigned to them round-robin
+    #[arg(long, default_value_t = 1)]
+    num_nodes: u64,
+
+    /// Node IDs in validators.yaml are <prefix>_0, <prefix>_1, ...
+    #[arg(long, default_value = "lean_client")]
+    node_prefix: String,
+
+    /// First epoch (slot) the XMSS keys can sign at
+    #[arg(long, default_value_t = 0)]
+    activation_epoch: u64,
+
+    /// The XMSS keys can sign at 2^ACTIVE_EPOCH epochs. Pass the same value to `genesis`
+    #[arg(long, default_value_t = 18)]
+    active_epoch: u32,
+
+    /// Path: directory to write hash-sig-keys/, the node keys and validators.yaml to
+    #[arg(long, default_value = ".")]
+    output_dir: String,
+
+    /// Path: file with a password to encrypt the secret keys into keystores with.
+    /// Plaintext secret keys are written when unset
+    #[arg(long)]
+    password_file: Option<String>,
+
+    /// Overwrite keys, node keys and validators.yaml already in --output-dir
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct GenesisArgs {
+    /// Path: directory holding validator_N_pk.ssz files, as written by `keygen`
+    #[arg(long)]
+    keys_dir: String,
+
+    /// Number of genesis validators, read as validator_0_pk.ssz and up
+    #[arg(long)]
+    num_validators: u64,
+
+    /// Unix time of genesis. Defaults to 30 seconds from now
+    #[arg(long)]
+    genesis_time: Option<u64>,
+
+    /// Written as ACTIVE_EPOCH, the log2 of the key lifetime used by `keygen`
+    #[arg(long, default_value_t = 18)]
+    active_epoch: u32,
+
+    /// Path: genesis config to write
+    #[arg(long, default_value = "config.yaml")]
+    output: String,
+}
+
+pub fn run_keygen(args: &KeygenArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.num_nodes == 0 {
+        return Err("--num-nodes must be at least 1".into());
+    }
+    let num_active_epochs = 1u64
+        .checked_shl(args.active_epoch)
+        .ok_or("--active-epoch is too large")?;
+
+    let output_dir = PathBuf::from(&args.output_dir);
+    let keys_dir = output_dir.join(KEYS_DIR);
+    fs::create_dir_all(&keys_dir)?;
+
+    let password = args
+        .password_file
+        .as_deref()
+        .map(read_password_file)
+        .transpose()?;
+
+    let key_manager = KeyManager::new(&keys_dir)?;
+    if !args.force {
+        // Checked up front so a refused run leaves no mix of old and new keys behind
+        let secret_key_path = |index| {
+            if password.is_some() {
+                key_manager.keystore_path(index)
+            } else {
+                key_manager.secret_key_path(index)
+            }
+        };
+        let existing = (0..args.num_validators)
+            .flat_map(|index| {
+                [
+                    public_key_path(&keys_dir, index),
+                    secret_key_path(index),
+                    key_manager.metadata_path(index),
+                ]
+            })
+            .chain((0..args.num_nodes).map(|node| node_key_path(&output_dir, args, node)))
+            .chain([output_dir.join(VALIDATORS_FILE)])
+            .find(|path| path.exists());
+        if let Some(path) = existing {
+            return Err(format!(
+                "{} already exists, pass --force to overwrite it",
+                path.display()
+            )
+            .into());
+        }
+    }
+
+    for index in 0..args.num_validators {
+        write_validator_keys(
+            &key_manager,
+            &keys_dir,
+            index,
+            args,
+            num_active_epochs,
+            password.as_deref(),
+        )?;
+        println!("Generated XMSS key for validator {index}");
+    }
+
+    for node in 0..args.num_nodes {
+        let peer_id = write_node_key(&node_key_path(&output_dir, args, node))?;
+        println!(
+            "Generated node key for {} (peer ID {peer_id})",
+            node_id(args, node)
+        );
+    }
+
+    let validators_path = output_dir.join(VALIDATORS_FILE);
+    fs::write(
+        &validators_path,
+        serde_yaml::to_string(&validator_registry(args))?,
+    )?;
+    println!("Wrote {}", validators_path.display());
+
+    Ok(())
+}
+
+pub fn run_genesis(args: &GenesisArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let keys_dir = Path::new(&args.keys_dir);
+    let genesis_validators = (0..args.num_validators)
+        .map(|index| {
+            let path = public_key_path(keys_dir, index);
+            let bytes = fs::read(&path).map_err(|e| format!("Failed to read {path:?}: {e}"))?;
+            // Check the key before writing it out
+            let hex = format!("0x{}", hex::encode(bytes));
+            BlsPublicKey::from_hex(&hex)
+                .map_err(|e| format!("Invalid public key {path:?}: {e}"))?;
+            Ok(hex)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let genesis_time = match args.genesis_time {
+        Some(genesis_time) => genesis_time,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + GENESIS_DELAY_SECONDS,
+    };
+
+    let config = GenesisConfig {
+        genesis_time,
+        active_epoch: args.active_epoch.into(),
+        validator_count: args.num_validators,
+        genesis_validators,
+    };
+    config.write_to_file(&args.output)?;
+    println!(
+        "Wrote {} with {} validators, genesis time {}",
+        args.output, args.num_validators, genesis_time
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "xmss-signing")]
+fn write_validator_keys(
+    key_manager: &KeyManager,
+    keys_dir: &Path,
+    index: u64,
+    args: &KeygenArgs,
+    num_active_epochs: u64,
+    password: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (public_key, secret_key) =
+        containers::crypto::generate_keypair(args.activation_epoch, num_active_epochs)?;
+
+    fs::write(public_key_path(keys_dir, index), public_key.0.as_bytes())?;
+    match password {
+        Some(password) => Keystore::encrypt(
+            &secret_key,
+            password,
+            Kdf::default(),
+            Some(index),
+            Some(public_key.0.as_bytes()),
+        )?
+        .write_json_file(key_manager.keystore_path(index))?,
+        None => write_secret_file(key_manager.secret_key_path(index), &secret_key)?,
+    }
+    key_manager.write_metadata(
+        index,
+        &KeyMetadata::new(args.activation_epoch, num_active_epochs),
+    )?;
+    Ok(())
+}
+
+#[cfg(not(feature = "xmss-signing"))]
+fn write_validator_keys(
+    _key_manager: &KeyManager,
+    _keys_dir: &Path,
+    _index: u64,
+    _args: &KeygenArgs,
+    _num_active_epochs: u64,
+    _password: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("XMSS key generation needs the xmss-signing feature".into())
+}
+
+/// Contents of validators.yaml: the validators dealt out to the nodes round-robin.
+fn validator_registry(args: &KeygenArgs) -> BTreeMap<String, Vec<u64>> {
+    (0..args.num_nodes)
+        .map(|node| {
+            let indices = (node..args.num_validators)
+                .step_by(args.num_nodes as usize)
+                .collect();
+            (node_id(args, node), indices)
+        })
+        .collect()
+}
+
+fn public_key_path(keys_dir: &Path, index: u64) -> PathBuf {
+    keys_dir.join(format!("validator_{index}_pk.ssz"))
+}
+
+fn node_id(args: &KeygenArgs, node: u64) -> String {
+    format!("{}_{}", args.node_prefix, node)
+}
+
+fn node_key_path(output_dir: &Path, args: &KeygenArgs, node: u64) -> PathBuf {
+    output_dir.join(format!("{}.key", node_id(args, node)))
+}
+
+/// Write a secp256k1 key in the hex format `--node-key` reads, and return its peer ID.
+fn write_node_key(path: &Path) -> Result<PeerId, Box<dyn std::error::Error>> {
+    let keypair = secp256k1::Keypair::generate();
+    write_secret_file(path, hex::encode(keypair.secret().to_bytes()).as_bytes())?;
+    Ok(PeerId::from(Keypair::from(keypair).public()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_config::load_genesis;
+    use validator::ValidatorConfig;
+
+    fn keygen_args(output_dir: &Path, force: bool) -> KeygenArgs {
+        KeygenArgs {
+            num_validators: 1,
+            num_nodes: 1,
+            node_prefix: "node".to_string(),
+            activation_epoch: 0,
+            active_epoch: 3,
+            output_dir: output_dir.to_string_lossy().into_owned(),
+            password_file: None,
+            force,
+        }
+    }
+
+    #[test]
+    fn test_keygen_refuses_to_overwrite_without_force() {
+        let dir = std::env::temp_dir().join(format!("lean-keygen-force-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(VALIDATORS_FILE), "existing").unwrap();
+
+        let err = run_keygen(&keygen_args(&dir, false)).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{err}");
+        assert_eq!(
+            fs::read_to_string(dir.join(VALIDATORS_FILE)).unwrap(),
+            "existing"
+        );
+        assert!(!dir.join("node_0.key").exists());
+        assert!(!public_key_path(&dir.join(KEYS_DIR), 0).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_validators_are_assigned_round_robin() {
+        let dir = std::env::temp_dir().join(format!("lean-keygen-registry-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let args = KeygenArgs {
+            num_validators: 5,
+            num_nodes: 2,
+            ..keygen_args(&dir, false)
+        };
+        let path = dir.join(VALIDATORS_FILE);
+        fs::write(
+            &path,
+            serde_yaml::to_string(&validator_registry(&args)).unwrap(),
+        )
+        .unwrap();
+
+        for (node_id, indices) in [("node_0", vec![0, 2, 4]), ("node_1", vec![1, 3])] {
+            let config = ValidatorConfig::load_from_file(&path, node_id).unwrap();
+            assert_eq!(config.validator_indices, indices, "{node_id}");
+        }
+        assert!(ValidatorConfig::load_from_file(&path, "node_2").is_err());
+
+        // More nodes than validators leaves the extra nodes without duties
+        let args = KeygenArgs {
+            num_validators: 1,
+            num_nodes: 3,
+            ..keygen_args(&dir, false)
+        };
+        let registry = validator_registry(&args);
+        assert_eq!(registry["node_0"], vec![0]);
+        assert!(registry["node_1"].is_empty());
+        assert!(registry["node_2"].is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "xmss-signing")]
+    #[test]
+    fn test_genesis_from_generated_keys_loads() {
+        let dir = std::env::temp_dir().join(format!("lean-keygen-genesis-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let args = KeygenArgs {
+            num_validators: 2,
+            num_nodes: 2,
+            ..keygen_args(&dir, false)
+        };
+        run_keygen(&args).unwrap();
+
+        let genesis_path = dir.join("config.yaml");
+        run_genesis(&GenesisArgs {
+            keys_dir: dir.join(KEYS_DIR).to_string_lossy().into_owned(),
+            num_validators: 2,
+            genesis_time: Some(1_000),
+            active_epoch: 3,
+            output: genesis_path.to_string_lossy().into_owned(),
+        })
+        .unwrap();
+
+        let genesis = load_genesis(&genesis_path).unwrap();
+        assert_eq!(genesis.genesis_time, 1_000);
+        assert_eq!(genesis.active_epoch, 3);
+        assert_eq!(genesis.validator_count, 2);
+        for (index, pubkey) in genesis.genesis_validators.iter().enumerate() {
+            let bytes = fs::read(public_key_path(&dir.join(KEYS_DIR), index as u64)).unwrap();
+            assert_eq!(*pubkey, format!("0x{}", hex::encode(bytes)));
+        }
+
+        let validators_path = dir.join(VALIDATORS_FILE);
+        assert_eq!(
+            ValidatorConfig::load_from_file(&validators_path, "node_1")
+                .unwrap()
+                .validator_indices,
+            vec![1]
+        );
+
+        // Keys of a second run would silently replace those in the genesis
+        assert!(run_keygen(&args).is_err());
+        run_keygen(&KeygenArgs {
+            force: true,
+            ..args
+        })
+        .unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
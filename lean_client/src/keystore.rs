@@ -0,0 +1,200 @@
+//! `keystore import` and `keystore export` subcommands, which convert between
+//! plaintext `validator_N_sk.ssz` files and encrypted `validator_N_keystore.json`
+//! files in a key directory.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use clap::{Args, Subcommand, ValueEnum};
+use validator::keys::{write_secret_file, KeyManager};
+use validator::keystore::{read_password_file, Kdf, Keystore, PBKDF2_ROUNDS};
+
+#[derive(Subcommand, Debug)]
+pub enum KeystoreCommand {
+    /// Encrypt the plaintext secret keys in a key directory into keystores
+    Import(ImportArgs),
+    /// Decrypt the keystores in a key directory back into plaintext secret keys
+    Export(ExportArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum KdfArg {
+    Scrypt,
+    Pbkdf2,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// Path: directory holding validator_N_sk.ssz files
+    #[arg(long)]
+    keys_dir: String,
+
+    /// Path: file with the keystore password. Prompted for when unset
+    #[arg(long)]
+    password_file: Option<String>,
+
+    /// Key derivation function for the new keystores
+    #[arg(long, value_enum, default_value_t = KdfArg::Scrypt)]
+    kdf: KdfArg,
+
+    /// Delete each plaintext secret key once its keystore is written
+    #[arg(long)]
+    delete_plaintext: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Path: directory holding validator_N_keystore.json files
+    #[arg(long)]
+    keys_dir: String,
+
+    /// Path: file with the keystore password. Prompted for when unset
+    #[arg(long)]
+    password_file: Option<String>,
+}
+
+impl From<KdfArg> for Kdf {
+    fn from(kdf: KdfArg) -> Self {
+        match kdf {
+            KdfArg::Scrypt => Kdf::default(),
+            KdfArg::Pbkdf2 => Kdf::Pbkdf2 { c: PBKDF2_ROUNDS },
+        }
+    }
+}
+
+pub fn run(command: &KeystoreCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        KeystoreCommand::Import(args) => run_import(args),
+        KeystoreCommand::Export(args) => run_export(args),
+    }
+}
+
+/// Key manager for `keys_dir` that can decrypt the keystores of `indices`.
+/// The password is read from `password_file`, or prompted for if one of the
+/// validators has a keystore.
+pub fn open_key_manager(
+    keys_dir: &Path,
+    indices: &[u64],
+    password_file: Option<&Path>,
+    allow_plaintext: bool,
+) -> Result<KeyManager, Box<dyn std::error::Error>> {
+    let key_manager = KeyManager::new(keys_dir)?.with_plaintext_keys(allow_plaintext);
+
+    let password = match password_file {
+        Some(path) => Some(read_password_file(path)?),
+        None if indices.iter().any(|&index| key_manager.has_keystore(index)) => {
+            Some(rpassword::prompt_password("Keystore password: ")?)
+        }
+        None => None,
+    };
+
+    Ok(match password {
+        Some(password) => key_manager.with_password(password),
+        None => key_manager,
+    })
+}
+
+/// Password from `password_file`, or prompted for, twice if `confirm` is set.
+pub fn read_password(
+    password_file: Option<&str>,
+    confirm: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(path) = password_file {
+        return Ok(read_password_file(path)?);
+    }
+
+    let password = rpassword::prompt_password("Keystore password: ")?;
+    if confirm && rpassword::prompt_password("Repeat password: ")? != password {
+        return Err("Passwords do not match".into());
+    }
+    Ok(password)
+}
+
+fn run_import(args: &ImportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let keys_dir = Path::new(&args.keys_dir);
+    let key_manager = KeyManager::new(keys_dir)?;
+    let indices = validator_indices(keys_dir, "_sk.ssz")?;
+    if indices.is_empty() {
+        return Err(format!("No validator_N_sk.ssz files in {}", keys_dir.display()).into());
+    }
+
+    let password = read_password(args.password_file.as_deref(), true)?;
+
+    for index in indices {
+        let keystore_path = key_manager.keystore_path(index);
+        if keystore_path.exists() {
+            println!("Validator {index} already has a keystore, skipping");
+            continue;
+        }
+
+        let sk_path = key_manager.secret_key_path(index);
+        let secret_key = fs::read(&sk_path)?;
+        let public_key = fs::read(keys_dir.join(format!("validator_{index}_pk.ssz"))).ok();
+
+        Keystore::encrypt(
+            &secret_key,
+            &password,
+            args.kdf.into(),
+            Some(index),
+            public_key.as_deref(),
+        )?
+        .write_json_file(&keystore_path)?;
+
+        if args.delete_plaintext {
+            fs::remove_file(&sk_path)?;
+        }
+        println!("Wrote {}", keystore_path.display());
+    }
+
+    Ok(())
+}
+
+fn run_export(args: &ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let keys_dir = Path::new(&args.keys_dir);
+    let key_manager = KeyManager::new(keys_dir)?;
+    let indices = validator_indices(keys_dir, "_keystore.json")?;
+    if indices.is_empty() {
+        return Err(format!(
+            "No validator_N_keystore.json files in {}",
+            keys_dir.display()
+        )
+        .into());
+    }
+
+    let password = read_password(args.password_file.as_deref(), false)?;
+
+    for index in indices {
+        let sk_path = key_manager.secret_key_path(index);
+        if sk_path.exists() {
+            println!("Validator {index} already has a plaintext secret key, skipping");
+            continue;
+        }
+
+        let secret_key = Keystore::from_json_file(key_manager.keystore_path(index))?
+            .decrypt(&password)
+            .map_err(|e| format!("Validator {index}: {e}"))?;
+        write_secret_file(&sk_path, &secret_key)?;
+        println!("Wrote {}", sk_path.display());
+    }
+
+    Ok(())
+}
+
+/// Indices of the `validator_N<suffix>` files in `keys_dir`.
+fn validator_indices(
+    keys_dir: &Path,
+    suffix: &str,
+) -> Result<BTreeSet<u64>, Box<dyn std::error::Error>> {
+    let mut indices = BTreeSet::new();
+    for entry in fs::read_dir(keys_dir)? {
+        let file_name = entry?.file_name();
+        let index = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix("validator_"))
+            .and_then(|name| name.strip_suffix(suffix))
+            .and_then(|index| index.parse().ok());
+        indices.extend(index);
+    }
+    Ok(indices)
+}
@@ -1,42 +1,258 @@
-use clap::Parser;
-use containers::ssz::SszHash;
+use clap::{Parser, Subcommand};
+use containers::ssz::{SszHash, SszReadDefault};
 use containers::{
-    attestation::{Attestation, AttestationData, BlockSignatures},
-    block::{Block, BlockBody, BlockWithAttestation, SignedBlockWithAttestation},
+    attestation::{Attestation, AttestationData, BlockSignatures, SignedAttestation},
+    block::{
+        Block, BlockBody, BlockVersion, BlockWithAttestation, SignedBlockWithAttestation,
+        VersionedSignedBlock,
+    },
     checkpoint::Checkpoint,
-    config::Config,
+    config::{Config, GenesisConfig},
     ssz,
     state::State,
     types::{Bytes32, Uint64, ValidatorIndex},
-    Slot,
+    Slot, Status,
 };
 use fork_choice::{
-    handlers::{on_attestation, on_block, on_tick},
-    store::{get_forkchoice_store, Store, INTERVALS_PER_SLOT},
+    error::OnBlockError,
+    handlers::{on_aggregated_attestation, on_attestation, on_block, on_tick},
+    store::{
+        get_ancestor_at_slot, get_canonical_blocks_by_range, get_forkchoice_store,
+        get_forkchoice_store_from_checkpoint, get_forkchoice_store_from_storage, Store,
+        INTERVALS_PER_SLOT,
+    },
+    validation::{
+        validate_gossip_aggregate, validate_gossip_attestation, validate_gossip_block, GossipError,
+    },
 };
-use libp2p_identity::Keypair;
+use http_api::{ApiContext, ApiQuery, NodeIdentity};
 use networking::gossipsub::config::GossipsubConfig;
-use networking::gossipsub::topic::get_topics;
+use networking::gossipsub::topic::{get_subnet_topics, get_topics};
 use networking::network::{NetworkService, NetworkServiceConfig};
-use networking::types::{ChainMessage, OutboundP2pRequest};
-use std::net::IpAddr;
+use networking::req_resp::MAX_REQUEST_BLOCKS;
+use networking::types::{ChainMessage, ChainQuery, GossipValidation, OutboundP2pRequest};
+use node_config::{KeySource, NodeConfig, ValidatorSetup};
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
+use storage::{FileStorage, Storage};
 use tokio::{
-    sync::mpsc,
+    sync::{broadcast, mpsc, watch},
     task,
     time::{interval, Duration},
 };
 use tracing::{debug, info, warn};
-use validator::{ValidatorConfig, ValidatorService};
-
-fn load_node_key(path: &str) -> Result<Keypair, Box<dyn std::error::Error>> {
-    let hex_str = std::fs::read_to_string(path)?.trim().to_string();
-    let bytes = hex::decode(&hex_str)?;
-    let secret = libp2p_identity::secp256k1::SecretKey::try_from_bytes(bytes)?;
-    let keypair = libp2p_identity::secp256k1::Keypair::from(secret);
-    Ok(Keypair::from(keypair))
+use validator::{
+    remote_signer::RemoteSigner, slashing_protection::SlashingProtection, ValidatorService,
+};
+
+mod db;
+mod inspect;
+mod keygen;
+mod keystore;
+mod node_config;
+
+/// A message signed off the chain task, to be imported and gossiped.
+enum SignedDuty {
+    Block(VersionedSignedBlock),
+    Attestation(SignedAttestation),
+}
+
+/// Chain events kept for slow event stream subscribers before they miss some.
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+/// Slashing protection database, kept in --data-dir.
+const SLASHING_PROTECTION_FILE: &str = "slashing_protection.json";
+
+fn load_checkpoint(
+    state_path: &Path,
+    block_path: &Path,
+    block_version: BlockVersion,
+) -> Result<(State, VersionedSignedBlock), Box<dyn std::error::Error>> {
+    let state = State::from_ssz_default(&std::fs::read(state_path)?)
+        .map_err(|e| format!("invalid checkpoint state {}: {e:?}", state_path.display()))?;
+    let block = VersionedSignedBlock::from_ssz(block_version, &std::fs::read(block_path)?)
+        .map_err(|e| format!("invalid checkpoint block {}: {e}", block_path.display()))?;
+    Ok((state, block))
+}
+
+/// Genesis state of the validators in `genesis`, whose public keys
+/// [`node_config::load_genesis`] has checked.
+fn genesis_state(genesis: &GenesisConfig) -> State {
+    let validators = genesis
+        .genesis_validators
+        .iter()
+        .enumerate()
+        .map(|(i, pubkey)| containers::validator::Validator {
+            pubkey: containers::validator::BlsPublicKey::from_hex(pubkey)
+                .expect("genesis public keys are checked by node_config::load_genesis"),
+            index: Uint64(i as u64),
+        })
+        .collect();
+
+    State::generate_genesis_with_validators(Uint64(genesis.genesis_time), validators)
+}
+
+/// The validator service for `setup`, with its keys loaded or its remote signer set.
+fn build_validator_service(
+    setup: ValidatorSetup,
+    num_validators: u64,
+) -> Result<ValidatorService, Box<dyn std::error::Error>> {
+    let ValidatorSetup { config, key_source } = setup;
+    let node_id = config.node_id.clone();
+    let indices = config.validator_indices.clone();
+
+    match key_source {
+        KeySource::Local {
+            keys_dir,
+            password_file,
+            allow_plaintext,
+        } => {
+            let key_manager = keystore::open_key_manager(
+                &keys_dir,
+                &indices,
+                password_file.as_deref(),
+                allow_plaintext,
+            )?;
+            let service = ValidatorService::new_with_keys(config, num_validators, key_manager)
+                .map_err(|e| {
+                    format!("Failed to load XMSS keys from {}: {e}", keys_dir.display())
+                })?;
+            info!(
+                node_id = %node_id,
+                indices = ?indices,
+                keys_dir = ?keys_dir,
+                "Validator mode enabled with XMSS signing"
+            );
+            Ok(service)
+        }
+        KeySource::Remote { url } => {
+            let signer = RemoteSigner::new(url.as_str());
+            let upcheck_signer = RemoteSigner::new(url.as_str());
+            task::spawn_blocking(move || {
+                if let Err(e) = upcheck_signer.upcheck() {
+                    warn!("Remote signer is not reachable yet: {}", e);
+                }
+            });
+            info!(
+                node_id = %node_id,
+                indices = ?indices,
+                url = %url,
+                "Validator mode enabled with remote signer"
+            );
+            Ok(ValidatorService::new(config, num_validators).with_signer(signer))
+        }
+        KeySource::ZeroSignatures => {
+            warn!(
+                node_id = %node_id,
+                indices = ?indices,
+                "Validator mode enabled with zero signatures (--allow-zero-signatures)"
+            );
+            Ok(ValidatorService::new(config, num_validators))
+        }
+    }
+}
+
+fn answer_chain_query(store: &Store, query: ChainQuery) {
+    match query {
+        ChainQuery::BlocksByRoot { roots, respond_to } => {
+            let blocks = roots
+                .iter()
+                .filter_map(|root| store.blocks.get(root).cloned())
+                .take(MAX_REQUEST_BLOCKS)
+                .collect::<Vec<_>>();
+
+            debug!(
+                requested = roots.len(),
+                found = blocks.len(),
+                "Answering BlocksByRoot query"
+            );
+
+            // The requester may have given up already; nothing to do then.
+            let _ = respond_to.send(blocks);
+        }
+        ChainQuery::BlocksByRange {
+            start_slot,
+            count,
+            respond_to,
+        } => {
+            let count = count.min(MAX_REQUEST_BLOCKS as u64);
+            let _ = respond_to.send(get_canonical_blocks_by_range(store, start_slot, count));
+        }
+        ChainQuery::BlockRootAtSlot { slot, respond_to } => {
+            let _ = respond_to.send(get_ancestor_at_slot(store, store.head, slot));
+        }
+        ChainQuery::ValidateBlock { block, respond_to } => {
+            let _ = respond_to.send(gossip_validation(validate_gossip_block(store, &block)));
+        }
+        ChainQuery::ValidateAttestation {
+            attestation,
+            respond_to,
+        } => {
+            let _ = respond_to.send(gossip_validation(validate_gossip_attestation(
+                store,
+                &attestation,
+            )));
+        }
+        ChainQuery::ValidateAggregate {
+            aggregate,
+            respond_to,
+        } => {
+            let _ = respond_to.send(gossip_validation(validate_gossip_aggregate(
+                store, &aggregate,
+            )));
+        }
+    }
+}
+
+fn gossip_validation(result: Result<(), GossipError>) -> GossipValidation {
+    match result {
+        Ok(()) => GossipValidation::Accept,
+        Err(GossipError::UnknownParent(_)) => GossipValidation::UnknownParent,
+        Err(GossipError::Ignore(reason)) => GossipValidation::Ignore(reason),
+        Err(GossipError::Reject(reason)) => GossipValidation::Reject(reason),
+    }
+}
+
+fn local_status(store: &Store) -> Status {
+    let head_slot = store
+        .blocks
+        .get(&store.head)
+        .map(|block| block.slot())
+        .unwrap_or_default();
+
+    Status::new(
+        store.latest_finalized.clone(),
+        Checkpoint {
+            root: store.head,
+            slot: head_slot,
+        },
+    )
+}
+
+fn record_chain_metrics(store: &Store) {
+    let head_slot = store
+        .blocks
+        .get(&store.head)
+        .map(|block| block.slot().0)
+        .unwrap_or_default();
+
+    metrics::HEAD_SLOT.set(head_slot as i64);
+    metrics::JUSTIFIED_SLOT.set(store.latest_justified.slot.0 as i64);
+    metrics::FINALIZED_SLOT.set(store.latest_finalized.slot.0 as i64);
+
+    let queued_blocks = store.blocks_queue.values().map(Vec::len).sum::<usize>();
+    for (kind, size) in [
+        ("blocks", store.blocks.len()),
+        ("states", store.states.len()),
+        ("queued_blocks", queued_blocks),
+        ("new_attestations", store.latest_new_attestations.len()),
+        ("known_attestations", store.latest_known_attestations.len()),
+    ] {
+        metrics::STORE_SIZE
+            .with_label_values(&[kind])
+            .set(size as i64);
+    }
 }
 
 fn print_chain_status(store: &Store, connected_peers: u64) {
@@ -45,7 +261,7 @@ fn print_chain_status(store: &Store, connected_peers: u64) {
     let head_slot = store
         .blocks
         .get(&store.head)
-        .map(|b| b.message.block.slot.0)
+        .map(|b| b.slot().0)
         .unwrap_or(0);
 
     let behind = if current_slot > head_slot {
@@ -56,8 +272,8 @@ fn print_chain_status(store: &Store, connected_peers: u64) {
 
     let (head_root, parent_root, state_root) = if let Some(block) = store.blocks.get(&store.head) {
         let head_root = store.head;
-        let parent_root = block.message.block.parent_root;
-        let state_root = block.message.block.state_root;
+        let parent_root = block.parent_root();
+        let state_root = block.state_root();
         (head_root, parent_root, state_root)
     } else {
         (
@@ -97,37 +313,52 @@ fn print_chain_status(store: &Store, connected_peers: u64) {
         "  Latest Finalized:   Slot {:>5} | Root: 0x{:x}",
         finalized.slot.0, finalized.root.0
     );
+    println!("+---------------------------------------------------------------+");
+    println!(
+        "  Stored:             {} blocks | {} states",
+        store.blocks.len(),
+        store.states.len()
+    );
+    println!(
+        "  Pruned:             {} blocks | {} states | {} queued | {} attestations",
+        store.prune_stats.blocks,
+        store.prune_stats.states,
+        store.prune_stats.queued_blocks,
+        store.prune_stats.attestations
+    );
     println!("+===============================================================+\n");
 }
 
-#[derive(Parser, Debug)]
-struct Args {
-    #[arg(short, long, default_value = "127.0.0.1")]
-    address: IpAddr,
-
-    #[arg(short, long, default_value_t = 8083)]
-    port: u16,
-
-    #[arg(short, long)]
-    bootnodes: Vec<String>,
-
-    #[arg(short, long)]
-    genesis: Option<String>,
-
-    #[arg(long)]
-    node_id: Option<String>,
-
-    /// Path: validators.yaml
-    #[arg(long)]
-    validator_registry_path: Option<String>,
-
-    /// Path: p2p private key
-    #[arg(long)]
-    node_key: Option<String>,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a beacon node, with validator duties if --node-id and
+    /// --validator-registry-path are set
+    Node(node_config::NodeArgs),
+    /// Generate XMSS validator keys, node keys and validators.yaml for a devnet
+    Keygen(keygen::KeygenArgs),
+    /// Write a genesis config.yaml for the validator keys made by `keygen`
+    Genesis(keygen::GenesisArgs),
+    /// Encrypt or decrypt the XMSS secret keys in a key directory
+    Keystore {
+        #[command(subcommand)]
+        command: keystore::KeystoreCommand,
+    },
+    /// Print a summary of a genesis config, SSZ state or block, keystore or node key
+    Inspect {
+        #[command(subcommand)]
+        command: inspect::InspectCommand,
+    },
+    /// Inspect or export the contents of a --data-dir while the node is stopped
+    Db {
+        #[command(subcommand)]
+        command: db::DbCommand,
+    },
+}
 
-    /// Path: directory containing XMSS validator keys (validator_N_sk.ssz files)
-    #[arg(long)]
-    hash_sig_key_dir: Option<String>,
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
 #[tokio::main]
@@ -136,44 +367,44 @@ async fn main() {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    let args = Args::parse();
+    let result = match Cli::parse().command {
+        Command::Node(node_args) => match node_args.into_config() {
+            Ok(config) => run_node(config).await,
+            Err(e) => Err(format!("Invalid configuration: {e}").into()),
+        },
+        Command::Keygen(keygen_args) => keygen::run_keygen(&keygen_args),
+        Command::Genesis(genesis_args) => keygen::run_genesis(&genesis_args),
+        Command::Keystore { command } => keystore::run(&command),
+        Command::Inspect { command } => inspect::run(&command),
+        Command::Db { command } => db::run(&command),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+async fn run_node(config: NodeConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let block_version = config.block_version;
+
+    if let Some(address) = config.metrics_address {
+        task::spawn(async move {
+            if let Err(err) = metrics::serve(address).await {
+                warn!(%address, %err, "Metrics server stopped");
+            }
+        });
+    }
 
     let (outbound_p2p_sender, outbound_p2p_receiver) =
         mpsc::unbounded_channel::<OutboundP2pRequest>();
     let (chain_message_sender, mut chain_message_receiver) =
         mpsc::unbounded_channel::<ChainMessage>();
+    let (chain_query_sender, mut chain_query_receiver) = mpsc::unbounded_channel::<ChainQuery>();
+    let (api_query_sender, mut api_query_receiver) = mpsc::unbounded_channel::<ApiQuery>();
 
-    let (genesis_time, validators) = if let Some(genesis_path) = &args.genesis {
-        let genesis_config = containers::GenesisConfig::load_from_file(genesis_path)
-            .expect("Failed to load genesis config");
-
-        let validators: Vec<containers::validator::Validator> = genesis_config
-            .genesis_validators
-            .iter()
-            .enumerate()
-            .map(|(i, v_str)| {
-                let pubkey = containers::validator::BlsPublicKey::from_hex(v_str)
-                    .expect("Invalid genesis validator pubkey");
-                containers::validator::Validator {
-                    pubkey,
-                    index: Uint64(i as u64),
-                }
-            })
-            .collect();
-
-        (genesis_config.genesis_time, validators)
-    } else {
-        let num_validators = 3;
-        let validators = (0..num_validators)
-            .map(|i| containers::validator::Validator {
-                pubkey: containers::validator::BlsPublicKey::default(),
-                index: Uint64(i as u64),
-            })
-            .collect();
-        (1763757427, validators)
-    };
-
-    let genesis_state = State::generate_genesis_with_validators(Uint64(genesis_time), validators);
+    let genesis_time = config.genesis.genesis_time;
+    let genesis_state = genesis_state(&config.genesis);
 
     let genesis_block = Block {
         slot: Slot(0),
@@ -211,122 +442,201 @@ async fn main() {
         signature: BlockSignatures::default(),
     };
 
-    let config = Config { genesis_time };
-    let store = get_forkchoice_store(genesis_state.clone(), genesis_signed_block, config);
+    let checkpoint = match &config.checkpoint {
+        Some((state_path, block_path)) => {
+            Some(load_checkpoint(state_path, block_path, block_version)?)
+        }
+        None => None,
+    };
 
-    let num_validators = genesis_state.validators.len_u64();
-    info!(num_validators = num_validators, "Genesis state loaded");
+    let chain_config = match &checkpoint {
+        Some((state, _)) => state.config.clone(),
+        None => Config { genesis_time },
+    };
 
-    let validator_service = if let (Some(node_id), Some(registry_path)) =
-        (&args.node_id, &args.validator_registry_path)
-    {
-        match ValidatorConfig::load_from_file(registry_path, node_id) {
-            Ok(config) => {
-                // Use explicit hash-sig-key-dir if provided
-                if let Some(ref keys_dir) = args.hash_sig_key_dir {
-                    let keys_path = std::path::Path::new(keys_dir);
-                    if keys_path.exists() {
-                        match ValidatorService::new_with_keys(
-                            config.clone(),
-                            num_validators,
-                            keys_path,
-                        ) {
-                            Ok(service) => {
-                                info!(
-                                    node_id = %node_id,
-                                    indices = ?config.validator_indices,
-                                    keys_dir = ?keys_path,
-                                    "Validator mode enabled with XMSS signing"
-                                );
-                                Some(service)
-                            }
-                            Err(e) => {
-                                warn!(
-                                    "Failed to load XMSS keys: {}, falling back to zero signatures",
-                                    e
-                                );
-                                Some(ValidatorService::new(config, num_validators))
-                            }
-                        }
-                    } else {
-                        warn!(
-                            "Hash-sig key directory not found: {:?}, using zero signatures",
-                            keys_path
-                        );
-                        Some(ValidatorService::new(config, num_validators))
-                    }
-                } else {
+    let storage = match &config.data_dir {
+        Some(data_dir) => {
+            let storage = FileStorage::open(data_dir)
+                .map_err(|e| format!("Failed to open database {}: {e:#}", data_dir.display()))?;
+            info!(data_dir = ?data_dir, "Database opened");
+            Some(Arc::new(storage) as Arc<dyn Storage>)
+        }
+        None => None,
+    };
+
+    let resumed_store = match storage.clone() {
+        Some(storage) => get_forkchoice_store_from_storage(storage, chain_config.clone())
+            .map_err(|e| format!("Failed to load fork choice store from database: {e}"))?,
+        None => None,
+    };
+
+    let store = match resumed_store {
+        Some(store) => {
+            info!(
+                head = %format!("0x{:x}", store.head.0),
+                finalized_slot = store.latest_finalized.slot.0,
+                blocks = store.blocks.len(),
+                "Resumed from database"
+            );
+            if checkpoint.is_some() {
+                warn!("Database is not empty, ignoring --checkpoint-state and --checkpoint-block");
+            }
+            store
+        }
+        None => {
+            let store = match checkpoint {
+                Some((state, block)) => {
+                    let store = get_forkchoice_store_from_checkpoint(state, block, chain_config)
+                        .map_err(|e| format!("Invalid checkpoint: {e}"))?;
                     info!(
-                        node_id = %node_id,
-                        indices = ?config.validator_indices,
-                        "Validator mode enabled (no --hash-sig-key-dir specified - using zero signatures)"
+                        slot = store.latest_finalized.slot.0,
+                        root = %format!("0x{:x}", store.latest_finalized.root.0),
+                        "Starting from checkpoint"
                     );
-                    Some(ValidatorService::new(config, num_validators))
+                    store
+                }
+                None => {
+                    get_forkchoice_store(genesis_state.clone(), genesis_signed_block, chain_config)
                 }
+            };
+            match storage {
+                Some(storage) => store
+                    .with_storage(storage)
+                    .map_err(|e| format!("Failed to write genesis to database: {e}"))?,
+                None => store,
             }
-            Err(e) => {
-                warn!("Failed to load validator config: {}", e);
-                None
+        }
+    };
+
+    // Without xmss-verify `containers::crypto::verify` accepts every signature
+    if store.verify_signatures && !cfg!(feature = "xmss-verify") {
+        return Err(
+            "This build cannot verify XMSS signatures, rebuild with the xmss-verify feature".into(),
+        );
+    }
+
+    let (event_sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let store = store.with_events(event_sender.clone());
+
+    let num_validators = store
+        .states
+        .get(&store.head)
+        .map(|state| state.validators.len_u64())
+        .unwrap_or_else(|| genesis_state.validators.len_u64());
+    info!(num_validators = num_validators, "Genesis state loaded");
+
+    let validator_service = match config.validator {
+        Some(setup) => Some(build_validator_service(setup, num_validators)?),
+        None => {
+            info!("Running in passive mode (no validator duties)");
+            None
+        }
+    };
+
+    let slashing_protection = match &config.data_dir {
+        Some(data_dir) => SlashingProtection::open(
+            data_dir.join(SLASHING_PROTECTION_FILE),
+            store.config.genesis_time,
+        )
+        .map_err(|e| format!("Failed to open slashing protection database: {e}"))?,
+        None => {
+            if validator_service.is_some() {
+                warn!("No --data-dir, slashing protection history is lost on restart");
             }
+            SlashingProtection::in_memory(store.config.genesis_time)
         }
-    } else {
-        info!("Running in passive mode (no validator duties)");
-        None
     };
+    if let Some(interchange) = config.slashing_protection_import {
+        slashing_protection
+            .import_interchange(interchange)
+            .map_err(|e| format!("Failed to import slashing protection data: {e}"))?;
+    }
 
-    let fork = "devnet0".to_string();
-    let gossipsub_topics = get_topics(fork);
+    let validator_service = validator_service.map(|service| {
+        Arc::new(
+            service
+                .with_block_version(block_version)
+                .with_slashing_protection(slashing_protection),
+        )
+    });
+
+    let fork = config.fork.clone();
+    let mut gossipsub_topics = get_topics(fork.clone());
+    // Aggregators must hear every attestation of their subnet
+    if let Some(ref vs) = validator_service {
+        gossipsub_topics.extend(get_subnet_topics(fork, vs.subnet_ids()));
+    }
     let mut gossipsub_config = GossipsubConfig::new();
     gossipsub_config.set_topics(gossipsub_topics);
+    gossipsub_config.set_active_validators(num_validators);
 
-    let network_service_config = Arc::new(NetworkServiceConfig::new(
+    let mut network_service_config = NetworkServiceConfig::new(
         gossipsub_config,
-        args.address,
-        args.port,
-        args.bootnodes,
-    ));
+        config.listen_address,
+        config.port,
+        config.bootnodes,
+    );
+    if let Some(discovery_port) = config.discovery_port {
+        network_service_config = network_service_config.with_discovery_port(discovery_port);
+    }
+    let network_service_config = Arc::new(network_service_config);
+
+    let (status_sender, status_receiver) = watch::channel(local_status(&store));
 
     let peer_count = Arc::new(AtomicU64::new(0));
     let peer_count_for_status = peer_count.clone();
 
-    // LOAD NODE KEY
-    let mut network_service = if let Some(key_path) = &args.node_key {
-        match load_node_key(key_path) {
-            Ok(keypair) => {
-                let peer_id = keypair.public().to_peer_id();
-                info!(peer_id = %peer_id, "Using custom node key");
-                NetworkService::new_with_keypair(
-                    network_service_config.clone(),
-                    outbound_p2p_receiver,
-                    chain_message_sender.clone(),
-                    peer_count,
-                    keypair,
-                )
-                .await
-                .expect("Failed to create network service with custom key")
-            }
-            Err(e) => {
-                warn!("Failed to load node key: {}, using random key", e);
-                NetworkService::new_with_peer_count(
-                    network_service_config.clone(),
-                    outbound_p2p_receiver,
-                    chain_message_sender.clone(),
-                    peer_count,
-                )
-                .await
-                .expect("Failed to create network service")
-            }
+    let network_service = match config.node_key {
+        Some(keypair) => {
+            let peer_id = keypair.public().to_peer_id();
+            info!(peer_id = %peer_id, "Using custom node key");
+            NetworkService::new_with_keypair(
+                network_service_config.clone(),
+                outbound_p2p_receiver,
+                chain_message_sender.clone(),
+                peer_count,
+                keypair,
+            )
+            .await
         }
-    } else {
-        NetworkService::new_with_peer_count(
-            network_service_config.clone(),
-            outbound_p2p_receiver,
-            chain_message_sender.clone(),
-            peer_count,
-        )
-        .await
-        .expect("Failed to create network service")
-    };
+        None => {
+            info!("No --node-key, using a random node key");
+            NetworkService::new_with_peer_count(
+                network_service_config.clone(),
+                outbound_p2p_receiver,
+                chain_message_sender.clone(),
+                peer_count,
+            )
+            .await
+        }
+    }
+    .map_err(|e| format!("Failed to create network service: {e:#}"))?;
+    let mut network_service = network_service
+        .with_chain_queries(chain_query_sender)
+        .with_status(status_receiver);
+
+    if let Some(address) = config.http_address {
+        let peer_id = network_service.local_peer_id();
+        let identity = NodeIdentity {
+            peer_id: peer_id.to_string(),
+            enr: network_service.local_enr().map(|enr| enr.to_string()),
+            p2p_addresses: network_service
+                .listen_multiaddr()
+                .map(|addr| vec![format!("{addr}/p2p/{peer_id}")])
+                .unwrap_or_default(),
+        };
+        let context = ApiContext::new(
+            api_query_sender,
+            identity,
+            network_service.peer_manager(),
+            event_sender,
+        );
+        task::spawn(async move {
+            if let Err(err) = http_api::serve(address, context).await {
+                warn!(%address, %err, "HTTP API server stopped");
+            }
+        });
+    }
 
     let network_handle = task::spawn(async move {
         if let Err(err) = network_service.start().await {
@@ -342,10 +652,16 @@ async fn main() {
         let mut last_status_slot: Option<u64> = None;
         let mut last_proposal_slot: Option<u64> = None;
         let mut last_attestation_slot: Option<u64> = None;
+        let mut last_aggregation_slot: Option<u64> = None;
 
         let peer_count = peer_count_for_status;
         let mut store = store;
 
+        // Signing may wait on a remote signer, so it runs on blocking threads
+        // and the signed messages come back here
+        let (signed_duty_sender, mut signed_duty_receiver) =
+            mpsc::unbounded_channel::<SignedDuty>();
+
         loop {
             tokio::select! {
                 _ = tick_interval.tick() => {
@@ -375,34 +691,18 @@ async fn main() {
                                             "Our turn to propose block!"
                                         );
 
-                                        match vs.build_block_proposal(&mut store, Slot(current_slot), proposer_idx) {
-                                            Ok(signed_block) => {
-                                                let block_root = Bytes32(signed_block.message.block.hash_tree_root());
-                                                info!(
-                                                    slot = current_slot,
-                                                    block_root = %format!("0x{:x}", block_root.0),
-                                                    "Built block, processing and gossiping"
-                                                );
-
-                                                // Synchronize store time with wall clock before processing own block
-                                                let now = SystemTime::now()
-                                                    .duration_since(UNIX_EPOCH)
-                                                    .unwrap()
-                                                    .as_secs();
-                                                on_tick(&mut store, now, false);
-
-                                                match on_block(&mut store, signed_block.clone()) {
-                                                    Ok(()) => {
-                                                        info!("Own block processed successfully");
-                                                        // GOSSIP TO NETWORK
-                                                        if let Err(e) = chain_outbound_sender.send(
-                                                            OutboundP2pRequest::GossipBlockWithAttestation(signed_block)
-                                                        ) {
-                                                            warn!("Failed to gossip our block: {}", e);
+                                        match vs.prepare_block_proposal(&mut store, Slot(current_slot), proposer_idx) {
+                                            Ok(proposal) => {
+                                                let vs = Arc::clone(vs);
+                                                let signed_duty_sender = signed_duty_sender.clone();
+                                                task::spawn_blocking(move || {
+                                                    match vs.sign_block_proposal(proposal) {
+                                                        Ok(signed_block) => {
+                                                            let _ = signed_duty_sender.send(SignedDuty::Block(signed_block));
                                                         }
+                                                        Err(e) => warn!("Failed to sign block proposal: {}", e),
                                                     }
-                                                    Err(e) => warn!("Failed to process our own block: {}", e),
-                                                }
+                                                });
                                             }
                                             Err(e) => warn!("Failed to build block proposal: {}", e),
                                         }
@@ -414,26 +714,15 @@ async fn main() {
                         1 => {
                             if let Some(ref vs) = validator_service {
                                 if last_attestation_slot != Some(current_slot) {
-                                    let attestations = vs.create_attestations(&store, Slot(current_slot));
-                                    for signed_att in attestations {
-                                        let validator_id = signed_att.message.validator_id.0;
-                                        info!(
-                                            slot = current_slot,
-                                            validator = validator_id,
-                                            "Broadcasting attestation"
-                                        );
-
-
-                                        match on_attestation(&mut store, signed_att.clone(), false) {
-                                            Ok(()) => {
-                                                if let Err(e) = chain_outbound_sender.send(
-                                                    OutboundP2pRequest::GossipAttestation(signed_att)
-                                                ) {
-                                                    warn!("Failed to gossip attestation: {}", e);
-                                                }
+                                    let attestations = vs.prepare_attestations(&store, Slot(current_slot));
+                                    if !attestations.is_empty() {
+                                        let vs = Arc::clone(vs);
+                                        let signed_duty_sender = signed_duty_sender.clone();
+                                        task::spawn_blocking(move || {
+                                            for signed_att in vs.sign_attestations(attestations) {
+                                                let _ = signed_duty_sender.send(SignedDuty::Attestation(signed_att));
                                             }
-                                            Err(e) => warn!("Error processing own attestation: {}", e),
-                                        }
+                                        });
                                     }
                                     last_attestation_slot = Some(current_slot);
                                 }
@@ -441,6 +730,19 @@ async fn main() {
                         }
                         2 => {
                             info!(slot = current_slot, tick = store.time, "Computing safe target");
+
+                            if let Some(ref vs) = validator_service {
+                                if last_aggregation_slot != Some(current_slot) {
+                                    for aggregate in vs.create_aggregates(&store, Slot(current_slot)) {
+                                        if let Err(e) = chain_outbound_sender.send(
+                                            OutboundP2pRequest::GossipAggregate(aggregate)
+                                        ) {
+                                            warn!("Failed to gossip aggregate: {}", e);
+                                        }
+                                    }
+                                    last_aggregation_slot = Some(current_slot);
+                                }
+                            }
                         }
                         3 => {
                             info!(slot = current_slot, tick = store.time, "Accepting new attestations");
@@ -456,18 +758,72 @@ async fn main() {
                         last_logged_slot = current_slot;
                     }
                 }
+                Some(duty) = signed_duty_receiver.recv() => match duty {
+                    SignedDuty::Block(signed_block) => {
+                        info!(
+                            slot = signed_block.slot().0,
+                            block_root = %format!("0x{:x}", signed_block.block_root().0),
+                            "Built block, processing and gossiping"
+                        );
+
+                        // Synchronize store time with wall clock before processing own block
+                        let now = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap()
+                            .as_secs();
+                        on_tick(&mut store, now, false);
+
+                        match on_block(&mut store, signed_block.clone(), false) {
+                            Ok(()) => {
+                                info!("Own block processed successfully");
+                                // GOSSIP TO NETWORK
+                                if let Err(e) = chain_outbound_sender.send(
+                                    OutboundP2pRequest::GossipBlockWithAttestation(signed_block)
+                                ) {
+                                    warn!("Failed to gossip our block: {}", e);
+                                }
+                            }
+                            Err(e) => warn!("Failed to process our own block: {}", e),
+                        }
+                    }
+                    SignedDuty::Attestation(signed_att) => {
+                        info!(
+                            slot = signed_att.message.data.slot.0,
+                            validator = signed_att.message.validator_id.0,
+                            "Broadcasting attestation"
+                        );
+
+                        match on_attestation(&mut store, signed_att.clone(), false, false) {
+                            Ok(()) => {
+                                if let Err(e) = chain_outbound_sender.send(
+                                    OutboundP2pRequest::GossipAttestation(signed_att)
+                                ) {
+                                    warn!("Failed to gossip attestation: {}", e);
+                                }
+                            }
+                            Err(e) => warn!("Error processing own attestation: {}", e),
+                        }
+                    }
+                },
+                Some(query) = chain_query_receiver.recv() => {
+                    answer_chain_query(&store, query);
+                }
+                Some(query) = api_query_receiver.recv() => {
+                    http_api::answer_query(&store, query);
+                }
                 message = chain_message_receiver.recv() => {
                     let Some(message) = message else { break };
                     match message {
                         ChainMessage::ProcessBlock {
                             signed_block_with_attestation,
                             should_gossip,
+                            signatures_verified,
                             ..
                         } => {
-                            let block_slot = signed_block_with_attestation.message.block.slot.0;
-                            let proposer = signed_block_with_attestation.message.block.proposer_index.0;
-                            let block_root = Bytes32(signed_block_with_attestation.message.block.hash_tree_root());
-                            let parent_root = signed_block_with_attestation.message.block.parent_root;
+                            let block_slot = signed_block_with_attestation.slot().0;
+                            let proposer = signed_block_with_attestation.proposer_index().0;
+                            let block_root = signed_block_with_attestation.block_root();
+                            let parent_root = signed_block_with_attestation.parent_root();
 
                             info!(
                                 slot = block_slot,
@@ -483,7 +839,7 @@ async fn main() {
                                 .as_secs();
                             on_tick(&mut store, now, false);
 
-                            match on_block(&mut store, signed_block_with_attestation.clone()) {
+                            match on_block(&mut store, signed_block_with_attestation.clone(), signatures_verified) {
                                 Ok(()) => {
                                     info!("Block processed successfully");
 
@@ -497,7 +853,7 @@ async fn main() {
                                         }
                                     }
                                 }
-                                Err(e) if e.starts_with("Err: (Fork-choice::Handlers::OnBlock) Block queued") => {
+                                Err(e @ OnBlockError::UnknownParent { .. }) => {
                                     debug!("Block queued, requesting missing parent: {}", e);
 
                                     // Request missing parent block from peers
@@ -511,12 +867,16 @@ async fn main() {
                                         }
                                     }
                                 }
+                                Err(e @ OnBlockError::Duplicate(_)) => {
+                                    debug!("Ignoring block: {}", e);
+                                }
                                 Err(e) => warn!("Problem processing block: {}", e),
                             }
                         }
                         ChainMessage::ProcessAttestation {
                             signed_attestation,
                             should_gossip,
+                            signatures_verified,
                             ..
                         } => {
                             let att_slot = signed_attestation.message.data.slot.0;
@@ -531,7 +891,7 @@ async fn main() {
                                 validator_id
                             );
 
-                            match on_attestation(&mut store, signed_attestation.clone(), false) {
+                            match on_attestation(&mut store, signed_attestation.clone(), false, signatures_verified) {
                                 Ok(()) => {
                                     if should_gossip {
                                         if let Err(e) = outbound_p2p_sender.send(
@@ -546,9 +906,47 @@ async fn main() {
                                 Err(e) => warn!("Error processing attestation: {}", e),
                             }
                         }
+                        ChainMessage::ProcessAggregate {
+                            signed_aggregate,
+                            should_gossip,
+                            signatures_verified,
+                            ..
+                        } => {
+                            let att_slot = signed_aggregate.message.data.slot.0;
+                            info!(
+                                slot = att_slot,
+                                participants = signed_aggregate.signature.len_u64(),
+                                "Processing aggregate"
+                            );
+
+                            match on_aggregated_attestation(&mut store, signed_aggregate.clone(), signatures_verified) {
+                                Ok(()) => {
+                                    if should_gossip {
+                                        if let Err(e) = outbound_p2p_sender.send(
+                                            OutboundP2pRequest::GossipAggregate(signed_aggregate)
+                                        ) {
+                                            warn!("Failed to gossip aggregate: {}", e);
+                                        }
+                                    }
+                                }
+                                Err(e) => warn!("Error processing aggregate: {}", e),
+                            }
+                        }
                     }
                 }
             }
+
+            record_chain_metrics(&store);
+
+            // Keep the handshake Status in step with fork choice
+            let status = local_status(&store);
+            status_sender.send_if_modified(|current| {
+                if *current == status {
+                    return false;
+                }
+                *current = status;
+                true
+            });
         }
     });
 
@@ -562,4 +960,5 @@ async fn main() {
     }
 
     println!("Main async task exiting...");
+    Ok(())
 }
@@ -0,0 +1,631 @@
+//! Options of the `node` subcommand and their validation.
+//!
+//! Every option can be given as a flag or in a TOML or YAML file passed with
+//! `--config`, under the flag's name (`hash-sig-key-dir: ./keys`). Flags override
+//! the file. [`NodeArgs::into_config`] merges both and checks everything that can
+//! be checked before the node starts, so a wrong path is a startup error rather
+//! than a node running with a random identity or zero signatures.
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use containers::{block::BlockVersion, GenesisConfig};
+use libp2p_identity::Keypair;
+use serde::Deserialize;
+use validator::keys::KeyManager;
+use validator::slashing_protection::Interchange;
+use validator::ValidatorConfig;
+
+const DEFAULT_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const DEFAULT_PORT: u16 = 8083;
+const DEFAULT_HTTP_PORT: u16 = 5052;
+const DEFAULT_METRICS_PORT: u16 = 5054;
+const DEFAULT_FORK: &str = "devnet0";
+
+#[derive(Args, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NodeArgs {
+    /// Path: TOML or YAML file with any of the options below, by flag name. Flags take
+    /// precedence over the file
+    #[arg(long)]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+
+    /// Listen address for libp2p (default 127.0.0.1)
+    #[arg(short, long)]
+    address: Option<IpAddr>,
+
+    /// Listen port for libp2p (default 8083)
+    #[arg(short, long)]
+    port: Option<u16>,
+
+    /// UDP port for discv5 peer discovery. Discovery is disabled when unset
+    #[arg(long)]
+    discovery_port: Option<u16>,
+
+    /// Multiaddr or ENR of a peer to connect to. Repeat for several
+    #[arg(short, long)]
+    #[serde(default)]
+    bootnodes: Vec<String>,
+
+    /// Path: genesis config.yaml
+    #[arg(short, long)]
+    genesis: Option<PathBuf>,
+
+    /// Gossip fork name (default devnet0). It selects the block format: devnet0 blocks
+    /// carry one attestation per validator, devnet1 blocks carry aggregated attestations
+    #[arg(long)]
+    fork: Option<String>,
+
+    /// Path: p2p private key. A random key is used when unset
+    #[arg(long)]
+    node_key: Option<PathBuf>,
+
+    /// This node's entry in --validator-registry-path. Setting both enables validator duties
+    #[arg(long)]
+    node_id: Option<String>,
+
+    /// Path: validators.yaml
+    #[arg(long)]
+    validator_registry_path: Option<PathBuf>,
+
+    /// Path: directory containing XMSS validator keys (validator_N_keystore.json files). The
+    /// client tracks used signature epochs there in validator_N_key_state.json
+    #[arg(long)]
+    hash_sig_key_dir: Option<PathBuf>,
+
+    /// Path: file with the password of the validator keystores. Prompted for when unset
+    #[arg(long)]
+    keystore_password_file: Option<PathBuf>,
+
+    /// Load plaintext validator_N_sk.ssz files from --hash-sig-key-dir when a validator
+    /// has no keystore. Only meant for local devnets. `=false` overrides the config file
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    allow_plaintext_keys: Option<bool>,
+
+    /// URL of a remote signing service holding the validator keys, used instead of
+    /// --hash-sig-key-dir
+    #[arg(long)]
+    remote_signer_url: Option<String>,
+
+    /// Perform validator duties with zero signatures when neither --hash-sig-key-dir nor
+    /// --remote-signer-url is set. Only for devnets that do not verify signatures.
+    /// `=false` overrides the config file
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    allow_zero_signatures: Option<bool>,
+
+    /// Path: directory for the block and state database. The node resumes from it on restart
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Path: SSZ-encoded finalized State to start from instead of genesis
+    #[arg(long)]
+    checkpoint_state: Option<PathBuf>,
+
+    /// Path: SSZ-encoded signed block, in the --fork block format, whose post-state is
+    /// --checkpoint-state
+    #[arg(long)]
+    checkpoint_block: Option<PathBuf>,
+
+    /// Serve the HTTP API on this address. Setting it or --http-port enables the API
+    #[arg(long)]
+    http_address: Option<IpAddr>,
+
+    /// Port for the HTTP API (default 5052)
+    #[arg(long)]
+    http_port: Option<u16>,
+
+    /// Serve Prometheus metrics on this address. Setting it or --metrics-port enables metrics
+    #[arg(long)]
+    metrics_address: Option<IpAddr>,
+
+    /// Port for the Prometheus metrics endpoint (default 5054)
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// Path: slashing protection interchange JSON to merge into the database before signing
+    #[arg(long)]
+    slashing_protection_import: Option<PathBuf>,
+}
+
+/// Validated node configuration.
+pub struct NodeConfig {
+    pub listen_address: IpAddr,
+    pub port: u16,
+    pub discovery_port: Option<u16>,
+    pub bootnodes: Vec<String>,
+    pub genesis: GenesisConfig,
+    pub fork: String,
+    pub block_version: BlockVersion,
+    /// A random key is generated when unset.
+    pub node_key: Option<Keypair>,
+    pub validator: Option<ValidatorSetup>,
+    pub data_dir: Option<PathBuf>,
+    /// Paths of the checkpoint state and block.
+    pub checkpoint: Option<(PathBuf, PathBuf)>,
+    pub http_address: Option<SocketAddr>,
+    pub metrics_address: Option<SocketAddr>,
+    pub slashing_protection_import: Option<Interchange>,
+}
+
+pub struct ValidatorSetup {
+    pub config: ValidatorConfig,
+    pub key_source: KeySource,
+}
+
+/// Where validator signatures come from.
+pub enum KeySource {
+    Local {
+        keys_dir: PathBuf,
+        password_file: Option<PathBuf>,
+        allow_plaintext: bool,
+    },
+    Remote {
+        url: String,
+    },
+    /// Explicitly allowed with `--allow-zero-signatures`.
+    ZeroSignatures,
+}
+
+impl NodeArgs {
+    /// Merge in `--config` and validate the result.
+    pub fn into_config(self) -> Result<NodeConfig, Box<dyn std::error::Error>> {
+        let args = match self.config.clone() {
+            Some(path) => self.merge(read_config_file(&path)?),
+            None => self,
+        };
+        args.validate()
+    }
+
+    /// Options set in `self` win over those in `file`.
+    fn merge(self, file: NodeArgs) -> NodeArgs {
+        NodeArgs {
+            config: self.config,
+            address: self.address.or(file.address),
+            port: self.port.or(file.port),
+            discovery_port: self.discovery_port.or(file.discovery_port),
+            bootnodes: if self.bootnodes.is_empty() {
+                file.bootnodes
+            } else {
+                self.bootnodes
+            },
+            genesis: self.genesis.or(file.genesis),
+            fork: self.fork.or(file.fork),
+            node_key: self.node_key.or(file.node_key),
+            node_id: self.node_id.or(file.node_id),
+            validator_registry_path: self
+                .validator_registry_path
+                .or(file.validator_registry_path),
+            hash_sig_key_dir: self.hash_sig_key_dir.or(file.hash_sig_key_dir),
+            keystore_password_file: self.keystore_password_file.or(file.keystore_password_file),
+            allow_plaintext_keys: self.allow_plaintext_keys.or(file.allow_plaintext_keys),
+            remote_signer_url: self.remote_signer_url.or(file.remote_signer_url),
+            allow_zero_signatures: self.allow_zero_signatures.or(file.allow_zero_signatures),
+            data_dir: self.data_dir.or(file.data_dir),
+            checkpoint_state: self.checkpoint_state.or(file.checkpoint_state),
+            checkpoint_block: self.checkpoint_block.or(file.checkpoint_block),
+            http_address: self.http_address.or(file.http_address),
+            http_port: self.http_port.or(file.http_port),
+            metrics_address: self.metrics_address.or(file.metrics_address),
+            metrics_port: self.metrics_port.or(file.metrics_port),
+            slashing_protection_import: self
+                .slashing_protection_import
+                .or(file.slashing_protection_import),
+        }
+    }
+
+    fn validate(self) -> Result<NodeConfig, Box<dyn std::error::Error>> {
+        let genesis_path = self.genesis.as_ref().ok_or("--genesis is required")?;
+        let genesis = load_genesis(genesis_path)?;
+
+        let fork = self.fork.unwrap_or_else(|| DEFAULT_FORK.to_string());
+        let block_version = BlockVersion::from_fork(&fork)
+            .ok_or_else(|| format!("--fork {fork}: unknown fork, expected devnet0 or devnet1"))?;
+
+        let node_key = self
+            .node_key
+            .as_deref()
+            .map(|path| {
+                load_node_key(path).map_err(|e| format!("--node-key {}: {e}", path.display()))
+            })
+            .transpose()?;
+
+        let checkpoint = match (self.checkpoint_state, self.checkpoint_block) {
+            (Some(state), Some(block)) => {
+                require_file("--checkpoint-state", &state)?;
+                require_file("--checkpoint-block", &block)?;
+                Some((state, block))
+            }
+            (None, None) => None,
+            _ => {
+                return Err("--checkpoint-state and --checkpoint-block must be set together".into())
+            }
+        };
+
+        let allow_plaintext_keys = self.allow_plaintext_keys.unwrap_or(false);
+        if self.hash_sig_key_dir.is_none()
+            && (self.keystore_password_file.is_some() || allow_plaintext_keys)
+        {
+            return Err(
+                "--keystore-password-file and --allow-plaintext-keys need --hash-sig-key-dir"
+                    .into(),
+            );
+        }
+        let key_source = match (
+            self.hash_sig_key_dir,
+            self.remote_signer_url,
+            self.allow_zero_signatures.unwrap_or(false),
+        ) {
+            (Some(_), Some(_), _) => {
+                return Err(
+                    "--hash-sig-key-dir and --remote-signer-url are mutually exclusive".into(),
+                )
+            }
+            (Some(keys_dir), None, _) => {
+                if !keys_dir.is_dir() {
+                    return Err(format!(
+                        "--hash-sig-key-dir {}: directory not found",
+                        keys_dir.display()
+                    )
+                    .into());
+                }
+                if let Some(path) = &self.keystore_password_file {
+                    require_file("--keystore-password-file", path)?;
+                }
+                Some(KeySource::Local {
+                    keys_dir,
+                    password_file: self.keystore_password_file,
+                    allow_plaintext: allow_plaintext_keys,
+                })
+            }
+            (None, Some(url), _) => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(
+                        format!("--remote-signer-url {url}: expected an http(s) URL").into(),
+                    );
+                }
+                Some(KeySource::Remote { url })
+            }
+            (None, None, true) => Some(KeySource::ZeroSignatures),
+            (None, None, false) => None,
+        };
+
+        let validator = match (self.node_id, self.validator_registry_path) {
+            (Some(node_id), Some(registry_path)) => {
+                let config =
+                    ValidatorConfig::load_from_file(&registry_path, &node_id).map_err(|e| {
+                        format!("--validator-registry-path {}: {e}", registry_path.display())
+                    })?;
+                let key_source = key_source.ok_or(
+                    "Validator duties need --hash-sig-key-dir or --remote-signer-url \
+                     (or --allow-zero-signatures on devnets without signature checks)",
+                )?;
+                check_validator_setup(&config, &key_source, &genesis)?;
+                Some(ValidatorSetup { config, key_source })
+            }
+            (None, None) => {
+                if key_source.is_some() {
+                    return Err("Validator keys are configured but --node-id and \
+                         --validator-registry-path are not"
+                        .into());
+                }
+                None
+            }
+            _ => return Err("--node-id and --validator-registry-path must be set together".into()),
+        };
+
+        let slashing_protection_import = self
+            .slashing_protection_import
+            .as_deref()
+            .map(|path| {
+                Interchange::from_json_file(path)
+                    .map_err(|e| format!("--slashing-protection-import {}: {e}", path.display()))
+            })
+            .transpose()?;
+
+        let http_address = (self.http_address.is_some() || self.http_port.is_some()).then(|| {
+            SocketAddr::new(
+                self.http_address.unwrap_or(DEFAULT_ADDRESS),
+                self.http_port.unwrap_or(DEFAULT_HTTP_PORT),
+            )
+        });
+        let metrics_address =
+            (self.metrics_address.is_some() || self.metrics_port.is_some()).then(|| {
+                SocketAddr::new(
+                    self.metrics_address.unwrap_or(DEFAULT_ADDRESS),
+                    self.metrics_port.unwrap_or(DEFAULT_METRICS_PORT),
+                )
+            });
+
+        Ok(NodeConfig {
+            listen_address: self.address.unwrap_or(DEFAULT_ADDRESS),
+            port: self.port.unwrap_or(DEFAULT_PORT),
+            discovery_port: self.discovery_port,
+            bootnodes: self.bootnodes,
+            genesis,
+            fork,
+            block_version,
+            node_key,
+            validator,
+            data_dir: self.data_dir,
+            checkpoint,
+            http_address,
+            metrics_address,
+            slashing_protection_import,
+        })
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<NodeArgs, Box<dyn std::error::Error>> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("--config {}: {e}", path.display()))?;
+
+    let args = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string()),
+        Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+        _ => Err("expected a .toml, .yaml or .yml file".to_string()),
+    };
+    args.map_err(|e| format!("--config {}: {e}", path.display()).into())
+}
+
+/// The genesis config, with every validator public key checked.
+pub fn load_genesis(path: &Path) -> Result<GenesisConfig, Box<dyn std::error::Error>> {
+    let genesis = GenesisConfig::load_from_file(path)
+        .map_err(|e| format!("--genesis {}: {e}", path.display()))?;
+
+    if genesis.genesis_validators.is_empty() {
+        return Err(format!("--genesis {}: no genesis validators", path.display()).into());
+    }
+    if genesis.genesis_validators.len() as u64 != genesis.validator_count {
+        return Err(format!(
+            "--genesis {}: VALIDATOR_COUNT is {} but {} GENESIS_VALIDATORS are listed",
+            path.display(),
+            genesis.validator_count,
+            genesis.genesis_validators.len()
+        )
+        .into());
+    }
+    for (index, pubkey) in genesis.genesis_validators.iter().enumerate() {
+        containers::validator::BlsPublicKey::from_hex(pubkey).map_err(|e| {
+            format!(
+                "--genesis {}: invalid public key of validator {index}: {e}",
+                path.display()
+            )
+        })?;
+    }
+
+    Ok(genesis)
+}
+
+pub fn load_node_key(path: &Path) -> Result<Keypair, Box<dyn std::error::Error>> {
+    let hex_str = fs::read_to_string(path)?.trim().to_string();
+    let bytes = hex::decode(&hex_str)?;
+    let secret = libp2p_identity::secp256k1::SecretKey::try_from_bytes(bytes)?;
+    let keypair = libp2p_identity::secp256k1::Keypair::from(secret);
+    Ok(Keypair::from(keypair))
+}
+
+fn require_file(option: &str, path: &Path) -> Result<(), String> {
+    if path.is_file() {
+        Ok(())
+    } else {
+        Err(format!("{option} {}: file not found", path.display()))
+    }
+}
+
+/// Our validators must exist at genesis and, with local keys, have a key file.
+fn check_validator_setup(
+    config: &ValidatorConfig,
+    key_source: &KeySource,
+    genesis: &GenesisConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if config.validator_indices.is_empty() {
+        return Err(format!(
+            "Node '{}' has no validators in the registry",
+            config.node_id
+        )
+        .into());
+    }
+    if let Some(index) = config
+        .validator_indices
+        .iter()
+        .find(|&&index| index >= genesis.validator_count)
+    {
+        return Err(format!(
+            "Validator {index} of node '{}' is not in the genesis config, which has {} validators",
+            config.node_id, genesis.validator_count
+        )
+        .into());
+    }
+
+    if let KeySource::Local {
+        keys_dir,
+        allow_plaintext,
+        ..
+    } = key_source
+    {
+        let key_manager = KeyManager::new(keys_dir)?;
+        for &index in &config.validator_indices {
+            let plaintext = *allow_plaintext && key_manager.secret_key_path(index).is_file();
+            if !key_manager.has_keystore(index) && !plaintext {
+                return Err(format!(
+                    "--hash-sig-key-dir {}: no key for validator {index} ({}{})",
+                    keys_dir.display(),
+                    key_manager.keystore_path(index).display(),
+                    if *allow_plaintext {
+                        format!(" or {}", key_manager.secret_key_path(index).display())
+                    } else {
+                        String::new()
+                    }
+                )
+                .into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    use crate::{Cli, Command};
+
+    fn node_args(args: &[&str]) -> NodeArgs {
+        let args = ["lean_client", "node"].iter().chain(args);
+        match Cli::try_parse_from(args).unwrap().command {
+            Command::Node(node_args) => node_args,
+            command => panic!("parsed {command:?}"),
+        }
+    }
+
+    /// A directory with a one-validator genesis config and a registry assigning
+    /// validator 0 to node_0.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lean-node-config-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("keys")).unwrap();
+
+        GenesisConfig {
+            genesis_time: 1_000,
+            active_epoch: 18,
+            validator_count: 1,
+            genesis_validators: vec![format!("0x{}", "11".repeat(52))],
+        }
+        .write_to_file(dir.join("config.yaml"))
+        .unwrap();
+        fs::write(dir.join("validators.yaml"), "node_0: [0]\n").unwrap();
+        dir
+    }
+
+    fn path_arg(dir: &Path, file: &str) -> String {
+        dir.join(file).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_flags_override_config_file() {
+        let dir = test_dir("override");
+        let config_path = dir.join("node.toml");
+        fs::write(
+            &config_path,
+            "port = 9000\nfork = \"devnet1\"\nallow-zero-signatures = true\n\
+             allow-plaintext-keys = true\nbootnodes = [\"/ip4/127.0.0.1/udp/9001/quic-v1\"]\n",
+        )
+        .unwrap();
+        let config_arg = config_path.to_string_lossy().into_owned();
+
+        let args = node_args(&["--config", config_arg.as_str(), "--port", "9100"])
+            .merge(read_config_file(&config_path).unwrap());
+        assert_eq!(args.port, Some(9100));
+        assert_eq!(args.fork.as_deref(), Some("devnet1"));
+        assert_eq!(args.allow_zero_signatures, Some(true));
+        assert_eq!(args.bootnodes, vec!["/ip4/127.0.0.1/udp/9001/quic-v1"]);
+
+        // An explicit false on the command line wins over true in the file
+        let args = node_args(&[
+            "--config",
+            config_arg.as_str(),
+            "--allow-zero-signatures=false",
+            "--allow-plaintext-keys=false",
+        ])
+        .merge(read_config_file(&config_path).unwrap());
+        assert_eq!(args.allow_zero_signatures, Some(false));
+        assert_eq!(args.allow_plaintext_keys, Some(false));
+
+        assert_eq!(
+            node_args(&["--allow-plaintext-keys"]).allow_plaintext_keys,
+            Some(true)
+        );
+        assert_eq!(node_args(&[]).allow_plaintext_keys, None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_unknown_config_file_keys_are_rejected() {
+        let dir = test_dir("unknown");
+
+        for (file, contents) in [
+            ("node.toml", "hash-sig-keys-dir = \"./keys\"\n"),
+            ("node.yaml", "node_id: node_0\n"),
+        ] {
+            let path = dir.join(file);
+            fs::write(&path, contents).unwrap();
+            let err = read_config_file(&path).unwrap_err().to_string();
+            assert!(err.contains("unknown field"), "{file}: {err}");
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_half_set_pairs_are_rejected() {
+        let dir = test_dir("pairs");
+        let genesis = path_arg(&dir, "config.yaml");
+        let registry = path_arg(&dir, "validators.yaml");
+        fs::write(dir.join("state.ssz"), b"").unwrap();
+        let state = path_arg(&dir, "state.ssz");
+
+        for (args, expected) in [
+            (
+                vec!["--checkpoint-state", state.as_str()],
+                "--checkpoint-state and --checkpoint-block must be set together",
+            ),
+            (
+                vec!["--node-id", "node_0"],
+                "--node-id and --validator-registry-path must be set together",
+            ),
+            (
+                vec!["--validator-registry-path", registry.as_str()],
+                "--node-id and --validator-registry-path must be set together",
+            ),
+        ] {
+            let args = [vec!["--genesis", genesis.as_str()], args].concat();
+            let err = node_args(&args).into_config().err().unwrap().to_string();
+            assert_eq!(err, expected, "{args:?}");
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_missing_key_of_assigned_validator_is_rejected() {
+        let dir = test_dir("keys");
+        let genesis = path_arg(&dir, "config.yaml");
+        let registry = path_arg(&dir, "validators.yaml");
+        let keys = path_arg(&dir, "keys");
+        let args = [
+            "--genesis",
+            genesis.as_str(),
+            "--node-id",
+            "node_0",
+            "--validator-registry-path",
+            registry.as_str(),
+            "--hash-sig-key-dir",
+            keys.as_str(),
+            "--allow-plaintext-keys",
+        ];
+
+        let err = node_args(&args).into_config().err().unwrap().to_string();
+        assert!(err.contains("no key for validator 0"), "{err}");
+
+        fs::write(dir.join("keys").join("validator_0_sk.ssz"), [0u8; 8]).unwrap();
+        let config = node_args(&args).into_config().unwrap();
+        assert_eq!(config.validator.unwrap().config.validator_indices, vec![0]);
+
+        // Plaintext keys only count when allowed
+        let err = node_args(&args[..args.len() - 1])
+            .into_config()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("no key for validator 0"), "{err}");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,358 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use containers::{
+    block::{BlockVersion, VersionedSignedBlock},
+    state::State,
+    Root,
+};
+use ssz::{SszReadDefault, SszWrite};
+
+use crate::{ForkChoiceCheckpoints, Storage};
+
+const BLOCKS_DIR: &str = "blocks";
+const BLOCKS_V1_DIR: &str = "blocks_v1";
+const STATES_DIR: &str = "states";
+const CHECKPOINTS_FILE: &str = "checkpoints.ssz";
+const SSZ_EXTENSION: &str = "ssz";
+const TMP_EXTENSION: &str = "tmp";
+
+/// Directory-backed storage with one SSZ file per block and per state.
+///
+/// Layout:
+/// - `<dir>/blocks/<root>.ssz` for [`BlockVersion::V0`] blocks
+/// - `<dir>/blocks_v1/<root>.ssz` for [`BlockVersion::V1`] blocks
+/// - `<dir>/states/<root>.ssz`
+/// - `<dir>/checkpoints.ssz`
+///
+/// Every write goes to a temporary file first and is then renamed into place,
+/// so a crash never leaves a half-written entry behind.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    /// Open the storage at `dir`, creating the directory layout if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+
+        for sub_dir in [BLOCKS_DIR, BLOCKS_V1_DIR, STATES_DIR] {
+            let path = dir.join(sub_dir);
+            fs::create_dir_all(&path)
+                .with_context(|| format!("failed to create storage directory {path:?}"))?;
+        }
+
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn blocks_dir(version: BlockVersion) -> &'static str {
+        match version {
+            BlockVersion::V0 => BLOCKS_DIR,
+            BlockVersion::V1 => BLOCKS_V1_DIR,
+        }
+    }
+
+    fn entry_path(&self, sub_dir: &str, root: Root) -> PathBuf {
+        self.dir
+            .join(sub_dir)
+            .join(format!("{root}.{SSZ_EXTENSION}"))
+    }
+
+    /// The temp file is synced before the rename and the directory after it, so
+    /// the entry survives a power loss once this returns.
+    fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+        let tmp_path = path.with_extension(TMP_EXTENSION);
+        let write_tmp = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&tmp_path)?;
+            file.write_all(bytes)?;
+            file.sync_all()
+        };
+        write_tmp().with_context(|| format!("failed to write {tmp_path:?}"))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to move {tmp_path:?} to {path:?}"))?;
+        Self::sync_parent_dir(path).with_context(|| format!("failed to sync directory of {path:?}"))
+    }
+
+    #[cfg(unix)]
+    fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir)?.sync_all()
+    }
+
+    // Directories cannot be opened as files to sync them outside unix
+    #[cfg(not(unix))]
+    fn sync_parent_dir(_path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("failed to read {path:?}")),
+        }
+    }
+
+    fn remove_optional(path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).with_context(|| format!("failed to remove {path:?}")),
+        }
+    }
+
+    fn list_roots(&self, sub_dir: &str) -> Result<Vec<Root>> {
+        let path = self.dir.join(sub_dir);
+        let mut roots = Vec::new();
+
+        for entry in fs::read_dir(&path).with_context(|| format!("failed to list {path:?}"))? {
+            let entry_path = entry?.path();
+
+            if entry_path.extension().and_then(|ext| ext.to_str()) != Some(SSZ_EXTENSION) {
+                continue;
+            }
+
+            let Some(stem) = entry_path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let root = Root::from_str(stem)
+                .map_err(|err| anyhow!("invalid root in file name {entry_path:?}: {err}"))?;
+            roots.push(root);
+        }
+
+        Ok(roots)
+    }
+
+    fn put<T: SszWrite>(&self, sub_dir: &str, root: Root, value: &T) -> Result<()> {
+        let bytes = value
+            .to_ssz()
+            .map_err(|err| anyhow!("failed to encode {sub_dir} entry {root}: {err:?}"))?;
+        Self::write_atomic(&self.entry_path(sub_dir, root), &bytes)
+    }
+
+    fn get<T: SszReadDefault>(&self, sub_dir: &str, root: Root) -> Result<Option<T>> {
+        let Some(bytes) = Self::read_optional(&self.entry_path(sub_dir, root))? else {
+            return Ok(None);
+        };
+
+        T::from_ssz_default(&bytes)
+            .map(Some)
+            .map_err(|err| anyhow!("failed to decode {sub_dir} entry {root}: {err:?}"))
+    }
+}
+
+impl Storage for FileStorage {
+    fn put_block(&self, root: Root, block: &VersionedSignedBlock) -> Result<()> {
+        let sub_dir = Self::blocks_dir(block.version());
+        let bytes = block
+            .to_ssz()
+            .map_err(|err| anyhow!("failed to encode {sub_dir} entry {root}: {err}"))?;
+        Self::write_atomic(&self.entry_path(sub_dir, root), &bytes)
+    }
+
+    fn get_block(&self, root: Root) -> Result<Option<VersionedSignedBlock>> {
+        for version in [BlockVersion::V0, BlockVersion::V1] {
+            let sub_dir = Self::blocks_dir(version);
+            let Some(bytes) = Self::read_optional(&self.entry_path(sub_dir, root))? else {
+                continue;
+            };
+
+            return VersionedSignedBlock::from_ssz(version, &bytes)
+                .map(Some)
+                .map_err(|err| anyhow!("failed to decode {sub_dir} entry {root}: {err}"));
+        }
+
+        Ok(None)
+    }
+
+    fn delete_block(&self, root: Root) -> Result<()> {
+        Self::remove_optional(&self.entry_path(BLOCKS_DIR, root))?;
+        Self::remove_optional(&self.entry_path(BLOCKS_V1_DIR, root))
+    }
+
+    fn block_roots(&self) -> Result<Vec<Root>> {
+        let mut roots = self.list_roots(BLOCKS_DIR)?;
+        roots.extend(self.list_roots(BLOCKS_V1_DIR)?);
+        Ok(roots)
+    }
+
+    fn put_state(&self, root: Root, state: &State) -> Result<()> {
+        self.put(STATES_DIR, root, state)
+    }
+
+    fn get_state(&self, root: Root) -> Result<Option<State>> {
+        self.get(STATES_DIR, root)
+    }
+
+    fn delete_state(&self, root: Root) -> Result<()> {
+        Self::remove_optional(&self.entry_path(STATES_DIR, root))
+    }
+
+    fn state_roots(&self) -> Result<Vec<Root>> {
+        self.list_roots(STATES_DIR)
+    }
+
+    fn put_checkpoints(&self, checkpoints: &ForkChoiceCheckpoints) -> Result<()> {
+        let bytes = checkpoints
+            .to_ssz()
+            .map_err(|err| anyhow!("failed to encode checkpoints: {err:?}"))?;
+        Self::write_atomic(&self.dir.join(CHECKPOINTS_FILE), &bytes)
+    }
+
+    fn get_checkpoints(&self) -> Result<Option<ForkChoiceCheckpoints>> {
+        let Some(bytes) = Self::read_optional(&self.dir.join(CHECKPOINTS_FILE))? else {
+            return Ok(None);
+        };
+
+        ForkChoiceCheckpoints::from_ssz_default(&bytes)
+            .map(Some)
+            .map_err(|err| anyhow!("failed to decode checkpoints: {err:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use containers::{
+        block::{
+            AggregatedBlock, AggregatedBlockWithAttestation, Block, BlockWithAttestation,
+            SignedAggregatedBlockWithAttestation, SignedBlockWithAttestation,
+        },
+        checkpoint::Checkpoint,
+        Bytes32, Slot, Uint64, ValidatorIndex,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lean-storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn sample_block(slot: u64) -> VersionedSignedBlock {
+        SignedBlockWithAttestation {
+            message: BlockWithAttestation {
+                block: Block {
+                    slot: Slot(slot),
+                    proposer_index: ValidatorIndex(slot),
+                    ..Block::default()
+                },
+                proposer_attestation: Default::default(),
+            },
+            signature: Default::default(),
+        }
+        .into()
+    }
+
+    fn sample_aggregated_block(slot: u64) -> VersionedSignedBlock {
+        SignedAggregatedBlockWithAttestation {
+            message: AggregatedBlockWithAttestation {
+                block: AggregatedBlock {
+                    slot: Slot(slot),
+                    proposer_index: ValidatorIndex(slot),
+                    ..AggregatedBlock::default()
+                },
+                proposer_attestation: Default::default(),
+            },
+            signature: Default::default(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_block_and_state_round_trip() {
+        let dir = temp_dir("round-trip");
+        let storage = FileStorage::open(&dir).unwrap();
+
+        let block = sample_block(3);
+        let root = Bytes32(ssz::H256::repeat_byte(0xab));
+        let state = State::generate_genesis(Uint64(1000), Uint64(4));
+
+        storage.put_block(root, &block).unwrap();
+        storage.put_state(root, &state).unwrap();
+
+        assert_eq!(storage.get_block(root).unwrap(), Some(block));
+        assert_eq!(storage.get_state(root).unwrap(), Some(state));
+        assert_eq!(storage.block_roots().unwrap(), vec![root]);
+        assert_eq!(storage.state_roots().unwrap(), vec![root]);
+
+        storage.delete_block(root).unwrap();
+        storage.delete_state(root).unwrap();
+
+        assert_eq!(storage.get_block(root).unwrap(), None);
+        assert_eq!(storage.get_state(root).unwrap(), None);
+        assert!(storage.block_roots().unwrap().is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_blocks_of_both_versions() {
+        let dir = temp_dir("versions");
+        let storage = FileStorage::open(&dir).unwrap();
+
+        let v0_root = Bytes32(ssz::H256::repeat_byte(1));
+        let v1_root = Bytes32(ssz::H256::repeat_byte(2));
+        storage.put_block(v0_root, &sample_block(1)).unwrap();
+        storage
+            .put_block(v1_root, &sample_aggregated_block(2))
+            .unwrap();
+
+        assert_eq!(storage.get_block(v0_root).unwrap(), Some(sample_block(1)));
+        assert_eq!(
+            storage.get_block(v1_root).unwrap(),
+            Some(sample_aggregated_block(2))
+        );
+
+        let mut roots = storage.block_roots().unwrap();
+        roots.sort();
+        assert_eq!(roots, vec![v0_root, v1_root]);
+
+        storage.delete_block(v1_root).unwrap();
+        assert_eq!(storage.get_block(v1_root).unwrap(), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_checkpoints_survive_reopen() {
+        let dir = temp_dir("checkpoints");
+        let checkpoints = ForkChoiceCheckpoints {
+            head: Checkpoint {
+                root: Bytes32(ssz::H256::repeat_byte(3)),
+                slot: Slot(9),
+            },
+            latest_justified: Checkpoint {
+                root: Bytes32(ssz::H256::repeat_byte(2)),
+                slot: Slot(6),
+            },
+            latest_finalized: Checkpoint {
+                root: Bytes32(ssz::H256::repeat_byte(1)),
+                slot: Slot(4),
+            },
+        };
+
+        {
+            let storage = FileStorage::open(&dir).unwrap();
+            assert_eq!(storage.get_checkpoints().unwrap(), None);
+            storage.put_checkpoints(&checkpoints).unwrap();
+        }
+
+        let storage = FileStorage::open(&dir).unwrap();
+        assert_eq!(storage.get_checkpoints().unwrap(), Some(checkpoints));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,54 @@
+pub mod file;
+pub mod memory;
+
+pub use file::FileStorage;
+pub use memory::MemoryStorage;
+
+use anyhow::Result;
+use containers::{block::VersionedSignedBlock, checkpoint::Checkpoint, state::State, Root};
+use ssz_derive::Ssz;
+use std::fmt::Debug;
+
+/// Fork-choice checkpoints persisted next to blocks and states.
+///
+/// Together with the stored blocks and states this is enough to rebuild a
+/// `Store` on restart without replaying the chain from genesis.
+#[derive(Clone, Debug, PartialEq, Eq, Ssz, Default)]
+pub struct ForkChoiceCheckpoints {
+    /// Head block selected by fork choice when the checkpoints were written.
+    pub head: Checkpoint,
+    /// Highest justified checkpoint known to the store.
+    pub latest_justified: Checkpoint,
+    /// Highest finalized checkpoint known to the store.
+    pub latest_finalized: Checkpoint,
+}
+
+/// Key-value storage for blocks, states and fork-choice checkpoints.
+///
+/// Blocks and states are keyed by block root and stored as SSZ. Implementations
+/// must be safe to share between tasks; writes are expected to be durable once
+/// the call returns.
+pub trait Storage: Debug + Send + Sync {
+    fn put_block(&self, root: Root, block: &VersionedSignedBlock) -> Result<()>;
+
+    fn get_block(&self, root: Root) -> Result<Option<VersionedSignedBlock>>;
+
+    fn delete_block(&self, root: Root) -> Result<()>;
+
+    /// Roots of all stored blocks, in no particular order.
+    fn block_roots(&self) -> Result<Vec<Root>>;
+
+    fn put_state(&self, root: Root, state: &State) -> Result<()>;
+
+    fn get_state(&self, root: Root) -> Result<Option<State>>;
+
+    fn delete_state(&self, root: Root) -> Result<()>;
+
+    /// Roots of all blocks that have a stored post-state, in no particular order.
+    fn state_roots(&self) -> Result<Vec<Root>>;
+
+    fn put_checkpoints(&self, checkpoints: &ForkChoiceCheckpoints) -> Result<()>;
+
+    /// Returns `None` if nothing has been persisted yet.
+    fn get_checkpoints(&self) -> Result<Option<ForkChoiceCheckpoints>>;
+}
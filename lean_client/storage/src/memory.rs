@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Result};
+use containers::{block::VersionedSignedBlock, state::State, Root};
+
+use crate::{ForkChoiceCheckpoints, Storage};
+
+/// Volatile storage keeping everything in process memory.
+///
+/// Useful for tests and for nodes started without a data directory.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    blocks: RwLock<HashMap<Root, VersionedSignedBlock>>,
+    states: RwLock<HashMap<Root, State>>,
+    checkpoints: RwLock<Option<ForkChoiceCheckpoints>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn poisoned<T>(_: T) -> anyhow::Error {
+    anyhow!("memory storage lock poisoned")
+}
+
+impl Storage for MemoryStorage {
+    fn put_block(&self, root: Root, block: &VersionedSignedBlock) -> Result<()> {
+        self.blocks
+            .write()
+            .map_err(poisoned)?
+            .insert(root, block.clone());
+        Ok(())
+    }
+
+    fn get_block(&self, root: Root) -> Result<Option<VersionedSignedBlock>> {
+        Ok(self.blocks.read().map_err(poisoned)?.get(&root).cloned())
+    }
+
+    fn delete_block(&self, root: Root) -> Result<()> {
+        self.blocks.write().map_err(poisoned)?.remove(&root);
+        Ok(())
+    }
+
+    fn block_roots(&self) -> Result<Vec<Root>> {
+        Ok(self
+            .blocks
+            .read()
+            .map_err(poisoned)?
+            .keys()
+            .copied()
+            .collect())
+    }
+
+    fn put_state(&self, root: Root, state: &State) -> Result<()> {
+        self.states
+            .write()
+            .map_err(poisoned)?
+            .insert(root, state.clone());
+        Ok(())
+    }
+
+    fn get_state(&self, root: Root) -> Result<Option<State>> {
+        Ok(self.states.read().map_err(poisoned)?.get(&root).cloned())
+    }
+
+    fn delete_state(&self, root: Root) -> Result<()> {
+        self.states.write().map_err(poisoned)?.remove(&root);
+        Ok(())
+    }
+
+    fn state_roots(&self) -> Result<Vec<Root>> {
+        Ok(self
+            .states
+            .read()
+            .map_err(poisoned)?
+            .keys()
+            .copied()
+            .collect())
+    }
+
+    fn put_checkpoints(&self, checkpoints: &ForkChoiceCheckpoints) -> Result<()> {
+        *self.checkpoints.write().map_err(poisoned)? = Some(checkpoints.clone());
+        Ok(())
+    }
+
+    fn get_checkpoints(&self) -> Result<Option<ForkChoiceCheckpoints>> {
+        Ok(self.checkpoints.read().map_err(poisoned)?.clone())
+    }
+}
@@ -1,26 +1,91 @@
-use containers::attestation::U3112;
-use containers::ssz::ByteVector;
+use crate::keystore::Keystore;
+use crate::slashing_protection::write_atomic;
 use containers::Signature;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use tracing::info;
+use std::sync::{Mutex, MutexGuard};
+use tracing::{info, warn};
 
 #[cfg(feature = "xmss-signing")]
-use leansig::signature::generalized_xmss::instantiations_poseidon_top_level::lifetime_2_to_the_32::hashing_optimized::SIGTopLevelTargetSumLifetime32Dim64Base8;
-#[cfg(feature = "xmss-signing")]
-use leansig::signature::SignatureScheme;
-#[cfg(feature = "xmss-signing")]
-use leansig::serialization::Serializable;
+use containers::crypto;
 
-#[cfg(not(feature = "xmss-signing"))]
-use tracing::warn;
+/// Epochs covered by a key of the 2^32 lifetime scheme in `containers::crypto`.
+pub const MAX_KEY_LIFETIME: u64 = 1 << 32;
+
+/// Warn once a key has fewer signatures left than this, about a day of 4 second slots.
+pub const KEY_EXHAUSTION_WARNING: u64 = 21_600;
+
+/// Which epochs an XMSS key may sign at, and how far it has signed.
+///
+/// Each epoch is a one-time signature, so signing twice at the same epoch can
+/// leak the key. Stored next to the secret key as `validator_N_key_state.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    pub activation_epoch: u64,
+    pub num_active_epochs: u64,
+    /// Every epoch up to and including this one counts as used.
+    pub last_signed_epoch: Option<u64>,
+}
+
+impl KeyMetadata {
+    pub fn new(activation_epoch: u64, num_active_epochs: u64) -> Self {
+        Self {
+            activation_epoch,
+            num_active_epochs,
+            last_signed_epoch: None,
+        }
+    }
+
+    /// First epoch after the key's active range.
+    pub fn end_epoch(&self) -> u64 {
+        self.activation_epoch.saturating_add(self.num_active_epochs)
+    }
+
+    /// Earliest epoch the key can still sign at.
+    pub fn next_epoch(&self) -> u64 {
+        self.last_signed_epoch
+            .map_or(self.activation_epoch, |epoch| {
+                (epoch + 1).max(self.activation_epoch)
+            })
+    }
+
+    pub fn remaining_signatures(&self) -> u64 {
+        self.end_epoch().saturating_sub(self.next_epoch())
+    }
+
+    pub fn check_epoch(&self, epoch: u64) -> Result<(), String> {
+        if epoch < self.activation_epoch || epoch >= self.end_epoch() {
+            return Err(format!(
+                "Epoch {} is outside the key's active range {}..{}",
+                epoch,
+                self.activation_epoch,
+                self.end_epoch()
+            ));
+        }
+        if let Some(last_signed_epoch) = self.last_signed_epoch.filter(|&last| epoch <= last) {
+            return Err(format!(
+                "Epoch {} is already used, the key last signed at epoch {}",
+                epoch, last_signed_epoch
+            ));
+        }
+        Ok(())
+    }
+}
 
 /// Manages XMSS secret keys for validators
 pub struct KeyManager {
     /// Map of validator index to secret key bytes
     keys: HashMap<u64, Vec<u8>>,
+    /// Epoch usage of each loaded key. Held while signing so two signatures
+    /// can never reserve the same epoch.
+    metadata: Mutex<HashMap<u64, KeyMetadata>>,
     /// Path to keys directory
     keys_dir: PathBuf,
+    /// Decrypts `validator_N_keystore.json` files.
+    password: Option<String>,
+    /// Whether plaintext `validator_N_sk.ssz` files may be loaded. Only for local devnets.
+    allow_plaintext: bool,
 }
 
 impl KeyManager {
@@ -36,32 +101,147 @@ impl KeyManager {
 
         Ok(KeyManager {
             keys: HashMap::new(),
+            metadata: Mutex::new(HashMap::new()),
             keys_dir,
+            password: None,
+            allow_plaintext: false,
         })
     }
 
-    /// Load a secret key for a specific validator index
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn with_plaintext_keys(mut self, allow_plaintext: bool) -> Self {
+        self.allow_plaintext = allow_plaintext;
+        self
+    }
+
+    pub fn keystore_path(&self, validator_index: u64) -> PathBuf {
+        self.keys_dir
+            .join(format!("validator_{}_keystore.json", validator_index))
+    }
+
+    pub fn secret_key_path(&self, validator_index: u64) -> PathBuf {
+        self.keys_dir
+            .join(format!("validator_{}_sk.ssz", validator_index))
+    }
+
+    pub fn has_keystore(&self, validator_index: u64) -> bool {
+        self.keystore_path(validator_index).exists()
+    }
+
+    /// Load a secret key for a specific validator index, from its keystore or,
+    /// if allowed, from a plaintext file.
     pub fn load_key(&mut self, validator_index: u64) -> Result<(), Box<dyn std::error::Error>> {
-        let sk_path = self
-            .keys_dir
-            .join(format!("validator_{}_sk.ssz", validator_index));
+        let keystore_path = self.keystore_path(validator_index);
+        let sk_path = self.secret_key_path(validator_index);
 
-        if !sk_path.exists() {
-            return Err(format!("Secret key file not found: {:?}", sk_path).into());
-        }
+        let key_bytes = if keystore_path.exists() {
+            let password = self
+                .password
+                .as_deref()
+                .ok_or_else(|| format!("Keystore {:?} needs a password", keystore_path))?;
+            Keystore::from_json_file(&keystore_path)?.decrypt(password)?
+        } else if sk_path.exists() {
+            if !self.allow_plaintext {
+                return Err(format!(
+                    "Refusing to load plaintext secret key {:?}, import it into a keystore or allow plaintext keys",
+                    sk_path
+                )
+                .into());
+            }
+            std::fs::read(&sk_path)?
+        } else {
+            return Err(format!(
+                "Neither keystore {:?} nor secret key file {:?} found",
+                keystore_path, sk_path
+            )
+            .into());
+        };
 
-        let key_bytes = std::fs::read(&sk_path)?;
+        // Keys without a state file have never signed through this client. Their
+        // active range is read from the key itself, never guessed.
+        let metadata_path = self.metadata_path(validator_index);
+        let metadata = if metadata_path.exists() {
+            serde_json::from_slice(&std::fs::read(&metadata_path)?)
+                .map_err(|e| format!("Invalid key state file {:?}: {}", metadata_path, e))?
+        } else {
+            let metadata = initial_metadata(&key_bytes)
+                .map_err(|e| format!("Key state file {:?} not found and {}", metadata_path, e))?;
+            self.write_metadata(validator_index, &metadata)?;
+            metadata
+        };
 
         info!(
             validator = validator_index,
             size = key_bytes.len(),
+            next_epoch = metadata.next_epoch(),
+            remaining_signatures = metadata.remaining_signatures(),
             "Loaded secret key"
         );
+        report_remaining_signatures(validator_index, &metadata);
 
         self.keys.insert(validator_index, key_bytes);
+        self.lock_metadata().insert(validator_index, metadata);
+        Ok(())
+    }
+
+    /// Persist the key state file for `validator_index`, synced to disk before returning.
+    pub fn write_metadata(
+        &self,
+        validator_index: u64,
+        metadata: &KeyMetadata,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.metadata_path(validator_index);
+        write_atomic(&path, &serde_json::to_vec_pretty(metadata)?)?;
+        Ok(())
+    }
+
+    pub fn metadata(&self, validator_index: u64) -> Option<KeyMetadata> {
+        self.lock_metadata().get(&validator_index).copied()
+    }
+
+    /// Mark `epoch` as used by `validator_index`, on disk first: a crash after
+    /// this can only skip an epoch, never reuse it.
+    fn reserve_epoch(
+        &self,
+        validator_index: u64,
+        epoch: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut metadata = self.lock_metadata();
+        let key_metadata = metadata
+            .get_mut(&validator_index)
+            .ok_or_else(|| format!("No key loaded for validator {}", validator_index))?;
+
+        key_metadata
+            .check_epoch(epoch)
+            .map_err(|e| format!("Refusing to sign for validator {}: {}", validator_index, e))?;
+
+        let used = KeyMetadata {
+            last_signed_epoch: Some(epoch),
+            ..*key_metadata
+        };
+        self.write_metadata(validator_index, &used)?;
+        *key_metadata = used;
+
+        report_remaining_signatures(validator_index, &used);
         Ok(())
     }
 
+    /// Key state file of `validator_index`, see [`KeyMetadata`].
+    pub fn metadata_path(&self, validator_index: u64) -> PathBuf {
+        self.keys_dir
+            .join(format!("validator_{}_key_state.json", validator_index))
+    }
+
+    fn lock_metadata(&self) -> MutexGuard<'_, HashMap<u64, KeyMetadata>> {
+        self.metadata
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     /// Sign a message with the validator's secret key
     pub fn sign(
         &self,
@@ -69,6 +249,8 @@ impl KeyManager {
         epoch: u32,
         message: &[u8; 32],
     ) -> Result<Signature, Box<dyn std::error::Error>> {
+        self.reserve_epoch(validator_index, epoch.into())?;
+
         #[cfg(feature = "xmss-signing")]
         {
             let key_bytes = self
@@ -76,34 +258,9 @@ impl KeyManager {
                 .get(&validator_index)
                 .ok_or_else(|| format!("No key loaded for validator {}", validator_index))?;
 
-            type SecretKey =
-                <SIGTopLevelTargetSumLifetime32Dim64Base8 as SignatureScheme>::SecretKey;
-
-            let secret_key = SecretKey::from_bytes(key_bytes)
-                .map_err(|e| format!("Failed to deserialize secret key: {:?}", e))?;
+            let secret_key = crypto::secret_key_from_bytes(key_bytes)?;
 
-            let leansig_signature =
-                SIGTopLevelTargetSumLifetime32Dim64Base8::sign(&secret_key, epoch, message)
-                    .map_err(|e| format!("Failed to sign message: {:?}", e))?;
-
-            let sig_bytes = leansig_signature.to_bytes();
-
-            if sig_bytes.len() != 3112 {
-                return Err(format!(
-                    "Invalid signature size: expected 3112, got {}",
-                    sig_bytes.len()
-                )
-                .into());
-            }
-
-            // Convert to ByteVector<U3112> using unsafe pointer copy (same pattern as BlsPublicKey)
-            let mut byte_vec: ByteVector<U3112> = ByteVector::default();
-            unsafe {
-                let dest = &mut byte_vec as *mut ByteVector<U3112> as *mut u8;
-                std::ptr::copy_nonoverlapping(sig_bytes.as_ptr(), dest, 3112);
-            }
-
-            Ok(byte_vec)
+            Ok(crypto::sign(&secret_key, epoch, message)?)
         }
 
         #[cfg(not(feature = "xmss-signing"))]
@@ -123,6 +280,53 @@ impl KeyManager {
     }
 }
 
+fn report_remaining_signatures(validator_index: u64, metadata: &KeyMetadata) {
+    let remaining = metadata.remaining_signatures();
+    metrics::KEY_REMAINING_SIGNATURES
+        .with_label_values(&[&validator_index.to_string()])
+        .set(remaining.try_into().unwrap_or(i64::MAX));
+
+    // Once per hundred signatures, or it would be every slot
+    if remaining < KEY_EXHAUSTION_WARNING && remaining % 100 == 0 {
+        warn!(
+            validator = validator_index,
+            remaining_signatures = remaining,
+            end_epoch = metadata.end_epoch(),
+            "XMSS key is close to exhaustion, rotate it"
+        );
+    }
+}
+
+/// State of a key that has never signed, covering the epochs it was generated for.
+#[cfg(feature = "xmss-signing")]
+fn initial_metadata(key_bytes: &[u8]) -> Result<KeyMetadata, String> {
+    let secret_key = crypto::secret_key_from_bytes(key_bytes)?;
+    let (activation_epoch, num_active_epochs) = crypto::activation_interval(&secret_key);
+    Ok(KeyMetadata::new(activation_epoch, num_active_epochs))
+}
+
+#[cfg(not(feature = "xmss-signing"))]
+fn initial_metadata(_key_bytes: &[u8]) -> Result<KeyMetadata, String> {
+    Err("reading the key's active epochs needs the xmss-signing feature".to_string())
+}
+
+/// Write key material readable by the owner only. The mode is also set on an
+/// existing file, which `OpenOptions::mode` leaves alone.
+pub fn write_secret_file(path: impl AsRef<Path>, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,4 +337,140 @@ mod tests {
         let result = KeyManager::new("/nonexistent/path");
         assert!(result.is_err());
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_secret_files_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("lean-secret-file-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("validator_0_sk.ssz");
+
+        // An existing world-readable file is tightened too
+        std::fs::write(&path, b"old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_secret_file(&path, b"secret").unwrap();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::read(&path).unwrap(), b"secret");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_key_metadata_epochs() {
+        let mut metadata = KeyMetadata::new(10, 5);
+        assert_eq!(metadata.remaining_signatures(), 5);
+
+        assert!(metadata.check_epoch(9).is_err());
+        assert!(metadata.check_epoch(15).is_err());
+        assert!(metadata.check_epoch(12).is_ok());
+
+        metadata.last_signed_epoch = Some(12);
+        assert!(metadata.check_epoch(12).is_err());
+        assert!(metadata.check_epoch(11).is_err());
+        assert!(metadata.check_epoch(13).is_ok());
+        assert_eq!(metadata.remaining_signatures(), 2);
+
+        metadata.last_signed_epoch = Some(14);
+        assert_eq!(metadata.remaining_signatures(), 0);
+    }
+
+    #[test]
+    fn test_used_epochs_survive_reload() {
+        let dir = std::env::temp_dir().join(format!("lean-keys-reload-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("validator_1_sk.ssz"), [0u8; 8]).unwrap();
+
+        let mut key_manager = KeyManager::new(&dir).unwrap().with_plaintext_keys(true);
+        let metadata = KeyMetadata::new(0, MAX_KEY_LIFETIME);
+        key_manager.write_metadata(1, &metadata).unwrap();
+        key_manager.load_key(1).unwrap();
+        assert_eq!(key_manager.metadata(1), Some(metadata));
+
+        key_manager.reserve_epoch(1, 5).unwrap();
+        assert!(key_manager.reserve_epoch(1, 5).is_err());
+        assert!(key_manager.reserve_epoch(2, 6).is_err());
+
+        let mut key_manager = KeyManager::new(&dir).unwrap().with_plaintext_keys(true);
+        key_manager.load_key(1).unwrap();
+        assert_eq!(key_manager.metadata(1).unwrap().last_signed_epoch, Some(5));
+        assert!(key_manager.reserve_epoch(1, 5).is_err());
+        key_manager.reserve_epoch(1, 6).unwrap();
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_keystore_required_unless_plaintext_allowed() {
+        let dir = std::env::temp_dir().join(format!("lean-keys-keystore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("validator_1_sk.ssz"), [0u8; 8]).unwrap();
+
+        let mut key_manager = KeyManager::new(&dir).unwrap().with_password("password");
+        for index in [1, 2] {
+            key_manager
+                .write_metadata(index, &KeyMetadata::new(0, 10))
+                .unwrap();
+        }
+        assert!(key_manager.load_key(1).is_err());
+
+        let keystore = Keystore::encrypt(
+            &[1, 2, 3],
+            "password",
+            crate::keystore::Kdf::Pbkdf2 { c: 2 },
+            Some(2),
+            None,
+        )
+        .unwrap();
+        keystore
+            .write_json_file(key_manager.keystore_path(2))
+            .unwrap();
+        key_manager.load_key(2).unwrap();
+        assert_eq!(key_manager.keys.get(&2), Some(&vec![1, 2, 3]));
+
+        let mut key_manager = KeyManager::new(&dir).unwrap().with_password("wrong");
+        assert!(key_manager.load_key(2).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_key_without_state_file_is_not_given_a_guessed_range() {
+        let dir = std::env::temp_dir().join(format!("lean-keys-no-state-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // Not a leansig key, so its active range cannot be read
+        std::fs::write(dir.join("validator_1_sk.ssz"), [0u8; 8]).unwrap();
+
+        let mut key_manager = KeyManager::new(&dir).unwrap().with_plaintext_keys(true);
+        assert!(key_manager.load_key(1).is_err());
+        assert_eq!(key_manager.metadata(1), None);
+        assert!(!key_manager.metadata_path(1).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "xmss-signing")]
+    #[test]
+    fn test_key_without_state_file_uses_its_own_range() {
+        let dir = std::env::temp_dir().join(format!("lean-keys-own-range-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (_, secret_key) = crypto::generate_keypair(3, 8).unwrap();
+        std::fs::write(dir.join("validator_1_sk.ssz"), secret_key).unwrap();
+
+        let mut key_manager = KeyManager::new(&dir).unwrap().with_plaintext_keys(true);
+        key_manager.load_key(1).unwrap();
+        let metadata = key_manager.metadata(1).unwrap();
+        assert!(metadata.activation_epoch <= 3);
+        assert!(metadata.end_epoch() >= 11);
+        assert!(key_manager.metadata_path(1).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,445 @@
+//! EIP-2335 style encrypted keystores for XMSS secret keys.
+//!
+//! The payload is the SSZ secret key, as `keygen` writes it. It is encrypted
+//! with AES-128-CTR under a key derived from the password with scrypt or
+//! PBKDF2, and a SHA-256 checksum over the ciphertext detects a wrong password.
+//! The JSON container is the EIP-2335 one plus the validator index, since XMSS
+//! keys are registered by index rather than derived from a path.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use aes::Aes128;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+pub const KEYSTORE_VERSION: u32 = 4;
+
+const DERIVED_KEY_SIZE: u32 = 32;
+const SALT_SIZE: usize = 32;
+const IV_SIZE: usize = 16;
+const CIPHER_FUNCTION: &str = "aes-128-ctr";
+const CHECKSUM_FUNCTION: &str = "sha256";
+const PBKDF2_PRF: &str = "hmac-sha256";
+
+/// PBKDF2 rounds from the EIP-2335 test vectors.
+pub const PBKDF2_ROUNDS: u32 = 262_144;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeystoreError {
+    /// The checksum does not match, almost always because of a wrong password.
+    WrongPassword,
+    /// A KDF, cipher or parameter this implementation does not handle.
+    Unsupported(String),
+    /// Malformed JSON, hex or parameters.
+    Invalid(String),
+    Io(String),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongPassword => write!(f, "Wrong keystore password"),
+            Self::Unsupported(what) => write!(f, "Unsupported keystore {what}"),
+            Self::Invalid(err) => write!(f, "Invalid keystore: {err}"),
+            Self::Io(err) => write!(f, "Keystore file: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+/// Password-based key derivation for new keystores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Scrypt { n: u32, r: u32, p: u32 },
+    Pbkdf2 { c: u32 },
+}
+
+impl Default for Kdf {
+    /// The EIP-2335 scrypt parameters: 256 MiB and about a second per key.
+    fn default() -> Self {
+        Self::Scrypt {
+            n: 262_144,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub crypto: KeystoreCrypto,
+    #[serde(default)]
+    pub description: String,
+    /// Hex encoded public key, empty when it was not known at encryption time.
+    #[serde(default)]
+    pub pubkey: String,
+    /// Always empty, XMSS keys are not derived.
+    #[serde(default)]
+    pub path: String,
+    pub uuid: String,
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator_index: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeystoreCrypto {
+    pub kdf: KdfModule,
+    pub checksum: ChecksumModule,
+    pub cipher: CipherModule,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfModule {
+    pub function: String,
+    pub params: KdfParams,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt {
+        dklen: u32,
+        n: u32,
+        p: u32,
+        r: u32,
+        salt: String,
+    },
+    Pbkdf2 {
+        dklen: u32,
+        c: u32,
+        prf: String,
+        salt: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumModule {
+    pub function: String,
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CipherModule {
+    pub function: String,
+    pub params: CipherParams,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+impl Keystore {
+    /// Encrypt `secret` with a fresh salt and IV.
+    pub fn encrypt(
+        secret: &[u8],
+        password: &str,
+        kdf: Kdf,
+        validator_index: Option<u64>,
+        pubkey: Option<&[u8]>,
+    ) -> Result<Self, KeystoreError> {
+        let mut rng = rand::rng();
+        let mut salt = [0; SALT_SIZE];
+        let mut iv = [0; IV_SIZE];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut iv);
+
+        let (function, params) = match kdf {
+            Kdf::Scrypt { n, r, p } => (
+                "scrypt",
+                KdfParams::Scrypt {
+                    dklen: DERIVED_KEY_SIZE,
+                    n,
+                    p,
+                    r,
+                    salt: hex::encode(salt),
+                },
+            ),
+            Kdf::Pbkdf2 { c } => (
+                "pbkdf2",
+                KdfParams::Pbkdf2 {
+                    dklen: DERIVED_KEY_SIZE,
+                    c,
+                    prf: PBKDF2_PRF.to_string(),
+                    salt: hex::encode(salt),
+                },
+            ),
+        };
+        let kdf = KdfModule {
+            function: function.to_string(),
+            params,
+            message: String::new(),
+        };
+
+        let decryption_key = kdf.derive_key(password)?;
+        let mut cipher_message = secret.to_vec();
+        apply_cipher(&decryption_key, &iv, &mut cipher_message);
+
+        Ok(Self {
+            crypto: KeystoreCrypto {
+                checksum: ChecksumModule {
+                    function: CHECKSUM_FUNCTION.to_string(),
+                    params: Default::default(),
+                    message: hex::encode(checksum(&decryption_key, &cipher_message)),
+                },
+                cipher: CipherModule {
+                    function: CIPHER_FUNCTION.to_string(),
+                    params: CipherParams {
+                        iv: hex::encode(iv),
+                    },
+                    message: hex::encode(cipher_message),
+                },
+                kdf,
+            },
+            description: validator_index
+                .map(|index| format!("XMSS secret key of validator {index}"))
+                .unwrap_or_default(),
+            pubkey: pubkey.map(hex::encode).unwrap_or_default(),
+            path: String::new(),
+            uuid: uuid::Uuid::new_v4().to_string(),
+            version: KEYSTORE_VERSION,
+            validator_index,
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<Vec<u8>, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::Unsupported(format!(
+                "version {}",
+                self.version
+            )));
+        }
+        let crypto = &self.crypto;
+        if crypto.checksum.function != CHECKSUM_FUNCTION {
+            return Err(KeystoreError::Unsupported(format!(
+                "checksum function {}",
+                crypto.checksum.function
+            )));
+        }
+        if crypto.cipher.function != CIPHER_FUNCTION {
+            return Err(KeystoreError::Unsupported(format!(
+                "cipher function {}",
+                crypto.cipher.function
+            )));
+        }
+
+        let decryption_key = crypto.kdf.derive_key(password)?;
+        let mut message = decode_hex("cipher message", &crypto.cipher.message)?;
+
+        let expected_checksum = decode_hex("checksum", &crypto.checksum.message)?;
+        if checksum(&decryption_key, &message).as_slice() != expected_checksum.as_slice() {
+            return Err(KeystoreError::WrongPassword);
+        }
+
+        let iv = decode_hex("IV", &crypto.cipher.params.iv)?;
+        if iv.len() != IV_SIZE {
+            return Err(KeystoreError::Invalid(format!(
+                "IV is {} bytes, expected {IV_SIZE}",
+                iv.len()
+            )));
+        }
+        apply_cipher(&decryption_key, &iv, &mut message);
+        Ok(message)
+    }
+
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, KeystoreError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| KeystoreError::Io(format!("failed to read {path:?}: {err}")))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|err| KeystoreError::Invalid(format!("{path:?}: {err}")))
+    }
+
+    pub fn write_json_file(&self, path: impl AsRef<Path>) -> Result<(), KeystoreError> {
+        let path = path.as_ref();
+        let bytes = serde_json::to_vec_pretty(self)
+            .map_err(|err| KeystoreError::Invalid(err.to_string()))?;
+        crate::keys::write_secret_file(path, &bytes)
+            .map_err(|err| KeystoreError::Io(format!("failed to write {path:?}: {err}")))
+    }
+}
+
+impl KdfModule {
+    fn derive_key(&self, password: &str) -> Result<Vec<u8>, KeystoreError> {
+        let password = normalize_password(password);
+
+        match (self.function.as_str(), &self.params) {
+            (
+                "scrypt",
+                KdfParams::Scrypt {
+                    dklen,
+                    n,
+                    p,
+                    r,
+                    salt,
+                },
+            ) => {
+                if !n.is_power_of_two() || *n < 2 {
+                    return Err(KeystoreError::Invalid(format!(
+                        "scrypt n {n} is not a power of two"
+                    )));
+                }
+                let mut key = vec![0; derived_key_size(*dklen)?];
+                let params = scrypt::Params::new(n.trailing_zeros() as u8, *r, *p, key.len())
+                    .map_err(|err| KeystoreError::Invalid(format!("scrypt params: {err}")))?;
+                scrypt::scrypt(&password, &decode_hex("salt", salt)?, &params, &mut key)
+                    .map_err(|err| KeystoreError::Invalid(format!("scrypt: {err}")))?;
+                Ok(key)
+            }
+            (
+                "pbkdf2",
+                KdfParams::Pbkdf2 {
+                    dklen,
+                    c,
+                    prf,
+                    salt,
+                },
+            ) => {
+                if prf != PBKDF2_PRF {
+                    return Err(KeystoreError::Unsupported(format!("pbkdf2 prf {prf}")));
+                }
+                let mut key = vec![0; derived_key_size(*dklen)?];
+                pbkdf2::pbkdf2_hmac::<Sha256>(&password, &decode_hex("salt", salt)?, *c, &mut key);
+                Ok(key)
+            }
+            (function, _) => Err(KeystoreError::Unsupported(format!(
+                "KDF {function} with these parameters"
+            ))),
+        }
+    }
+}
+
+/// NFKD-normalize the password and drop control codes, as EIP-2335 requires.
+pub fn normalize_password(password: &str) -> Vec<u8> {
+    password
+        .nfkd()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Read a password file, without its trailing newline.
+pub fn read_password_file(path: impl AsRef<Path>) -> std::io::Result<String> {
+    let password = fs::read_to_string(path)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// The cipher key is the first half of the derived key, so the checksum needs
+/// at least 32 bytes.
+fn derived_key_size(dklen: u32) -> Result<usize, KeystoreError> {
+    if dklen < DERIVED_KEY_SIZE {
+        return Err(KeystoreError::Invalid(format!(
+            "dklen {dklen} is below {DERIVED_KEY_SIZE}"
+        )));
+    }
+    Ok(dklen as usize)
+}
+
+fn checksum(decryption_key: &[u8], cipher_message: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(&decryption_key[16..32]);
+    hasher.update(cipher_message);
+    hasher.finalize().into()
+}
+
+fn apply_cipher(decryption_key: &[u8], iv: &[u8], data: &mut [u8]) {
+    let mut cipher = Aes128Ctr::new(decryption_key[..16].into(), iv.into());
+    cipher.apply_keystream(data);
+}
+
+fn decode_hex(what: &str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    hex::decode(value).map_err(|err| KeystoreError::Invalid(format!("{what}: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PBKDF2 test vector from EIP-2335.
+    const EIP_2335_PBKDF2: &str = r#"{
+        "crypto": {
+            "kdf": {
+                "function": "pbkdf2",
+                "params": {
+                    "dklen": 32,
+                    "c": 262144,
+                    "prf": "hmac-sha256",
+                    "salt": "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+                },
+                "message": ""
+            },
+            "checksum": {
+                "function": "sha256",
+                "params": {},
+                "message": "8a9f5d9912ed7e75ea794bc5a89bca5f193721d30868ade6f73043c6ea6febf1"
+            },
+            "cipher": {
+                "function": "aes-128-ctr",
+                "params": {
+                    "iv": "264daa3f303d7259501c93d997d84fe6"
+                },
+                "message": "cee03fde2af33149775b7223e7845e4fb2c8ae1792e5f99fe9ecf474cc8c16ad"
+            }
+        },
+        "description": "This is a test keystore that uses PBKDF2 to secure the secret.",
+        "pubkey": "9612d7a727c9d0a22e185a1c768478dfe919cada9266988cb32359c11f2b7b27f4ae4040902382ae2910c15e2b420d07",
+        "path": "m/12381/60/0/0",
+        "uuid": "64625def-3331-4eea-ab6f-782f3ed16a83",
+        "version": 4
+    }"#;
+
+    const EIP_2335_PASSWORD: &str = "\u{1d531}\u{1d522}\u{1d530}\u{1d531}\u{1d52d}\u{1d51e}\u{1d530}\u{1d530}\u{1d534}\u{1d52c}\u{1d52f}\u{1d521}\u{1f511}";
+
+    const CHEAP_KDFS: [Kdf; 2] = [Kdf::Scrypt { n: 16, r: 8, p: 1 }, Kdf::Pbkdf2 { c: 2 }];
+
+    #[test]
+    fn decrypts_eip_2335_vector() {
+        let keystore: Keystore = serde_json::from_str(EIP_2335_PBKDF2).unwrap();
+
+        assert_eq!(
+            hex::encode(keystore.decrypt(EIP_2335_PASSWORD).unwrap()),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+
+    #[test]
+    fn encrypt_roundtrip() {
+        let secret = (0..=255).cycle().take(5000).collect::<Vec<u8>>();
+
+        for kdf in CHEAP_KDFS {
+            let keystore = Keystore::encrypt(&secret, "password", kdf, Some(3), None).unwrap();
+            assert_eq!(keystore.validator_index, Some(3));
+            assert_ne!(keystore.crypto.cipher.message, hex::encode(&secret));
+
+            let json = serde_json::to_string(&keystore).unwrap();
+            let keystore: Keystore = serde_json::from_str(&json).unwrap();
+            assert_eq!(keystore.decrypt("password").unwrap(), secret);
+            assert_eq!(
+                keystore.decrypt("wrong password"),
+                Err(KeystoreError::WrongPassword)
+            );
+        }
+    }
+
+    #[test]
+    fn password_control_codes_are_ignored() {
+        let keystore =
+            Keystore::encrypt(b"secret", "pass\u{7f}word", CHEAP_KDFS[1], None, None).unwrap();
+
+        assert_eq!(keystore.decrypt("password").unwrap(), b"secret");
+    }
+}
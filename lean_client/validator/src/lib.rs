@@ -3,18 +3,33 @@ use std::collections::HashMap;
 use std::path::Path;
 
 use containers::{
-    attestation::{Attestation, AttestationData, Signature, SignedAttestation},
-    block::{hash_tree_root, BlockWithAttestation, SignedBlockWithAttestation},
+    attestation::{
+        compute_subnet_id, is_aggregator, AggregatedAttestationSignatures, Attestation,
+        AttestationData, BlockSignatures, Signature, SignedAggregatedAttestations,
+        SignedAttestation,
+    },
+    block::{
+        hash_tree_root, AggregatedBlock, AggregatedBlockSignatures, AggregatedBlockWithAttestation,
+        Block, BlockVersion, BlockWithAttestation, SignedAggregatedBlockWithAttestation,
+        SignedBlockWithAttestation, VersionedSignedBlock,
+    },
     checkpoint::Checkpoint,
     types::{Uint64, ValidatorIndex},
-    Slot,
+    Root, Slot,
 };
 use fork_choice::store::{get_proposal_head, get_vote_target, Store};
 use tracing::{info, warn};
 
 pub mod keys;
+pub mod keystore;
+pub mod remote_signer;
+mod serde_utils;
+pub mod signer;
+pub mod slashing_protection;
 
 use keys::KeyManager;
+use signer::Signer;
+use slashing_protection::SlashingProtection;
 
 pub type ValidatorRegistry = HashMap<String, Vec<u64>>;
 // Node
@@ -51,10 +66,40 @@ impl ValidatorConfig {
     }
 }
 
+/// A block waiting for its proposer signature, from
+/// [`ValidatorService::prepare_block_proposal`].
+pub struct BlockProposal {
+    proposer_attestation: Attestation,
+    block: UnsignedBlock,
+}
+
+enum UnsignedBlock {
+    V0 {
+        block: Block,
+        /// Signatures of the body attestations.
+        signatures: BlockSignatures,
+    },
+    V1 {
+        block: AggregatedBlock,
+        attestation_signatures: AggregatedAttestationSignatures,
+    },
+}
+
+impl BlockProposal {
+    pub fn slot(&self) -> Slot {
+        self.proposer_attestation.data.slot
+    }
+}
+
 pub struct ValidatorService {
     pub config: ValidatorConfig,
     pub num_validators: u64,
-    key_manager: Option<KeyManager>,
+    /// Attestations are signed with zero signatures when unset.
+    signer: Option<Box<dyn Signer>>,
+    /// Format of the blocks we propose.
+    block_version: BlockVersion,
+    /// Consulted before every signature. Nothing stops conflicting messages when unset.
+    slashing_protection: Option<SlashingProtection>,
 }
 
 impl ValidatorService {
@@ -68,17 +113,18 @@ impl ValidatorService {
         Self {
             config,
             num_validators,
-            key_manager: None,
+            signer: None,
+            block_version: BlockVersion::default(),
+            slashing_protection: None,
         }
     }
 
+    /// Load the keys of all assigned validators into `key_manager` and sign with them.
     pub fn new_with_keys(
         config: ValidatorConfig,
         num_validators: u64,
-        keys_dir: impl AsRef<Path>,
+        mut key_manager: KeyManager,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut key_manager = KeyManager::new(keys_dir)?;
-
         // Load keys for all assigned validators
         for &idx in &config.validator_indices {
             key_manager.load_key(idx)?;
@@ -95,10 +141,47 @@ impl ValidatorService {
         Ok(Self {
             config,
             num_validators,
-            key_manager: Some(key_manager),
+            signer: Some(Box::new(key_manager)),
+            block_version: BlockVersion::default(),
+            slashing_protection: None,
         })
     }
 
+    /// Sign with `signer`, for example a
+    /// [`RemoteSigner`](remote_signer::RemoteSigner), instead of local keys.
+    pub fn with_signer(mut self, signer: impl Signer + 'static) -> Self {
+        self.signer = Some(Box::new(signer));
+        self
+    }
+
+    pub fn with_block_version(mut self, block_version: BlockVersion) -> Self {
+        self.block_version = block_version;
+        self
+    }
+
+    pub fn with_slashing_protection(mut self, slashing_protection: SlashingProtection) -> Self {
+        self.slashing_protection = Some(slashing_protection);
+        self
+    }
+
+    pub fn slashing_protection(&self) -> Option<&SlashingProtection> {
+        self.slashing_protection.as_ref()
+    }
+
+    /// Attestation subnets of our validators, which this node must subscribe to
+    /// in order to aggregate them.
+    pub fn subnet_ids(&self) -> Vec<u64> {
+        let mut subnet_ids = self
+            .config
+            .validator_indices
+            .iter()
+            .map(|&idx| compute_subnet_id(idx))
+            .collect::<Vec<_>>();
+        subnet_ids.sort_unstable();
+        subnet_ids.dedup();
+        subnet_ids
+    }
+
     pub fn get_proposer_for_slot(&self, slot: Slot) -> Option<ValidatorIndex> {
         if self.num_validators == 0 {
             return None;
@@ -112,13 +195,24 @@ impl ValidatorService {
         }
     }
 
-    /// Build a block proposal for the given slot
+    /// Build and sign a block proposal for the given slot
     pub fn build_block_proposal(
         &self,
         store: &mut Store,
         slot: Slot,
         proposer_index: ValidatorIndex,
-    ) -> Result<SignedBlockWithAttestation, String> {
+    ) -> Result<VersionedSignedBlock, String> {
+        let proposal = self.prepare_block_proposal(store, slot, proposer_index)?;
+        self.sign_block_proposal(proposal)
+    }
+
+    /// Build the block for `slot`, without the proposer signature.
+    pub fn prepare_block_proposal(
+        &self,
+        store: &mut Store,
+        slot: Slot,
+        proposer_index: ValidatorIndex,
+    ) -> Result<BlockProposal, String> {
         info!(
             slot = slot.0,
             proposer = proposer_index.0,
@@ -147,7 +241,7 @@ impl ValidatorService {
             .ok_or("Head block not found")?;
         let head_checkpoint = Checkpoint {
             root: store.head,
-            slot: head_block.message.block.slot,
+            slot: head_block.slot(),
         };
 
         let proposer_attestation = Attestation {
@@ -196,68 +290,224 @@ impl ValidatorService {
             "Collected new attestations for block"
         );
 
-        // Build block with collected attestations (empty body - attestations go to state)
-        let (block, _post_state, _collected_atts, sigs) = parent_state.build_block(
-            slot,
-            proposer_index,
-            parent_root,
-            Some(valid_attestations),
-            None,
-            None,
-        )?;
-
-        // Collect signatures from the attestations we included
-        let mut signatures = sigs;
-        for signed_att in &valid_signed_attestations {
-            signatures
-                .push(signed_att.signature.clone())
-                .map_err(|e| format!("Failed to add attestation signature: {:?}", e))?;
-        }
+        let block = match self.block_version {
+            BlockVersion::V0 => {
+                // Build block with collected attestations (empty body - attestations go to state)
+                let (block, _post_state, _collected_atts, sigs) = parent_state.build_block(
+                    slot,
+                    proposer_index,
+                    parent_root,
+                    Some(valid_attestations),
+                    None,
+                    None,
+                )?;
+
+                // Collect signatures from the attestations we included, the proposer's
+                // is appended once signed
+                let mut signatures = sigs;
+                for signed_att in &valid_signed_attestations {
+                    signatures
+                        .push(signed_att.signature.clone())
+                        .map_err(|e| format!("Failed to add attestation signature: {:?}", e))?;
+                }
+
+                UnsignedBlock::V0 { block, signatures }
+            }
+            BlockVersion::V1 => {
+                // One aggregate per distinct attestation data
+                let (block, _post_state, attestation_signatures) = parent_state
+                    .build_aggregated_block(
+                        slot,
+                        proposer_index,
+                        parent_root,
+                        valid_signed_attestations.iter().copied(),
+                    )?;
+
+                UnsignedBlock::V1 {
+                    block,
+                    attestation_signatures,
+                }
+            }
+        };
+
+        Ok(BlockProposal {
+            proposer_attestation,
+            block,
+        })
+    }
+
+    /// Sign a proposal from [`prepare_block_proposal`](Self::prepare_block_proposal).
+    ///
+    /// This may wait on a remote signer, so async callers should run it on a
+    /// blocking thread.
+    pub fn sign_block_proposal(
+        &self,
+        proposal: BlockProposal,
+    ) -> Result<VersionedSignedBlock, String> {
+        let BlockProposal {
+            proposer_attestation,
+            block,
+        } = proposal;
+        let proposer_index = proposer_attestation.validator_id.0;
+
+        let signed_block: VersionedSignedBlock = match block {
+            UnsignedBlock::V0 {
+                block,
+                mut signatures,
+            } => {
+                let proposer_signature =
+                    self.sign_proposal(hash_tree_root(&block), &proposer_attestation)?;
+                signatures
+                    .push(proposer_signature)
+                    .map_err(|e| format!("Failed to add proposer signature: {:?}", e))?;
+
+                SignedBlockWithAttestation {
+                    message: BlockWithAttestation {
+                        block,
+                        proposer_attestation,
+                    },
+                    signature: signatures,
+                }
+                .into()
+            }
+            UnsignedBlock::V1 {
+                block,
+                attestation_signatures,
+            } => {
+                let proposer_signature =
+                    self.sign_proposal(hash_tree_root(&block), &proposer_attestation)?;
+
+                SignedAggregatedBlockWithAttestation {
+                    message: AggregatedBlockWithAttestation {
+                        block,
+                        proposer_attestation,
+                    },
+                    signature: AggregatedBlockSignatures {
+                        attestation_signatures,
+                        proposer_signature,
+                    },
+                }
+                .into()
+            }
+        };
 
         info!(
-            slot = block.slot.0,
-            proposer = block.proposer_index.0,
-            parent_root = %format!("0x{:x}", block.parent_root.0),
-            state_root = %format!("0x{:x}", block.state_root.0),
-            attestation_sigs = valid_signed_attestations.len(),
+            slot = signed_block.slot().0,
+            proposer = signed_block.proposer_index().0,
+            parent_root = %format!("0x{:x}", signed_block.parent_root().0),
+            state_root = %format!("0x{:x}", signed_block.state_root().0),
+            version = signed_block.version().fork(),
+            attestation_sigs = signed_block.signed_attestations().len(),
             "Block built successfully"
         );
 
-        // Sign the proposer attestation
-        if let Some(ref key_manager) = self.key_manager {
+        metrics::BLOCKS_PRODUCED
+            .with_label_values(&[&proposer_index.to_string()])
+            .inc();
+
+        Ok(signed_block)
+    }
+
+    /// Sign the proposer attestation of the block with root `block_root`.
+    /// The signature covers both the block and a vote, so both are checked
+    /// against slashing protection.
+    fn sign_proposal(
+        &self,
+        block_root: Root,
+        proposer_attestation: &Attestation,
+    ) -> Result<Signature, String> {
+        let proposer_index = proposer_attestation.validator_id.0;
+        let slot = proposer_attestation.data.slot;
+        let message = hash_tree_root(proposer_attestation);
+
+        if let Some(ref slashing_protection) = self.slashing_protection {
+            slashing_protection
+                .check_and_record_proposal(proposer_index, slot, block_root)
+                .and_then(|()| {
+                    slashing_protection.check_and_record_attestation(
+                        proposer_index,
+                        &proposer_attestation.data,
+                        message,
+                    )
+                })
+                .map_err(|e| format!("Refusing to sign proposal: {}", e))?;
+        }
+
+        if let Some(ref signer) = self.signer {
             // Sign proposer attestation with XMSS
-            let message = hash_tree_root(&proposer_attestation);
             let epoch = slot.0 as u32;
 
-            match key_manager.sign(proposer_index.0, epoch, &message.0.into()) {
-                Ok(sig) => {
-                    signatures
-                        .push(sig)
-                        .map_err(|e| format!("Failed to add proposer signature: {:?}", e))?;
-                    info!(proposer = proposer_index.0, "Signed proposer attestation");
+            let sig = signer
+                .sign(proposer_index, epoch, &message.0.into())
+                .map_err(|e| format!("Failed to sign proposer attestation: {}", e))?;
+            info!(proposer = proposer_index, "Signed proposer attestation");
+            Ok(sig)
+        } else {
+            // No signer - use zero signature
+            warn!("Building block with zero signature (no signer)");
+            Ok(Signature::default())
+        }
+    }
+
+    /// Aggregator duty: aggregate the attestations for `slot` received on the
+    /// subnets one of our validators aggregates at `slot`.
+    ///
+    /// Runs before the store accepts new attestations, so everything gossiped
+    /// during the slot is still in `latest_new_attestations`. devnet0 has no
+    /// subnets or aggregates, so nothing is produced there.
+    pub fn create_aggregates(
+        &self,
+        store: &Store,
+        slot: Slot,
+    ) -> Vec<SignedAggregatedAttestations> {
+        let mut aggregates = Vec::new();
+        if self.block_version == BlockVersion::V0 {
+            return aggregates;
+        }
+
+        for &aggregator in &self.config.validator_indices {
+            if !is_aggregator(aggregator, slot, self.num_validators) {
+                continue;
+            }
+
+            let subnet_id = compute_subnet_id(aggregator);
+            let attestations = store.latest_new_attestations.values().filter(|att| {
+                att.message.data.slot == slot && att.message.subnet_id() == subnet_id
+            });
+
+            match SignedAggregatedAttestations::aggregate(attestations) {
+                Ok(subnet_aggregates) => {
+                    for aggregate in &subnet_aggregates {
+                        info!(
+                            slot = slot.0,
+                            subnet_id,
+                            aggregator,
+                            participants = aggregate.signature.len_u64(),
+                            "Created subnet aggregate"
+                        );
+                        metrics::AGGREGATES_PRODUCED
+                            .with_label_values(&[&aggregator.to_string()])
+                            .inc();
+                    }
+                    aggregates.extend(subnet_aggregates);
                 }
                 Err(e) => {
-                    return Err(format!("Failed to sign proposer attestation: {}", e));
+                    warn!(subnet_id, error = %e, "Failed to aggregate subnet attestations");
                 }
             }
-        } else {
-            // No key manager - use zero signature
-            warn!("Building block with zero signature (no key manager)");
         }
 
-        let signed_block = SignedBlockWithAttestation {
-            message: BlockWithAttestation {
-                block,
-                proposer_attestation,
-            },
-            signature: signatures,
-        };
-
-        Ok(signed_block)
+        aggregates
     }
 
-    /// Create attestations for all our validators for the given slot
+    /// Create and sign attestations for all our validators for the given slot.
     pub fn create_attestations(&self, store: &Store, slot: Slot) -> Vec<SignedAttestation> {
+        self.sign_attestations(self.prepare_attestations(store, slot))
+    }
+
+    /// Unsigned attestations of all our validators for the given slot.
+    /// The slot's proposer already attested in its block and is skipped.
+    pub fn prepare_attestations(&self, store: &Store, slot: Slot) -> Vec<Attestation> {
         let vote_target = get_vote_target(store);
 
         // Skip attestation creation if target slot is not strictly greater than source slot
@@ -283,35 +533,60 @@ impl ValidatorService {
 
         let head_checkpoint = Checkpoint {
             root: store.head,
-            slot: get_head_block_info.message.block.slot,
+            slot: get_head_block_info.slot(),
         };
 
+        let proposer = self.get_proposer_for_slot(slot);
+
         self.config
             .validator_indices
             .iter()
-            .filter_map(|&idx| {
-                let attestation = Attestation {
-                    validator_id: Uint64(idx),
-                    data: AttestationData {
-                        slot,
-                        head: head_checkpoint.clone(),
-                        target: vote_target.clone(),
-                        source: store.latest_justified.clone(),
-                    },
-                };
+            .filter(|&&idx| proposer != Some(ValidatorIndex(idx)))
+            .map(|&idx| Attestation {
+                validator_id: Uint64(idx),
+                data: AttestationData {
+                    slot,
+                    head: head_checkpoint.clone(),
+                    target: vote_target.clone(),
+                    source: store.latest_justified.clone(),
+                },
+            })
+            .collect()
+    }
+
+    /// Sign attestations from [`prepare_attestations`](Self::prepare_attestations),
+    /// skipping those slashing protection refuses or the signer fails on.
+    ///
+    /// This may wait on a remote signer, so async callers should run it on a
+    /// blocking thread.
+    pub fn sign_attestations(&self, attestations: Vec<Attestation>) -> Vec<SignedAttestation> {
+        attestations
+            .into_iter()
+            .filter_map(|attestation| {
+                let idx = attestation.validator_id.0;
+                let data = &attestation.data;
+                let message = hash_tree_root(&attestation);
+
+                if let Some(ref slashing_protection) = self.slashing_protection {
+                    if let Err(e) =
+                        slashing_protection.check_and_record_attestation(idx, data, message)
+                    {
+                        warn!(validator = idx, error = %e, "Refusing to sign attestation");
+                        return None;
+                    }
+                }
 
-                let signature = if let Some(ref key_manager) = self.key_manager {
+                let signature = if let Some(ref signer) = self.signer {
                     // Sign with XMSS
-                    let message = hash_tree_root(&attestation);
-                    let epoch = slot.0 as u32;
+                    let epoch = data.slot.0 as u32;
 
-                    match key_manager.sign(idx, epoch, &message.0.into()) {
+                    match signer.sign(idx, epoch, &message.0.into()) {
                         Ok(sig) => {
                             info!(
-                                slot = slot.0,
+                                slot = data.slot.0,
                                 validator = idx,
-                                target_slot = vote_target.slot.0,
-                                source_slot = store.latest_justified.slot.0,
+                                target_slot = data.target.slot.0,
+                                source_slot = data.source.slot.0,
                                 "Created signed attestation"
                             );
                             sig
@@ -326,17 +601,21 @@ impl ValidatorService {
                         }
                     }
                 } else {
-                    // No key manager - use zero signature
+                    // No signer - use zero signature
                     info!(
-                        slot = slot.0,
+                        slot = data.slot.0,
                         validator = idx,
-                        target_slot = vote_target.slot.0,
-                        source_slot = store.latest_justified.slot.0,
+                        target_slot = data.target.slot.0,
+                        source_slot = data.source.slot.0,
                         "Created attestation with zero signature"
                     );
                     Signature::default()
                 };
 
+                metrics::ATTESTATIONS_PRODUCED
+                    .with_label_values(&[&idx.to_string()])
+                    .inc();
+
                 Some(SignedAttestation {
                     message: attestation,
                     signature,
@@ -350,6 +629,8 @@ impl ValidatorService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use containers::{config::Config, state::State, validator::Validator};
+    use fork_choice::store::get_forkchoice_store;
 
     #[test]
     fn test_proposer_selection() {
@@ -386,4 +667,57 @@ mod tests {
         assert!(!config.is_assigned(1));
         assert!(!config.is_assigned(3));
     }
+
+    #[test]
+    fn test_subnet_ids() {
+        let config = ValidatorConfig {
+            node_id: "test_0".to_string(),
+            validator_indices: vec![65, 1, 2],
+        };
+        let service = ValidatorService::new(config, 128);
+
+        assert_eq!(service.subnet_ids(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_aggregates_only_own_subnet() {
+        let validators = vec![Validator::default(); 4];
+        let state = State::generate_genesis_with_validators(Uint64(0), validators);
+        let block = Block {
+            state_root: hash_tree_root(&state),
+            ..Block::default()
+        };
+        let genesis = SignedBlockWithAttestation {
+            message: BlockWithAttestation {
+                block,
+                proposer_attestation: Attestation::default(),
+            },
+            signature: Default::default(),
+        };
+        let mut store = get_forkchoice_store(state, genesis, Config::default());
+
+        for validator_id in 0..4 {
+            let mut attestation = SignedAttestation::default();
+            attestation.message.validator_id = Uint64(validator_id);
+            attestation.message.data.slot = Slot(1);
+            store
+                .latest_new_attestations
+                .insert(ValidatorIndex(validator_id), attestation);
+        }
+
+        let config = ValidatorConfig {
+            node_id: "test_0".to_string(),
+            validator_indices: vec![2],
+        };
+        let service = ValidatorService::new(config, 4);
+        assert!(service.create_aggregates(&store, Slot(1)).is_empty());
+
+        let service = service.with_block_version(BlockVersion::V1);
+        let aggregates = service.create_aggregates(&store, Slot(1));
+        assert_eq!(aggregates.len(), 1);
+        assert_eq!(
+            aggregates[0].message.validator_ids().collect::<Vec<_>>(),
+            vec![Uint64(2)]
+        );
+    }
 }
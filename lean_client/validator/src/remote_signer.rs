@@ -0,0 +1,341 @@
+//! Signing through an HTTP signing service, so XMSS secret keys never have to
+//! be on the node's host.
+//!
+//! The API follows Web3Signer. `POST /api/v1/lean/sign/{validator_index}` with
+//! `{"type": "ATTESTATION", "epoch": "<epoch>", "signingRoot": "0x<root>"}` is
+//! answered with `{"signature": "0x<ssz signature>"}`, and `GET /upcheck`
+//! reports liveness. Validators are addressed by index, as lean registers keys
+//! by index.
+//!
+//! [`LocalSignerServer`] serves the same API from any [`Signer`], for tests and
+//! local devnets.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use containers::{Root, Signature};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::serde_utils::{quoted_u64, root};
+use crate::signer::Signer;
+
+pub const SIGN_PATH: &str = "/api/v1/lean/sign";
+pub const UPCHECK_PATH: &str = "/upcheck";
+
+/// A signature that takes longer than this is too late for its interval anyway.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Sign requests are well under this, anything larger is refused unread.
+const MAX_REQUEST_BODY: usize = 4096;
+
+/// What is being signed. Proposals are signed through their proposer
+/// attestation, so attestation roots are all a lean validator ever signs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SignType {
+    Attestation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignRequest {
+    #[serde(rename = "type")]
+    pub sign_type: SignType,
+    /// The XMSS epoch to sign at, which is the slot.
+    #[serde(with = "quoted_u64")]
+    pub epoch: u64,
+    /// Hash tree root of the attestation.
+    #[serde(with = "root")]
+    pub signing_root: Root,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignResponse {
+    /// `0x`-prefixed SSZ bytes of the signature.
+    pub signature: String,
+}
+
+/// [`Signer`] backed by a remote signing service at `url`.
+pub struct RemoteSigner {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl RemoteSigner {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            agent: build_agent(DEFAULT_TIMEOUT),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = build_agent(timeout);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Check that the signing service is reachable.
+    pub fn upcheck(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.agent
+            .get(&format!("{}{UPCHECK_PATH}", self.url))
+            .call()
+            .map_err(|e| request_error(&self.url, e))?;
+        Ok(())
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn sign(
+        &self,
+        validator_index: u64,
+        epoch: u32,
+        message: &[u8; 32],
+    ) -> Result<Signature, Box<dyn std::error::Error>> {
+        let request = SignRequest {
+            sign_type: SignType::Attestation,
+            epoch: epoch.into(),
+            signing_root: Root((*message).into()),
+        };
+
+        let response: SignResponse = self
+            .agent
+            .post(&format!("{}{SIGN_PATH}/{validator_index}", self.url))
+            .set("Accept", "application/json")
+            .send_json(&request)
+            .map_err(|e| request_error(&self.url, e))?
+            .into_json()?;
+
+        let bytes = hex::decode(response.signature.trim_start_matches("0x"))?;
+        let signature = Signature::try_from(bytes.as_slice()).map_err(|_| {
+            format!(
+                "Invalid signature size from remote signer: expected 3112, got {}",
+                bytes.len()
+            )
+        })?;
+        Ok(signature)
+    }
+}
+
+fn build_agent(timeout: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(timeout).build()
+}
+
+fn request_error(url: &str, error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(status, response) => format!(
+            "Remote signer {} answered {}: {}",
+            url,
+            status,
+            response.into_string().unwrap_or_default()
+        ),
+        error => format!("Remote signer {} unreachable: {}", url, error),
+    }
+}
+
+/// Minimal HTTP server for the signing API, signing with a local [`Signer`].
+///
+/// Handles one connection at a time on a background thread. It is meant for
+/// tests and local devnets, not as a hardened key custody service.
+pub struct LocalSignerServer {
+    local_addr: SocketAddr,
+}
+
+impl LocalSignerServer {
+    /// Serve on `address` until the process exits. Port 0 picks a free port.
+    pub fn spawn(address: impl ToSocketAddrs, signer: Arc<dyn Signer>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| handle_connection(stream, signer.as_ref()));
+                if let Err(e) = result {
+                    warn!(error = %e, "Local signer connection failed");
+                }
+            }
+        });
+
+        info!(address = %local_addr, "Local signer listening");
+        Ok(Self { local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Base URL for [`RemoteSigner::new`].
+    pub fn url(&self) -> String {
+        format!("http://{}", self.local_addr)
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, signer: &dyn Signer) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(usize::MAX);
+            }
+        }
+    }
+
+    let (status, body) = if content_length > MAX_REQUEST_BODY {
+        (413, error_body("request body too large"))
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        route(&request_line, &body, signer)
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason_phrase(status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn route(request_line: &str, body: &[u8], signer: &dyn Signer) -> (u16, String) {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+
+    match (method, path.strip_prefix(SIGN_PATH)) {
+        ("GET", _) if path == UPCHECK_PATH => (200, json!({ "status": "OK" }).to_string()),
+        ("POST", Some(validator_index)) => match validator_index.strip_prefix('/') {
+            Some(validator_index) => sign(validator_index, body, signer),
+            None => (404, error_body("not found")),
+        },
+        _ => (404, error_body("not found")),
+    }
+}
+
+fn sign(validator_index: &str, body: &[u8], signer: &dyn Signer) -> (u16, String) {
+    let Ok(validator_index) = validator_index.parse::<u64>() else {
+        return (400, error_body("invalid validator index"));
+    };
+    let request = match serde_json::from_slice::<SignRequest>(body) {
+        Ok(request) => request,
+        Err(e) => return (400, error_body(&format!("invalid sign request: {e}"))),
+    };
+    let Ok(epoch) = u32::try_from(request.epoch) else {
+        return (400, error_body("epoch out of range"));
+    };
+
+    match signer.sign(validator_index, epoch, &request.signing_root.0.into()) {
+        Ok(signature) => {
+            let signature = format!("0x{}", hex::encode(signature.as_bytes()));
+            (200, json!({ "signature": signature }).to_string())
+        }
+        Err(e) => (500, error_body(&e.to_string())),
+    }
+}
+
+fn error_body(message: &str) -> String {
+    json!({ "message": message }).to_string()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic stand-in for XMSS keys, which know validators 0 to 3.
+    struct TestSigner;
+
+    impl Signer for TestSigner {
+        fn sign(
+            &self,
+            validator_index: u64,
+            epoch: u32,
+            message: &[u8; 32],
+        ) -> Result<Signature, Box<dyn std::error::Error>> {
+            if validator_index > 3 {
+                return Err(format!("No key loaded for validator {}", validator_index).into());
+            }
+            let byte = message[0] ^ validator_index as u8 ^ epoch as u8;
+            Ok(Signature::try_from([byte; 3112].as_slice()).unwrap())
+        }
+    }
+
+    #[test]
+    fn sign_request_json() {
+        let request = SignRequest {
+            sign_type: SignType::Attestation,
+            epoch: 5,
+            signing_root: Root::default(),
+        };
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(
+            json,
+            json!({
+                "type": "ATTESTATION",
+                "epoch": "5",
+                "signingRoot": format!("0x{}", "00".repeat(32)),
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<SignRequest>(json).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn signs_through_local_server() {
+        let server = LocalSignerServer::spawn("127.0.0.1:0", Arc::new(TestSigner)).unwrap();
+        let remote = RemoteSigner::new(server.url());
+
+        remote.upcheck().unwrap();
+
+        let message = [7; 32];
+        assert_eq!(
+            remote.sign(2, 9, &message).unwrap(),
+            TestSigner.sign(2, 9, &message).unwrap()
+        );
+
+        let error = remote.sign(4, 9, &message).unwrap_err().to_string();
+        assert!(error.contains("No key loaded for validator 4"), "{error}");
+    }
+
+    #[test]
+    fn unreachable_signer_fails() {
+        // Bind and drop to get a port nothing listens on
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let remote = RemoteSigner::new(format!("http://{address}"));
+
+        assert!(remote.sign(0, 0, &[0; 32]).is_err());
+    }
+}
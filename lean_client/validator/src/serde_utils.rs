@@ -0,0 +1,73 @@
+use containers::Root;
+use serde::{de::Error as SerdeError, Deserialize, Deserializer, Serializer};
+
+pub mod quoted_u64 {
+    use super::{Deserialize, Deserializer, SerdeError, Serializer};
+
+    pub fn serialize<S>(value: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        value
+            .parse::<u64>()
+            .map_err(|err| SerdeError::custom(format!("invalid u64: {err}")))
+    }
+}
+
+/// A root as a `0x`-prefixed hex string.
+pub mod root {
+    use super::{Deserialize, Deserializer, Root, SerdeError, Serializer};
+
+    pub fn serialize<S>(value: &Root, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("0x{value}"))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Root, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        parse_root(&value).map_err(SerdeError::custom)
+    }
+}
+
+pub mod optional_root {
+    use super::{Deserialize, Deserializer, Root, SerdeError, Serializer};
+
+    pub fn serialize<S>(value: &Option<Root>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(root) => serializer.serialize_some(&format!("0x{root}")),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Root>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| parse_root(&value).map_err(SerdeError::custom))
+            .transpose()
+    }
+}
+
+fn parse_root(value: &str) -> Result<Root, String> {
+    value
+        .trim_start_matches("0x")
+        .parse::<Root>()
+        .map_err(|err| format!("invalid root: {err}"))
+}
@@ -0,0 +1,29 @@
+use containers::Signature;
+
+use crate::keys::KeyManager;
+
+/// Produces XMSS signatures for our validators.
+///
+/// [`KeyManager`] signs with secret keys held in this process,
+/// [`RemoteSigner`](crate::remote_signer::RemoteSigner) asks a signing service
+/// that holds them instead.
+pub trait Signer: Send + Sync {
+    /// Sign a 32-byte message (a hash tree root) at `epoch` with the key of `validator_index`.
+    fn sign(
+        &self,
+        validator_index: u64,
+        epoch: u32,
+        message: &[u8; 32],
+    ) -> Result<Signature, Box<dyn std::error::Error>>;
+}
+
+impl Signer for KeyManager {
+    fn sign(
+        &self,
+        validator_index: u64,
+        epoch: u32,
+        message: &[u8; 32],
+    ) -> Result<Signature, Box<dyn std::error::Error>> {
+        KeyManager::sign(self, validator_index, epoch, message)
+    }
+}
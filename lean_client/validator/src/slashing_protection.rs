@@ -0,0 +1,772 @@
+//! Slashing protection: a record of what each of our validators has signed,
+//! consulted before signing anything new.
+//!
+//! A proposal must be for a later slot than every earlier proposal. An
+//! attestation must be for a later slot than every earlier attestation, and
+//! neither its source nor its target may go backwards, which rules out
+//! surrounding and surrounded votes. Signing exactly the same message again is
+//! allowed. Only the records these checks need are kept.
+//!
+//! The database is a JSON file in the interchange format, rewritten atomically
+//! after every change. The format follows EIP-3076 with lean's slots in place
+//! of epochs and validator indices in place of public keys.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use containers::{attestation::AttestationData, Root, Slot};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::serde_utils::{optional_root, quoted_u64};
+
+pub const INTERCHANGE_FORMAT_VERSION: &str = "5";
+
+const TMP_EXTENSION: &str = "tmp";
+
+/// Why a message was not signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashingProtectionError {
+    /// A different block was already signed at this slot.
+    DoubleProposal {
+        validator_index: u64,
+        slot: Slot,
+    },
+    /// A block was already signed at a later slot.
+    ProposalNotNewer {
+        validator_index: u64,
+        slot: Slot,
+        latest_slot: Slot,
+    },
+    /// A different attestation was already signed at this slot.
+    DoubleVote {
+        validator_index: u64,
+        slot: Slot,
+    },
+    /// An attestation was already signed at a later slot.
+    AttestationNotNewer {
+        validator_index: u64,
+        slot: Slot,
+        latest_slot: Slot,
+    },
+    /// The source is older than an earlier attestation's, so the vote could
+    /// surround it.
+    SurroundingVote {
+        validator_index: u64,
+        source_slot: Slot,
+        latest_source_slot: Slot,
+    },
+    /// The target is older than an earlier attestation's, so the vote could be
+    /// surrounded by it.
+    SurroundedVote {
+        validator_index: u64,
+        target_slot: Slot,
+        latest_target_slot: Slot,
+    },
+    SourceAfterTarget {
+        source_slot: Slot,
+        target_slot: Slot,
+    },
+    /// The interchange data is for another chain.
+    GenesisMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    UnsupportedFormatVersion(String),
+    /// The database could not be read or written. Nothing is signed until it can.
+    Database(String),
+}
+
+impl fmt::Display for SlashingProtectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DoubleProposal {
+                validator_index,
+                slot,
+            } => write!(
+                f,
+                "Validator {validator_index} already signed a different block at slot {}",
+                slot.0
+            ),
+            Self::ProposalNotNewer {
+                validator_index,
+                slot,
+                latest_slot,
+            } => write!(
+                f,
+                "Validator {validator_index} already signed a block at slot {}, refusing slot {}",
+                latest_slot.0, slot.0
+            ),
+            Self::DoubleVote {
+                validator_index,
+                slot,
+            } => write!(
+                f,
+                "Validator {validator_index} already signed a different attestation at slot {}",
+                slot.0
+            ),
+            Self::AttestationNotNewer {
+                validator_index,
+                slot,
+                latest_slot,
+            } => write!(
+                f,
+                "Validator {validator_index} already signed an attestation at slot {}, refusing slot {}",
+                latest_slot.0, slot.0
+            ),
+            Self::SurroundingVote {
+                validator_index,
+                source_slot,
+                latest_source_slot,
+            } => write!(
+                f,
+                "Validator {validator_index} already attested with source slot {}, refusing source slot {}",
+                latest_source_slot.0, source_slot.0
+            ),
+            Self::SurroundedVote {
+                validator_index,
+                target_slot,
+                latest_target_slot,
+            } => write!(
+                f,
+                "Validator {validator_index} already attested with target slot {}, refusing target slot {}",
+                latest_target_slot.0, target_slot.0
+            ),
+            Self::SourceAfterTarget {
+                source_slot,
+                target_slot,
+            } => write!(
+                f,
+                "Source slot {} exceeds target slot {}",
+                source_slot.0, target_slot.0
+            ),
+            Self::GenesisMismatch { expected, actual } => write!(
+                f,
+                "Slashing protection data is for genesis time {actual}, expected {expected}"
+            ),
+            Self::UnsupportedFormatVersion(version) => {
+                write!(f, "Unsupported interchange format version {version}")
+            }
+            Self::Database(err) => write!(f, "Slashing protection database: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SlashingProtectionError {}
+
+/// EIP-3076 style interchange document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interchange {
+    pub metadata: InterchangeMetadata,
+    pub data: Vec<InterchangeData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterchangeMetadata {
+    pub interchange_format_version: String,
+    /// Lean has no genesis validators root, so the chain is identified by its
+    /// genesis time.
+    #[serde(with = "quoted_u64")]
+    pub genesis_time: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterchangeData {
+    #[serde(with = "quoted_u64")]
+    pub validator_index: u64,
+    #[serde(default)]
+    pub signed_blocks: Vec<SignedBlock>,
+    #[serde(default)]
+    pub signed_attestations: Vec<SignedAttestation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedBlock {
+    #[serde(with = "quoted_u64")]
+    pub slot: u64,
+    /// Missing in imported data that did not record it. Such a message is
+    /// never considered identical to a new one.
+    #[serde(default, with = "optional_root")]
+    pub signing_root: Option<Root>,
+}
+
+/// `slot` is not part of EIP-3076: a lean validator attests every slot, so
+/// several attestations may share a target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    #[serde(with = "quoted_u64")]
+    pub slot: u64,
+    #[serde(with = "quoted_u64")]
+    pub source_slot: u64,
+    #[serde(with = "quoted_u64")]
+    pub target_slot: u64,
+    #[serde(default, with = "optional_root")]
+    pub signing_root: Option<Root>,
+}
+
+impl Interchange {
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, SlashingProtectionError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|err| {
+            SlashingProtectionError::Database(format!("failed to read {path:?}: {err}"))
+        })?;
+        serde_json::from_slice(&bytes).map_err(|err| {
+            SlashingProtectionError::Database(format!("failed to parse {path:?}: {err}"))
+        })
+    }
+
+    pub fn write_json_file(&self, path: impl AsRef<Path>) -> Result<(), SlashingProtectionError> {
+        let bytes = serde_json::to_vec_pretty(self)
+            .map_err(|err| SlashingProtectionError::Database(err.to_string()))?;
+        write_atomic(path.as_ref(), &bytes).map_err(SlashingProtectionError::Database)
+    }
+}
+
+impl InterchangeData {
+    fn latest_block(&self) -> Option<&SignedBlock> {
+        self.signed_blocks.iter().max_by_key(|block| block.slot)
+    }
+
+    fn latest_attestation(&self) -> Option<&SignedAttestation> {
+        self.signed_attestations.iter().max_by_key(|att| att.slot)
+    }
+
+    fn check_proposal(
+        &self,
+        slot: Slot,
+        signing_root: Root,
+    ) -> Result<bool, SlashingProtectionError> {
+        let Some(latest) = self.latest_block() else {
+            return Ok(true);
+        };
+
+        if latest.slot == slot.0 {
+            return if latest.signing_root == Some(signing_root) {
+                Ok(false)
+            } else {
+                Err(SlashingProtectionError::DoubleProposal {
+                    validator_index: self.validator_index,
+                    slot,
+                })
+            };
+        }
+
+        if latest.slot > slot.0 {
+            return Err(SlashingProtectionError::ProposalNotNewer {
+                validator_index: self.validator_index,
+                slot,
+                latest_slot: Slot(latest.slot),
+            });
+        }
+
+        Ok(true)
+    }
+
+    fn check_attestation(
+        &self,
+        data: &AttestationData,
+        signing_root: Root,
+    ) -> Result<bool, SlashingProtectionError> {
+        if data.source.slot > data.target.slot {
+            return Err(SlashingProtectionError::SourceAfterTarget {
+                source_slot: data.source.slot,
+                target_slot: data.target.slot,
+            });
+        }
+
+        let Some(latest) = self.latest_attestation() else {
+            return Ok(true);
+        };
+
+        if latest.slot == data.slot.0 {
+            return if latest.signing_root == Some(signing_root) {
+                Ok(false)
+            } else {
+                Err(SlashingProtectionError::DoubleVote {
+                    validator_index: self.validator_index,
+                    slot: data.slot,
+                })
+            };
+        }
+
+        if latest.slot > data.slot.0 {
+            return Err(SlashingProtectionError::AttestationNotNewer {
+                validator_index: self.validator_index,
+                slot: data.slot,
+                latest_slot: Slot(latest.slot),
+            });
+        }
+
+        // Surrounding an earlier vote needs an older source, being surrounded
+        // by one needs an older target.
+        let latest_source_slot = self
+            .signed_attestations
+            .iter()
+            .map(|att| att.source_slot)
+            .max();
+        if let Some(latest_source_slot) = latest_source_slot.filter(|&s| data.source.slot.0 < s) {
+            return Err(SlashingProtectionError::SurroundingVote {
+                validator_index: self.validator_index,
+                source_slot: data.source.slot,
+                latest_source_slot: Slot(latest_source_slot),
+            });
+        }
+
+        let latest_target_slot = self
+            .signed_attestations
+            .iter()
+            .map(|att| att.target_slot)
+            .max();
+        if let Some(latest_target_slot) = latest_target_slot.filter(|&t| data.target.slot.0 < t) {
+            return Err(SlashingProtectionError::SurroundedVote {
+                validator_index: self.validator_index,
+                target_slot: data.target.slot,
+                latest_target_slot: Slot(latest_target_slot),
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Keep only the latest block and the attestations holding the latest
+    /// slot, source and target, which is all the checks look at.
+    fn prune(&mut self) {
+        if let Some(latest) = self.latest_block().cloned() {
+            self.signed_blocks = vec![latest];
+        }
+
+        let attestations = &self.signed_attestations;
+        let mut keep = [
+            attestations
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, att)| att.slot),
+            attestations
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, att)| att.source_slot),
+            attestations
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, att)| att.target_slot),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+        keep.sort_unstable();
+        keep.dedup();
+
+        self.signed_attestations = keep
+            .into_iter()
+            .map(|index| attestations[index].clone())
+            .collect();
+    }
+}
+
+/// Signing history of our validators, keyed by validator index.
+pub struct SlashingProtection {
+    /// In memory only when unset.
+    path: Option<PathBuf>,
+    genesis_time: u64,
+    history: Mutex<BTreeMap<u64, InterchangeData>>,
+}
+
+impl SlashingProtection {
+    /// History is lost on restart. Only for nodes without a data directory.
+    pub fn in_memory(genesis_time: u64) -> Self {
+        Self {
+            path: None,
+            genesis_time,
+            history: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Open the database at `path`, creating it if it does not exist.
+    pub fn open(
+        path: impl AsRef<Path>,
+        genesis_time: u64,
+    ) -> Result<Self, SlashingProtectionError> {
+        let path = path.as_ref();
+        let protection = Self {
+            path: Some(path.to_path_buf()),
+            ..Self::in_memory(genesis_time)
+        };
+
+        match fs::metadata(path) {
+            Ok(_) => protection.merge(Interchange::from_json_file(path)?)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|err| {
+                        SlashingProtectionError::Database(format!(
+                            "failed to create {parent:?}: {err}"
+                        ))
+                    })?;
+                }
+                protection.persist(&protection.lock())?;
+            }
+            Err(err) => {
+                return Err(SlashingProtectionError::Database(format!(
+                    "failed to read {path:?}: {err}"
+                )))
+            }
+        }
+
+        info!(path = ?path, "Slashing protection database opened");
+        Ok(protection)
+    }
+
+    /// Record a proposal at `slot` unless it conflicts with an earlier one.
+    /// `signing_root` identifies the block, so the same block can be signed again.
+    pub fn check_and_record_proposal(
+        &self,
+        validator_index: u64,
+        slot: Slot,
+        signing_root: Root,
+    ) -> Result<(), SlashingProtectionError> {
+        self.check_and_record(validator_index, |history| {
+            let is_new = history.check_proposal(slot, signing_root)?;
+            if is_new {
+                history.signed_blocks.push(SignedBlock {
+                    slot: slot.0,
+                    signing_root: Some(signing_root),
+                });
+            }
+            Ok(is_new)
+        })
+    }
+
+    /// Record an attestation unless it is a double or surround vote.
+    pub fn check_and_record_attestation(
+        &self,
+        validator_index: u64,
+        data: &AttestationData,
+        signing_root: Root,
+    ) -> Result<(), SlashingProtectionError> {
+        self.check_and_record(validator_index, |history| {
+            let is_new = history.check_attestation(data, signing_root)?;
+            if is_new {
+                history.signed_attestations.push(SignedAttestation {
+                    slot: data.slot.0,
+                    source_slot: data.source.slot.0,
+                    target_slot: data.target.slot.0,
+                    signing_root: Some(signing_root),
+                });
+            }
+            Ok(is_new)
+        })
+    }
+
+    pub fn export_interchange(&self) -> Interchange {
+        self.to_interchange(&self.lock())
+    }
+
+    /// Merge the history of another client. Every imported record counts as
+    /// signed, so the result is at least as strict as either side.
+    pub fn import_interchange(
+        &self,
+        interchange: Interchange,
+    ) -> Result<(), SlashingProtectionError> {
+        let validators = interchange.data.len();
+        self.merge(interchange)?;
+        info!(validators, "Imported slashing protection data");
+        Ok(())
+    }
+
+    fn merge(&self, interchange: Interchange) -> Result<(), SlashingProtectionError> {
+        let metadata = &interchange.metadata;
+        if metadata.interchange_format_version != INTERCHANGE_FORMAT_VERSION {
+            return Err(SlashingProtectionError::UnsupportedFormatVersion(
+                metadata.interchange_format_version.clone(),
+            ));
+        }
+        if metadata.genesis_time != self.genesis_time {
+            return Err(SlashingProtectionError::GenesisMismatch {
+                expected: self.genesis_time,
+                actual: metadata.genesis_time,
+            });
+        }
+
+        let mut history = self.lock();
+        for data in interchange.data {
+            let entry = history
+                .entry(data.validator_index)
+                .or_insert_with(|| InterchangeData {
+                    validator_index: data.validator_index,
+                    ..InterchangeData::default()
+                });
+            entry.signed_blocks.extend(data.signed_blocks);
+            entry.signed_attestations.extend(data.signed_attestations);
+            entry.prune();
+        }
+        self.persist(&history)
+    }
+
+    fn check_and_record(
+        &self,
+        validator_index: u64,
+        record: impl FnOnce(&mut InterchangeData) -> Result<bool, SlashingProtectionError>,
+    ) -> Result<(), SlashingProtectionError> {
+        let mut history = self.lock();
+        let entry = history
+            .entry(validator_index)
+            .or_insert_with(|| InterchangeData {
+                validator_index,
+                ..InterchangeData::default()
+            });
+
+        if record(entry)? {
+            entry.prune();
+            // The record stays even if this fails, so nothing conflicting is
+            // signed later in this run either.
+            self.persist(&history)?;
+        }
+        Ok(())
+    }
+
+    fn persist(
+        &self,
+        history: &BTreeMap<u64, InterchangeData>,
+    ) -> Result<(), SlashingProtectionError> {
+        match &self.path {
+            Some(path) => self.to_interchange(history).write_json_file(path),
+            None => Ok(()),
+        }
+    }
+
+    fn to_interchange(&self, history: &BTreeMap<u64, InterchangeData>) -> Interchange {
+        Interchange {
+            metadata: InterchangeMetadata {
+                interchange_format_version: INTERCHANGE_FORMAT_VERSION.to_string(),
+                genesis_time: self.genesis_time,
+            },
+            data: history.values().cloned().collect(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, InterchangeData>> {
+        // A panic while holding the lock cannot leave a half-applied record
+        self.history
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Replaces `path` with `bytes` so that a crash leaves either the old or the new
+/// contents on disk: the temp file is synced before the rename, and the directory
+/// after it so the rename itself survives a power loss.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp_path = path.with_extension(TMP_EXTENSION);
+    let write_tmp = || -> std::io::Result<()> {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()
+    };
+    write_tmp().map_err(|err| format!("failed to write {tmp_path:?}: {err}"))?;
+    fs::rename(&tmp_path, path)
+        .map_err(|err| format!("failed to move {tmp_path:?} to {path:?}: {err}"))?;
+    sync_parent_dir(path).map_err(|err| format!("failed to sync directory of {path:?}: {err}"))
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)?.sync_all()
+}
+
+// Directories cannot be opened as files to sync them outside unix
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use containers::checkpoint::Checkpoint;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "lean-slashing-protection-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("slashing_protection.json")
+    }
+
+    fn root(byte: u8) -> Root {
+        format!("{byte:02x}").repeat(32).parse().unwrap()
+    }
+
+    fn data(slot: u64, source_slot: u64, target_slot: u64) -> AttestationData {
+        AttestationData {
+            slot: Slot(slot),
+            head: Checkpoint::default(),
+            target: Checkpoint {
+                root: Root::default(),
+                slot: Slot(target_slot),
+            },
+            source: Checkpoint {
+                root: Root::default(),
+                slot: Slot(source_slot),
+            },
+        }
+    }
+
+    #[test]
+    fn refuses_double_and_older_proposals() {
+        let protection = SlashingProtection::in_memory(0);
+
+        protection
+            .check_and_record_proposal(1, Slot(5), root(1))
+            .unwrap();
+        // Signing the same block again is harmless
+        protection
+            .check_and_record_proposal(1, Slot(5), root(1))
+            .unwrap();
+
+        assert_eq!(
+            protection.check_and_record_proposal(1, Slot(5), root(2)),
+            Err(SlashingProtectionError::DoubleProposal {
+                validator_index: 1,
+                slot: Slot(5)
+            })
+        );
+        assert!(matches!(
+            protection.check_and_record_proposal(1, Slot(4), root(3)),
+            Err(SlashingProtectionError::ProposalNotNewer { .. })
+        ));
+
+        // Other validators are unaffected
+        protection
+            .check_and_record_proposal(2, Slot(5), root(2))
+            .unwrap();
+        protection
+            .check_and_record_proposal(1, Slot(6), root(3))
+            .unwrap();
+    }
+
+    #[test]
+    fn refuses_double_and_surround_votes() {
+        let protection = SlashingProtection::in_memory(0);
+
+        protection
+            .check_and_record_attestation(1, &data(10, 4, 8), root(1))
+            .unwrap();
+        protection
+            .check_and_record_attestation(1, &data(10, 4, 8), root(1))
+            .unwrap();
+
+        assert!(matches!(
+            protection.check_and_record_attestation(1, &data(10, 4, 9), root(2)),
+            Err(SlashingProtectionError::DoubleVote { .. })
+        ));
+        assert!(matches!(
+            protection.check_and_record_attestation(1, &data(9, 4, 8), root(2)),
+            Err(SlashingProtectionError::AttestationNotNewer { .. })
+        ));
+        // Surrounds (4, 8)
+        assert!(matches!(
+            protection.check_and_record_attestation(1, &data(11, 3, 9), root(2)),
+            Err(SlashingProtectionError::SurroundingVote { .. })
+        ));
+        // Surrounded by (4, 8)
+        assert!(matches!(
+            protection.check_and_record_attestation(1, &data(11, 5, 7), root(2)),
+            Err(SlashingProtectionError::SurroundedVote { .. })
+        ));
+
+        // Later slots may repeat the source and target
+        protection
+            .check_and_record_attestation(1, &data(11, 4, 8), root(2))
+            .unwrap();
+        protection
+            .check_and_record_attestation(1, &data(12, 8, 11), root(3))
+            .unwrap();
+    }
+
+    #[test]
+    fn history_survives_restart() {
+        let path = temp_path("restart");
+
+        let protection = SlashingProtection::open(&path, 7).unwrap();
+        protection
+            .check_and_record_proposal(1, Slot(5), root(1))
+            .unwrap();
+        protection
+            .check_and_record_attestation(1, &data(5, 2, 4), root(2))
+            .unwrap();
+        drop(protection);
+
+        let protection = SlashingProtection::open(&path, 7).unwrap();
+        assert!(protection
+            .check_and_record_proposal(1, Slot(5), root(3))
+            .is_err());
+        assert!(protection
+            .check_and_record_attestation(1, &data(5, 2, 4), root(4))
+            .is_err());
+        protection
+            .check_and_record_proposal(1, Slot(5), root(1))
+            .unwrap();
+
+        assert_eq!(
+            SlashingProtection::open(&path, 8).err(),
+            Some(SlashingProtectionError::GenesisMismatch {
+                expected: 8,
+                actual: 7
+            })
+        );
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn import_keeps_strictest_history() {
+        let json = r#"{
+            "metadata": {"interchange_format_version": "5", "genesis_time": "0"},
+            "data": [{
+                "validator_index": "3",
+                "signed_blocks": [{"slot": "20"}, {"slot": "12"}],
+                "signed_attestations": [
+                    {"slot": "20", "source_slot": "16", "target_slot": "18"},
+                    {"slot": "19", "source_slot": "17", "target_slot": "17"},
+                    {"slot": "15", "source_slot": "10", "target_slot": "19"}
+                ]
+            }]
+        }"#;
+        let interchange: Interchange = serde_json::from_str(json).unwrap();
+
+        let protection = SlashingProtection::in_memory(0);
+        protection.import_interchange(interchange).unwrap();
+
+        // No signing root was recorded, so slot 20 cannot be signed again
+        assert!(protection
+            .check_and_record_proposal(3, Slot(20), root(1))
+            .is_err());
+        assert!(matches!(
+            protection.check_and_record_attestation(3, &data(21, 16, 20), root(1)),
+            Err(SlashingProtectionError::SurroundingVote { .. })
+        ));
+        assert!(matches!(
+            protection.check_and_record_attestation(3, &data(21, 17, 18), root(1)),
+            Err(SlashingProtectionError::SurroundedVote { .. })
+        ));
+        protection
+            .check_and_record_attestation(3, &data(21, 17, 19), root(1))
+            .unwrap();
+
+        let exported = protection.export_interchange();
+        assert_eq!(exported.data[0].signed_blocks.len(), 1);
+        let roundtrip: Interchange =
+            serde_json::from_str(&serde_json::to_string(&exported).unwrap()).unwrap();
+        assert_eq!(roundtrip, exported);
+    }
+}